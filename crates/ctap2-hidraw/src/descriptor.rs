@@ -0,0 +1,171 @@
+//! Minimal parsing of HID report descriptors, sufficient to recognize FIDO
+//! authenticators.
+
+/// The HID usage page assigned to the FIDO Alliance.
+pub const FIDO_USAGE_PAGE: u32 = 0xF1D0;
+
+/// The CTAPHID usage, within the FIDO usage page.
+pub const CTAPHID_USAGE: u32 = 0x01;
+
+/// The prefix of a long item, which carries its own size and tag.
+const LONG_ITEM: u8 = 0xFE;
+
+const TYPE_MAIN: u8 = 0;
+const TYPE_GLOBAL: u8 = 1;
+const TYPE_LOCAL: u8 = 2;
+
+const TAG_USAGE_PAGE: u8 = 0x0;
+const TAG_USAGE: u8 = 0x0;
+const TAG_COLLECTION: u8 = 0xA;
+
+/// Returns whether the report descriptor declares an application collection
+/// with the CTAPHID usage on the FIDO usage page.
+pub fn is_fido(descriptor: &[u8]) -> bool {
+    let mut usage_page = 0;
+    // The usage page given by a four byte usage, if any, and the usage ID
+    let mut usage: Option<(Option<u32>, u32)> = None;
+
+    let mut items = descriptor;
+    while let Some((&prefix, rest)) = items.split_first() {
+        if prefix == LONG_ITEM {
+            // bDataSize, bLongItemTag, data
+            let Some(&size) = rest.first() else {
+                return false;
+            };
+            let Some(rest) = rest.get(2 + size as usize..) else {
+                return false;
+            };
+            items = rest;
+            continue;
+        }
+
+        let size = match prefix & 0b11 {
+            3 => 4,
+            size => size as usize,
+        };
+        let Some(data) = rest.get(..size) else {
+            return false;
+        };
+        items = &rest[size..];
+
+        let value = data
+            .iter()
+            .rev()
+            .fold(0u32, |value, &byte| (value << 8) | u32::from(byte));
+
+        match ((prefix >> 2) & 0b11, prefix >> 4) {
+            (TYPE_GLOBAL, TAG_USAGE_PAGE) => usage_page = value,
+            // A four byte usage includes its own usage page in the high bytes,
+            // which does not replace the global one
+            (TYPE_LOCAL, TAG_USAGE) if size == 4 => {
+                usage = Some((Some(value >> 16), value & 0xFFFF))
+            }
+            (TYPE_LOCAL, TAG_USAGE) => usage = Some((None, value)),
+            (TYPE_MAIN, TAG_COLLECTION) => {
+                if let Some((page, id)) = usage.take() {
+                    if page.unwrap_or(usage_page) == FIDO_USAGE_PAGE && id == CTAPHID_USAGE {
+                        return true;
+                    }
+                }
+            }
+            (TYPE_MAIN, _) => usage = None,
+            _ => {}
+        }
+    }
+
+    false
+}
+
+/// A report descriptor for a CTAPHID device with 64 byte input and output
+/// reports and no report IDs.
+pub const CTAPHID_REPORT_DESCRIPTOR: [u8; 34] = [
+    0x06, 0xD0, 0xF1, // Usage Page (FIDO Alliance)
+    0x09, 0x01, //       Usage (CTAPHID)
+    0xA1, 0x01, //       Collection (Application)
+    0x09, 0x20, //         Usage (Input Report Data)
+    0x15, 0x00, //         Logical Minimum (0)
+    0x26, 0xFF, 0x00, //   Logical Maximum (255)
+    0x75, 0x08, //         Report Size (8)
+    0x95, 0x40, //         Report Count (64)
+    0x81, 0x02, //         Input (Data, Variable, Absolute)
+    0x09, 0x21, //         Usage (Output Report Data)
+    0x15, 0x00, //         Logical Minimum (0)
+    0x26, 0xFF, 0x00, //   Logical Maximum (255)
+    0x75, 0x08, //         Report Size (8)
+    0x95, 0x40, //         Report Count (64)
+    0x91, 0x02, //         Output (Data, Variable, Absolute)
+    0xC0, //             End Collection
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The application collection of a boot keyboard.
+    const KEYBOARD: [u8; 9] = [
+        0x05, 0x01, // Usage Page (Generic Desktop)
+        0x09, 0x06, // Usage (Keyboard)
+        0xA1, 0x01, // Collection (Application)
+        0x05, 0x07, //   Usage Page (Keyboard)
+        0xC0, //     End Collection
+    ];
+
+    #[test]
+    fn ctaphid_descriptor() {
+        assert!(is_fido(&CTAPHID_REPORT_DESCRIPTOR));
+    }
+
+    #[test]
+    fn other_descriptors() {
+        assert!(!is_fido(&KEYBOARD));
+        assert!(!is_fido(&[]));
+        // The FIDO usage page with another usage
+        assert!(!is_fido(&[0x06, 0xD0, 0xF1, 0x09, 0x02, 0xA1, 0x01, 0xC0]));
+        // The usage is consumed by the collection it applies to
+        assert!(!is_fido(&[
+            0x06, 0xD0, 0xF1, 0x09, 0x02, 0xA1, 0x01, 0xA1, 0x01
+        ]));
+    }
+
+    #[test]
+    fn later_collection() {
+        let descriptor = [KEYBOARD.as_slice(), &CTAPHID_REPORT_DESCRIPTOR].concat();
+        assert!(is_fido(&descriptor));
+    }
+
+    #[test]
+    fn long_items() {
+        // A long item whose data would be a FIDO usage if it were parsed
+        let long = [LONG_ITEM, 0x02, 0xF0, 0x09, 0x01];
+        let descriptor = [long.as_slice(), &CTAPHID_REPORT_DESCRIPTOR].concat();
+        assert!(is_fido(&descriptor));
+        assert!(!is_fido(&[
+            0x06, 0xD0, 0xF1, LONG_ITEM, 0x02, 0xF0, 0x09, 0x01, 0xA1, 0x01
+        ]));
+
+        // Truncated long items
+        assert!(!is_fido(&[LONG_ITEM]));
+        assert!(!is_fido(&[LONG_ITEM, 0x04, 0xF0, 0x00]));
+    }
+
+    #[test]
+    fn four_byte_usages() {
+        // Usage (FIDO Alliance: CTAPHID) without a global usage page
+        assert!(is_fido(&[0x0B, 0x01, 0x00, 0xD0, 0xF1, 0xA1, 0x01, 0xC0]));
+        // Usage (Generic Desktop: Keyboard) on the FIDO usage page
+        assert!(!is_fido(&[
+            0x06, 0xD0, 0xF1, 0x0B, 0x06, 0x00, 0x01, 0x00, 0xA1, 0x01, 0xC0
+        ]));
+        // The four byte usage does not change the global usage page
+        assert!(is_fido(&[
+            0x06, 0xD0, 0xF1, 0x0B, 0x06, 0x00, 0x01, 0x00, 0xA1, 0x01, 0xC0, 0x09, 0x01, 0xA1,
+            0x01, 0xC0
+        ]));
+    }
+
+    #[test]
+    fn truncated_items() {
+        assert!(!is_fido(&CTAPHID_REPORT_DESCRIPTOR[..2]));
+        assert!(!is_fido(&[0x0B, 0x01, 0x00, 0xD0]));
+    }
+}
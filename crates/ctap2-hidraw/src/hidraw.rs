@@ -0,0 +1,96 @@
+//! Discovery of and raw report I/O with Linux `hidraw` devices.
+
+use crate::descriptor;
+use ctap2_proto::hid::{self, Report, REPORT_SIZE};
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+};
+
+const DEVICE_DIRECTORY: &str = "/dev";
+const SYSFS_DIRECTORY: &str = "/sys/class/hidraw";
+
+/// A `hidraw` device node which was identified as a FIDO authenticator.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceInfo {
+    /// The path to the device node, e.g. `/dev/hidraw0`.
+    pub path: PathBuf,
+    /// The raw HID report descriptor of the device.
+    pub report_descriptor: Vec<u8>,
+}
+
+/// Lists every `/dev/hidraw*` device whose report descriptor declares the
+/// FIDO usage page.
+///
+/// Devices whose report descriptor cannot be read, e.g. due to missing
+/// permissions, are skipped.
+pub fn enumerate() -> io::Result<Vec<DeviceInfo>> {
+    let mut devices = Vec::new();
+
+    for entry in fs::read_dir(DEVICE_DIRECTORY)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        let Some(name) = name.to_str().filter(|name| name.starts_with("hidraw")) else {
+            continue;
+        };
+
+        let Ok(report_descriptor) = read_report_descriptor(name) else {
+            continue;
+        };
+
+        if descriptor::is_fido(&report_descriptor) {
+            devices.push(DeviceInfo {
+                path: entry.path(),
+                report_descriptor,
+            });
+        }
+    }
+
+    devices.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(devices)
+}
+
+fn read_report_descriptor(name: &str) -> io::Result<Vec<u8>> {
+    let path = Path::new(SYSFS_DIRECTORY)
+        .join(name)
+        .join("device")
+        .join("report_descriptor");
+    fs::read(path)
+}
+
+/// An open `hidraw` device node, exchanging raw CTAPHID reports.
+pub struct Device {
+    file: File,
+}
+
+impl Device {
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let file = OpenOptions::new().read(true).write(true).open(path)?;
+        Ok(Self { file })
+    }
+}
+
+impl hid::Device for Device {
+    type Error = io::Error;
+
+    fn send_report(&mut self, report: &Report) -> Result<(), Self::Error> {
+        // The first byte written to a hidraw node is the report number, which
+        // is zero for devices that do not use numbered reports.
+        let mut buffer = [0; REPORT_SIZE + 1];
+        buffer[1..].copy_from_slice(report);
+        self.file.write_all(&buffer)
+    }
+
+    fn receive_report(&mut self) -> Result<Report, Self::Error> {
+        let mut report = [0; REPORT_SIZE];
+        let length = self.file.read(&mut report)?;
+        if length != REPORT_SIZE {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "short HID report",
+            ));
+        }
+        Ok(report)
+    }
+}
@@ -0,0 +1,486 @@
+//! A platform-side CTAP2 client for authenticators attached through the Linux
+//! `hidraw` driver.
+
+use ctap2_proto::{
+    hid::{self, platform::Channel, NONCE_SIZE},
+    prelude::{
+        bio_enrollment, client_pin, config, device, get, large_blobs, make, management, reset,
+        selection, Command, Ctap2_2Authenticator, StatusCode,
+    },
+};
+use serde::{de::DeserializeOwned, Serialize};
+use std::{fmt::Display, fs::File, io, io::Read};
+
+pub mod descriptor;
+pub mod hidraw;
+pub mod uhid;
+
+pub use hidraw::{enumerate, DeviceInfo};
+
+#[derive(Debug)]
+pub enum Error<E = io::Error> {
+    /// The device node could not be opened or read.
+    Io(io::Error),
+    /// The CTAPHID transaction failed.
+    Hid(hid::Error<E>),
+    /// A request could not be encoded or a response could not be decoded.
+    Cbor,
+    /// The authenticator responded with an unsuccessful status code.
+    Status(StatusCode),
+}
+
+impl<E> Error<E> {
+    /// The status code returned by the authenticator, or `CTAP1_ERR_OTHER` if
+    /// the request failed before a response was received.
+    fn status(&self) -> StatusCode {
+        match self {
+            Error::Status(status) => *status,
+            _ => StatusCode::Other,
+        }
+    }
+}
+
+impl<E: Display> Display for Error<E> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Io(error) => write!(f, "I/O error: {}", error),
+            Error::Hid(error) => write!(f, "CTAPHID error: {}", error),
+            Error::Cbor => write!(f, "Invalid CBOR"),
+            Error::Status(status) => write!(f, "CTAP2 status: {}", status),
+        }
+    }
+}
+
+/// A FIDO authenticator reached over a `hidraw` device node, or any other
+/// [`hid::Device`].
+pub struct Authenticator<D: hid::Device = hidraw::Device> {
+    channel: Channel<D>,
+    info: device::Info,
+    last_error: Option<Error<D::Error>>,
+}
+
+impl Authenticator {
+    /// Opens the device node, allocates a CTAPHID channel and reads the
+    /// authenticator's info.
+    pub fn open(device: &DeviceInfo) -> Result<Self, Error> {
+        let device = hidraw::Device::open(&device.path).map_err(Error::Io)?;
+        Self::new(device)
+    }
+}
+
+impl<D: hid::Device> Authenticator<D> {
+    /// Allocates a CTAPHID channel on the device and reads the
+    /// authenticator's info.
+    pub fn new(device: D) -> Result<Self, Error<D::Error>> {
+        let mut channel = Channel::open(device, nonce().map_err(Error::Io)?).map_err(Error::Hid)?;
+
+        let response = transact(&mut channel, Command::AuthenticatorGetInfo, None)?;
+        let info = decode(&response)?;
+
+        Ok(Self {
+            channel,
+            info,
+            last_error: None,
+        })
+    }
+
+    /// The underlying CTAPHID channel, e.g. to send `CTAPHID_WINK`.
+    pub fn channel(&mut self) -> &mut Channel<D> {
+        &mut self.channel
+    }
+
+    /// Why the last command failed, if it did.
+    ///
+    /// Failures to reach the authenticator, such as the device being
+    /// unplugged, are reported as `CTAP1_ERR_OTHER` by the
+    /// [`Ctap2_2Authenticator`] methods, just like the authenticator
+    /// answering with that status. This tells the two apart.
+    pub fn last_error(&self) -> Option<&Error<D::Error>> {
+        self.last_error.as_ref()
+    }
+
+    /// Keeps the error of a failed command for [`Authenticator::last_error`],
+    /// returning the status code to report it with.
+    fn record<T>(&mut self, result: Result<T, Error<D::Error>>) -> Result<T, StatusCode> {
+        match result {
+            Ok(value) => {
+                self.last_error = None;
+                Ok(value)
+            }
+            Err(error) => {
+                let status = error.status();
+                self.last_error = Some(error);
+                Err(status)
+            }
+        }
+    }
+
+    /// `authenticatorBioEnrollment`, or its prototype command for
+    /// authenticators that only support the CTAP2.1 preview.
+    fn bio_enrollment_command(&self) -> Command {
+        if !self.supports(device::OptionId::BiometricEnroll)
+            && self.supports(device::OptionId::UvManagementPreview)
+        {
+            Command::PrototypeAuthenticatorBioEnrollment
+        } else {
+            Command::AuthenticatorBioEnrollment
+        }
+    }
+
+    /// `authenticatorCredentialManagement`, or its prototype command for
+    /// authenticators that only support the CTAP2.1 preview.
+    fn credential_management_command(&self) -> Command {
+        if !self.supports(device::OptionId::CredentialManagement)
+            && self.supports(device::OptionId::CredentialManagementPreview)
+        {
+            Command::PrototypeAuthenticatorCredentialManagement
+        } else {
+            Command::AuthenticatorCredentialManagement
+        }
+    }
+
+    /// Whether the authenticator reports the option, whatever its value.
+    fn supports(&self, option: device::OptionId) -> bool {
+        self.info
+            .options
+            .as_ref()
+            .is_some_and(|options| options.contains_key(&option))
+    }
+
+    fn request<T: Serialize, R: DeserializeOwned>(
+        &mut self,
+        command: Command,
+        parameters: &T,
+    ) -> Result<R, Error<D::Error>> {
+        let response = transact(
+            &mut self.channel,
+            command,
+            Some(encode(parameters)?.as_slice()),
+        )?;
+        decode(&response)
+    }
+}
+
+impl<D: hid::Device> Ctap2_2Authenticator for Authenticator<D> {
+    fn make_credential(&mut self, request: make::Request) -> Result<make::Response, make::Error> {
+        let result = self.request(Command::AuthenticatorMakeCredential, &request);
+        self.record(result).map_err(Into::into)
+    }
+
+    fn get_assertion(&mut self, request: get::Request) -> Result<get::Response, get::Error> {
+        let result = self.request(Command::AuthenticatorGetAssertion, &request);
+        self.record(result).map_err(Into::into)
+    }
+
+    fn get_next_assertion(&mut self) -> Result<get::Response, get::Error> {
+        let result = transact(
+            &mut self.channel,
+            Command::AuthenticatorGetNextAssertion,
+            None,
+        )
+        .and_then(|response| decode(&response));
+        self.record(result).map_err(Into::into)
+    }
+
+    fn get_info(&self) -> device::Info {
+        self.info.clone()
+    }
+
+    fn client_pin(
+        &mut self,
+        request: client_pin::Request,
+    ) -> Result<client_pin::Response, client_pin::Error> {
+        let result = self.request(Command::AuthenticatorClientPin, &request);
+        let response: client_pin::Response =
+            self.record(result).map_err(client_pin::Error::from)?;
+        response.for_request(&request)
+    }
+
+    fn reset(&mut self) -> Result<(), reset::Error> {
+        let result = transact(&mut self.channel, Command::AuthenticatorReset, None);
+        self.record(result).map(|_| ()).map_err(Into::into)
+    }
+
+    fn bio_enrollment(
+        &mut self,
+        request: bio_enrollment::Request,
+    ) -> Result<bio_enrollment::Response, bio_enrollment::Error> {
+        let command = self.bio_enrollment_command();
+        let result = encode(&request)
+            .and_then(|parameters| transact(&mut self.channel, command, Some(&parameters)))
+            .and_then(|response| match response.as_slice() {
+                // `cancelCurrentEnrollment`, `setFriendlyName` and
+                // `removeEnrollment` return no data
+                [] => Ok(bio_enrollment::Response::CancelCurrentEnrollment),
+                response => decode(response),
+            });
+        let response = self.record(result).map_err(bio_enrollment::Error::from)?;
+        response.for_request(&request)
+    }
+
+    fn credential_management(
+        &mut self,
+        request: management::Request,
+    ) -> Result<management::Response, management::Error> {
+        let command = self.credential_management_command();
+        let result = encode(&request)
+            .and_then(|parameters| transact(&mut self.channel, command, Some(&parameters)))
+            .and_then(|response| match response.as_slice() {
+                // `deleteCredential` and `updateUserInformation` return no data
+                [] => Ok(management::Response::DeleteCredential),
+                response => decode(response),
+            });
+        let response = self.record(result).map_err(management::Error::from)?;
+        response.for_request(&request)
+    }
+
+    fn selection(&mut self) -> Result<(), selection::Error> {
+        let result = transact(&mut self.channel, Command::AuthenticatorSelection, None);
+        self.record(result).map(|_| ()).map_err(Into::into)
+    }
+
+    fn large_blobs(
+        &mut self,
+        request: large_blobs::Request,
+    ) -> Result<large_blobs::Response, large_blobs::Error> {
+        let result = encode(&request)
+            .and_then(|parameters| {
+                transact(
+                    &mut self.channel,
+                    Command::AuthenticatorLargeBlobs,
+                    Some(&parameters),
+                )
+            })
+            .and_then(|response| match response.as_slice() {
+                // `set` returns no data
+                [] => Ok(large_blobs::Response::default()),
+                response => decode(response),
+            });
+        self.record(result).map_err(Into::into)
+    }
+
+    fn authenticator_config(&mut self, request: config::Request) -> Result<(), config::Error> {
+        let result = encode(&request).and_then(|parameters| {
+            transact(
+                &mut self.channel,
+                Command::AuthenticatorConfig,
+                Some(&parameters),
+            )
+        });
+        self.record(result).map(|_| ()).map_err(Into::into)
+    }
+}
+
+/// Sends the command byte followed by the CBOR encoded parameters, if any,
+/// and returns the response data following a successful status byte.
+fn transact<D: hid::Device>(
+    channel: &mut Channel<D>,
+    command: Command,
+    parameters: Option<&[u8]>,
+) -> Result<Vec<u8>, Error<D::Error>> {
+    let mut request = vec![command.into()];
+    request.extend_from_slice(parameters.unwrap_or_default());
+
+    let mut response = channel.cbor(&request, |_| {}).map_err(Error::Hid)?;
+    match response.first().copied().map(StatusCode::from) {
+        Some(StatusCode::Ok) => Ok(response.split_off(1)),
+        Some(status) => Err(Error::Status(status)),
+        None => Err(Error::Cbor),
+    }
+}
+
+fn encode<T: Serialize, E>(value: &T) -> Result<Vec<u8>, Error<E>> {
+    let mut data = Vec::new();
+    ciborium::ser::into_writer(value, &mut data).map_err(|_| Error::Cbor)?;
+    Ok(data)
+}
+
+fn decode<R: DeserializeOwned, E>(data: &[u8]) -> Result<R, Error<E>> {
+    ciborium::de::from_reader(data).map_err(|_| Error::Cbor)
+}
+
+/// Reads a fresh nonce for `CTAPHID_INIT` from the kernel's random source.
+fn nonce() -> io::Result<[u8; NONCE_SIZE]> {
+    let mut nonce = [0; NONCE_SIZE];
+    File::open("/dev/urandom")?.read_exact(&mut nonce)?;
+    Ok(nonce)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ctap2_proto::{
+        dispatch::dispatch,
+        hid::{
+            authenticator::{Handler, Loopback, NoPendingReport, Server},
+            Capability, Report,
+        },
+        prelude::{credential, credential::public_key},
+    };
+    use ctap2_virtual::{AlwaysPresent, Memory};
+    use std::{cell::Cell, collections::BTreeMap, rc::Rc};
+
+    /// Answers CTAPHID_CBOR messages with a virtual authenticator.
+    struct Virtual(ctap2_virtual::Authenticator<Memory>);
+
+    impl Handler for Virtual {
+        fn cbor(&mut self, request: &[u8]) -> Vec<u8> {
+            dispatch(&mut self.0, request)
+        }
+    }
+
+    #[derive(Debug, PartialEq)]
+    enum CableError {
+        Unplugged,
+        NoPendingReport,
+    }
+
+    /// Connects the client to a virtual authenticator until it is unplugged.
+    struct Cable {
+        loopback: Loopback<Virtual>,
+        plugged: Rc<Cell<bool>>,
+    }
+
+    impl hid::Device for Cable {
+        type Error = CableError;
+
+        fn send_report(&mut self, report: &Report) -> Result<(), Self::Error> {
+            if !self.plugged.get() {
+                return Err(CableError::Unplugged);
+            }
+            self.loopback
+                .send_report(report)
+                .map_err(|NoPendingReport| CableError::NoPendingReport)
+        }
+
+        fn receive_report(&mut self) -> Result<Report, Self::Error> {
+            self.loopback
+                .receive_report()
+                .map_err(|NoPendingReport| CableError::NoPendingReport)
+        }
+    }
+
+    fn authenticator() -> (Authenticator<Cable>, Rc<Cell<bool>>) {
+        let server = Server::new((1, 0, 0), Capability::Cbor | Capability::NoMsg);
+        let handler = Virtual(ctap2_virtual::Authenticator::new(
+            Memory::default(),
+            AlwaysPresent,
+        ));
+        let plugged = Rc::new(Cell::new(true));
+        let cable = Cable {
+            loopback: Loopback::new(server, handler),
+            plugged: Rc::clone(&plugged),
+        };
+        (Authenticator::new(cable).unwrap(), plugged)
+    }
+
+    #[test]
+    fn round_trip() {
+        let (mut authenticator, _) = authenticator();
+        let info = ctap2_virtual::Authenticator::new(Memory::default(), AlwaysPresent).get_info();
+        assert_eq!(authenticator.get_info().aaguid, info.aaguid);
+        assert_eq!(authenticator.get_info().options, info.options);
+
+        let relying_party = public_key::RelyingPartyEntity {
+            id: "example.com".to_owned(),
+            name: None,
+        };
+        let user = public_key::UserEntity {
+            id: vec![0x01],
+            name: Some("user".to_owned()),
+            display_name: None,
+        };
+        let parameters = [public_key::Parameters {
+            credential_type: credential::Type::PublicKey,
+            algorithm: coset::iana::Algorithm::ES256,
+        }];
+        let options = BTreeMap::from([(make::OptionKey::Discoverable, true)]);
+        let request = make::Request::builder()
+            .client_data_hash(&[0x42; 32])
+            .relying_party(&relying_party)
+            .user(&user)
+            .public_key_credential_params(&parameters)
+            .options(&options)
+            .build();
+        let response = authenticator.make_credential(request).unwrap();
+        let credential = response
+            .authenticator_data
+            .attested_credential_data
+            .unwrap();
+
+        let request = get::Request {
+            relying_party_id: "example.com",
+            client_data_hash: &[0x42; 32],
+            allow_list: None,
+            extensions: None,
+            options: None,
+            pin_uv_auth_param: client_pin::PinUvAuth::None,
+            pin_uv_auth_protocol_version: None,
+        };
+        let response = authenticator.get_assertion(request).unwrap();
+        assert_eq!(response.credential.id, credential.id);
+        assert_eq!(response.user.unwrap().id, user.id);
+        assert!(authenticator.last_error().is_none());
+    }
+
+    #[test]
+    fn status_error() {
+        let (mut authenticator, _) = authenticator();
+        assert_eq!(
+            authenticator.get_next_assertion().err(),
+            Some(get::Error::NotAllowed)
+        );
+        assert!(matches!(
+            authenticator.last_error(),
+            Some(Error::Status(StatusCode::NotAllowed))
+        ));
+
+        authenticator.selection().unwrap();
+        assert!(authenticator.last_error().is_none());
+    }
+
+    #[test]
+    fn transport_error() {
+        let (mut authenticator, plugged) = authenticator();
+        plugged.set(false);
+        // Reported like the authenticator answering CTAP1_ERR_OTHER
+        assert_eq!(
+            authenticator.selection(),
+            Err(selection::Error::from(StatusCode::Other))
+        );
+        assert!(matches!(
+            authenticator.last_error(),
+            Some(Error::Hid(hid::Error::Transport(CableError::Unplugged)))
+        ));
+    }
+
+    #[test]
+    #[ignore = "requires write access to /dev/uhid"]
+    fn virtual_device() {
+        let existing = enumerate().unwrap();
+        let server = Server::new((1, 0, 0), Capability::Cbor | Capability::NoMsg);
+        let handler = Virtual(ctap2_virtual::Authenticator::new(
+            Memory::default(),
+            AlwaysPresent,
+        ));
+        let mut device = uhid::VirtualDevice::create("ctap2-hidraw test", server, handler).unwrap();
+        std::thread::spawn(move || device.run());
+
+        // The kernel creates the hidraw node asynchronously
+        let device = (0..50)
+            .find_map(|_| {
+                std::thread::sleep(std::time::Duration::from_millis(100));
+                enumerate()
+                    .unwrap()
+                    .into_iter()
+                    .find(|device| !existing.contains(device))
+            })
+            .expect("no hidraw node for the virtual device");
+        let mut authenticator = Authenticator::open(&device).unwrap();
+        assert_eq!(
+            authenticator.get_next_assertion().err(),
+            Some(get::Error::NotAllowed)
+        );
+        authenticator.selection().unwrap();
+    }
+}
@@ -0,0 +1,131 @@
+//! Virtual HID devices created through the Linux `uhid` driver.
+//!
+//! A [`VirtualDevice`] exposes a CTAPHID [`Server`] as a `/dev/hidraw*` node,
+//! so that the [`Authenticator`](crate::Authenticator) client can be exercised
+//! end-to-end against a software authenticator without any hardware.
+
+use crate::descriptor::CTAPHID_REPORT_DESCRIPTOR;
+use ctap2_proto::hid::{
+    authenticator::{Handler, Server},
+    Report, REPORT_SIZE,
+};
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, Read, Write},
+};
+
+const UHID_PATH: &str = "/dev/uhid";
+
+/// `struct uhid_event` is a packed `__u32` type followed by a union whose
+/// largest member is `struct uhid_create2_req`.
+const EVENT_SIZE: usize = 4 + CREATE2_SIZE;
+const CREATE2_SIZE: usize = 128 + 64 + 64 + 2 + 2 + 4 + 4 + 4 + 4 + HID_MAX_DESCRIPTOR_SIZE;
+const HID_MAX_DESCRIPTOR_SIZE: usize = 4096;
+const UHID_DATA_MAX: usize = 4096;
+
+const UHID_DESTROY: u32 = 1;
+const UHID_OUTPUT: u32 = 6;
+const UHID_CREATE2: u32 = 11;
+const UHID_INPUT2: u32 = 12;
+
+const BUS_USB: u16 = 0x03;
+
+/// A virtual FIDO authenticator, visible to the system as a `hidraw` device
+/// for as long as it is alive.
+pub struct VirtualDevice<H: Handler> {
+    file: File,
+    server: Server,
+    handler: H,
+}
+
+impl<H: Handler> VirtualDevice<H> {
+    /// Creates the device. Requires write access to `/dev/uhid`.
+    pub fn create(name: &str, server: Server, handler: H) -> io::Result<Self> {
+        let mut file = OpenOptions::new().read(true).write(true).open(UHID_PATH)?;
+
+        let mut event = [0; EVENT_SIZE];
+        event[..4].copy_from_slice(&UHID_CREATE2.to_ne_bytes());
+        let request = &mut event[4..];
+        let name = name.as_bytes();
+        // The name is a NUL-terminated string of at most 128 bytes
+        let name_length = name.len().min(127);
+        request[..name_length].copy_from_slice(&name[..name_length]);
+        // phys and uniq are left empty
+        let request = &mut request[128 + 64 + 64..];
+        request[..2].copy_from_slice(&(CTAPHID_REPORT_DESCRIPTOR.len() as u16).to_ne_bytes());
+        request[2..4].copy_from_slice(&BUS_USB.to_ne_bytes());
+        // vendor, product, version and country are left as zero
+        let descriptor = &mut request[2 + 2 + 4 + 4 + 4 + 4..];
+        descriptor[..CTAPHID_REPORT_DESCRIPTOR.len()].copy_from_slice(&CTAPHID_REPORT_DESCRIPTOR);
+
+        file.write_all(&event)?;
+
+        Ok(Self {
+            file,
+            server,
+            handler,
+        })
+    }
+
+    pub fn handler(&mut self) -> &mut H {
+        &mut self.handler
+    }
+
+    /// Waits for the next event from the kernel and, if it carries a report
+    /// written by the platform, answers it.
+    pub fn process_event(&mut self) -> io::Result<()> {
+        let mut event = [0; EVENT_SIZE];
+        let length = self.file.read(&mut event)?;
+        if length < 4 {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof));
+        }
+
+        let event_type = u32::from_ne_bytes([event[0], event[1], event[2], event[3]]);
+        if event_type != UHID_OUTPUT {
+            // Start, stop, open and close events require no action
+            return Ok(());
+        }
+
+        // struct uhid_output_req { __u8 data[UHID_DATA_MAX]; __u16 size; __u8 rtype; }
+        let data = &event[4..4 + UHID_DATA_MAX];
+        let size = u16::from_ne_bytes([event[4 + UHID_DATA_MAX], event[4 + UHID_DATA_MAX + 1]]);
+        // The report number written by the platform precedes the report
+        let Some(data) = data.get(1..size as usize) else {
+            return Ok(());
+        };
+
+        let mut report = [0; REPORT_SIZE];
+        let length = data.len().min(REPORT_SIZE);
+        report[..length].copy_from_slice(&data[..length]);
+
+        for response in self.server.handle_report(&report, &mut self.handler) {
+            self.input(&response)?;
+        }
+        Ok(())
+    }
+
+    /// Processes events until an I/O error occurs.
+    pub fn run(&mut self) -> io::Result<()> {
+        loop {
+            self.process_event()?;
+        }
+    }
+
+    fn input(&mut self, report: &Report) -> io::Result<()> {
+        // struct uhid_input2_req { __u16 size; __u8 data[UHID_DATA_MAX]; }
+        let mut event = [0; EVENT_SIZE];
+        event[..4].copy_from_slice(&UHID_INPUT2.to_ne_bytes());
+        event[4..6].copy_from_slice(&(REPORT_SIZE as u16).to_ne_bytes());
+        event[6..6 + REPORT_SIZE].copy_from_slice(report);
+        self.file.write_all(&event)
+    }
+}
+
+impl<H: Handler> Drop for VirtualDevice<H> {
+    fn drop(&mut self) {
+        let mut event = [0; EVENT_SIZE];
+        event[..4].copy_from_slice(&UHID_DESTROY.to_ne_bytes());
+        // The device is also destroyed when the file is closed
+        let _ = self.file.write_all(&event);
+    }
+}
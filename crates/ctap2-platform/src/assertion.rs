@@ -0,0 +1,175 @@
+//! Collecting every assertion for an `authenticatorGetAssertion` request.
+
+use crate::Sequence;
+use ctap2_proto::prelude::{get, Ctap2_2Authenticator};
+
+/// The assertions for an `authenticatorGetAssertion` request, one per
+/// applicable credential.
+///
+/// The first assertion is the response to the request itself. If the
+/// authenticator reports more than one applicable credential in
+/// `numberOfCredentials`, the remaining assertions are requested with
+/// `authenticatorGetNextAssertion` as the iterator advances. The authenticator
+/// discards them when it receives any other command or after 30 seconds, so
+/// the iterator should be drained before sending the next command.
+pub type Assertions<'a, A> = Sequence<'a, A, get::Response, get::Error>;
+
+/// Sends the `authenticatorGetAssertion` request, returning an iterator over
+/// the assertions for all applicable credentials.
+///
+/// All of them can be collected with
+/// `assertions.collect::<Result<Vec<_>, _>>()`.
+pub fn get_assertions<'a, A>(
+    authenticator: &'a mut A,
+    request: get::Request,
+) -> Result<Assertions<'a, A>, get::Error>
+where
+    A: Ctap2_2Authenticator + ?Sized,
+{
+    let response = authenticator.get_assertion(request)?;
+    let total = response.number_of_credentials.unwrap_or(1);
+    Ok(Sequence::new(
+        authenticator,
+        Some((response, total)),
+        |authenticator| authenticator.get_next_assertion(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ctap2_proto::prelude::{client_pin, credential, credential::public_key, make};
+    use ctap2_virtual::{AlwaysPresent, Authenticator, Intercepted, Memory};
+    use std::{cell::Cell, collections::BTreeMap};
+
+    const RELYING_PARTY_ID: &str = "example.com";
+
+    /// Discards the pending assertions after the given number of
+    /// `authenticatorGetNextAssertion` requests, counting the requests.
+    fn expiring(
+        authenticator: Authenticator<Memory>,
+        mut remaining: usize,
+        requests: &Cell<usize>,
+    ) -> Intercepted<'_, Memory> {
+        Intercepted::new(authenticator).on_get_next_assertion(move |authenticator| {
+            requests.set(requests.get() + 1);
+            if remaining == 0 {
+                // Invalidates the pending assertions
+                authenticator.selection().unwrap();
+            }
+            remaining = remaining.saturating_sub(1);
+            authenticator.get_next_assertion()
+        })
+    }
+
+    /// Creates a discoverable credential for each of the user IDs, returning
+    /// their credential IDs.
+    fn authenticator(users: &[u8]) -> (Authenticator<Memory>, Vec<Vec<u8>>) {
+        let mut authenticator = Authenticator::new(Memory::default(), AlwaysPresent);
+        let relying_party = public_key::RelyingPartyEntity {
+            id: RELYING_PARTY_ID.to_owned(),
+            name: None,
+        };
+        let parameters = [public_key::Parameters {
+            credential_type: credential::Type::PublicKey,
+            algorithm: coset::iana::Algorithm::ES256,
+        }];
+        let options = BTreeMap::from([(make::OptionKey::Discoverable, true)]);
+        let ids = users
+            .iter()
+            .map(|&id| {
+                let user = public_key::UserEntity {
+                    id: vec![id],
+                    name: None,
+                    display_name: None,
+                };
+                let request = make::Request::builder()
+                    .client_data_hash(&[0x42; 32])
+                    .relying_party(&relying_party)
+                    .user(&user)
+                    .public_key_credential_params(&parameters)
+                    .options(&options)
+                    .build();
+                let response = authenticator.make_credential(request).unwrap();
+                response
+                    .authenticator_data
+                    .attested_credential_data
+                    .unwrap()
+                    .id
+            })
+            .collect();
+        (authenticator, ids)
+    }
+
+    fn request() -> get::Request<'static> {
+        get::Request {
+            relying_party_id: RELYING_PARTY_ID,
+            client_data_hash: &[0x42; 32],
+            allow_list: None,
+            extensions: None,
+            options: None,
+            pin_uv_auth_param: client_pin::PinUvAuth::None,
+            pin_uv_auth_protocol_version: None,
+        }
+    }
+
+    #[test]
+    fn collects_every_assertion() {
+        let (mut authenticator, ids) = authenticator(&[1, 2, 3]);
+        let mut assertions = get_assertions(&mut authenticator, request()).unwrap();
+        assert_eq!(assertions.size_hint(), (2, Some(3)));
+        assertions.next().unwrap().unwrap();
+        assert_eq!(assertions.size_hint(), (1, Some(2)));
+
+        let assertions = get_assertions(&mut authenticator, request())
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        // The most recently created credential is asserted first
+        let asserted: Vec<_> = assertions
+            .iter()
+            .map(|assertion| assertion.credential.id.clone())
+            .collect();
+        assert_eq!(asserted, ids.into_iter().rev().collect::<Vec<_>>());
+        assert_eq!(assertions[0].number_of_credentials, Some(3));
+    }
+
+    #[test]
+    fn single_assertion() {
+        let requests = Cell::new(0);
+        let mut authenticator = expiring(authenticator(&[1]).0, 0, &requests);
+        let mut assertions = get_assertions(&mut authenticator, request()).unwrap();
+        assert_eq!(assertions.size_hint(), (1, Some(1)));
+        assert!(assertions.next().unwrap().is_ok());
+        assert_eq!(assertions.size_hint(), (0, Some(0)));
+        assert!(assertions.next().is_none());
+        assert_eq!(requests.get(), 0);
+    }
+
+    #[test]
+    fn no_credentials() {
+        let (mut authenticator, _) = authenticator(&[]);
+        assert_eq!(
+            get_assertions(&mut authenticator, request()).err(),
+            Some(get::Error::NoCredentials)
+        );
+    }
+
+    #[test]
+    fn stops_once_invalidated() {
+        let requests = Cell::new(0);
+        let mut authenticator = expiring(authenticator(&[1, 2, 3, 4]).0, 1, &requests);
+        let mut assertions = get_assertions(&mut authenticator, request()).unwrap();
+        assert!(assertions.next().unwrap().is_ok());
+        assert!(assertions.next().unwrap().is_ok());
+        assert_eq!(
+            assertions.next().unwrap().err(),
+            Some(get::Error::NotAllowed)
+        );
+        // The remaining assertions are not requested again
+        assert_eq!(assertions.size_hint(), (0, Some(0)));
+        assert!(assertions.next().is_none());
+        assert!(assertions.next().is_none());
+        assert_eq!(requests.get(), 2);
+    }
+}
@@ -0,0 +1,495 @@
+//! Enrolling fingerprints and managing the enrolled templates.
+
+use crate::{
+    token::{self, PinUvAuthToken},
+    Error,
+};
+use ctap2_proto::prelude::{
+    bio_enrollment::{self, LastEnrollSampleStatus, Request, Response, TemplateInfo},
+    client_pin::Permission,
+    device::{self, OptionId},
+    Ctap2_2Authenticator,
+};
+use std::{collections::BTreeSet, time::Duration};
+
+/// Receives feedback on the samples captured during an enrollment, e.g. to
+/// prompt the user to touch the sensor again or to hold still.
+pub trait Progress {
+    /// Called after every sample, including the first one captured by
+    /// `enrollBegin`. The sample only counts towards the enrollment if its
+    /// status is [`LastEnrollSampleStatus::Good`].
+    ///
+    /// A sample the user did not provide in time is reported as
+    /// [`LastEnrollSampleStatus::NoUserActivity`]. Returning `false` cancels
+    /// the enrollment.
+    fn sample(&mut self, status: LastEnrollSampleStatus, remaining_samples: usize) -> bool;
+}
+
+impl<F> Progress for F
+where
+    F: FnMut(LastEnrollSampleStatus, usize) -> bool,
+{
+    fn sample(&mut self, status: LastEnrollSampleStatus, remaining_samples: usize) -> bool {
+        self(status, remaining_samples)
+    }
+}
+
+/// The fingerprint sensor, as reported by `getFingerprintSensorInfo`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SensorInfo {
+    pub fingerprint_kind: bio_enrollment::FingerprintKind,
+    /// The number of good samples required to complete an enrollment.
+    pub max_capture_samples_required_for_enroll: usize,
+    /// The maximum length of a template's friendly name in bytes, if limited.
+    pub max_template_friendly_name: Option<usize>,
+}
+
+/// A `pinUvAuthToken` with the
+/// [`Permission::BiometricEnrollment`] permission and the authenticator it
+/// was obtained from.
+pub struct Session<'a, A: ?Sized> {
+    authenticator: &'a mut A,
+    pin_uv_auth_token: PinUvAuthToken,
+}
+
+impl<'a, A> Session<'a, A>
+where
+    A: Ctap2_2Authenticator + ?Sized,
+{
+    /// Obtains a `pinUvAuthToken` for managing the authenticator's
+    /// fingerprints. See [`token::acquire`] for how `pin` is used.
+    pub fn open<P>(authenticator: &'a mut A, pin: P) -> Result<Self, Error>
+    where
+        P: FnOnce() -> Option<String>,
+    {
+        if !supported(&authenticator.get_info()) {
+            return Err(Error::BioEnrollmentUnsupported);
+        }
+        let permissions = BTreeSet::from([Permission::BiometricEnrollment]);
+        let pin_uv_auth_token = token::acquire(authenticator, permissions, None, pin)?;
+        Ok(Self {
+            authenticator,
+            pin_uv_auth_token,
+        })
+    }
+
+    /// Describes the fingerprint sensor.
+    pub fn sensor_info(&mut self) -> Result<SensorInfo, Error> {
+        match self
+            .authenticator
+            .bio_enrollment(Request::GetFingerprintSensorInfo)?
+        {
+            Response::GetFingerprintSensorInfo {
+                fingerprint_kind,
+                max_capture_samples_required_for_enroll,
+                max_template_friendly_name,
+            } => Ok(SensorInfo {
+                fingerprint_kind,
+                max_capture_samples_required_for_enroll,
+                max_template_friendly_name,
+            }),
+            _ => Err(bio_enrollment::Error::MissingParameter.into()),
+        }
+    }
+
+    /// Enrolls a new fingerprint, capturing samples until the authenticator
+    /// requires no more, and returns its template ID.
+    ///
+    /// Each sample must be provided within `timeout`, or the authenticator's
+    /// default timeout if `None`. The enrollment is cancelled if `progress`
+    /// returns `false` or any sample fails with an error.
+    pub fn enroll<P>(
+        &mut self,
+        timeout: Option<Duration>,
+        mut progress: P,
+    ) -> Result<Vec<u8>, Error>
+    where
+        P: Progress,
+    {
+        let timeout_milliseconds = timeout.map(|timeout| timeout.as_millis() as usize);
+        let version = self.pin_uv_auth_token.version();
+        let request = Request::enroll_begin(
+            timeout_milliseconds,
+            version,
+            self.pin_uv_auth_token.as_ref(),
+        );
+        let (template_id, status, mut remaining_samples) =
+            match self.authenticator.bio_enrollment(request)? {
+                Response::EnrollBegin {
+                    template_id,
+                    last_enroll_sample_status,
+                    remaining_samples,
+                } => (template_id, last_enroll_sample_status, remaining_samples),
+                _ => return Err(bio_enrollment::Error::MissingParameter.into()),
+            };
+
+        let mut proceed = progress.sample(status, remaining_samples);
+        while remaining_samples > 0 {
+            if !proceed {
+                self.cancel()?;
+                return Err(Error::EnrollmentCancelled);
+            }
+            let request = Request::enroll_capture_next_sample(
+                &template_id,
+                timeout_milliseconds,
+                version,
+                self.pin_uv_auth_token.as_ref(),
+            );
+            proceed = match self.authenticator.bio_enrollment(request) {
+                Ok(Response::EnrollCaptureNextSample {
+                    last_enroll_sample_status,
+                    remaining_samples: remaining,
+                }) => {
+                    remaining_samples = remaining;
+                    progress.sample(last_enroll_sample_status, remaining_samples)
+                }
+                // The enrollment can continue with the next sample
+                Err(bio_enrollment::Error::UserActionTimeout) => {
+                    progress.sample(LastEnrollSampleStatus::NoUserActivity, remaining_samples)
+                }
+                Ok(_) => {
+                    self.cancel()?;
+                    return Err(bio_enrollment::Error::MissingParameter.into());
+                }
+                Err(error) => {
+                    // The authenticator already ended the enrollment
+                    if error != bio_enrollment::Error::KeepaliveCancel {
+                        self.cancel()?;
+                    }
+                    return Err(error.into());
+                }
+            };
+        }
+        Ok(template_id)
+    }
+
+    /// Cancels the ongoing enrollment, if any.
+    pub fn cancel(&mut self) -> Result<(), Error> {
+        self.authenticator
+            .bio_enrollment(Request::CancelCurrentEnrollment)?;
+        Ok(())
+    }
+
+    /// Lists the enrolled fingerprints.
+    pub fn templates(&mut self) -> Result<Vec<TemplateInfo>, Error> {
+        let request = Request::enumerate_enrollments(
+            self.pin_uv_auth_token.version(),
+            self.pin_uv_auth_token.as_ref(),
+        );
+        match self.authenticator.bio_enrollment(request) {
+            Ok(Response::EnumerateEnrollments { template_infos }) => Ok(template_infos),
+            Ok(_) => Err(bio_enrollment::Error::MissingParameter.into()),
+            // Returned when there are no enrollments
+            Err(bio_enrollment::Error::InvalidOption) => Ok(Vec::new()),
+            Err(error) => Err(error.into()),
+        }
+    }
+
+    /// Names the enrolled fingerprint with the given template ID.
+    pub fn set_friendly_name(&mut self, template_id: &[u8], name: &str) -> Result<(), Error> {
+        let request = Request::set_friendly_name(
+            template_id,
+            name,
+            self.pin_uv_auth_token.version(),
+            self.pin_uv_auth_token.as_ref(),
+        );
+        self.authenticator.bio_enrollment(request)?;
+        Ok(())
+    }
+
+    /// Removes the enrolled fingerprint with the given template ID.
+    pub fn remove(&mut self, template_id: &[u8]) -> Result<(), Error> {
+        let request = Request::remove_enrollment(
+            template_id,
+            self.pin_uv_auth_token.version(),
+            self.pin_uv_auth_token.as_ref(),
+        );
+        self.authenticator.bio_enrollment(request)?;
+        Ok(())
+    }
+}
+
+/// Whether the authenticator supports `authenticatorBioEnrollment`, or its
+/// prototype command. Either option is present, whether or not any
+/// fingerprints are enrolled yet.
+pub fn supported(info: &device::Info) -> bool {
+    info.options.as_ref().is_some_and(|options| {
+        options.contains_key(&OptionId::BiometricEnroll)
+            || options.contains_key(&OptionId::UvManagementPreview)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ctap2_proto::prelude::client_pin::{self, auth_protocol::KeyAgreementKey};
+    use ctap2_virtual::{AlwaysPresent, Authenticator, Intercepted, Memory};
+    use std::{cell::RefCell, collections::VecDeque};
+
+    const TEMPLATE_ID: &[u8] = &[0x01, 0x02];
+    const TOKEN: [u8; 32] = [0x42; 32];
+
+    /// A `authenticatorBioEnrollment` subcommand sent to the [`Sensor`].
+    #[derive(Debug, PartialEq, Eq)]
+    enum Sent {
+        EnrollBegin(Option<usize>),
+        EnrollCaptureNextSample(Vec<u8>, Option<usize>),
+        CancelCurrentEnrollment,
+    }
+
+    /// A fingerprint sensor answering the enrollment with the given
+    /// responses in turn, recording the subcommands sent to it.
+    struct Sensor {
+        responses: RefCell<VecDeque<Result<Response, bio_enrollment::Error>>>,
+        sent: RefCell<Vec<Sent>>,
+    }
+
+    impl Sensor {
+        fn new(
+            remaining_samples: usize,
+            responses: impl IntoIterator<Item = Result<Response, bio_enrollment::Error>>,
+        ) -> Self {
+            let begin = Response::EnrollBegin {
+                template_id: TEMPLATE_ID.to_vec(),
+                last_enroll_sample_status: LastEnrollSampleStatus::Good,
+                remaining_samples,
+            };
+            Self {
+                responses: RefCell::new([Ok(begin)].into_iter().chain(responses).collect()),
+                sent: RefCell::new(Vec::new()),
+            }
+        }
+
+        /// A virtual authenticator with the sensor, which issues tokens for
+        /// fingerprint enrollment with any PIN.
+        fn authenticator(&self) -> Intercepted<'_, Memory> {
+            let key = KeyAgreementKey::generate();
+            Intercepted::new(Authenticator::new(Memory::default(), AlwaysPresent))
+                .on_get_info(|authenticator| {
+                    let mut info = authenticator.get_info();
+                    let options = info.options.get_or_insert_with(Default::default);
+                    options.insert(OptionId::BiometricEnroll, false);
+                    options.insert(OptionId::ClientPin, true);
+                    info
+                })
+                .on_client_pin(move |authenticator, request| match request {
+                    client_pin::Request::GetKeyAgreement { .. } => {
+                        Ok(client_pin::Response::GetKeyAgreement {
+                            key_agreement: key.public_key(),
+                        })
+                    }
+                    client_pin::Request::GetPinUvAuthTokenUsingPinWithPermissions {
+                        version,
+                        key_agreement,
+                        permissions,
+                        ..
+                    } => {
+                        assert_eq!(
+                            *permissions,
+                            BTreeSet::from([Permission::BiometricEnrollment])
+                        );
+                        let shared_secret = key.shared_secret(version, &key_agreement)?;
+                        Ok(
+                            client_pin::Response::GetPinUvAuthTokenUsingPinWithPermissions {
+                                pin_uv_auth_token: shared_secret.encrypt(&TOKEN)?,
+                            },
+                        )
+                    }
+                    request => authenticator.client_pin(request),
+                })
+                .on_bio_enrollment(|_, request| self.bio_enrollment(request))
+        }
+
+        fn bio_enrollment(&self, request: Request) -> Result<Response, bio_enrollment::Error> {
+            match request {
+                Request::EnrollBegin {
+                    timeout_milliseconds,
+                    ..
+                } => self
+                    .sent
+                    .borrow_mut()
+                    .push(Sent::EnrollBegin(timeout_milliseconds)),
+                Request::EnrollCaptureNextSample {
+                    template_id,
+                    timeout_milliseconds,
+                    ..
+                } => self.sent.borrow_mut().push(Sent::EnrollCaptureNextSample(
+                    template_id.into_owned(),
+                    timeout_milliseconds,
+                )),
+                Request::CancelCurrentEnrollment => {
+                    self.sent.borrow_mut().push(Sent::CancelCurrentEnrollment);
+                    return Ok(Response::CancelCurrentEnrollment);
+                }
+                _ => unreachable!(),
+            }
+            self.responses.borrow_mut().pop_front().unwrap()
+        }
+    }
+
+    fn sample(
+        last_enroll_sample_status: LastEnrollSampleStatus,
+        remaining_samples: usize,
+    ) -> Result<Response, bio_enrollment::Error> {
+        Ok(Response::EnrollCaptureNextSample {
+            last_enroll_sample_status,
+            remaining_samples,
+        })
+    }
+
+    /// The status and remaining samples reported for each sample.
+    type Samples = Vec<(LastEnrollSampleStatus, usize)>;
+
+    /// Enrolls a fingerprint, returning the result and the reported samples.
+    fn enroll(
+        sensor: &Sensor,
+        timeout: Option<Duration>,
+        cancel_after: usize,
+    ) -> (Result<Vec<u8>, Error>, Samples) {
+        let mut samples = Vec::new();
+        let mut authenticator = sensor.authenticator();
+        let mut session = Session::open(&mut authenticator, || Some("1234".to_owned())).unwrap();
+        let result = session.enroll(timeout, |status, remaining_samples| {
+            samples.push((status, remaining_samples));
+            samples.len() <= cancel_after
+        });
+        (result, samples)
+    }
+
+    fn capture(timeout_milliseconds: Option<usize>) -> Sent {
+        Sent::EnrollCaptureNextSample(TEMPLATE_ID.to_vec(), timeout_milliseconds)
+    }
+
+    #[test]
+    fn enrolls() {
+        let sensor = Sensor::new(
+            2,
+            [
+                sample(LastEnrollSampleStatus::TooFast, 2),
+                sample(LastEnrollSampleStatus::Good, 1),
+                sample(LastEnrollSampleStatus::Good, 0),
+            ],
+        );
+        let (result, samples) = enroll(&sensor, Some(Duration::from_secs(5)), usize::MAX);
+        assert_eq!(result, Ok(TEMPLATE_ID.to_vec()));
+        assert_eq!(
+            samples,
+            [
+                (LastEnrollSampleStatus::Good, 2),
+                (LastEnrollSampleStatus::TooFast, 2),
+                (LastEnrollSampleStatus::Good, 1),
+                (LastEnrollSampleStatus::Good, 0),
+            ]
+        );
+        assert_eq!(
+            *sensor.sent.borrow(),
+            [
+                Sent::EnrollBegin(Some(5000)),
+                capture(Some(5000)),
+                capture(Some(5000)),
+                capture(Some(5000)),
+            ]
+        );
+    }
+
+    #[test]
+    fn enrolls_with_single_sample() {
+        let sensor = Sensor::new(0, []);
+        let (result, samples) = enroll(&sensor, None, usize::MAX);
+        assert_eq!(result, Ok(TEMPLATE_ID.to_vec()));
+        assert_eq!(samples, [(LastEnrollSampleStatus::Good, 0)]);
+        assert_eq!(*sensor.sent.borrow(), [Sent::EnrollBegin(None)]);
+    }
+
+    #[test]
+    fn continues_after_timeout() {
+        let sensor = Sensor::new(
+            1,
+            [
+                Err(bio_enrollment::Error::UserActionTimeout),
+                sample(LastEnrollSampleStatus::Good, 0),
+            ],
+        );
+        let (result, samples) = enroll(&sensor, None, usize::MAX);
+        assert_eq!(result, Ok(TEMPLATE_ID.to_vec()));
+        assert_eq!(
+            samples,
+            [
+                (LastEnrollSampleStatus::Good, 1),
+                (LastEnrollSampleStatus::NoUserActivity, 1),
+                (LastEnrollSampleStatus::Good, 0),
+            ]
+        );
+        assert_eq!(
+            *sensor.sent.borrow(),
+            [Sent::EnrollBegin(None), capture(None), capture(None)]
+        );
+    }
+
+    #[test]
+    fn cancelled_by_progress() {
+        let sensor = Sensor::new(2, [sample(LastEnrollSampleStatus::TooSlow, 2)]);
+        let (result, samples) = enroll(&sensor, None, 1);
+        assert_eq!(result, Err(Error::EnrollmentCancelled));
+        assert_eq!(samples.len(), 2);
+        assert_eq!(
+            *sensor.sent.borrow(),
+            [
+                Sent::EnrollBegin(None),
+                capture(None),
+                Sent::CancelCurrentEnrollment,
+            ]
+        );
+
+        // The last sample completes the enrollment regardless
+        let sensor = Sensor::new(1, [sample(LastEnrollSampleStatus::Good, 0)]);
+        let (result, _) = enroll(&sensor, None, 1);
+        assert_eq!(result, Ok(TEMPLATE_ID.to_vec()));
+    }
+
+    #[test]
+    fn cancelled_by_error() {
+        let sensor = Sensor::new(1, [Err(bio_enrollment::Error::FingerprintDatabaseFull)]);
+        let (result, _) = enroll(&sensor, None, usize::MAX);
+        assert_eq!(
+            result,
+            Err(bio_enrollment::Error::FingerprintDatabaseFull.into())
+        );
+        assert_eq!(
+            *sensor.sent.borrow(),
+            [
+                Sent::EnrollBegin(None),
+                capture(None),
+                Sent::CancelCurrentEnrollment,
+            ]
+        );
+
+        // Unexpected responses also cancel the enrollment
+        let sensor = Sensor::new(1, [Ok(Response::RemoveEnrollment)]);
+        let (result, _) = enroll(&sensor, None, usize::MAX);
+        assert_eq!(result, Err(bio_enrollment::Error::MissingParameter.into()));
+        assert_eq!(
+            sensor.sent.borrow().last(),
+            Some(&Sent::CancelCurrentEnrollment)
+        );
+    }
+
+    #[test]
+    fn cancelled_by_authenticator() {
+        // The authenticator already ended the enrollment
+        let sensor = Sensor::new(1, [Err(bio_enrollment::Error::KeepaliveCancel)]);
+        let (result, _) = enroll(&sensor, None, usize::MAX);
+        assert_eq!(result, Err(bio_enrollment::Error::KeepaliveCancel.into()));
+        assert_eq!(
+            *sensor.sent.borrow(),
+            [Sent::EnrollBegin(None), capture(None)]
+        );
+    }
+
+    #[test]
+    fn unsupported() {
+        let mut authenticator = Authenticator::new(Memory::default(), AlwaysPresent);
+        let result = Session::open(&mut authenticator, || Some("1234".to_owned()));
+        assert_eq!(result.err(), Some(Error::BioEnrollmentUnsupported));
+    }
+}
@@ -0,0 +1,406 @@
+//! Reading and writing the authenticator's serialized large-blob array, and
+//! the blobs of individual credentials stored in it.
+
+use crate::{token::PinUvAuthToken, Error};
+use aes_gcm::{
+    aead::{Aead, KeyInit, Payload},
+    Aes256Gcm, Nonce,
+};
+use ctap2_proto::prelude::{
+    device::{self, OptionId},
+    large_blobs::{self, array, Request},
+    Ctap2_2Authenticator,
+};
+use rand::{rngs::OsRng, RngCore};
+use std::borrow::Cow;
+
+/// The maximum message size of authenticators that do not report a
+/// `maxMsgSize`.
+pub const DEFAULT_MAX_MESSAGE_SIZE: usize = 1024;
+
+/// The DEFLATE compression level of blobs, from 0 to 10.
+const COMPRESSION_LEVEL: u8 = 6;
+
+/// The maximum length of a fragment the authenticator can read or write at
+/// once.
+pub fn max_fragment_length(info: &device::Info) -> usize {
+    info.max_message_size
+        .unwrap_or(DEFAULT_MAX_MESSAGE_SIZE)
+        .saturating_sub(large_blobs::MESSAGE_OVERHEAD)
+        .max(1)
+}
+
+/// Reads the serialized large-blob array, including its checksum, in as many
+/// fragments as needed.
+///
+/// The checksum is not verified. See [`read`] for reading the blobs.
+pub fn read_serialized<A>(authenticator: &mut A) -> Result<Vec<u8>, Error>
+where
+    A: Ctap2_2Authenticator + ?Sized,
+{
+    let info = authenticator.get_info();
+    supported(&info)?;
+    let length = max_fragment_length(&info);
+
+    let mut serialized = Vec::new();
+    loop {
+        let response = authenticator.large_blobs(Request::Get {
+            offset: serialized.len(),
+            length,
+        })?;
+        let fragment = response
+            .config
+            .ok_or(Error::LargeBlobs(large_blobs::Error::MissingParameter))?;
+        serialized.extend_from_slice(&fragment);
+        // Only the last fragment is shorter than requested
+        if fragment.len() < length {
+            return Ok(serialized);
+        }
+    }
+}
+
+/// Reads the elements of the large-blob array.
+///
+/// An array with an invalid checksum is treated as the initial, empty array,
+/// as it may have been left behind by an interrupted write.
+pub fn read<A>(authenticator: &mut A) -> Result<Vec<array::Element>, Error>
+where
+    A: Ctap2_2Authenticator + ?Sized,
+{
+    let serialized = read_serialized(authenticator)?;
+    match array::deserialize(&serialized) {
+        Err(large_blobs::Error::IntegrityFailure) => Ok(Vec::new()),
+        elements => Ok(elements?),
+    }
+}
+
+/// Replaces the serialized large-blob array, which must include its checksum,
+/// writing it in as many fragments as needed.
+///
+/// Authenticators protected by user verification require a `pinUvAuthToken`
+/// with the
+/// [`Permission::LargeBlobWrite`](ctap2_proto::prelude::client_pin::Permission::LargeBlobWrite)
+/// permission.
+pub fn write_serialized<A>(
+    authenticator: &mut A,
+    serialized: &[u8],
+    pin_uv_auth_token: Option<&PinUvAuthToken>,
+) -> Result<(), Error>
+where
+    A: Ctap2_2Authenticator + ?Sized,
+{
+    let info = authenticator.get_info();
+    let max_size = supported(&info)?;
+    if serialized.len() > max_size {
+        return Err(Error::LargeBlobArrayTooLarge { max_size });
+    }
+
+    let mut offset = 0;
+    for fragment in serialized.chunks(max_fragment_length(&info)) {
+        let pin_uv_auth_param = match pin_uv_auth_token {
+            Some(pin_uv_auth_token) => Some(
+                pin_uv_auth_token
+                    .authenticate(&large_blobs::authenticated_message(offset, fragment)?),
+            ),
+            None => None,
+        };
+        authenticator.large_blobs(Request::Set {
+            fragment: Cow::Borrowed(fragment),
+            offset,
+            length: (offset == 0).then_some(serialized.len()),
+            pin_uv_auth_protocol: pin_uv_auth_token.map(PinUvAuthToken::version),
+            pin_uv_auth_param,
+        })?;
+        offset += fragment.len();
+    }
+    Ok(())
+}
+
+/// Replaces the large-blob array with the given elements.
+///
+/// See [`write_serialized`].
+pub fn write<A>(
+    authenticator: &mut A,
+    elements: &[array::Element],
+    pin_uv_auth_token: Option<&PinUvAuthToken>,
+) -> Result<(), Error>
+where
+    A: Ctap2_2Authenticator + ?Sized,
+{
+    write_serialized(
+        authenticator,
+        &array::serialize(elements),
+        pin_uv_auth_token,
+    )
+}
+
+/// Reads the blob of the credential with the given `largeBlobKey`, as returned
+/// by `authenticatorMakeCredential` or `authenticatorGetAssertion`.
+pub fn read_blob<A>(authenticator: &mut A, large_blob_key: &[u8]) -> Result<Option<Vec<u8>>, Error>
+where
+    A: Ctap2_2Authenticator + ?Sized,
+{
+    let cipher = cipher(large_blob_key)?;
+    read(authenticator)?
+        .iter()
+        .filter_map(array::Element::blob)
+        .find_map(|blob| open(&cipher, blob).map(|compressed| decompress(&compressed, blob)))
+        .transpose()
+}
+
+/// Stores the blob of the credential with the given `largeBlobKey`, replacing
+/// its previous blob, if any.
+///
+/// The large-blob array is read and written in its entirety, so the blobs of
+/// other credentials and any elements that are not blobs are preserved. See
+/// [`write_serialized`] for when a `pinUvAuthToken` is required.
+pub fn write_blob<A>(
+    authenticator: &mut A,
+    large_blob_key: &[u8],
+    data: &[u8],
+    pin_uv_auth_token: Option<&PinUvAuthToken>,
+) -> Result<(), Error>
+where
+    A: Ctap2_2Authenticator + ?Sized,
+{
+    let cipher = cipher(large_blob_key)?;
+    let mut elements = read(authenticator)?;
+    elements.retain(|element| !belongs_to(&cipher, element));
+    elements.push(seal(&cipher, data).into());
+    write(authenticator, &elements, pin_uv_auth_token)
+}
+
+/// Deletes the blob of the credential with the given `largeBlobKey`,
+/// returning whether it had one.
+pub fn delete_blob<A>(
+    authenticator: &mut A,
+    large_blob_key: &[u8],
+    pin_uv_auth_token: Option<&PinUvAuthToken>,
+) -> Result<bool, Error>
+where
+    A: Ctap2_2Authenticator + ?Sized,
+{
+    let cipher = cipher(large_blob_key)?;
+    let mut elements = read(authenticator)?;
+    let count = elements.len();
+    elements.retain(|element| !belongs_to(&cipher, element));
+    if elements.len() == count {
+        return Ok(false);
+    }
+    write(authenticator, &elements, pin_uv_auth_token)?;
+    Ok(true)
+}
+
+/// Compresses the data and encrypts it with the `largeBlobKey`.
+pub fn encrypt(large_blob_key: &[u8], data: &[u8]) -> Result<array::Blob, Error> {
+    Ok(seal(&cipher(large_blob_key)?, data))
+}
+
+/// Decrypts and decompresses the blob, or returns `None` if it was encrypted
+/// with the `largeBlobKey` of another credential.
+pub fn decrypt(large_blob_key: &[u8], blob: &array::Blob) -> Result<Option<Vec<u8>>, Error> {
+    open(&cipher(large_blob_key)?, blob)
+        .map(|compressed| decompress(&compressed, blob))
+        .transpose()
+}
+
+fn cipher(large_blob_key: &[u8]) -> Result<Aes256Gcm, Error> {
+    Aes256Gcm::new_from_slice(large_blob_key).map_err(|_| Error::LargeBlobKeyInvalid)
+}
+
+/// `"blob" || uint64LittleEndian(origSize)`
+fn associated_data(orig_size: usize) -> Vec<u8> {
+    [b"blob".as_slice(), &(orig_size as u64).to_le_bytes()].concat()
+}
+
+fn seal(cipher: &Aes256Gcm, data: &[u8]) -> array::Blob {
+    let compressed = miniz_oxide::deflate::compress_to_vec(data, COMPRESSION_LEVEL);
+    let mut nonce = [0; array::NONCE_LENGTH];
+    OsRng.fill_bytes(&mut nonce);
+    let ciphertext = cipher
+        .encrypt(
+            Nonce::from_slice(&nonce),
+            Payload {
+                msg: &compressed,
+                aad: &associated_data(data.len()),
+            },
+        )
+        // Only messages longer than 64 GiB can't be encrypted
+        .expect("blob is short enough to encrypt");
+    array::Blob {
+        ciphertext,
+        nonce,
+        orig_size: data.len(),
+    }
+}
+
+/// Returns the compressed data, or `None` if the blob was not encrypted with
+/// the cipher's key.
+fn open(cipher: &Aes256Gcm, blob: &array::Blob) -> Option<Vec<u8>> {
+    cipher
+        .decrypt(
+            Nonce::from_slice(&blob.nonce),
+            Payload {
+                msg: &blob.ciphertext,
+                aad: &associated_data(blob.orig_size),
+            },
+        )
+        .ok()
+}
+
+/// Whether the element is a blob encrypted with the cipher's key.
+fn belongs_to(cipher: &Aes256Gcm, element: &array::Element) -> bool {
+    element
+        .blob()
+        .is_some_and(|blob| open(cipher, blob).is_some())
+}
+
+/// Inflates the compressed data, which must be exactly `origSize` bytes long
+/// once decompressed.
+fn decompress(compressed: &[u8], blob: &array::Blob) -> Result<Vec<u8>, Error> {
+    miniz_oxide::inflate::decompress_to_vec_with_limit(compressed, blob.orig_size)
+        .ok()
+        .filter(|data| data.len() == blob.orig_size)
+        .ok_or(Error::LargeBlobs(large_blobs::Error::IntegrityFailure))
+}
+
+/// Returns the maximum size of the serialized large-blob array.
+fn supported(info: &device::Info) -> Result<usize, Error> {
+    let enabled = info
+        .options
+        .as_ref()
+        .and_then(|options| options.get(&OptionId::LargeBlobs))
+        .copied()
+        .unwrap_or(false);
+    match &info.max_serialized_large_blob_array_size {
+        Some(max_size) if enabled => Ok(max_size.get()),
+        _ => Err(Error::LargeBlobsUnsupported),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ctap2_virtual::{AlwaysPresent, Authenticator, Memory, Storage};
+
+    fn authenticator() -> Authenticator<Memory> {
+        Authenticator::new(Memory::default(), AlwaysPresent)
+    }
+
+    /// A large-blob array holding a single element that is not a blob: `[1]`.
+    fn foreign_array() -> Vec<u8> {
+        let array = [0x81, 0x01];
+        [array.as_slice(), &array::checksum(&array)].concat()
+    }
+
+    fn foreign_elements(authenticator: &mut Authenticator<Memory>) -> usize {
+        read(authenticator)
+            .unwrap()
+            .iter()
+            .filter(|element| element.blob().is_none())
+            .count()
+    }
+
+    #[test]
+    fn write_and_read_blob() {
+        let mut authenticator = authenticator();
+        assert_eq!(read_blob(&mut authenticator, &[0x42; 32]), Ok(None));
+
+        write_blob(&mut authenticator, &[0x42; 32], b"first", None).unwrap();
+        write_blob(&mut authenticator, &[0x43; 32], b"second", None).unwrap();
+        assert_eq!(
+            read_blob(&mut authenticator, &[0x42; 32]),
+            Ok(Some(b"first".to_vec()))
+        );
+        assert_eq!(
+            read_blob(&mut authenticator, &[0x43; 32]),
+            Ok(Some(b"second".to_vec()))
+        );
+
+        // Writing again replaces the credential's blob
+        write_blob(&mut authenticator, &[0x42; 32], b"third", None).unwrap();
+        assert_eq!(read(&mut authenticator).unwrap().len(), 2);
+        assert_eq!(
+            read_blob(&mut authenticator, &[0x42; 32]),
+            Ok(Some(b"third".to_vec()))
+        );
+    }
+
+    #[test]
+    fn delete_blob_of_credential() {
+        let mut authenticator = authenticator();
+        write_blob(&mut authenticator, &[0x42; 32], b"first", None).unwrap();
+        write_blob(&mut authenticator, &[0x43; 32], b"second", None).unwrap();
+
+        assert_eq!(delete_blob(&mut authenticator, &[0x42; 32], None), Ok(true));
+        assert_eq!(
+            delete_blob(&mut authenticator, &[0x42; 32], None),
+            Ok(false)
+        );
+        assert_eq!(read_blob(&mut authenticator, &[0x42; 32]), Ok(None));
+        assert_eq!(
+            read_blob(&mut authenticator, &[0x43; 32]),
+            Ok(Some(b"second".to_vec()))
+        );
+    }
+
+    #[test]
+    fn preserves_foreign_elements() {
+        let mut authenticator = authenticator();
+        authenticator
+            .storage_mut()
+            .set_large_blob_array(foreign_array());
+        assert_eq!(foreign_elements(&mut authenticator), 1);
+
+        write_blob(&mut authenticator, &[0x42; 32], b"certificate", None).unwrap();
+        assert_eq!(foreign_elements(&mut authenticator), 1);
+        assert_eq!(
+            read_blob(&mut authenticator, &[0x42; 32]),
+            Ok(Some(b"certificate".to_vec()))
+        );
+
+        assert_eq!(delete_blob(&mut authenticator, &[0x42; 32], None), Ok(true));
+        assert_eq!(authenticator.storage().large_blob_array(), foreign_array());
+    }
+
+    #[test]
+    fn invalid_checksum_reads_as_empty() {
+        let mut authenticator = authenticator();
+        let mut serialized = foreign_array();
+        *serialized.last_mut().unwrap() ^= 0x01;
+        authenticator.storage_mut().set_large_blob_array(serialized);
+        assert_eq!(read(&mut authenticator), Ok(Vec::new()));
+    }
+
+    #[test]
+    fn round_trip() {
+        let key = [0x42; 32];
+        let data = b"-----BEGIN CERTIFICATE-----".repeat(8);
+        let blob = encrypt(&key, &data).unwrap();
+        assert_eq!(blob.orig_size, data.len());
+        assert!(blob.ciphertext.len() < data.len());
+        assert_eq!(decrypt(&key, &blob), Ok(Some(data)));
+    }
+
+    #[test]
+    fn other_credential() {
+        let blob = encrypt(&[0x42; 32], b"certificate").unwrap();
+        assert_eq!(decrypt(&[0x43; 32], &blob), Ok(None));
+    }
+
+    #[test]
+    fn tampered_size() {
+        let key = [0x42; 32];
+        let mut blob = encrypt(&key, b"certificate").unwrap();
+        blob.orig_size += 1;
+        assert_eq!(decrypt(&key, &blob), Ok(None));
+    }
+
+    #[test]
+    fn invalid_key() {
+        assert_eq!(
+            encrypt(&[0x42; 16], b"certificate"),
+            Err(Error::LargeBlobKeyInvalid)
+        );
+    }
+}
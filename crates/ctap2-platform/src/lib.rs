@@ -0,0 +1,165 @@
+//! Platform-side workflows built on top of the raw CTAP2 commands.
+//!
+//! Where [`Ctap2_2Authenticator`](ctap2_proto::Ctap2_2Authenticator) exposes
+//! each command as-is, the helpers in this crate take care of the steps a
+//! platform has to perform around them, such as choosing a PIN/UV auth
+//! protocol, key agreement, enforcing the authenticator's PIN policy and
+//! choosing between built-in user verification and the PIN, fetching the
+//! remaining assertions of a request, splitting the large-blob array into
+//! fragments, capturing fingerprint samples until an enrollment completes or
+//! enumerating the discoverable credentials of every relying party.
+
+use ctap2_proto::prelude::client_pin;
+
+pub mod assertion;
+pub mod bio_enrollment;
+pub mod large_blobs;
+pub mod management;
+pub mod pin;
+pub mod token;
+
+mod sequence;
+
+pub use sequence::Sequence;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// The authenticator does not support `authenticatorClientPIN`.
+    PinUnsupported,
+    /// The PIN has fewer Unicode code points than the authenticator's
+    /// `minPINLength`.
+    PinTooShort { min_pin_length: usize },
+    /// The UTF-8 encoding of the PIN is longer than 63 bytes.
+    PinTooLong,
+    /// The authenticator rejected the PIN.
+    PinInvalid {
+        /// The number of PIN attempts left before the authenticator is
+        /// blocked, if known.
+        pin_retries: Option<usize>,
+        /// Whether the authenticator must be power cycled before the PIN can
+        /// be tried again.
+        power_cycle_required: bool,
+    },
+    /// No PIN attempts are left. The authenticator must be reset before it
+    /// can be used with a PIN again.
+    PinBlocked,
+    /// Too many consecutive PIN attempts failed. The authenticator must be
+    /// power cycled before the PIN can be tried again.
+    PinAuthBlocked,
+    /// The authenticator does not have a PIN yet.
+    PinNotSet,
+    /// The authenticator already has a PIN, which can only be changed given
+    /// the current one.
+    PinAlreadySet,
+    /// A PIN is needed, but the user did not provide one.
+    PinRequired,
+    /// Any other error returned by `authenticatorClientPIN`.
+    ClientPin(client_pin::Error),
+    /// The authenticator does not support `authenticatorLargeBlobs`.
+    LargeBlobsUnsupported,
+    /// The serialized large-blob array is larger than the authenticator's
+    /// `maxSerializedLargeBlobArray`.
+    LargeBlobArrayTooLarge { max_size: usize },
+    /// The `largeBlobKey` is not 32 bytes long.
+    LargeBlobKeyInvalid,
+    /// Any other error returned by `authenticatorLargeBlobs`.
+    LargeBlobs(ctap2_proto::prelude::large_blobs::Error),
+    /// The authenticator does not support `authenticatorBioEnrollment`.
+    BioEnrollmentUnsupported,
+    /// The enrollment was cancelled before all samples were captured.
+    EnrollmentCancelled,
+    /// Any other error returned by `authenticatorBioEnrollment`.
+    BioEnrollment(ctap2_proto::prelude::bio_enrollment::Error),
+    /// The authenticator does not support
+    /// `authenticatorCredentialManagement`.
+    CredentialManagementUnsupported,
+    /// Any other error returned by `authenticatorCredentialManagement`.
+    CredentialManagement(ctap2_proto::prelude::management::Error),
+}
+
+impl From<client_pin::Error> for Error {
+    fn from(value: client_pin::Error) -> Self {
+        match value {
+            client_pin::Error::PinInvalid => Self::PinInvalid {
+                pin_retries: None,
+                power_cycle_required: false,
+            },
+            client_pin::Error::PinBlocked => Self::PinBlocked,
+            client_pin::Error::PinAuthBlocked => Self::PinAuthBlocked,
+            client_pin::Error::PinNotSet => Self::PinNotSet,
+            error => Self::ClientPin(error),
+        }
+    }
+}
+
+impl From<ctap2_proto::prelude::large_blobs::Error> for Error {
+    fn from(value: ctap2_proto::prelude::large_blobs::Error) -> Self {
+        Self::LargeBlobs(value)
+    }
+}
+
+impl From<ctap2_proto::prelude::bio_enrollment::Error> for Error {
+    fn from(value: ctap2_proto::prelude::bio_enrollment::Error) -> Self {
+        Self::BioEnrollment(value)
+    }
+}
+
+impl From<ctap2_proto::prelude::management::Error> for Error {
+    fn from(value: ctap2_proto::prelude::management::Error) -> Self {
+        Self::CredentialManagement(value)
+    }
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::PinUnsupported => write!(f, "Authenticator does not support PINs"),
+            Error::PinTooShort { min_pin_length } => {
+                write!(f, "PIN must be at least {} characters long", min_pin_length)
+            }
+            Error::PinTooLong => write!(f, "PIN must be at most 63 bytes long"),
+            Error::PinInvalid {
+                pin_retries,
+                power_cycle_required,
+            } => {
+                write!(f, "PIN invalid")?;
+                if let Some(pin_retries) = pin_retries {
+                    write!(f, ", {} retries left", pin_retries)?;
+                }
+                if *power_cycle_required {
+                    write!(f, ", authenticator must be power cycled")?;
+                }
+                Ok(())
+            }
+            Error::PinBlocked => write!(f, "PIN blocked, authenticator must be reset"),
+            Error::PinAuthBlocked => {
+                write!(f, "PIN auth blocked, authenticator must be power cycled")
+            }
+            Error::PinNotSet => write!(f, "PIN not set"),
+            Error::PinAlreadySet => write!(f, "PIN already set"),
+            Error::PinRequired => write!(f, "PIN required"),
+            Error::ClientPin(error) => write!(f, "{}", error),
+            Error::LargeBlobsUnsupported => {
+                write!(f, "Authenticator does not support large blobs")
+            }
+            Error::LargeBlobArrayTooLarge { max_size } => {
+                write!(
+                    f,
+                    "Large-blob array must be at most {} bytes long",
+                    max_size
+                )
+            }
+            Error::LargeBlobKeyInvalid => write!(f, "Large blob key must be 32 bytes long"),
+            Error::LargeBlobs(error) => write!(f, "{}", error),
+            Error::BioEnrollmentUnsupported => {
+                write!(f, "Authenticator does not support fingerprint enrollment")
+            }
+            Error::EnrollmentCancelled => write!(f, "Enrollment cancelled"),
+            Error::BioEnrollment(error) => write!(f, "{}", error),
+            Error::CredentialManagementUnsupported => {
+                write!(f, "Authenticator does not support credential management")
+            }
+            Error::CredentialManagement(error) => write!(f, "{}", error),
+        }
+    }
+}
@@ -0,0 +1,375 @@
+//! Auditing and cleaning up the discoverable credentials stored on an
+//! authenticator.
+
+use crate::{
+    token::{self, PinUvAuthToken},
+    Error, Sequence,
+};
+use ctap2_proto::{
+    extensions::cred_protect,
+    prelude::{
+        client_pin::Permission,
+        credential::public_key,
+        device::{self, OptionId},
+        management::{self, RelyingParty, Request, Response},
+        Ctap2_2Authenticator, Sha256Hash,
+    },
+};
+use std::collections::BTreeSet;
+
+/// A discoverable credential, as enumerated by `enumerateCredentialsBegin`
+/// and `enumerateCredentialsGetNextCredential`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Credential {
+    pub user: public_key::UserEntity,
+    pub credential_id: public_key::Descriptor,
+    pub public_key: public_key::Key,
+    pub credential_protection_policy: cred_protect::Policy,
+    /// The key encrypting the credential's blob in the large-blob array, if
+    /// it has one.
+    pub large_blob_key: Option<Vec<u8>>,
+}
+
+impl From<management::Credential> for Credential {
+    fn from(value: management::Credential) -> Self {
+        Self {
+            user: value.user,
+            credential_id: value.credential_id,
+            public_key: value.public_key,
+            credential_protection_policy: value.credential_protection_policy,
+            large_blob_key: (!value.large_blob_key.is_empty()).then_some(value.large_blob_key),
+        }
+    }
+}
+
+/// A relying party and its discoverable credentials.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    pub relying_party: RelyingParty,
+    pub credentials: Vec<Credential>,
+}
+
+/// The number of discoverable credentials stored on the authenticator, as
+/// reported by `getCredsMetadata`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Metadata {
+    pub existing_resident_credentials_count: usize,
+    pub max_possible_remaining_resident_credentials_count: usize,
+}
+
+/// A `pinUvAuthToken` with the [`Permission::CredentialManagement`]
+/// permission and the authenticator it was obtained from.
+pub struct Session<'a, A: ?Sized> {
+    authenticator: &'a mut A,
+    pin_uv_auth_token: PinUvAuthToken,
+}
+
+impl<'a, A> Session<'a, A>
+where
+    A: Ctap2_2Authenticator + ?Sized,
+{
+    /// Obtains a `pinUvAuthToken` for managing the authenticator's
+    /// discoverable credentials. See [`token::acquire`] for how `pin` is used.
+    pub fn open<P>(authenticator: &'a mut A, pin: P) -> Result<Self, Error>
+    where
+        P: FnOnce() -> Option<String>,
+    {
+        if !supported(&authenticator.get_info()) {
+            return Err(Error::CredentialManagementUnsupported);
+        }
+        let permissions = BTreeSet::from([Permission::CredentialManagement]);
+        let pin_uv_auth_token = token::acquire(authenticator, permissions, None, pin)?;
+        Ok(Self {
+            authenticator,
+            pin_uv_auth_token,
+        })
+    }
+
+    /// Counts the existing discoverable credentials and the remaining space
+    /// for new ones.
+    pub fn metadata(&mut self) -> Result<Metadata, Error> {
+        let request = Request::get_credentials_metadata(
+            self.pin_uv_auth_token.version(),
+            self.pin_uv_auth_token.as_ref(),
+        );
+        match self.authenticator.credential_management(request)? {
+            Response::GetCredentialsMetadata {
+                existing_resident_credentials_count,
+                max_possible_remaining_resident_credentials_count,
+            } => Ok(Metadata {
+                existing_resident_credentials_count,
+                max_possible_remaining_resident_credentials_count,
+            }),
+            _ => Err(management::Error::MissingParameter.into()),
+        }
+    }
+
+    /// Enumerates the relying parties with discoverable credentials.
+    ///
+    /// The authenticator discards the enumeration when it receives any other
+    /// command, so the iterator should be drained before sending the next
+    /// one.
+    pub fn relying_parties(&mut self) -> Result<Enumeration<'_, A, RelyingParty>, Error> {
+        let request = Request::enumerate_rps_begin(
+            self.pin_uv_auth_token.version(),
+            self.pin_uv_auth_token.as_ref(),
+        );
+        let first = match self.authenticator.credential_management(request) {
+            Ok(Response::EnumerateRPsBegin {
+                relying_party,
+                total_relying_parties,
+            }) => Some((relying_party, total_relying_parties)),
+            Ok(_) => return Err(management::Error::MissingParameter.into()),
+            Err(management::Error::NoCredentials) => None,
+            Err(error) => return Err(error.into()),
+        };
+        Ok(Sequence::new(
+            self.authenticator,
+            first,
+            |authenticator| match authenticator
+                .credential_management(Request::EnumerateRPsGetNextRP)?
+            {
+                Response::EnumerateRPsGetNextRP { relying_party } => Ok(relying_party),
+                _ => Err(management::Error::MissingParameter.into()),
+            },
+        ))
+    }
+
+    /// Enumerates the discoverable credentials of the relying party with the
+    /// given RP ID hash.
+    ///
+    /// See [`Session::relying_parties`] for when the enumeration is
+    /// discarded.
+    pub fn credentials(
+        &mut self,
+        relying_party_id_hash: &Sha256Hash,
+    ) -> Result<Enumeration<'_, A, Credential>, Error> {
+        let request = Request::enumerate_credentials_begin(
+            relying_party_id_hash,
+            self.pin_uv_auth_token.version(),
+            self.pin_uv_auth_token.as_ref(),
+        );
+        let first = match self.authenticator.credential_management(request) {
+            Ok(Response::EnumerateCredentialsBegin {
+                credential,
+                total_credentials,
+            }) => Some((credential.into(), total_credentials)),
+            Ok(_) => return Err(management::Error::MissingParameter.into()),
+            Err(management::Error::NoCredentials) => None,
+            Err(error) => return Err(error.into()),
+        };
+        Ok(Sequence::new(self.authenticator, first, |authenticator| {
+            let request = Request::EnumerateCredentialsGetNextCredential;
+            match authenticator.credential_management(request)? {
+                Response::EnumerateCredentialsGetNextCredential { credential } => {
+                    Ok(credential.into())
+                }
+                _ => Err(management::Error::MissingParameter.into()),
+            }
+        }))
+    }
+
+    /// Lists every relying party along with its discoverable credentials.
+    pub fn inventory(&mut self) -> Result<Vec<Entry>, Error> {
+        // Enumerating the credentials discards the enumeration of the relying
+        // parties, so they are collected first
+        let relying_parties = self.relying_parties()?.collect::<Result<Vec<_>, _>>()?;
+        relying_parties
+            .into_iter()
+            .map(|relying_party| {
+                let credentials = self
+                    .credentials(&relying_party.relying_party_id_hash)?
+                    .collect::<Result<_, _>>()?;
+                Ok(Entry {
+                    relying_party,
+                    credentials,
+                })
+            })
+            .collect()
+    }
+
+    /// Deletes the discoverable credential with the given ID.
+    pub fn delete(&mut self, credential_id: &public_key::Descriptor) -> Result<(), Error> {
+        let request = Request::delete_credential(
+            credential_id,
+            self.pin_uv_auth_token.version(),
+            self.pin_uv_auth_token.as_ref(),
+        );
+        self.authenticator.credential_management(request)?;
+        Ok(())
+    }
+
+    /// Replaces the user name and display name of the discoverable credential
+    /// with the given ID. The user ID must be that of the credential.
+    pub fn update_user(
+        &mut self,
+        credential_id: &public_key::Descriptor,
+        user: &public_key::UserEntity,
+    ) -> Result<(), Error> {
+        let request = Request::update_user_information(
+            credential_id,
+            user,
+            self.pin_uv_auth_token.version(),
+            self.pin_uv_auth_token.as_ref(),
+        );
+        self.authenticator.credential_management(request)?;
+        Ok(())
+    }
+}
+
+/// The results of an enumeration: the first one is returned by the subcommand
+/// beginning it, and the remaining ones are requested as the iterator
+/// advances.
+pub type Enumeration<'s, A, T> = Sequence<'s, A, T, Error>;
+
+/// Whether the authenticator supports `authenticatorCredentialManagement`, or
+/// its prototype command.
+pub fn supported(info: &device::Info) -> bool {
+    info.options.as_ref().is_some_and(|options| {
+        options.get(&OptionId::CredentialManagement) == Some(&true)
+            || options.get(&OptionId::CredentialManagementPreview) == Some(&true)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pin;
+    use ctap2_proto::prelude::{credential, make};
+    use ctap2_virtual::{AlwaysPresent, Authenticator, Memory};
+    use std::collections::BTreeMap;
+
+    const PIN: &str = "1234";
+
+    fn user(id: u8, name: &str) -> public_key::UserEntity {
+        public_key::UserEntity {
+            id: vec![id],
+            name: Some(name.to_owned()),
+            display_name: None,
+        }
+    }
+
+    /// Creates a discoverable credential for each RP ID and user, then sets
+    /// the PIN.
+    fn authenticator(credentials: &[(&str, u8)]) -> Authenticator<Memory> {
+        let mut authenticator = Authenticator::new(Memory::default(), AlwaysPresent);
+        let parameters = [public_key::Parameters {
+            credential_type: credential::Type::PublicKey,
+            algorithm: coset::iana::Algorithm::ES256,
+        }];
+        let options = BTreeMap::from([(make::OptionKey::Discoverable, true)]);
+        for &(relying_party_id, id) in credentials {
+            let relying_party = public_key::RelyingPartyEntity {
+                id: relying_party_id.to_owned(),
+                name: None,
+            };
+            let user = user(id, "alice");
+            let request = make::Request::builder()
+                .client_data_hash(&[0x42; 32])
+                .relying_party(&relying_party)
+                .user(&user)
+                .public_key_credential_params(&parameters)
+                .options(&options)
+                .build();
+            authenticator.make_credential(request).unwrap();
+        }
+        pin::set_pin(&mut authenticator, PIN).unwrap();
+        authenticator
+    }
+
+    fn open(authenticator: &mut Authenticator<Memory>) -> Session<'_, Authenticator<Memory>> {
+        Session::open(authenticator, || Some(PIN.to_owned())).unwrap()
+    }
+
+    /// The RP IDs and user IDs of the inventory.
+    fn summary(inventory: &[Entry]) -> BTreeMap<String, BTreeSet<Vec<u8>>> {
+        inventory
+            .iter()
+            .map(|entry| {
+                let users = entry
+                    .credentials
+                    .iter()
+                    .map(|credential| credential.user.id.clone())
+                    .collect();
+                (entry.relying_party.relying_party.id.clone(), users)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn inventory() {
+        let mut authenticator =
+            authenticator(&[("example.com", 1), ("example.org", 2), ("example.com", 3)]);
+        let inventory = open(&mut authenticator).inventory().unwrap();
+        assert_eq!(
+            summary(&inventory),
+            BTreeMap::from([
+                ("example.com".to_owned(), BTreeSet::from([vec![1], vec![3]])),
+                ("example.org".to_owned(), BTreeSet::from([vec![2]])),
+            ])
+        );
+        let credential = &inventory[0].credentials[0];
+        assert_eq!(credential.user.name.as_deref(), Some("alice"));
+        assert_eq!(credential.large_blob_key, None);
+    }
+
+    #[test]
+    fn empty_inventory() {
+        let mut authenticator = authenticator(&[]);
+        let mut session = open(&mut authenticator);
+        assert_eq!(session.inventory(), Ok(Vec::new()));
+        assert_eq!(
+            session
+                .metadata()
+                .unwrap()
+                .existing_resident_credentials_count,
+            0
+        );
+    }
+
+    #[test]
+    fn delete() {
+        let mut authenticator = authenticator(&[("example.com", 1), ("example.org", 2)]);
+        let mut session = open(&mut authenticator);
+        let inventory = session.inventory().unwrap();
+        let credential_id = &inventory[0].credentials[0].credential_id;
+        session.delete(credential_id).unwrap();
+
+        // Relying parties without credentials are no longer enumerated
+        let inventory = session.inventory().unwrap();
+        assert_eq!(
+            summary(&inventory),
+            BTreeMap::from([("example.org".to_owned(), BTreeSet::from([vec![2]]))])
+        );
+        assert_eq!(
+            session.delete(credential_id),
+            Err(management::Error::NoCredentials.into())
+        );
+    }
+
+    #[test]
+    fn update_user() {
+        let mut authenticator = authenticator(&[("example.com", 1)]);
+        let mut session = open(&mut authenticator);
+        let inventory = session.inventory().unwrap();
+        let credential_id = &inventory[0].credentials[0].credential_id;
+        session.update_user(credential_id, &user(1, "bob")).unwrap();
+        let inventory = session.inventory().unwrap();
+        assert_eq!(inventory[0].credentials[0].user, user(1, "bob"));
+
+        // The user ID cannot be changed
+        assert_eq!(
+            session.update_user(credential_id, &user(2, "bob")),
+            Err(management::Error::InvalidParameter.into())
+        );
+    }
+
+    #[test]
+    fn requires_pin() {
+        let mut authenticator = authenticator(&[("example.com", 1)]);
+        assert_eq!(
+            Session::open(&mut authenticator, || None).err(),
+            Some(Error::PinRequired)
+        );
+    }
+}
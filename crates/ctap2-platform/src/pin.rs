@@ -0,0 +1,264 @@
+//! Setting and changing the authenticator's PIN.
+
+use crate::Error;
+use ctap2_proto::prelude::{
+    client_pin::{
+        self,
+        auth_protocol::{KeyAgreementKey, SharedSecret, Version},
+        Request, Response,
+    },
+    device, Ctap2_2Authenticator,
+};
+use sha2::{Digest, Sha256};
+use unicode_normalization::UnicodeNormalization;
+
+/// The minimum PIN length, in Unicode code points, for authenticators that
+/// do not report a `minPINLength`.
+pub const DEFAULT_MIN_PIN_LENGTH: usize = 4;
+
+/// The maximum length of the UTF-8 encoded PIN, leaving room for at least one
+/// byte of padding.
+pub const MAX_PIN_LENGTH: usize = 63;
+
+/// The length of the padded PIN that is encrypted as `newPinEnc`.
+const PADDED_PIN_LENGTH: usize = 64;
+
+/// The state of the authenticator's PIN retry counter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Retries {
+    /// The number of PIN attempts left before the authenticator is blocked.
+    pub pin_retries: usize,
+    /// Whether the authenticator must be power cycled before the PIN can be
+    /// tried again.
+    pub power_cycle_required: bool,
+}
+
+/// Sets the PIN of an authenticator that does not have one yet. Use
+/// [`change_pin`] if it already has one.
+pub fn set_pin<A>(authenticator: &mut A, pin: &str) -> Result<(), Error>
+where
+    A: Ctap2_2Authenticator + ?Sized,
+{
+    let info = authenticator.get_info();
+    if pin_is_set(&info)? {
+        return Err(Error::PinAlreadySet);
+    }
+    let padded_pin = pad_pin(pin, min_pin_length(&info))?;
+
+    let version = protocol(&info);
+    let (key_agreement, shared_secret) = key_agreement(authenticator, version)?;
+    let new_pin_encrypted = shared_secret.encrypt(&padded_pin)?;
+    let pin_uv_auth_param = shared_secret.authenticate(&new_pin_encrypted);
+
+    authenticator.client_pin(Request::SetPin {
+        version,
+        key_agreement,
+        new_pin_encrypted,
+        pin_uv_auth_param,
+    })?;
+    Ok(())
+}
+
+/// Replaces the authenticator's PIN, given its current PIN.
+///
+/// If the current PIN is wrong, the returned [`Error::PinInvalid`] includes
+/// the number of retries left.
+pub fn change_pin<A>(authenticator: &mut A, current_pin: &str, new_pin: &str) -> Result<(), Error>
+where
+    A: Ctap2_2Authenticator + ?Sized,
+{
+    let info = authenticator.get_info();
+    if !pin_is_set(&info)? {
+        return Err(Error::PinNotSet);
+    }
+    let padded_pin = pad_pin(new_pin, min_pin_length(&info))?;
+
+    let version = protocol(&info);
+    let (key_agreement, shared_secret) = key_agreement(authenticator, version)?;
+    let pin_hash_encrypted = encrypt_pin_hash(&shared_secret, current_pin)?;
+    let new_pin_encrypted = shared_secret.encrypt(&padded_pin)?;
+    let message = [new_pin_encrypted.as_slice(), &pin_hash_encrypted].concat();
+    let pin_uv_auth_param = shared_secret.authenticate(&message);
+
+    let result = authenticator.client_pin(Request::ChangePin {
+        version,
+        key_agreement,
+        pin_hash_encrypted,
+        new_pin_encrypted,
+        pin_uv_auth_param,
+    });
+    match result {
+        Ok(_) => Ok(()),
+        Err(client_pin::Error::PinInvalid) => Err(pin_invalid(authenticator)),
+        Err(error) => Err(error.into()),
+    }
+}
+
+/// Reads the authenticator's PIN retry counter.
+pub fn pin_retries<A>(authenticator: &mut A) -> Result<Retries, Error>
+where
+    A: Ctap2_2Authenticator + ?Sized,
+{
+    match authenticator.client_pin(Request::GetPinRetries)? {
+        Response::GetPinRetries {
+            pin_retries,
+            power_cycle_state,
+        } => Ok(Retries {
+            pin_retries,
+            power_cycle_required: power_cycle_state.unwrap_or(false),
+        }),
+        _ => Err(Error::ClientPin(client_pin::Error::MissingParameter)),
+    }
+}
+
+/// Describes a rejected PIN, including the state of the retry counter if the
+/// authenticator reports it.
+pub(crate) fn pin_invalid<A>(authenticator: &mut A) -> Error
+where
+    A: Ctap2_2Authenticator + ?Sized,
+{
+    match pin_retries(authenticator) {
+        Ok(retries) => Error::PinInvalid {
+            pin_retries: Some(retries.pin_retries),
+            power_cycle_required: retries.power_cycle_required,
+        },
+        Err(_) => Error::from(client_pin::Error::PinInvalid),
+    }
+}
+
+/// Returns the value of the `clientPin` option, i.e. whether a PIN has been
+/// set, or [`Error::PinUnsupported`] if the option is absent.
+pub(crate) fn pin_is_set(info: &device::Info) -> Result<bool, Error> {
+    info.options
+        .as_ref()
+        .and_then(|options| options.get(&device::OptionId::ClientPin))
+        .copied()
+        .ok_or(Error::PinUnsupported)
+}
+
+/// Selects the authenticator's most preferred PIN/UV auth protocol.
+pub(crate) fn protocol(info: &device::Info) -> Version {
+    info.pin_uv_auth_protocols
+        .as_ref()
+        .and_then(|versions| versions.first().copied())
+        // CTAP 2.0 authenticators only support protocol one
+        .unwrap_or(Version::One)
+}
+
+fn min_pin_length(info: &device::Info) -> usize {
+    info.min_pin_length.unwrap_or(DEFAULT_MIN_PIN_LENGTH)
+}
+
+/// Performs key agreement with the authenticator, returning the platform's
+/// public key and the shared secret for a single request.
+pub(crate) fn key_agreement<A>(
+    authenticator: &mut A,
+    version: Version,
+) -> Result<(cosey::PublicKey, SharedSecret), Error>
+where
+    A: Ctap2_2Authenticator + ?Sized,
+{
+    let peer_cose_key = match authenticator.client_pin(Request::GetKeyAgreement { version })? {
+        Response::GetKeyAgreement { key_agreement } => key_agreement,
+        _ => return Err(Error::ClientPin(client_pin::Error::MissingParameter)),
+    };
+
+    let key = KeyAgreementKey::generate();
+    let shared_secret = key.shared_secret(version, &peer_cose_key)?;
+    Ok((key.public_key(), shared_secret))
+}
+
+/// Encrypts `LEFT(SHA-256(pin), 16)` of the normalized PIN for the
+/// authenticator.
+pub(crate) fn encrypt_pin_hash(shared_secret: &SharedSecret, pin: &str) -> Result<Vec<u8>, Error> {
+    let pin_hash = &Sha256::digest(normalize(pin).as_bytes())[..16];
+    Ok(shared_secret.encrypt(pin_hash)?)
+}
+
+/// The PIN in Unicode Normalization Form C, so that the same PIN is sent
+/// however it was entered.
+fn normalize(pin: &str) -> String {
+    pin.nfc().collect()
+}
+
+/// Normalizes the PIN, checks it against the length limits and pads it with
+/// zero bytes.
+fn pad_pin(pin: &str, min_pin_length: usize) -> Result<[u8; PADDED_PIN_LENGTH], Error> {
+    let pin = normalize(pin);
+    if pin.chars().count() < min_pin_length {
+        return Err(Error::PinTooShort { min_pin_length });
+    }
+    if pin.len() > MAX_PIN_LENGTH {
+        return Err(Error::PinTooLong);
+    }
+
+    let mut padded = [0; PADDED_PIN_LENGTH];
+    padded[..pin.len()].copy_from_slice(pin.as_bytes());
+    Ok(padded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ctap2_virtual::{AlwaysPresent, Authenticator, Memory};
+
+    #[test]
+    fn counts_code_points() {
+        // Four code points, but twelve bytes
+        assert!(pad_pin("日本語の", 4).is_ok());
+        assert_eq!(
+            pad_pin("日本語", 4),
+            Err(Error::PinTooShort { min_pin_length: 4 })
+        );
+    }
+
+    #[test]
+    fn normalizes() {
+        // "é" as "e" followed by U+0301 COMBINING ACUTE ACCENT
+        let decomposed = "cafe\u{301}";
+        let composed = "caf\u{e9}";
+        assert_eq!(pad_pin(decomposed, 4), pad_pin(composed, 4));
+        let padded = pad_pin(decomposed, 4).unwrap();
+        assert_eq!(&padded[..composed.len()], composed.as_bytes());
+        assert_eq!(padded[composed.len()], 0);
+        // Four code points once composed
+        assert_eq!(
+            pad_pin(decomposed, 5),
+            Err(Error::PinTooShort { min_pin_length: 5 })
+        );
+
+        let key = KeyAgreementKey::generate();
+        let shared_secret = key.shared_secret(Version::Two, &key.public_key()).unwrap();
+        let pin_hash = |pin| {
+            let encrypted = encrypt_pin_hash(&shared_secret, pin).unwrap();
+            shared_secret.decrypt(&encrypted).unwrap()
+        };
+        assert_eq!(pin_hash(decomposed), pin_hash(composed));
+    }
+
+    #[test]
+    fn limits_bytes() {
+        let padded = pad_pin(&"1".repeat(63), 4).unwrap();
+        assert_eq!(padded[62], b'1');
+        assert_eq!(padded[63], 0);
+        // 21 code points, but 63 and 66 bytes
+        assert!(pad_pin(&"語".repeat(21), 4).is_ok());
+        assert_eq!(pad_pin(&"語".repeat(22), 4), Err(Error::PinTooLong));
+    }
+
+    #[test]
+    fn set_and_change_pin() {
+        let mut authenticator = Authenticator::new(Memory::default(), AlwaysPresent);
+        assert_eq!(
+            change_pin(&mut authenticator, "1234", "5678"),
+            Err(Error::PinNotSet)
+        );
+        set_pin(&mut authenticator, "1234").unwrap();
+        assert_eq!(
+            set_pin(&mut authenticator, "5678"),
+            Err(Error::PinAlreadySet)
+        );
+        change_pin(&mut authenticator, "1234", "5678").unwrap();
+        assert_eq!(pin_retries(&mut authenticator).unwrap().pin_retries, 8);
+    }
+}
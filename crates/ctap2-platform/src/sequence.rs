@@ -0,0 +1,65 @@
+//! Responses split across a command and its follow-up requests.
+
+use std::iter::FusedIterator;
+
+/// The results of a command that returns them one at a time: the first one is
+/// the response to the command itself, which also reports the total number of
+/// results, and the remaining ones are requested as the iterator advances.
+///
+/// The authenticator only keeps the remaining results until it receives any
+/// other command, so the iterator should be drained before sending the next
+/// one.
+pub struct Sequence<'a, A: ?Sized, T, E> {
+    authenticator: &'a mut A,
+    first: Option<T>,
+    remaining: usize,
+    next: fn(&mut A) -> Result<T, E>,
+}
+
+impl<'a, A: ?Sized, T, E> Sequence<'a, A, T, E> {
+    /// Takes the first result along with the total number of results, or
+    /// `None` if there are none, and the request for each of the remaining
+    /// ones.
+    pub(crate) fn new(
+        authenticator: &'a mut A,
+        first: Option<(T, usize)>,
+        next: fn(&mut A) -> Result<T, E>,
+    ) -> Self {
+        let (first, total) = first.unzip();
+        Self {
+            authenticator,
+            first,
+            remaining: total.map_or(0, |total: usize| total.saturating_sub(1)),
+            next,
+        }
+    }
+}
+
+impl<A: ?Sized, T, E> Iterator for Sequence<'_, A, T, E> {
+    type Item = Result<T, E>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(first) = self.first.take() {
+            return Some(Ok(first));
+        }
+        if self.remaining == 0 {
+            return None;
+        }
+
+        let result = (self.next)(self.authenticator);
+        // The remaining results are lost once the authenticator rejects a
+        // request for one of them
+        self.remaining = match result {
+            Ok(_) => self.remaining - 1,
+            Err(_) => 0,
+        };
+        Some(result)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let first = usize::from(self.first.is_some());
+        (first + self.remaining.min(1), Some(first + self.remaining))
+    }
+}
+
+impl<A: ?Sized, T, E> FusedIterator for Sequence<'_, A, T, E> {}
@@ -0,0 +1,694 @@
+//! Acquiring `pinUvAuthToken`s for authenticating commands.
+
+use crate::{pin, Error};
+use ctap2_proto::prelude::{
+    client_pin::{
+        self,
+        auth_protocol::{self, Version},
+        Permission, PinUvAuthParam, Request, Response,
+    },
+    device::{self, OptionId},
+    Ctap2_2Authenticator,
+};
+use std::{borrow::Cow, collections::BTreeSet, num::NonZeroUsize};
+
+/// A decrypted `pinUvAuthToken`, along with the permissions and RP ID it was
+/// requested for.
+#[derive(Clone)]
+pub struct PinUvAuthToken {
+    version: Version,
+    token: client_pin::PinUvAuthToken,
+    permissions: BTreeSet<Permission>,
+    relying_party_id: Option<String>,
+}
+
+impl PinUvAuthToken {
+    /// The PIN/UV auth protocol used to obtain the token, which must also be
+    /// used for the `pinUvAuthParam`s computed with it.
+    pub fn version(&self) -> Version {
+        self.version
+    }
+
+    /// The permissions the token was requested with.
+    ///
+    /// Tokens from authenticators without the `pinUvAuthToken` option are
+    /// obtained using `getPinToken` and are not scoped by the authenticator.
+    pub fn permissions(&self) -> &BTreeSet<Permission> {
+        &self.permissions
+    }
+
+    /// The RP ID the token is bound to, if any.
+    pub fn relying_party_id(&self) -> Option<&str> {
+        self.relying_party_id.as_deref()
+    }
+
+    /// Whether the token can be used for an operation requiring the given
+    /// permission on behalf of the given RP.
+    pub fn permits(&self, permission: Permission, relying_party_id: Option<&str>) -> bool {
+        let relying_party_allowed = match (&self.relying_party_id, relying_party_id) {
+            (Some(bound), Some(requested)) => bound == requested,
+            (Some(_), None) => false,
+            (None, _) => true,
+        };
+        self.permissions.contains(&permission) && relying_party_allowed
+    }
+
+    /// Computes the `pinUvAuthParam` for the given message.
+    pub fn authenticate(&self, message: &[u8]) -> PinUvAuthParam {
+        auth_protocol::authenticate(self.version, self.token.as_ref(), message)
+    }
+}
+
+impl AsRef<[u8]> for PinUvAuthToken {
+    fn as_ref(&self) -> &[u8] {
+        self.token.as_ref()
+    }
+}
+
+/// Obtains a `pinUvAuthToken` with the given permissions, scoped to the RP ID
+/// if one is provided.
+///
+/// Built-in user verification is attempted first, as many times as the
+/// authenticator's `preferredPlatformUvAttempts`. If it is unavailable or
+/// keeps failing, the PIN is requested from `pin` and used instead. If `pin`
+/// returns `None`, [`Error::PinRequired`] is returned.
+pub fn acquire<A, P>(
+    authenticator: &mut A,
+    permissions: BTreeSet<Permission>,
+    relying_party_id: Option<&str>,
+    pin: P,
+) -> Result<PinUvAuthToken, Error>
+where
+    A: Ctap2_2Authenticator + ?Sized,
+    P: FnOnce() -> Option<String>,
+{
+    let info = authenticator.get_info();
+    let version = pin::protocol(&info);
+
+    // CTAP 2.0 authenticators only issue unscoped tokens, and only using a PIN
+    if option(&info, OptionId::PinUvAuthToken) != Some(true) {
+        let pin = pin_for(&info, pin)?;
+        let (key_agreement, shared_secret) = pin::key_agreement(authenticator, version)?;
+        let request = Request::GetPinToken {
+            version,
+            key_agreement,
+            pin_hash_encrypted: pin::encrypt_pin_hash(&shared_secret, &pin)?,
+        };
+        let token = match get_token(authenticator, request)? {
+            Response::GetPinToken { pin_uv_auth_token } => pin_uv_auth_token,
+            _ => return Err(Error::ClientPin(client_pin::Error::MissingParameter)),
+        };
+        return Ok(PinUvAuthToken {
+            version,
+            token: decrypt(&shared_secret, &token)?,
+            permissions,
+            relying_party_id: None,
+        });
+    }
+
+    if option(&info, OptionId::UserVerification) == Some(true) {
+        let attempts = info
+            .preferred_platform_uv_attempts
+            .map_or(1, NonZeroUsize::get);
+        let mut uv_error = None;
+        for _ in 0..attempts {
+            let (key_agreement, shared_secret) = pin::key_agreement(authenticator, version)?;
+            let request = Request::GetPinUvAuthTokenUsingUvWithPermissions {
+                version,
+                key_agreement,
+                permissions: Cow::Borrowed(&permissions),
+                relying_party_id: relying_party_id.map(Cow::Borrowed),
+            };
+            match authenticator.client_pin(request) {
+                Ok(Response::GetPinUvAuthTokenUsingUvWithPermissions { pin_uv_auth_token }) => {
+                    return Ok(PinUvAuthToken {
+                        version,
+                        token: decrypt(&shared_secret, &pin_uv_auth_token)?,
+                        permissions,
+                        relying_party_id: relying_party_id.map(str::to_owned),
+                    });
+                }
+                Ok(_) => return Err(Error::ClientPin(client_pin::Error::MissingParameter)),
+                Err(error @ client_pin::Error::UserVerificationInvalid) => uv_error = Some(error),
+                Err(
+                    error @ (client_pin::Error::UserVerificationBlocked
+                    | client_pin::Error::UserActionTimeout),
+                ) => {
+                    uv_error = Some(error);
+                    break;
+                }
+                Err(error) => return Err(error.into()),
+            }
+        }
+
+        // Without a PIN to fall back to, report why user verification failed
+        if let Some(error) = uv_error {
+            if option(&info, OptionId::ClientPin) != Some(true) {
+                return Err(error.into());
+            }
+        }
+    }
+
+    let mc_ga = permissions.contains(&Permission::MakeCredential)
+        || permissions.contains(&Permission::GetAssertion);
+    if mc_ga && option(&info, OptionId::NoMcGaPermissionsWithClientPin) == Some(true) {
+        return Err(Error::ClientPin(client_pin::Error::UnauthorizedPermission));
+    }
+
+    let pin = pin_for(&info, pin)?;
+    let (key_agreement, shared_secret) = pin::key_agreement(authenticator, version)?;
+    let request = Request::GetPinUvAuthTokenUsingPinWithPermissions {
+        version,
+        key_agreement,
+        pin_hash_encrypted: pin::encrypt_pin_hash(&shared_secret, &pin)?,
+        permissions: Cow::Borrowed(&permissions),
+        relying_party_id: relying_party_id.map(Cow::Borrowed),
+    };
+    let token = match get_token(authenticator, request)? {
+        Response::GetPinUvAuthTokenUsingPinWithPermissions { pin_uv_auth_token } => {
+            pin_uv_auth_token
+        }
+        _ => return Err(Error::ClientPin(client_pin::Error::MissingParameter)),
+    };
+    Ok(PinUvAuthToken {
+        version,
+        token: decrypt(&shared_secret, &token)?,
+        permissions,
+        relying_party_id: relying_party_id.map(str::to_owned),
+    })
+}
+
+fn option(info: &device::Info, id: OptionId) -> Option<bool> {
+    info.options
+        .as_ref()
+        .and_then(|options| options.get(&id))
+        .copied()
+}
+
+/// Requests the PIN from the user, once it is known that the authenticator
+/// has one.
+fn pin_for<P>(info: &device::Info, pin: P) -> Result<String, Error>
+where
+    P: FnOnce() -> Option<String>,
+{
+    if !pin::pin_is_set(info)? {
+        return Err(Error::PinNotSet);
+    }
+    pin().ok_or(Error::PinRequired)
+}
+
+/// Sends a request for a token using the PIN, reporting the remaining retries
+/// if the PIN is rejected.
+fn get_token<A>(authenticator: &mut A, request: Request) -> Result<Response, Error>
+where
+    A: Ctap2_2Authenticator + ?Sized,
+{
+    match authenticator.client_pin(request) {
+        Ok(response) => Ok(response),
+        Err(client_pin::Error::PinInvalid) => Err(pin::pin_invalid(authenticator)),
+        Err(error) => Err(error.into()),
+    }
+}
+
+fn decrypt(
+    shared_secret: &auth_protocol::SharedSecret,
+    pin_uv_auth_token: &[u8],
+) -> Result<client_pin::PinUvAuthToken, Error> {
+    let token = shared_secret.decrypt(pin_uv_auth_token)?;
+    Ok(client_pin::PinUvAuthToken::try_from(token.as_slice())?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use auth_protocol::{KeyAgreementKey, SharedSecret};
+    use ctap2_proto::prelude::{credential, credential::public_key, make};
+    use ctap2_virtual::{AlwaysPresent, Authenticator, Intercepted, Memory};
+    use sha2::{Digest, Sha256};
+    use std::{
+        cell::{Cell, RefCell},
+        collections::{BTreeMap, VecDeque},
+    };
+
+    const PIN: &str = "1234";
+
+    /// Built-in user verification, failing with the given errors before it
+    /// succeeds.
+    ///
+    /// It stands between the platform and the virtual authenticator for the
+    /// key agreement, so that it can issue tokens after user verification.
+    struct UserVerification {
+        preferred_platform_uv_attempts: Option<NonZeroUsize>,
+        failures: RefCell<VecDeque<client_pin::Error>>,
+        attempts: Cell<usize>,
+        key: KeyAgreementKey,
+    }
+
+    impl UserVerification {
+        fn new(attempts: usize, failures: &[client_pin::Error]) -> Self {
+            Self {
+                preferred_platform_uv_attempts: NonZeroUsize::new(attempts),
+                failures: RefCell::new(failures.iter().copied().collect()),
+                attempts: Cell::new(0),
+                key: KeyAgreementKey::generate(),
+            }
+        }
+
+        /// Obtains a token from the virtual authenticator with the PIN hash,
+        /// encrypted for the platform.
+        fn token<'a>(
+            &self,
+            authenticator: &mut Authenticator<Memory>,
+            version: Version,
+            platform_key_agreement: &cosey::PublicKey,
+            pin_hash: &[u8],
+            request: impl FnOnce(cosey::PublicKey, Vec<u8>) -> Request<'a>,
+        ) -> Result<Vec<u8>, client_pin::Error> {
+            let platform = self.key.shared_secret(version, platform_key_agreement)?;
+            let (key_agreement, shared_secret) =
+                pin::key_agreement(authenticator, version).unwrap();
+            let request = request(key_agreement, shared_secret.encrypt(pin_hash)?);
+            let token = match authenticator.client_pin(request)? {
+                Response::GetPinToken { pin_uv_auth_token }
+                | Response::GetPinUvAuthTokenUsingPinWithPermissions { pin_uv_auth_token } => {
+                    pin_uv_auth_token
+                }
+                response => panic!("unexpected response: {response:?}"),
+            };
+            platform.encrypt(&shared_secret.decrypt(&token)?)
+        }
+
+        fn decrypt_pin_hash(
+            &self,
+            version: Version,
+            platform_key_agreement: &cosey::PublicKey,
+            pin_hash_encrypted: &[u8],
+        ) -> Result<Vec<u8>, client_pin::Error> {
+            let shared_secret: SharedSecret =
+                self.key.shared_secret(version, platform_key_agreement)?;
+            shared_secret.decrypt(pin_hash_encrypted)
+        }
+
+        fn client_pin(
+            &self,
+            authenticator: &mut Authenticator<Memory>,
+            request: Request,
+        ) -> Result<Response, client_pin::Error> {
+            match request {
+                Request::GetKeyAgreement { .. } => Ok(Response::GetKeyAgreement {
+                    key_agreement: self.key.public_key(),
+                }),
+                Request::GetPinToken {
+                    version,
+                    key_agreement,
+                    pin_hash_encrypted,
+                } => {
+                    let pin_hash =
+                        self.decrypt_pin_hash(version, &key_agreement, &pin_hash_encrypted)?;
+                    let pin_uv_auth_token = self.token(
+                        authenticator,
+                        version,
+                        &key_agreement,
+                        &pin_hash,
+                        |key_agreement, pin_hash_encrypted| Request::GetPinToken {
+                            version,
+                            key_agreement,
+                            pin_hash_encrypted,
+                        },
+                    )?;
+                    Ok(Response::GetPinToken { pin_uv_auth_token })
+                }
+                Request::GetPinUvAuthTokenUsingPinWithPermissions {
+                    version,
+                    key_agreement,
+                    pin_hash_encrypted,
+                    permissions,
+                    relying_party_id,
+                } => {
+                    let pin_hash =
+                        self.decrypt_pin_hash(version, &key_agreement, &pin_hash_encrypted)?;
+                    let pin_uv_auth_token = self.token(
+                        authenticator,
+                        version,
+                        &key_agreement,
+                        &pin_hash,
+                        |key_agreement, pin_hash_encrypted| {
+                            Request::GetPinUvAuthTokenUsingPinWithPermissions {
+                                version,
+                                key_agreement,
+                                pin_hash_encrypted,
+                                permissions,
+                                relying_party_id,
+                            }
+                        },
+                    )?;
+                    Ok(Response::GetPinUvAuthTokenUsingPinWithPermissions { pin_uv_auth_token })
+                }
+                Request::GetPinUvAuthTokenUsingUvWithPermissions {
+                    version,
+                    key_agreement,
+                    permissions,
+                    relying_party_id,
+                } => {
+                    self.attempts.set(self.attempts.get() + 1);
+                    if let Some(error) = self.failures.borrow_mut().pop_front() {
+                        return Err(error);
+                    }
+                    let pin_hash = Sha256::digest(PIN.as_bytes());
+                    let pin_uv_auth_token = self.token(
+                        authenticator,
+                        version,
+                        &key_agreement,
+                        &pin_hash[..16],
+                        |key_agreement, pin_hash_encrypted| {
+                            Request::GetPinUvAuthTokenUsingPinWithPermissions {
+                                version,
+                                key_agreement,
+                                pin_hash_encrypted,
+                                permissions,
+                                relying_party_id,
+                            }
+                        },
+                    )?;
+                    Ok(Response::GetPinUvAuthTokenUsingUvWithPermissions { pin_uv_auth_token })
+                }
+                request => authenticator.client_pin(request),
+            }
+        }
+    }
+
+    /// A virtual authenticator that reports other options, and has the
+    /// built-in user verification if any.
+    fn authenticator<'a>(
+        pin: bool,
+        options: &[(OptionId, bool)],
+        user_verification: Option<&'a UserVerification>,
+    ) -> Intercepted<'a, Memory> {
+        let mut authenticator = Authenticator::new(Memory::default(), AlwaysPresent);
+        if pin {
+            pin::set_pin(&mut authenticator, PIN).unwrap();
+        }
+        let mut options: BTreeMap<_, _> = options.iter().copied().collect();
+        if user_verification.is_some() {
+            options.insert(OptionId::UserVerification, true);
+        }
+        let preferred_platform_uv_attempts =
+            user_verification.and_then(|uv| uv.preferred_platform_uv_attempts);
+        let device = Intercepted::new(authenticator).on_get_info(move |authenticator| {
+            let mut info = authenticator.get_info();
+            if let Some(info_options) = &mut info.options {
+                info_options.extend(&options);
+            }
+            info.preferred_platform_uv_attempts = preferred_platform_uv_attempts;
+            info
+        });
+        match user_verification {
+            Some(uv) => {
+                device.on_client_pin(|authenticator, request| uv.client_pin(authenticator, request))
+            }
+            None => device,
+        }
+    }
+
+    fn permissions(permissions: &[Permission]) -> BTreeSet<Permission> {
+        permissions.iter().copied().collect()
+    }
+
+    /// A PIN callback that records whether it was called.
+    fn pin(called: &Cell<bool>) -> impl FnOnce() -> Option<String> + '_ {
+        || {
+            called.set(true);
+            Some(PIN.to_owned())
+        }
+    }
+
+    /// Whether the authenticator accepts the token for creating a credential
+    /// for "example.com".
+    fn make_credential<A: Ctap2_2Authenticator>(
+        authenticator: &mut A,
+        token: &PinUvAuthToken,
+    ) -> Result<make::Response, make::Error> {
+        let relying_party = public_key::RelyingPartyEntity {
+            id: "example.com".to_owned(),
+            name: None,
+        };
+        let user = public_key::UserEntity {
+            id: vec![0x01],
+            name: None,
+            display_name: None,
+        };
+        let parameters = [public_key::Parameters {
+            credential_type: credential::Type::PublicKey,
+            algorithm: coset::iana::Algorithm::ES256,
+        }];
+        let options = BTreeMap::from([(make::OptionKey::Discoverable, true)]);
+        let request = make::Request::builder()
+            .client_data_hash(&[0x42; 32])
+            .relying_party(&relying_party)
+            .user(&user)
+            .public_key_credential_params(&parameters)
+            .options(&options)
+            .build()
+            .authenticated(token.version(), token.as_ref());
+        authenticator.make_credential(request)
+    }
+
+    #[test]
+    fn using_pin() {
+        let mut device = authenticator(true, &[], None);
+        let called = Cell::new(false);
+        let token = acquire(
+            &mut device,
+            permissions(&[Permission::MakeCredential]),
+            Some("example.com"),
+            pin(&called),
+        )
+        .unwrap();
+        assert!(called.get());
+        assert_eq!(token.version(), Version::Two);
+        assert_eq!(token.relying_party_id(), Some("example.com"));
+        assert!(token.permits(Permission::MakeCredential, Some("example.com")));
+        assert!(!token.permits(Permission::MakeCredential, Some("example.org")));
+        assert!(!token.permits(Permission::GetAssertion, Some("example.com")));
+        assert!(make_credential(&mut device, &token).is_ok());
+    }
+
+    #[test]
+    fn pin_missing() {
+        let mut device = authenticator(false, &[], None);
+        let result = acquire(
+            &mut device,
+            permissions(&[Permission::CredentialManagement]),
+            None,
+            || Some(PIN.to_owned()),
+        );
+        assert_eq!(result.err(), Some(Error::PinNotSet));
+
+        let mut device = authenticator(true, &[], None);
+        let result = acquire(
+            &mut device,
+            permissions(&[Permission::CredentialManagement]),
+            None,
+            || None,
+        );
+        assert_eq!(result.err(), Some(Error::PinRequired));
+
+        let result = acquire(
+            &mut device,
+            permissions(&[Permission::CredentialManagement]),
+            None,
+            || Some("0000".to_owned()),
+        );
+        assert_eq!(
+            result.err(),
+            Some(Error::PinInvalid {
+                pin_retries: Some(7),
+                power_cycle_required: false,
+            })
+        );
+    }
+
+    #[test]
+    fn legacy_get_pin_token() {
+        let mut device = authenticator(true, &[(OptionId::PinUvAuthToken, false)], None);
+        let called = Cell::new(false);
+        let token = acquire(
+            &mut device,
+            permissions(&[Permission::MakeCredential]),
+            Some("example.com"),
+            pin(&called),
+        )
+        .unwrap();
+        assert!(called.get());
+        // The token is not scoped by the authenticator
+        assert_eq!(token.relying_party_id(), None);
+        assert_eq!(
+            token.permissions(),
+            &permissions(&[Permission::MakeCredential])
+        );
+        assert!(make_credential(&mut device, &token).is_ok());
+    }
+
+    #[test]
+    fn legacy_ignores_user_verification() {
+        let uv = UserVerification::new(1, &[]);
+        let mut device = authenticator(true, &[(OptionId::PinUvAuthToken, false)], Some(&uv));
+        let called = Cell::new(false);
+        acquire(
+            &mut device,
+            permissions(&[Permission::GetAssertion]),
+            Some("example.com"),
+            pin(&called),
+        )
+        .unwrap();
+        assert!(called.get());
+        assert_eq!(uv.attempts.get(), 0);
+    }
+
+    #[test]
+    fn no_mc_ga_permissions_with_client_pin() {
+        let mut device = authenticator(
+            true,
+            &[(OptionId::NoMcGaPermissionsWithClientPin, true)],
+            None,
+        );
+        let called = Cell::new(false);
+        let result = acquire(
+            &mut device,
+            permissions(&[Permission::MakeCredential, Permission::LargeBlobWrite]),
+            Some("example.com"),
+            pin(&called),
+        );
+        assert_eq!(
+            result.err(),
+            Some(Error::ClientPin(client_pin::Error::UnauthorizedPermission))
+        );
+        assert!(!called.get());
+
+        // Other permissions can still be obtained using the PIN
+        acquire(
+            &mut device,
+            permissions(&[Permission::CredentialManagement]),
+            None,
+            pin(&called),
+        )
+        .unwrap();
+        assert!(called.get());
+    }
+
+    #[test]
+    fn no_mc_ga_permissions_with_user_verification() {
+        let uv = UserVerification::new(1, &[]);
+        let mut device = authenticator(
+            true,
+            &[(OptionId::NoMcGaPermissionsWithClientPin, true)],
+            Some(&uv),
+        );
+        let token = acquire(
+            &mut device,
+            permissions(&[Permission::MakeCredential]),
+            Some("example.com"),
+            || None,
+        )
+        .unwrap();
+        assert!(make_credential(&mut device, &token).is_ok());
+    }
+
+    #[test]
+    fn user_verification_attempts() {
+        let uv = UserVerification::new(
+            3,
+            &[
+                client_pin::Error::UserVerificationInvalid,
+                client_pin::Error::UserVerificationInvalid,
+            ],
+        );
+        let mut device = authenticator(true, &[], Some(&uv));
+        // The PIN is not needed once user verification succeeds
+        let token = acquire(
+            &mut device,
+            permissions(&[Permission::MakeCredential]),
+            Some("example.com"),
+            || None,
+        )
+        .unwrap();
+        assert_eq!(uv.attempts.get(), 3);
+        assert_eq!(token.relying_party_id(), Some("example.com"));
+        assert!(make_credential(&mut device, &token).is_ok());
+    }
+
+    #[test]
+    fn user_verification_falls_back_to_pin() {
+        let uv = UserVerification::new(
+            2,
+            &[
+                client_pin::Error::UserVerificationInvalid,
+                client_pin::Error::UserVerificationInvalid,
+            ],
+        );
+        let mut device = authenticator(true, &[], Some(&uv));
+        let called = Cell::new(false);
+        let token = acquire(
+            &mut device,
+            permissions(&[Permission::MakeCredential]),
+            Some("example.com"),
+            pin(&called),
+        )
+        .unwrap();
+        assert_eq!(uv.attempts.get(), 2);
+        assert!(called.get());
+        assert!(make_credential(&mut device, &token).is_ok());
+    }
+
+    #[test]
+    fn user_verification_blocked() {
+        for error in [
+            client_pin::Error::UserVerificationBlocked,
+            client_pin::Error::UserActionTimeout,
+        ] {
+            // Blocked user verification is not attempted again
+            let uv = UserVerification::new(3, &[error]);
+            let mut device = authenticator(true, &[], Some(&uv));
+            let called = Cell::new(false);
+            acquire(
+                &mut device,
+                permissions(&[Permission::GetAssertion]),
+                Some("example.com"),
+                pin(&called),
+            )
+            .unwrap();
+            assert_eq!(uv.attempts.get(), 1);
+            assert!(called.get());
+
+            // Without a PIN, the reason is reported
+            let uv = UserVerification::new(3, &[error]);
+            let mut device = authenticator(false, &[], Some(&uv));
+            let result = acquire(
+                &mut device,
+                permissions(&[Permission::GetAssertion]),
+                Some("example.com"),
+                || None,
+            );
+            assert_eq!(result.err(), Some(Error::ClientPin(error)));
+            assert_eq!(uv.attempts.get(), 1);
+        }
+    }
+
+    #[test]
+    fn user_verification_error() {
+        let uv = UserVerification::new(3, &[client_pin::Error::UnauthorizedPermission]);
+        let mut device = authenticator(true, &[], Some(&uv));
+        let called = Cell::new(false);
+        let result = acquire(
+            &mut device,
+            permissions(&[Permission::BiometricEnrollment]),
+            None,
+            pin(&called),
+        );
+        assert_eq!(
+            result.err(),
+            Some(Error::ClientPin(client_pin::Error::UnauthorizedPermission))
+        );
+        assert!(!called.get());
+    }
+}
@@ -1,5 +1,9 @@
 use crate::Sha256Hash;
-use crate::{authenticator::client_pin::auth_protocol, extensions};
+use crate::{
+    authenticator::client_pin::{self, auth_protocol},
+    extensions,
+    status::StatusCode,
+};
 use fido_common::credential::public_key;
 use std::{collections::BTreeMap, usize};
 
@@ -8,7 +12,10 @@ use serde::{Deserialize, Serialize};
 #[cfg(feature = "serde")]
 use serde_with::{serde_as, skip_serializing_none, Bytes};
 
-#[derive(Debug)]
+#[cfg(feature = "serde")]
+pub(crate) mod raw;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Error {
     OperationDenied,
     PinNotSet,
@@ -22,6 +29,54 @@ pub enum Error {
     UserActionTimeout,
     PinBlocked,
     NoCredentials,
+    /// There is no pending `authenticatorGetAssertion` request to continue
+    /// with `authenticatorGetNextAssertion`.
+    NotAllowed,
+    /// Any other status code, including those defined by extensions and
+    /// vendors.
+    Other(StatusCode),
+}
+
+impl From<Error> for StatusCode {
+    fn from(value: Error) -> Self {
+        match value {
+            Error::OperationDenied => StatusCode::OperationDenied,
+            Error::PinNotSet => StatusCode::PinNotSet,
+            Error::PinInvalid => StatusCode::PinInvalid,
+            Error::InvalidParameter => StatusCode::InvalidParameter,
+            Error::MissingParameter => StatusCode::MissingParameter,
+            Error::InvalidOption => StatusCode::InvalidOption,
+            Error::UnsupportedOption => StatusCode::UnsupportedOption,
+            Error::PinUvAuthTokenRequired => StatusCode::PinUvAuthTokenRequired,
+            Error::PinAuthInvalid => StatusCode::PinAuthInvalid,
+            Error::UserActionTimeout => StatusCode::UserActionTimeout,
+            Error::PinBlocked => StatusCode::PinBlocked,
+            Error::NoCredentials => StatusCode::NoCredentials,
+            Error::NotAllowed => StatusCode::NotAllowed,
+            Error::Other(status) => status,
+        }
+    }
+}
+
+impl From<StatusCode> for Error {
+    fn from(value: StatusCode) -> Self {
+        match value {
+            StatusCode::OperationDenied => Error::OperationDenied,
+            StatusCode::PinNotSet => Error::PinNotSet,
+            StatusCode::PinInvalid => Error::PinInvalid,
+            StatusCode::InvalidParameter => Error::InvalidParameter,
+            StatusCode::MissingParameter => Error::MissingParameter,
+            StatusCode::InvalidOption => Error::InvalidOption,
+            StatusCode::UnsupportedOption => Error::UnsupportedOption,
+            StatusCode::PinUvAuthTokenRequired => Error::PinUvAuthTokenRequired,
+            StatusCode::PinAuthInvalid => Error::PinAuthInvalid,
+            StatusCode::UserActionTimeout => Error::UserActionTimeout,
+            StatusCode::PinBlocked => Error::PinBlocked,
+            StatusCode::NoCredentials => Error::NoCredentials,
+            StatusCode::NotAllowed => Error::NotAllowed,
+            status => Error::Other(status),
+        }
+    }
 }
 
 /// > The following option keys are defined for use in
@@ -60,18 +115,78 @@ pub struct Request<'a> {
     pub allow_list: Option<&'a Vec<&'a public_key::Descriptor>>,
     /// > Parameters to influence authenticator operation. These parameters
     /// > might be authenticator specific.
-    #[cfg_attr(feature = "serde", serde(rename = 0x04))]
+    #[cfg_attr(
+        feature = "serde",
+        serde(rename = 0x04, serialize_with = "extensions::raw::serialize")
+    )]
     pub extensions: Option<&'a BTreeMap<extensions::Identifier, &'a [u8]>>,
     /// > Parameters to influence authenticator operation.
     #[cfg_attr(feature = "serde", serde(rename = 0x05))]
     pub options: Option<&'a BTreeMap<OptionKey, bool>>,
-    #[cfg_attr(feature = "serde", serde(rename = 0x06))]
-    pub pin_uv_auth_param: Option<&'a [u8]>,
+    #[cfg_attr(
+        feature = "serde",
+        serde(
+            rename = 0x06,
+            serialize_with = "client_pin::raw::serialize_pin_uv_auth",
+            skip_serializing_if = "client_pin::PinUvAuth::is_none"
+        )
+    )]
+    pub pin_uv_auth_param: client_pin::PinUvAuth,
     /// > PIN/UV protocol version selected by platform.
     #[cfg_attr(feature = "serde", serde(rename = 0x07))]
     pub pin_uv_auth_protocol_version: Option<auth_protocol::Version>,
 }
 
+impl Request<'_> {
+    /// The message authenticated by the `pinUvAuthParam`, which is just the
+    /// `clientDataHash`.
+    pub fn authenticated_message(&self) -> &[u8] {
+        self.client_data_hash
+    }
+
+    /// Sets the `pinUvAuthParam` computed with the given `pinUvAuthToken`,
+    /// and the PIN/UV protocol version.
+    #[cfg(feature = "crypto")]
+    #[must_use]
+    pub fn authenticated(
+        mut self,
+        pin_uv_auth_protocol_version: auth_protocol::Version,
+        pin_uv_auth_token: &[u8],
+    ) -> Self {
+        self.pin_uv_auth_param = client_pin::PinUvAuth::Param(auth_protocol::authenticate(
+            pin_uv_auth_protocol_version,
+            pin_uv_auth_token,
+            self.authenticated_message(),
+        ));
+        self.pin_uv_auth_protocol_version = Some(pin_uv_auth_protocol_version);
+        self
+    }
+
+    /// Verifies the `pinUvAuthParam` using the given `pinUvAuthToken`.
+    ///
+    /// Checking whether the token is in use, has the
+    /// [`Permission::GetAssertion`](crate::authenticator::client_pin::Permission::GetAssertion)
+    /// permission and is bound to the request's RP is left to the
+    /// authenticator.
+    #[cfg(feature = "crypto")]
+    pub fn verify(&self, pin_uv_auth_token: &[u8]) -> Result<(), Error> {
+        let pin_uv_auth_param = self
+            .pin_uv_auth_param
+            .param()
+            .ok_or(Error::PinUvAuthTokenRequired)?;
+        let version = self
+            .pin_uv_auth_protocol_version
+            .ok_or(Error::MissingParameter)?;
+        auth_protocol::verify(
+            version,
+            pin_uv_auth_token,
+            self.authenticated_message(),
+            pin_uv_auth_param.as_ref(),
+        )
+        .map_err(|_| Error::PinAuthInvalid)
+    }
+}
+
 /// Response structure for [`Ctap2Device::get_assertion`] operation.
 #[cfg_eval]
 #[derive(Debug, Clone)]
@@ -79,7 +194,7 @@ pub struct Request<'a> {
     feature = "serde",
     serde_as,
     skip_serializing_none,
-    derive(Deserialize)
+    derive(Serialize, Deserialize)
 )]
 pub struct Response {
     /// > PublicKeyCredentialDescriptor structure containing the credential
@@ -119,3 +234,46 @@ pub struct Response {
     )]
     pub large_blob_key: Option<Vec<u8>>,
 }
+
+#[cfg(all(test, feature = "crypto"))]
+mod tests {
+    use super::*;
+    use client_pin::{PinUvAuth, PinUvAuthParam};
+
+    #[test]
+    fn authenticated() {
+        let request = Request {
+            relying_party_id: "example.com",
+            client_data_hash: &[0x02; 32],
+            allow_list: None,
+            extensions: None,
+            options: None,
+            pin_uv_auth_param: PinUvAuth::None,
+            pin_uv_auth_protocol_version: None,
+        };
+        assert_eq!(
+            request.verify(&[0x03; 32]),
+            Err(Error::PinUvAuthTokenRequired)
+        );
+
+        let one = request.authenticated(auth_protocol::Version::One, &[0x03; 32]);
+        assert!(matches!(
+            one.pin_uv_auth_param,
+            PinUvAuth::Param(PinUvAuthParam::Short(_))
+        ));
+        assert_eq!(one.verify(&[0x03; 32]), Ok(()));
+        assert_eq!(one.verify(&[0x04; 32]), Err(Error::PinAuthInvalid));
+
+        let two = request.authenticated(auth_protocol::Version::Two, &[0x03; 32]);
+        assert!(matches!(
+            two.pin_uv_auth_param,
+            PinUvAuth::Param(PinUvAuthParam::Long(_))
+        ));
+        assert_eq!(
+            two.pin_uv_auth_protocol_version,
+            Some(auth_protocol::Version::Two)
+        );
+        assert_eq!(two.verify(&[0x03; 32]), Ok(()));
+        assert_eq!(two.verify(&[0x04; 32]), Err(Error::PinAuthInvalid));
+    }
+}
@@ -0,0 +1,145 @@
+//! Owned request parameters, used to deserialize a [`Request`] received by an
+//! authenticator.
+
+use super::{Error, OptionKey, Request};
+use crate::{
+    authenticator::client_pin::{auth_protocol, PinUvAuth, PinUvAuthParam},
+    extensions, Sha256Hash,
+};
+use fido_common::credential::public_key;
+use serde::Deserialize;
+use serde_with::{serde_as, Bytes};
+use std::collections::BTreeMap;
+
+#[serde_as]
+#[derive(Deserialize)]
+pub(crate) struct RawRequest {
+    #[serde(rename = 0x01)]
+    relying_party_id: String,
+    #[serde_as(as = "Bytes")]
+    #[serde(rename = 0x02)]
+    client_data_hash: Sha256Hash,
+    #[serde(rename = 0x03)]
+    allow_list: Option<Vec<public_key::Descriptor>>,
+    #[serde(
+        rename = 0x04,
+        deserialize_with = "extensions::raw::deserialize",
+        default
+    )]
+    extensions: Option<BTreeMap<extensions::Identifier, Vec<u8>>>,
+    #[serde(rename = 0x05)]
+    options: Option<BTreeMap<OptionKey, bool>>,
+    #[serde_as(as = "Option<Bytes>")]
+    #[serde(rename = 0x06)]
+    pin_uv_auth_param: Option<Vec<u8>>,
+    #[serde(rename = 0x07)]
+    pin_uv_auth_protocol_version: Option<auth_protocol::Version>,
+}
+
+impl RawRequest {
+    /// Calls `f` with a [`Request`] borrowing the deserialized parameters.
+    ///
+    /// A `pinUvAuthParam` whose length is that of neither PIN/UV auth
+    /// protocol is invalid.
+    pub(crate) fn with_request<T>(
+        &self,
+        f: impl FnOnce(Request<'_>) -> Result<T, Error>,
+    ) -> Result<T, Error> {
+        // A zero length pinUvAuthParam asks for authenticator selection
+        let pin_uv_auth_param = match self.pin_uv_auth_param.as_deref() {
+            None => PinUvAuth::None,
+            Some([]) => PinUvAuth::Selection,
+            Some(pin_uv_auth_param) => PinUvAuth::Param(
+                PinUvAuthParam::try_from(pin_uv_auth_param).map_err(|_| Error::PinAuthInvalid)?,
+            ),
+        };
+        let allow_list: Option<Vec<&public_key::Descriptor>> = self
+            .allow_list
+            .as_ref()
+            .map(|allow_list| allow_list.iter().collect());
+        let extensions: Option<BTreeMap<extensions::Identifier, &[u8]>> =
+            self.extensions.as_ref().map(|extensions| {
+                extensions
+                    .iter()
+                    .map(|(identifier, input)| (*identifier, input.as_slice()))
+                    .collect()
+            });
+
+        f(Request {
+            relying_party_id: &self.relying_party_id,
+            client_data_hash: &self.client_data_hash,
+            allow_list: allow_list.as_ref(),
+            extensions: extensions.as_ref(),
+            options: self.options.as_ref(),
+            pin_uv_auth_param,
+            pin_uv_auth_protocol_version: self.pin_uv_auth_protocol_version,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn raw(pin_uv_auth_param: Option<Vec<u8>>) -> RawRequest {
+        RawRequest {
+            relying_party_id: "example.com".to_owned(),
+            client_data_hash: [0x02; 32],
+            allow_list: None,
+            extensions: None,
+            options: None,
+            pin_uv_auth_param,
+            pin_uv_auth_protocol_version: Some(auth_protocol::Version::One),
+        }
+    }
+
+    fn decode(data: &[u8]) -> Result<PinUvAuth, Error> {
+        let raw: RawRequest = ciborium::de::from_reader(data).unwrap();
+        raw.with_request(|request| Ok(request.pin_uv_auth_param))
+    }
+
+    fn encode(pin_uv_auth_param: PinUvAuth) -> Vec<u8> {
+        let request = Request {
+            relying_party_id: "example.com",
+            client_data_hash: &[0x02; 32],
+            allow_list: None,
+            extensions: None,
+            options: None,
+            pin_uv_auth_param,
+            pin_uv_auth_protocol_version: Some(auth_protocol::Version::One),
+        };
+        let mut data = Vec::new();
+        ciborium::ser::into_writer(&request, &mut data).unwrap();
+        data
+    }
+
+    #[test]
+    fn pin_uv_auth_param() {
+        let pin_uv_auth_param = PinUvAuth::Param(PinUvAuthParam::Short([0x03; 16]));
+        let encoded = encode(pin_uv_auth_param);
+        // A map of the RP ID, clientDataHash, pinUvAuthParam and protocol
+        assert_eq!(encoded[0], 0xA4);
+        assert_eq!(decode(&encoded), Ok(pin_uv_auth_param));
+
+        let encoded = encode(PinUvAuth::None);
+        assert_eq!(encoded[0], 0xA3);
+        assert_eq!(decode(&encoded), Ok(PinUvAuth::None));
+    }
+
+    #[test]
+    fn authenticator_selection() {
+        let encoded = encode(PinUvAuth::Selection);
+        // The pinUvAuthParam is a zero length byte string
+        assert!(encoded.ends_with(&[0x06, 0x40, 0x07, 0x01]));
+        assert_eq!(decode(&encoded), Ok(PinUvAuth::Selection));
+    }
+
+    #[test]
+    fn invalid_pin_uv_auth_param_length() {
+        assert_eq!(
+            raw(Some(vec![0x03; 20])).with_request(|_| Ok(())),
+            Err(Error::PinAuthInvalid)
+        );
+        assert_eq!(raw(Some(vec![0x03; 16])).with_request(|_| Ok(())), Ok(()));
+    }
+}
@@ -1,5 +1,607 @@
-pub enum Request {}
+use crate::{authenticator::client_pin, status::StatusCode};
+use std::{borrow::Cow, fmt::Display};
 
-pub enum Response {}
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "serde")]
+use serde_with::{serde_as, Bytes};
 
-pub enum Error {}
+pub use client_pin::PinUvAuthParam;
+
+#[cfg(feature = "serde")]
+pub(crate) mod raw;
+
+#[cfg(feature = "serde")]
+use raw::{RawRequest, RawResponse};
+
+/// > The user verification modality being requested.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(into = "u8", try_from = "u8")
+)]
+pub enum Modality {
+    Fingerprint = 0x01,
+}
+
+impl From<Modality> for u8 {
+    fn from(value: Modality) -> Self {
+        value as u8
+    }
+}
+
+impl TryFrom<u8> for Modality {
+    type Error = Error;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0x01 => Ok(Modality::Fingerprint),
+            _ => Err(Error::InvalidParameter),
+        }
+    }
+}
+
+/// The type of fingerprint sensor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(into = "u8", try_from = "u8")
+)]
+pub enum FingerprintKind {
+    /// > touch type fingerprints
+    Touch = 0x01,
+    /// > swipe type fingerprints
+    Swipe = 0x02,
+}
+
+impl From<FingerprintKind> for u8 {
+    fn from(value: FingerprintKind) -> Self {
+        value as u8
+    }
+}
+
+impl TryFrom<u8> for FingerprintKind {
+    type Error = Error;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0x01 => Ok(FingerprintKind::Touch),
+            0x02 => Ok(FingerprintKind::Swipe),
+            _ => Err(Error::InvalidParameter),
+        }
+    }
+}
+
+/// > Provides last enrollment sample status.
+///
+/// Every byte maps to a status, so conversions between the two are lossless.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(into = "u8", from = "u8")
+)]
+pub enum LastEnrollSampleStatus {
+    /// > Good fingerprint capture.
+    Good,
+    /// > Fingerprint was too high.
+    TooHigh,
+    /// > Fingerprint was too low.
+    TooLow,
+    /// > Fingerprint was too left.
+    TooLeft,
+    /// > Fingerprint was too right.
+    TooRight,
+    /// > Fingerprint was too fast.
+    TooFast,
+    /// > Fingerprint was too slow.
+    TooSlow,
+    /// > Fingerprint was of poor quality.
+    PoorQuality,
+    /// > Fingerprint was too skewed.
+    TooSkewed,
+    /// > Fingerprint was too short.
+    TooShort,
+    /// > Merge failure of the capture.
+    MergeFailure,
+    /// > Fingerprint already exists.
+    Exists,
+    /// > User did not touch/swipe the authenticator.
+    NoUserActivity,
+    /// > User did not lift the finger off the sensor.
+    NoUserPresenceTransition,
+    /// A status not defined by the specification.
+    Other(u8),
+}
+
+impl From<u8> for LastEnrollSampleStatus {
+    fn from(value: u8) -> Self {
+        match value {
+            0x00 => Self::Good,
+            0x01 => Self::TooHigh,
+            0x02 => Self::TooLow,
+            0x03 => Self::TooLeft,
+            0x04 => Self::TooRight,
+            0x05 => Self::TooFast,
+            0x06 => Self::TooSlow,
+            0x07 => Self::PoorQuality,
+            0x08 => Self::TooSkewed,
+            0x09 => Self::TooShort,
+            0x0A => Self::MergeFailure,
+            0x0B => Self::Exists,
+            0x0D => Self::NoUserActivity,
+            0x0E => Self::NoUserPresenceTransition,
+            status => Self::Other(status),
+        }
+    }
+}
+
+impl From<LastEnrollSampleStatus> for u8 {
+    fn from(value: LastEnrollSampleStatus) -> Self {
+        match value {
+            LastEnrollSampleStatus::Good => 0x00,
+            LastEnrollSampleStatus::TooHigh => 0x01,
+            LastEnrollSampleStatus::TooLow => 0x02,
+            LastEnrollSampleStatus::TooLeft => 0x03,
+            LastEnrollSampleStatus::TooRight => 0x04,
+            LastEnrollSampleStatus::TooFast => 0x05,
+            LastEnrollSampleStatus::TooSlow => 0x06,
+            LastEnrollSampleStatus::PoorQuality => 0x07,
+            LastEnrollSampleStatus::TooSkewed => 0x08,
+            LastEnrollSampleStatus::TooShort => 0x09,
+            LastEnrollSampleStatus::MergeFailure => 0x0A,
+            LastEnrollSampleStatus::Exists => 0x0B,
+            LastEnrollSampleStatus::NoUserActivity => 0x0D,
+            LastEnrollSampleStatus::NoUserPresenceTransition => 0x0E,
+            LastEnrollSampleStatus::Other(status) => status,
+        }
+    }
+}
+
+impl Display for LastEnrollSampleStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let message = match self {
+            Self::Good => "Good fingerprint capture",
+            Self::TooHigh => "Fingerprint was too high",
+            Self::TooLow => "Fingerprint was too low",
+            Self::TooLeft => "Fingerprint was too left",
+            Self::TooRight => "Fingerprint was too right",
+            Self::TooFast => "Fingerprint was too fast",
+            Self::TooSlow => "Fingerprint was too slow",
+            Self::PoorQuality => "Fingerprint was of poor quality",
+            Self::TooSkewed => "Fingerprint was too skewed",
+            Self::TooShort => "Fingerprint was too short",
+            Self::MergeFailure => "Merge failure of the capture",
+            Self::Exists => "Fingerprint already exists",
+            Self::NoUserActivity => "No fingerprint was captured",
+            Self::NoUserPresenceTransition => "Finger was not lifted off the sensor",
+            Self::Other(status) => return write!(f, "Unknown sample status {:#04x}", status),
+        };
+        write!(f, "{}", message)
+    }
+}
+
+/// An enrolled fingerprint.
+#[cfg_eval]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", serde_as, derive(Serialize, Deserialize))]
+pub struct TemplateInfo {
+    /// > Template Identifier.
+    #[cfg_attr(feature = "serde", serde_as(as = "Bytes"), serde(rename = 0x01))]
+    pub template_id: Vec<u8>,
+    /// > Template Friendly Name.
+    #[cfg_attr(
+        feature = "serde",
+        serde(rename = 0x02, default, skip_serializing_if = "Option::is_none")
+    )]
+    pub template_friendly_name: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(into = "RawRequest", try_from = "RawRequest")
+)]
+pub enum Request<'a> {
+    /// > The platform can determine which type of user verification modality
+    /// > is supported by the authenticator.
+    GetModality,
+    /// > Following operation is used by platform to get fingerprint sensor
+    /// > information.
+    GetFingerprintSensorInfo,
+    /// > Starts a new enrollment.
+    EnrollBegin {
+        /// > Indicates timeout in milliseconds.
+        timeout_milliseconds: Option<usize>,
+        /// > PIN/UV protocol version chosen by the platform.
+        pin_uv_auth_protocol: client_pin::auth_protocol::Version,
+        /// > First 16 bytes of HMAC-SHA-256 of contents using `pinUvAuthToken`.
+        pin_uv_auth_param: PinUvAuthParam,
+    },
+    /// > Captures the next sample of the enrollment started by `enrollBegin`.
+    EnrollCaptureNextSample {
+        /// The template ID returned by `enrollBegin`.
+        template_id: Cow<'a, [u8]>,
+        /// > Indicates timeout in milliseconds.
+        timeout_milliseconds: Option<usize>,
+        /// > PIN/UV protocol version chosen by the platform.
+        pin_uv_auth_protocol: client_pin::auth_protocol::Version,
+        /// > First 16 bytes of HMAC-SHA-256 of contents using `pinUvAuthToken`.
+        pin_uv_auth_param: PinUvAuthParam,
+    },
+    /// > Cancels current ongoing enrollment.
+    CancelCurrentEnrollment,
+    /// > Enumerates all the enrollments.
+    EnumerateEnrollments {
+        /// > PIN/UV protocol version chosen by the platform.
+        pin_uv_auth_protocol: client_pin::auth_protocol::Version,
+        /// > First 16 bytes of HMAC-SHA-256 of contents using `pinUvAuthToken`.
+        pin_uv_auth_param: PinUvAuthParam,
+    },
+    /// > Sets the friendly name of an enrollment.
+    SetFriendlyName {
+        template_id: Cow<'a, [u8]>,
+        template_friendly_name: Cow<'a, str>,
+        /// > PIN/UV protocol version chosen by the platform.
+        pin_uv_auth_protocol: client_pin::auth_protocol::Version,
+        /// > First 16 bytes of HMAC-SHA-256 of contents using `pinUvAuthToken`.
+        pin_uv_auth_param: PinUvAuthParam,
+    },
+    /// > Removes an existing enrollment.
+    RemoveEnrollment {
+        template_id: Cow<'a, [u8]>,
+        /// > PIN/UV protocol version chosen by the platform.
+        pin_uv_auth_protocol: client_pin::auth_protocol::Version,
+        /// > First 16 bytes of HMAC-SHA-256 of contents using `pinUvAuthToken`.
+        pin_uv_auth_param: PinUvAuthParam,
+    },
+}
+
+impl Request<'_> {
+    /// The `pinUvAuthProtocol` and `pinUvAuthParam`, or `None` for the
+    /// requests that are not authenticated.
+    pub fn pin_uv_auth(&self) -> Option<(client_pin::auth_protocol::Version, PinUvAuthParam)> {
+        match *self {
+            Request::EnrollBegin {
+                pin_uv_auth_protocol,
+                pin_uv_auth_param,
+                ..
+            }
+            | Request::EnrollCaptureNextSample {
+                pin_uv_auth_protocol,
+                pin_uv_auth_param,
+                ..
+            }
+            | Request::EnumerateEnrollments {
+                pin_uv_auth_protocol,
+                pin_uv_auth_param,
+            }
+            | Request::SetFriendlyName {
+                pin_uv_auth_protocol,
+                pin_uv_auth_param,
+                ..
+            }
+            | Request::RemoveEnrollment {
+                pin_uv_auth_protocol,
+                pin_uv_auth_param,
+                ..
+            } => Some((pin_uv_auth_protocol, pin_uv_auth_param)),
+            Request::GetModality
+            | Request::GetFingerprintSensorInfo
+            | Request::CancelCurrentEnrollment => None,
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl Request<'_> {
+    /// The message authenticated by the `pinUvAuthParam`: `modality ||
+    /// subCommand || subCommandParams`. Returns `None` for the requests that
+    /// are not authenticated.
+    pub fn authenticated_message(&self) -> Option<Vec<u8>> {
+        self.authenticated_subcommand()
+            .map(|(sub_command, params)| raw::authenticated_message(sub_command, params))
+    }
+
+    /// Verifies the `pinUvAuthParam` using the given `pinUvAuthToken`.
+    ///
+    /// Checking whether the token is in use and has the
+    /// [`client_pin::Permission::BiometricEnrollment`] permission is left to
+    /// the authenticator.
+    #[cfg(feature = "crypto")]
+    pub fn verify(&self, pin_uv_auth_token: &[u8]) -> Result<(), Error> {
+        let (Some((version, pin_uv_auth_param)), Some(message)) =
+            (self.pin_uv_auth(), self.authenticated_message())
+        else {
+            return Ok(());
+        };
+        client_pin::auth_protocol::verify(
+            version,
+            pin_uv_auth_token,
+            &message,
+            pin_uv_auth_param.as_ref(),
+        )
+        .map_err(|_| Error::PinAuthInvalid)
+    }
+}
+
+#[cfg(all(feature = "serde", feature = "crypto"))]
+impl<'a> Request<'a> {
+    pub fn enroll_begin(
+        timeout_milliseconds: Option<usize>,
+        pin_uv_auth_protocol: client_pin::auth_protocol::Version,
+        pin_uv_auth_token: &[u8],
+    ) -> Self {
+        let params = timeout_milliseconds.map(|timeout_milliseconds| raw::RawSubcommandParams {
+            timeout_milliseconds: Some(timeout_milliseconds),
+            ..Default::default()
+        });
+        Request::EnrollBegin {
+            timeout_milliseconds,
+            pin_uv_auth_protocol,
+            pin_uv_auth_param: authenticate(
+                pin_uv_auth_protocol,
+                pin_uv_auth_token,
+                raw::RawSubcommand::EnrollBegin,
+                params,
+            ),
+        }
+    }
+
+    pub fn enroll_capture_next_sample(
+        template_id: &'a [u8],
+        timeout_milliseconds: Option<usize>,
+        pin_uv_auth_protocol: client_pin::auth_protocol::Version,
+        pin_uv_auth_token: &[u8],
+    ) -> Self {
+        let params = raw::RawSubcommandParams {
+            template_id: Some(template_id.to_vec()),
+            timeout_milliseconds,
+            ..Default::default()
+        };
+        Request::EnrollCaptureNextSample {
+            template_id: Cow::Borrowed(template_id),
+            timeout_milliseconds,
+            pin_uv_auth_protocol,
+            pin_uv_auth_param: authenticate(
+                pin_uv_auth_protocol,
+                pin_uv_auth_token,
+                raw::RawSubcommand::EnrollCaptureNextSample,
+                Some(params),
+            ),
+        }
+    }
+
+    pub fn enumerate_enrollments(
+        pin_uv_auth_protocol: client_pin::auth_protocol::Version,
+        pin_uv_auth_token: &[u8],
+    ) -> Self {
+        Request::EnumerateEnrollments {
+            pin_uv_auth_protocol,
+            pin_uv_auth_param: authenticate(
+                pin_uv_auth_protocol,
+                pin_uv_auth_token,
+                raw::RawSubcommand::EnumerateEnrollments,
+                None,
+            ),
+        }
+    }
+
+    pub fn set_friendly_name(
+        template_id: &'a [u8],
+        template_friendly_name: &'a str,
+        pin_uv_auth_protocol: client_pin::auth_protocol::Version,
+        pin_uv_auth_token: &[u8],
+    ) -> Self {
+        let params = raw::RawSubcommandParams {
+            template_id: Some(template_id.to_vec()),
+            template_friendly_name: Some(Cow::Borrowed(template_friendly_name)),
+            ..Default::default()
+        };
+        Request::SetFriendlyName {
+            template_id: Cow::Borrowed(template_id),
+            template_friendly_name: Cow::Borrowed(template_friendly_name),
+            pin_uv_auth_protocol,
+            pin_uv_auth_param: authenticate(
+                pin_uv_auth_protocol,
+                pin_uv_auth_token,
+                raw::RawSubcommand::SetFriendlyName,
+                Some(params),
+            ),
+        }
+    }
+
+    pub fn remove_enrollment(
+        template_id: &'a [u8],
+        pin_uv_auth_protocol: client_pin::auth_protocol::Version,
+        pin_uv_auth_token: &[u8],
+    ) -> Self {
+        let params = raw::RawSubcommandParams {
+            template_id: Some(template_id.to_vec()),
+            ..Default::default()
+        };
+        Request::RemoveEnrollment {
+            template_id: Cow::Borrowed(template_id),
+            pin_uv_auth_protocol,
+            pin_uv_auth_param: authenticate(
+                pin_uv_auth_protocol,
+                pin_uv_auth_token,
+                raw::RawSubcommand::RemoveEnrollment,
+                Some(params),
+            ),
+        }
+    }
+}
+
+#[cfg(all(feature = "serde", feature = "crypto"))]
+fn authenticate(
+    version: client_pin::auth_protocol::Version,
+    pin_uv_auth_token: &[u8],
+    sub_command: raw::RawSubcommand,
+    params: Option<raw::RawSubcommandParams>,
+) -> PinUvAuthParam {
+    let message = raw::authenticated_message(sub_command, params);
+    client_pin::auth_protocol::authenticate(version, pin_uv_auth_token, &message)
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(into = "RawResponse", try_from = "RawResponse")
+)]
+pub enum Response {
+    GetModality {
+        modality: Modality,
+    },
+    GetFingerprintSensorInfo {
+        fingerprint_kind: FingerprintKind,
+        /// > Indicates the maximum good samples required for enrollment.
+        max_capture_samples_required_for_enroll: usize,
+        /// > Indicates the maximum number of bytes the authenticator will
+        /// > accept as a `templateFriendlyName`.
+        max_template_friendly_name: Option<usize>,
+    },
+    EnrollBegin {
+        /// > Template Identifier.
+        template_id: Vec<u8>,
+        last_enroll_sample_status: LastEnrollSampleStatus,
+        /// > Number of more sample required for enrollment to complete.
+        remaining_samples: usize,
+    },
+    EnrollCaptureNextSample {
+        last_enroll_sample_status: LastEnrollSampleStatus,
+        /// > Number of more sample required for enrollment to complete.
+        remaining_samples: usize,
+    },
+    CancelCurrentEnrollment,
+    EnumerateEnrollments {
+        template_infos: Vec<TemplateInfo>,
+    },
+    SetFriendlyName,
+    RemoveEnrollment,
+}
+
+impl Response {
+    /// Interprets the response as the one to the given request.
+    ///
+    /// The encoding of a response doesn't identify its subcommand:
+    /// `cancelCurrentEnrollment`, `setFriendlyName` and `removeEnrollment` all
+    /// return an empty response. Returns [`Error::MissingParameter`] if the
+    /// response lacks the parameters of the request's response.
+    pub fn for_request(self, request: &Request) -> Result<Self, Error> {
+        Ok(match (request, self) {
+            (Request::GetModality, response @ Response::GetModality { .. })
+            | (
+                Request::GetFingerprintSensorInfo,
+                response @ Response::GetFingerprintSensorInfo { .. },
+            )
+            | (Request::EnrollBegin { .. }, response @ Response::EnrollBegin { .. })
+            | (
+                Request::EnrollCaptureNextSample { .. },
+                response @ Response::EnrollCaptureNextSample { .. },
+            )
+            | (
+                Request::EnumerateEnrollments { .. },
+                response @ Response::EnumerateEnrollments { .. },
+            ) => response,
+            (Request::CancelCurrentEnrollment, Response::CancelCurrentEnrollment) => {
+                Response::CancelCurrentEnrollment
+            }
+            (Request::SetFriendlyName { .. }, Response::CancelCurrentEnrollment) => {
+                Response::SetFriendlyName
+            }
+            (Request::RemoveEnrollment { .. }, Response::CancelCurrentEnrollment) => {
+                Response::RemoveEnrollment
+            }
+            _ => return Err(Error::MissingParameter),
+        })
+    }
+
+    /// Whether the response is encoded without any parameters.
+    pub fn is_empty(&self) -> bool {
+        matches!(
+            self,
+            Response::CancelCurrentEnrollment
+                | Response::SetFriendlyName
+                | Response::RemoveEnrollment
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    MissingParameter,
+    InvalidParameter,
+    InvalidSubcommand,
+    UnsupportedOption,
+    /// There are no enrollments, or none with the given template ID.
+    InvalidOption,
+    PinUvAuthTokenRequired,
+    PinAuthInvalid,
+    UserActionTimeout,
+    /// The enrollment was cancelled by `cancelCurrentEnrollment`.
+    KeepaliveCancel,
+    FingerprintDatabaseFull,
+    /// Any other status code, including those defined by extensions and
+    /// vendors.
+    Other(StatusCode),
+}
+
+impl From<Error> for StatusCode {
+    fn from(value: Error) -> Self {
+        match value {
+            Error::MissingParameter => StatusCode::MissingParameter,
+            Error::InvalidParameter => StatusCode::InvalidParameter,
+            Error::InvalidSubcommand => StatusCode::InvalidSubcommand,
+            Error::UnsupportedOption => StatusCode::UnsupportedOption,
+            Error::InvalidOption => StatusCode::InvalidOption,
+            Error::PinUvAuthTokenRequired => StatusCode::PinUvAuthTokenRequired,
+            Error::PinAuthInvalid => StatusCode::PinAuthInvalid,
+            Error::UserActionTimeout => StatusCode::UserActionTimeout,
+            Error::KeepaliveCancel => StatusCode::KeepaliveCancel,
+            Error::FingerprintDatabaseFull => StatusCode::FingerprintDatabaseFull,
+            Error::Other(status) => status,
+        }
+    }
+}
+
+impl From<StatusCode> for Error {
+    fn from(value: StatusCode) -> Self {
+        match value {
+            StatusCode::MissingParameter => Error::MissingParameter,
+            StatusCode::InvalidParameter => Error::InvalidParameter,
+            StatusCode::InvalidSubcommand => Error::InvalidSubcommand,
+            StatusCode::UnsupportedOption => Error::UnsupportedOption,
+            StatusCode::InvalidOption => Error::InvalidOption,
+            StatusCode::PinUvAuthTokenRequired => Error::PinUvAuthTokenRequired,
+            StatusCode::PinAuthInvalid => Error::PinAuthInvalid,
+            StatusCode::UserActionTimeout => Error::UserActionTimeout,
+            StatusCode::KeepaliveCancel => Error::KeepaliveCancel,
+            StatusCode::FingerprintDatabaseFull => Error::FingerprintDatabaseFull,
+            status => Error::Other(status),
+        }
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let message = match self {
+            Error::MissingParameter => "Missing parameter",
+            Error::InvalidParameter => "Invalid parameter",
+            Error::InvalidSubcommand => "Invalid subcommand",
+            Error::UnsupportedOption => "Unsupported option",
+            Error::InvalidOption => "No such enrollment",
+            Error::PinUvAuthTokenRequired => "PIN/UV auth token required",
+            Error::PinAuthInvalid => "PIN auth invalid",
+            Error::UserActionTimeout => "User action timeout",
+            Error::KeepaliveCancel => "Enrollment cancelled",
+            Error::FingerprintDatabaseFull => "Fingerprint database full",
+            Error::Other(status) => return write!(f, "{}", status),
+        };
+        write!(f, "{}", message)
+    }
+}
@@ -0,0 +1,551 @@
+//! Used to make serialization and deserialization of the request and response
+//! possible in CBOR format while maintaining ergonomic enum variants for public
+//! API.
+
+use super::{
+    Error, FingerprintKind, LastEnrollSampleStatus, Modality, Request, Response, TemplateInfo,
+};
+use crate::authenticator::client_pin::{auth_protocol, PinUvAuthParam};
+use serde::{Deserialize, Serialize};
+use serde_with::{serde_as, skip_serializing_none, Bytes};
+use std::borrow::Cow;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(into = "u8", try_from = "u8")]
+pub(crate) enum RawSubcommand {
+    EnrollBegin = 0x01,
+    EnrollCaptureNextSample = 0x02,
+    CancelCurrentEnrollment = 0x03,
+    EnumerateEnrollments = 0x04,
+    SetFriendlyName = 0x05,
+    RemoveEnrollment = 0x06,
+    GetFingerprintSensorInfo = 0x07,
+}
+
+impl From<RawSubcommand> for u8 {
+    fn from(value: RawSubcommand) -> Self {
+        value as u8
+    }
+}
+
+impl TryFrom<u8> for RawSubcommand {
+    type Error = Error;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        Ok(match value {
+            0x01 => Self::EnrollBegin,
+            0x02 => Self::EnrollCaptureNextSample,
+            0x03 => Self::CancelCurrentEnrollment,
+            0x04 => Self::EnumerateEnrollments,
+            0x05 => Self::SetFriendlyName,
+            0x06 => Self::RemoveEnrollment,
+            0x07 => Self::GetFingerprintSensorInfo,
+            _ => return Err(Error::InvalidSubcommand),
+        })
+    }
+}
+
+/// The `subCommandParams` of the subcommands that take any.
+#[serde_as]
+#[skip_serializing_none]
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub(crate) struct RawSubcommandParams<'a> {
+    #[serde_as(as = "Option<Bytes>")]
+    #[serde(rename = 0x01, default)]
+    pub template_id: Option<Vec<u8>>,
+    #[serde(rename = 0x02)]
+    pub template_friendly_name: Option<Cow<'a, str>>,
+    #[serde(rename = 0x03)]
+    pub timeout_milliseconds: Option<usize>,
+}
+
+#[serde_as]
+#[skip_serializing_none]
+#[derive(Clone, Serialize, Deserialize)]
+pub(crate) struct RawRequest<'a> {
+    #[serde(rename = 0x01)]
+    pub modality: Option<Modality>,
+    #[serde(rename = 0x02)]
+    pub sub_command: Option<RawSubcommand>,
+    #[serde(rename = 0x03)]
+    pub sub_command_params: Option<RawSubcommandParams<'a>>,
+    #[serde(rename = 0x04)]
+    pub pin_uv_auth_protocol: Option<auth_protocol::Version>,
+    #[serde_as(as = "Option<Bytes>")]
+    #[serde(rename = 0x05, default)]
+    pub pin_uv_auth_param: Option<PinUvAuthParam>,
+    #[serde(rename = 0x06)]
+    pub get_modality: Option<bool>,
+}
+
+impl Request<'_> {
+    /// The subcommand and its parameters, if any, or `None` for the requests
+    /// that do not take a `pinUvAuthParam`.
+    pub(super) fn authenticated_subcommand(
+        &self,
+    ) -> Option<(RawSubcommand, Option<RawSubcommandParams<'_>>)> {
+        Some(match self {
+            Request::EnrollBegin {
+                timeout_milliseconds,
+                ..
+            } => (
+                RawSubcommand::EnrollBegin,
+                // A request without parameters has no `subCommandParams` map
+                timeout_milliseconds.map(|timeout_milliseconds| RawSubcommandParams {
+                    timeout_milliseconds: Some(timeout_milliseconds),
+                    ..Default::default()
+                }),
+            ),
+            Request::EnrollCaptureNextSample {
+                template_id,
+                timeout_milliseconds,
+                ..
+            } => (
+                RawSubcommand::EnrollCaptureNextSample,
+                Some(RawSubcommandParams {
+                    template_id: Some(template_id.to_vec()),
+                    timeout_milliseconds: *timeout_milliseconds,
+                    ..Default::default()
+                }),
+            ),
+            Request::EnumerateEnrollments { .. } => (RawSubcommand::EnumerateEnrollments, None),
+            Request::SetFriendlyName {
+                template_id,
+                template_friendly_name,
+                ..
+            } => (
+                RawSubcommand::SetFriendlyName,
+                Some(RawSubcommandParams {
+                    template_id: Some(template_id.to_vec()),
+                    template_friendly_name: Some(Cow::Borrowed(template_friendly_name)),
+                    ..Default::default()
+                }),
+            ),
+            Request::RemoveEnrollment { template_id, .. } => (
+                RawSubcommand::RemoveEnrollment,
+                Some(RawSubcommandParams {
+                    template_id: Some(template_id.to_vec()),
+                    ..Default::default()
+                }),
+            ),
+            Request::GetModality
+            | Request::GetFingerprintSensorInfo
+            | Request::CancelCurrentEnrollment => return None,
+        })
+    }
+}
+
+/// `modality || subCommand || subCommandParams`, with the parameters in their
+/// CTAP2 canonical CBOR encoding.
+pub(super) fn authenticated_message(
+    sub_command: RawSubcommand,
+    params: Option<RawSubcommandParams>,
+) -> Vec<u8> {
+    let mut message = vec![Modality::Fingerprint.into(), sub_command.into()];
+    if let Some(params) = params {
+        // Neither writing to a `Vec` nor serializing the parameters can fail
+        ciborium::ser::into_writer(&params, &mut message)
+            .expect("subCommandParams are serializable");
+    }
+    message
+}
+
+impl<'a> From<Request<'a>> for RawRequest<'a> {
+    fn from(value: Request<'a>) -> Self {
+        let (sub_command, sub_command_params) = match &value {
+            Request::GetModality => {
+                return Self {
+                    modality: None,
+                    sub_command: None,
+                    sub_command_params: None,
+                    pin_uv_auth_protocol: None,
+                    pin_uv_auth_param: None,
+                    get_modality: Some(true),
+                }
+            }
+            Request::GetFingerprintSensorInfo => (RawSubcommand::GetFingerprintSensorInfo, None),
+            Request::CancelCurrentEnrollment => (RawSubcommand::CancelCurrentEnrollment, None),
+            request => {
+                let (sub_command, params) = request
+                    .authenticated_subcommand()
+                    .expect("remaining requests are authenticated");
+                (
+                    sub_command,
+                    params.map(|params| RawSubcommandParams {
+                        template_id: params.template_id,
+                        template_friendly_name: params
+                            .template_friendly_name
+                            .map(|name| Cow::Owned(name.into_owned())),
+                        timeout_milliseconds: params.timeout_milliseconds,
+                    }),
+                )
+            }
+        };
+        let (pin_uv_auth_protocol, pin_uv_auth_param) = value.pin_uv_auth().unzip();
+        Self {
+            modality: Some(Modality::Fingerprint),
+            sub_command: Some(sub_command),
+            sub_command_params,
+            pin_uv_auth_protocol,
+            pin_uv_auth_param,
+            get_modality: None,
+        }
+    }
+}
+
+impl<'a> TryFrom<RawRequest<'a>> for Request<'a> {
+    type Error = Error;
+
+    fn try_from(value: RawRequest<'a>) -> Result<Self, Self::Error> {
+        let RawRequest {
+            modality,
+            sub_command,
+            sub_command_params,
+            pin_uv_auth_protocol,
+            pin_uv_auth_param,
+            get_modality,
+        } = value;
+
+        if get_modality == Some(true) {
+            return Ok(Request::GetModality);
+        }
+        let (Some(Modality::Fingerprint), Some(sub_command)) = (modality, sub_command) else {
+            return Err(Error::MissingParameter);
+        };
+        let RawSubcommandParams {
+            template_id,
+            template_friendly_name,
+            timeout_milliseconds,
+        } = sub_command_params.unwrap_or_default();
+        let pin_uv_auth = || match (pin_uv_auth_protocol, pin_uv_auth_param) {
+            (Some(protocol), Some(param)) => Ok((protocol, param)),
+            (_, None) => Err(Error::PinUvAuthTokenRequired),
+            (None, Some(_)) => Err(Error::MissingParameter),
+        };
+
+        Ok(match sub_command {
+            RawSubcommand::GetFingerprintSensorInfo => Request::GetFingerprintSensorInfo,
+            RawSubcommand::CancelCurrentEnrollment => Request::CancelCurrentEnrollment,
+            RawSubcommand::EnrollBegin => {
+                let (pin_uv_auth_protocol, pin_uv_auth_param) = pin_uv_auth()?;
+                Request::EnrollBegin {
+                    timeout_milliseconds,
+                    pin_uv_auth_protocol,
+                    pin_uv_auth_param,
+                }
+            }
+            RawSubcommand::EnrollCaptureNextSample => {
+                let template_id = template_id.ok_or(Error::MissingParameter)?;
+                let (pin_uv_auth_protocol, pin_uv_auth_param) = pin_uv_auth()?;
+                Request::EnrollCaptureNextSample {
+                    template_id: Cow::Owned(template_id),
+                    timeout_milliseconds,
+                    pin_uv_auth_protocol,
+                    pin_uv_auth_param,
+                }
+            }
+            RawSubcommand::EnumerateEnrollments => {
+                let (pin_uv_auth_protocol, pin_uv_auth_param) = pin_uv_auth()?;
+                Request::EnumerateEnrollments {
+                    pin_uv_auth_protocol,
+                    pin_uv_auth_param,
+                }
+            }
+            RawSubcommand::SetFriendlyName => {
+                let (Some(template_id), Some(template_friendly_name)) =
+                    (template_id, template_friendly_name)
+                else {
+                    return Err(Error::MissingParameter);
+                };
+                let (pin_uv_auth_protocol, pin_uv_auth_param) = pin_uv_auth()?;
+                Request::SetFriendlyName {
+                    template_id: Cow::Owned(template_id),
+                    template_friendly_name,
+                    pin_uv_auth_protocol,
+                    pin_uv_auth_param,
+                }
+            }
+            RawSubcommand::RemoveEnrollment => {
+                let template_id = template_id.ok_or(Error::MissingParameter)?;
+                let (pin_uv_auth_protocol, pin_uv_auth_param) = pin_uv_auth()?;
+                Request::RemoveEnrollment {
+                    template_id: Cow::Owned(template_id),
+                    pin_uv_auth_protocol,
+                    pin_uv_auth_param,
+                }
+            }
+        })
+    }
+}
+
+#[serde_as]
+#[skip_serializing_none]
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub(crate) struct RawResponse {
+    #[serde(rename = 0x01)]
+    pub modality: Option<Modality>,
+    #[serde(rename = 0x02)]
+    pub fingerprint_kind: Option<FingerprintKind>,
+    #[serde(rename = 0x03)]
+    pub max_capture_samples_required_for_enroll: Option<usize>,
+    #[serde_as(as = "Option<Bytes>")]
+    #[serde(rename = 0x04, default)]
+    pub template_id: Option<Vec<u8>>,
+    #[serde(rename = 0x05)]
+    pub last_enroll_sample_status: Option<LastEnrollSampleStatus>,
+    #[serde(rename = 0x06)]
+    pub remaining_samples: Option<usize>,
+    #[serde(rename = 0x07)]
+    pub template_infos: Option<Vec<TemplateInfo>>,
+    #[serde(rename = 0x08)]
+    pub max_template_friendly_name: Option<usize>,
+}
+
+impl From<Response> for RawResponse {
+    fn from(value: Response) -> Self {
+        match value {
+            Response::GetModality { modality } => Self {
+                modality: Some(modality),
+                ..Default::default()
+            },
+            Response::GetFingerprintSensorInfo {
+                fingerprint_kind,
+                max_capture_samples_required_for_enroll,
+                max_template_friendly_name,
+            } => Self {
+                modality: Some(Modality::Fingerprint),
+                fingerprint_kind: Some(fingerprint_kind),
+                max_capture_samples_required_for_enroll: Some(
+                    max_capture_samples_required_for_enroll,
+                ),
+                max_template_friendly_name,
+                ..Default::default()
+            },
+            Response::EnrollBegin {
+                template_id,
+                last_enroll_sample_status,
+                remaining_samples,
+            } => Self {
+                template_id: Some(template_id),
+                last_enroll_sample_status: Some(last_enroll_sample_status),
+                remaining_samples: Some(remaining_samples),
+                ..Default::default()
+            },
+            Response::EnrollCaptureNextSample {
+                last_enroll_sample_status,
+                remaining_samples,
+            } => Self {
+                last_enroll_sample_status: Some(last_enroll_sample_status),
+                remaining_samples: Some(remaining_samples),
+                ..Default::default()
+            },
+            Response::EnumerateEnrollments { template_infos } => Self {
+                template_infos: Some(template_infos),
+                ..Default::default()
+            },
+            Response::CancelCurrentEnrollment
+            | Response::SetFriendlyName
+            | Response::RemoveEnrollment => Self::default(),
+        }
+    }
+}
+
+impl TryFrom<RawResponse> for Response {
+    type Error = Error;
+
+    /// Responses don't identify the subcommand they belong to, so an empty
+    /// response is read as [`Response::CancelCurrentEnrollment`]. See
+    /// [`Response::for_request`].
+    fn try_from(value: RawResponse) -> Result<Self, Self::Error> {
+        Ok(match value {
+            RawResponse {
+                fingerprint_kind: Some(fingerprint_kind),
+                max_capture_samples_required_for_enroll,
+                max_template_friendly_name,
+                ..
+            } => Response::GetFingerprintSensorInfo {
+                fingerprint_kind,
+                max_capture_samples_required_for_enroll: max_capture_samples_required_for_enroll
+                    .ok_or(Error::MissingParameter)?,
+                max_template_friendly_name,
+            },
+            RawResponse {
+                modality: Some(modality),
+                ..
+            } => Response::GetModality { modality },
+            RawResponse {
+                template_id,
+                last_enroll_sample_status: Some(last_enroll_sample_status),
+                remaining_samples,
+                ..
+            } => {
+                let remaining_samples = remaining_samples.ok_or(Error::MissingParameter)?;
+                match template_id {
+                    Some(template_id) => Response::EnrollBegin {
+                        template_id,
+                        last_enroll_sample_status,
+                        remaining_samples,
+                    },
+                    None => Response::EnrollCaptureNextSample {
+                        last_enroll_sample_status,
+                        remaining_samples,
+                    },
+                }
+            }
+            RawResponse {
+                template_infos: Some(template_infos),
+                ..
+            } => Response::EnumerateEnrollments { template_infos },
+            RawResponse {
+                template_id: None,
+                remaining_samples: None,
+                ..
+            } => Response::CancelCurrentEnrollment,
+            _ => return Err(Error::MissingParameter),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode<T: Serialize>(value: &T) -> Vec<u8> {
+        let mut data = Vec::new();
+        ciborium::ser::into_writer(value, &mut data).unwrap();
+        data
+    }
+
+    fn decode(data: &[u8]) -> Result<Request<'static>, Error> {
+        let raw: RawRequest = ciborium::de::from_reader(data).unwrap();
+        raw.try_into()
+    }
+
+    #[test]
+    fn get_modality() {
+        let encoded = encode(&Request::GetModality);
+        assert_eq!(encoded, [0xA1, 0x06, 0xF5]);
+        assert_eq!(decode(&encoded), Ok(Request::GetModality));
+    }
+
+    #[test]
+    fn get_fingerprint_sensor_info() {
+        let encoded = encode(&Request::GetFingerprintSensorInfo);
+        assert_eq!(encoded, [0xA2, 0x01, 0x01, 0x02, 0x07]);
+        assert_eq!(decode(&encoded), Ok(Request::GetFingerprintSensorInfo));
+    }
+
+    #[test]
+    fn set_friendly_name_round_trip() {
+        let request = Request::SetFriendlyName {
+            template_id: Cow::Borrowed(&[0x01, 0x02]),
+            template_friendly_name: Cow::Borrowed("Left thumb"),
+            pin_uv_auth_protocol: auth_protocol::Version::Two,
+            pin_uv_auth_param: PinUvAuthParam::Long([0x42; 32]),
+        };
+        assert_eq!(decode(&encode(&request)), Ok(request));
+    }
+
+    #[test]
+    fn missing_template_id() {
+        let request = encode(&RawRequest {
+            modality: Some(Modality::Fingerprint),
+            sub_command: Some(RawSubcommand::RemoveEnrollment),
+            sub_command_params: None,
+            pin_uv_auth_protocol: Some(auth_protocol::Version::One),
+            pin_uv_auth_param: Some(PinUvAuthParam::Short([0x42; 16])),
+            get_modality: None,
+        });
+        assert_eq!(decode(&request), Err(Error::MissingParameter));
+    }
+
+    #[test]
+    fn enroll_capture_next_sample_message() {
+        let message = authenticated_message(
+            RawSubcommand::EnrollCaptureNextSample,
+            Some(RawSubcommandParams {
+                template_id: Some(vec![0xAA, 0xBB]),
+                timeout_milliseconds: Some(10000),
+                ..Default::default()
+            }),
+        );
+        let expected = [
+            0x01, 0x02, 0xA2, 0x01, 0x42, 0xAA, 0xBB, 0x03, 0x19, 0x27, 0x10,
+        ];
+        assert_eq!(message, expected);
+    }
+
+    #[test]
+    fn enroll_begin_without_timeout() {
+        let request = Request::EnrollBegin {
+            timeout_milliseconds: None,
+            pin_uv_auth_protocol: auth_protocol::Version::Two,
+            pin_uv_auth_param: PinUvAuthParam::Long([0x42; 32]),
+        };
+        // No `subCommandParams` map is sent or authenticated
+        assert_eq!(request.authenticated_message(), Some(vec![0x01, 0x01]));
+        let encoded = encode(&request);
+        assert_eq!(encoded[..5], [0xA4, 0x01, 0x01, 0x02, 0x01]);
+        assert_eq!(decode(&encoded), Ok(request));
+
+        let request = Request::EnrollBegin {
+            timeout_milliseconds: Some(10000),
+            pin_uv_auth_protocol: auth_protocol::Version::Two,
+            pin_uv_auth_param: PinUvAuthParam::Long([0x42; 32]),
+        };
+        assert_eq!(
+            request.authenticated_message(),
+            Some(vec![0x01, 0x01, 0xA1, 0x03, 0x19, 0x27, 0x10])
+        );
+    }
+
+    #[test]
+    fn last_enroll_sample_status() {
+        for status in 0..=u8::MAX {
+            assert_eq!(u8::from(LastEnrollSampleStatus::from(status)), status);
+        }
+        assert_eq!(
+            LastEnrollSampleStatus::from(0x0C),
+            LastEnrollSampleStatus::Other(0x0C)
+        );
+    }
+
+    #[test]
+    fn responses() {
+        let response = |response: Response| {
+            let raw: RawResponse = ciborium::de::from_reader(encode(&response).as_slice()).unwrap();
+            Response::try_from(raw)
+        };
+        let enroll_begin = Response::EnrollBegin {
+            template_id: vec![0x01],
+            last_enroll_sample_status: LastEnrollSampleStatus::TooFast,
+            remaining_samples: 4,
+        };
+        assert_eq!(response(enroll_begin.clone()), Ok(enroll_begin));
+        let sensor_info = Response::GetFingerprintSensorInfo {
+            fingerprint_kind: FingerprintKind::Touch,
+            max_capture_samples_required_for_enroll: 5,
+            max_template_friendly_name: Some(64),
+        };
+        assert_eq!(response(sensor_info.clone()), Ok(sensor_info));
+        assert_eq!(
+            response(Response::RemoveEnrollment),
+            Ok(Response::CancelCurrentEnrollment)
+        );
+    }
+
+    #[cfg(feature = "crypto")]
+    #[test]
+    fn verify() {
+        let token = [0x42; 32];
+        for version in [auth_protocol::Version::One, auth_protocol::Version::Two] {
+            let request = Request::remove_enrollment(&[0x01], version, &token);
+            assert_eq!(request.verify(&token), Ok(()));
+            assert_eq!(request.verify(&[0x43; 32]), Err(Error::PinAuthInvalid));
+            for timeout_milliseconds in [None, Some(10000)] {
+                let request = Request::enroll_begin(timeout_milliseconds, version, &token);
+                assert_eq!(request.verify(&token), Ok(()));
+            }
+        }
+    }
+}
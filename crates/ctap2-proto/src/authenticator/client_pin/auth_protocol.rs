@@ -3,6 +3,14 @@ use serde::{Deserialize, Serialize};
 
 use std::marker::ConstParamTy;
 
+#[cfg(feature = "crypto")]
+mod crypto;
+
+#[cfg(feature = "crypto")]
+pub use crypto::{
+    authenticate, verify, AuthenticatorProtocol, KeyAgreementKey, PlatformSession, SharedSecret,
+};
+
 #[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, ConstParamTy)]
 #[cfg_attr(
     feature = "serde",
@@ -57,9 +65,15 @@ pub mod authenticator {
         /// shared secret, known to both platform and authenticator.
         fn decapsulate(&self, peer_cose_key: cosey::PublicKey) -> Result<Vec<u8>, Self::Error>;
 
+        /// Encrypts a plaintext, using sharedSecret as a key, to produce a
+        /// ciphertext, which may be longer than the plaintext. The plaintext
+        /// is restricted to being a multiple of the AES block size (16 bytes)
+        /// in length.
+        fn encrypt(&self, shared_secret: &[u8], plaintext: &[u8]) -> Result<Vec<u8>, Self::Error>;
+
         /// Decrypts a ciphertext, using sharedSecret as a key, and returns the
         /// plaintext.
-        fn decrypt(&self, ciphertext: &[u8]) -> Result<Vec<u8>, Self::Error>;
+        fn decrypt(&self, shared_secret: &[u8], ciphertext: &[u8]) -> Result<Vec<u8>, Self::Error>;
 
         /// Verifies that the signature is a valid MAC for the given message. If
         /// the key parameter value is the current pinUvAuthToken, it
@@ -69,7 +83,8 @@ pub mod authenticator {
 }
 
 pub mod platform {
-    use super::{Version, BLOCK_SIZE};
+    use super::Version;
+    use crate::authenticator::client_pin::PinUvAuthParam;
 
     pub trait Session<const VERSION: Version>: Sized {
         type Error; // TODO: Can the error cases be enumerated here?
@@ -86,20 +101,13 @@ pub mod platform {
         /// Encrypts a plaintext to produce a ciphertext, which may be longer
         /// than the plaintext. The plaintext is restricted to being a
         /// multiple of the AES block size (16 bytes) in length.
-        fn encrypt<const N: usize>(
-            &self,
-            plaintext: &[[u8; BLOCK_SIZE]; N],
-        ) -> Result<[[u8; BLOCK_SIZE]; N], Self::Error>;
+        fn encrypt(&self, plaintext: &[u8]) -> Result<Vec<u8>, Self::Error>;
 
         /// Decrypts a ciphertext and returns the plaintext.
         // TODO: Return a specific type instead of raw bytes?
-        fn decrypt<const N: usize>(
-            &self,
-            ciphertext: &[[u8; BLOCK_SIZE]; N],
-        ) -> [[u8; BLOCK_SIZE]; N];
+        fn decrypt(&self, ciphertext: &[u8]) -> Result<Vec<u8>, Self::Error>;
 
         /// Computes a MAC of the given message.
-        // TODO: Return a specific type instead of raw bytes?
-        fn authenticate(&self, message: &[u8]) -> Result<[u8; 16], Self::Error>;
+        fn authenticate(&self, message: &[u8]) -> Result<PinUvAuthParam, Self::Error>;
     }
 }
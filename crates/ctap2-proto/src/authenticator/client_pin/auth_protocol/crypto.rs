@@ -0,0 +1,566 @@
+//! Implementations of PIN/UV auth protocols one and two.
+
+use super::{authenticator, platform, Version, BLOCK_SIZE};
+use crate::authenticator::client_pin::{Error, PinUvAuthParam};
+use aes::Aes256;
+use cbc::cipher::{block_padding::NoPadding, BlockDecryptMut, BlockEncryptMut, KeyIvInit};
+use hkdf::Hkdf;
+use hmac::{Hmac, Mac};
+use p256::elliptic_curve::sec1::{FromEncodedPoint, ToEncodedPoint};
+use rand::{rngs::OsRng, RngCore};
+use sha2::{Digest, Sha256};
+
+/// The length of keys derived from the shared point, and of
+/// `pinUvAuthToken`s.
+const KEY_SIZE: usize = 32;
+
+/// A P-256 key agreement key, used by authenticators for as long as they are
+/// powered and by platforms for a single transaction.
+pub struct KeyAgreementKey(p256::SecretKey);
+
+impl KeyAgreementKey {
+    pub fn generate() -> Self {
+        Self(p256::SecretKey::random(&mut OsRng))
+    }
+
+    /// Returns `None` if the scalar is zero or not less than the curve order.
+    pub fn from_bytes(bytes: &[u8; 32]) -> Option<Self> {
+        p256::SecretKey::from_bytes(bytes.into()).ok().map(Self)
+    }
+
+    /// The public key as a COSE_Key, as returned by `getPublicKey` and sent
+    /// by platforms as their `keyAgreement`.
+    pub fn public_key(&self) -> cosey::PublicKey {
+        let point = self.0.public_key().to_encoded_point(false);
+        let coordinate = |bytes: Option<&p256::FieldBytes>| {
+            bytes
+                .and_then(|bytes| cosey::Bytes::from_slice(bytes).ok())
+                .unwrap_or_default()
+        };
+        cosey::PublicKey::EcdhEsHkdf256Key(cosey::EcdhEsHkdf256PublicKey {
+            x: coordinate(point.x()),
+            y: coordinate(point.y()),
+        })
+    }
+
+    /// > Processes the output of encapsulate from the peer and produces a
+    /// > shared secret, known to both platform and authenticator.
+    ///
+    /// Returns [`Error::InvalidParameter`] if the peer's key is not a P-256
+    /// point.
+    pub fn shared_secret(
+        &self,
+        version: Version,
+        peer_cose_key: &cosey::PublicKey,
+    ) -> Result<SharedSecret, Error> {
+        let (x, y) = match peer_cose_key {
+            cosey::PublicKey::EcdhEsHkdf256Key(key) => (&key.x, &key.y),
+            cosey::PublicKey::P256Key(key) => (&key.x, &key.y),
+            _ => return Err(Error::InvalidParameter),
+        };
+        if x.len() != 32 || y.len() != 32 {
+            return Err(Error::InvalidParameter);
+        }
+        let point = p256::EncodedPoint::from_affine_coordinates(
+            p256::FieldBytes::from_slice(x),
+            p256::FieldBytes::from_slice(y),
+            false,
+        );
+        let peer = Option::<p256::PublicKey>::from(p256::PublicKey::from_encoded_point(&point))
+            .ok_or(Error::InvalidParameter)?;
+
+        let z = p256::ecdh::diffie_hellman(self.0.to_nonzero_scalar(), peer.as_affine());
+        Ok(SharedSecret::derive(version, z.raw_secret_bytes()))
+    }
+}
+
+/// The keys derived from the x-coordinate of the shared point.
+#[derive(Clone)]
+pub struct SharedSecret {
+    version: Version,
+    hmac_key: [u8; KEY_SIZE],
+    aes_key: [u8; KEY_SIZE],
+}
+
+impl SharedSecret {
+    /// Protocol one uses `SHA-256(Z)` as both keys, while protocol two derives
+    /// separate keys with HKDF-SHA-256.
+    fn derive(version: Version, z: &[u8]) -> Self {
+        match version {
+            Version::One => {
+                let key: [u8; KEY_SIZE] = Sha256::digest(z).into();
+                Self {
+                    version,
+                    hmac_key: key,
+                    aes_key: key,
+                }
+            }
+            Version::Two => {
+                let hkdf = Hkdf::<Sha256>::new(Some(&[0; 32]), z);
+                let mut hmac_key = [0; KEY_SIZE];
+                let mut aes_key = [0; KEY_SIZE];
+                // Both outputs are well within the maximum HKDF-SHA-256 length
+                let _ = hkdf.expand(b"CTAP2 HMAC key", &mut hmac_key);
+                let _ = hkdf.expand(b"CTAP2 AES key", &mut aes_key);
+                Self {
+                    version,
+                    hmac_key,
+                    aes_key,
+                }
+            }
+        }
+    }
+
+    /// Reads a shared secret in the form returned by
+    /// [`SharedSecret::to_bytes`].
+    pub fn from_bytes(version: Version, bytes: &[u8]) -> Result<Self, Error> {
+        let key = |bytes: &[u8]| -> Result<[u8; KEY_SIZE], Error> {
+            bytes.try_into().map_err(|_| Error::InvalidParameter)
+        };
+        match (version, bytes.len()) {
+            (Version::One, KEY_SIZE) => Ok(Self {
+                version,
+                hmac_key: key(bytes)?,
+                aes_key: key(bytes)?,
+            }),
+            (Version::Two, 64) => Ok(Self {
+                version,
+                hmac_key: key(&bytes[..KEY_SIZE])?,
+                aes_key: key(&bytes[KEY_SIZE..])?,
+            }),
+            _ => Err(Error::InvalidParameter),
+        }
+    }
+
+    /// The shared secret as defined by the protocol: the single 32 byte key
+    /// of protocol one, or the HMAC key followed by the AES key for protocol
+    /// two.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        match self.version {
+            Version::One => self.aes_key.to_vec(),
+            Version::Two => [self.hmac_key, self.aes_key].concat(),
+        }
+    }
+
+    pub fn version(&self) -> Version {
+        self.version
+    }
+
+    /// > Encrypts a plaintext to produce a ciphertext, which may be longer
+    /// > than the plaintext. The plaintext is restricted to being a multiple
+    /// > of the AES block size (16 bytes) in length.
+    ///
+    /// Protocol two prepends a random IV to the ciphertext.
+    pub fn encrypt(&self, plaintext: &[u8]) -> Result<Vec<u8>, Error> {
+        let mut iv = [0; BLOCK_SIZE];
+        if self.version == Version::Two {
+            OsRng.fill_bytes(&mut iv);
+        }
+        self.encrypt_with_iv(iv, plaintext)
+    }
+
+    fn encrypt_with_iv(&self, iv: [u8; BLOCK_SIZE], plaintext: &[u8]) -> Result<Vec<u8>, Error> {
+        if plaintext.len() % BLOCK_SIZE != 0 {
+            return Err(Error::InvalidParameter);
+        }
+
+        let mut ciphertext = plaintext.to_vec();
+        cbc::Encryptor::<Aes256>::new(&self.aes_key.into(), &iv.into())
+            .encrypt_padded_mut::<NoPadding>(&mut ciphertext, plaintext.len())
+            .map_err(|_| Error::InvalidParameter)?;
+
+        Ok(match self.version {
+            Version::One => ciphertext,
+            Version::Two => [iv.as_slice(), &ciphertext].concat(),
+        })
+    }
+
+    /// > Decrypts a ciphertext and returns the plaintext.
+    ///
+    /// Returns [`Error::InvalidParameter`] if the length of the ciphertext is
+    /// invalid.
+    pub fn decrypt(&self, ciphertext: &[u8]) -> Result<Vec<u8>, Error> {
+        let (iv, ciphertext) = match self.version {
+            Version::One => ([0; BLOCK_SIZE], ciphertext),
+            Version::Two => {
+                if ciphertext.len() < BLOCK_SIZE {
+                    return Err(Error::InvalidParameter);
+                }
+                let (iv, ciphertext) = ciphertext.split_at(BLOCK_SIZE);
+                let mut block = [0; BLOCK_SIZE];
+                block.copy_from_slice(iv);
+                (block, ciphertext)
+            }
+        };
+        if ciphertext.len() % BLOCK_SIZE != 0 {
+            return Err(Error::InvalidParameter);
+        }
+
+        let mut plaintext = ciphertext.to_vec();
+        cbc::Decryptor::<Aes256>::new(&self.aes_key.into(), &iv.into())
+            .decrypt_padded_mut::<NoPadding>(&mut plaintext)
+            .map_err(|_| Error::InvalidParameter)?;
+        Ok(plaintext)
+    }
+
+    /// > Computes a MAC of the given message.
+    pub fn authenticate(&self, message: &[u8]) -> PinUvAuthParam {
+        authenticate(self.version, &self.hmac_key, message)
+    }
+
+    /// Verifies a `pinUvAuthParam` computed with the shared secret.
+    pub fn verify(&self, message: &[u8], signature: &[u8]) -> Result<(), Error> {
+        verify(self.version, &self.hmac_key, message, signature)
+    }
+}
+
+/// > Computes a MAC of the given message.
+///
+/// The MAC is the first 16 bytes of an HMAC-SHA-256 for protocol one, and all
+/// 32 bytes for protocol two. Protocol two only uses the first 32 bytes of
+/// the key.
+pub fn authenticate(version: Version, key: &[u8], message: &[u8]) -> PinUvAuthParam {
+    let mac = hmac(version, key, message).finalize().into_bytes();
+    match version {
+        Version::One => {
+            let mut short = [0; 16];
+            short.copy_from_slice(&mac[..16]);
+            PinUvAuthParam::Short(short)
+        }
+        Version::Two => PinUvAuthParam::Long(mac.into()),
+    }
+}
+
+/// > Verifies that the signature is a valid MAC for the given message.
+///
+/// Returns [`Error::PinAuthInvalid`] if it isn't.
+pub fn verify(version: Version, key: &[u8], message: &[u8], signature: &[u8]) -> Result<(), Error> {
+    let mac = hmac(version, key, message);
+    let verified = match (version, signature.len()) {
+        (Version::One, 16) => mac.verify_truncated_left(signature),
+        (Version::Two, 32) => mac.verify_slice(signature),
+        _ => return Err(Error::PinAuthInvalid),
+    };
+    verified.map_err(|_| Error::PinAuthInvalid)
+}
+
+fn hmac(version: Version, key: &[u8], message: &[u8]) -> Hmac<Sha256> {
+    let key = match version {
+        Version::One => key,
+        Version::Two => &key[..key.len().min(KEY_SIZE)],
+    };
+    let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(key).expect("HMAC accepts any key length");
+    mac.update(message);
+    mac
+}
+
+/// The authenticator's state for one PIN/UV auth protocol.
+pub struct AuthenticatorProtocol<const VERSION: Version> {
+    key_agreement_key: KeyAgreementKey,
+    pin_uv_auth_token: [u8; KEY_SIZE],
+    in_use: bool,
+}
+
+impl<const VERSION: Version> AuthenticatorProtocol<VERSION> {
+    /// Creates the protocol state, as at power-on.
+    pub fn new() -> Self {
+        let mut token = [0; KEY_SIZE];
+        OsRng.fill_bytes(&mut token);
+        Self {
+            key_agreement_key: KeyAgreementKey::generate(),
+            pin_uv_auth_token: token,
+            in_use: false,
+        }
+    }
+
+    pub fn pin_uv_auth_token(&self) -> &[u8; KEY_SIZE] {
+        &self.pin_uv_auth_token
+    }
+
+    /// Allows the `pinUvAuthToken` to be verified, after it is issued to a
+    /// platform.
+    pub fn begin_using_pin_uv_auth_token(&mut self) {
+        self.in_use = true;
+    }
+
+    pub fn stop_using_pin_uv_auth_token(&mut self) {
+        self.in_use = false;
+    }
+}
+
+impl<const VERSION: Version> Default for AuthenticatorProtocol<VERSION> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const VERSION: Version> authenticator::Authenticator for AuthenticatorProtocol<VERSION> {
+    type Error = Error;
+    const VERSION: Version = VERSION;
+
+    fn initialize(&mut self) -> Result<(), Self::Error> {
+        self.regenerate()?;
+        self.reset_pin_uv_auth_token()
+    }
+
+    fn regenerate(&mut self) -> Result<(), Self::Error> {
+        self.key_agreement_key = KeyAgreementKey::generate();
+        Ok(())
+    }
+
+    fn reset_pin_uv_auth_token(&mut self) -> Result<(), Self::Error> {
+        OsRng.fill_bytes(&mut self.pin_uv_auth_token);
+        self.in_use = false;
+        Ok(())
+    }
+
+    fn get_public_key(&self) -> Result<cosey::PublicKey, Self::Error> {
+        Ok(self.key_agreement_key.public_key())
+    }
+
+    fn decapsulate(&self, peer_cose_key: cosey::PublicKey) -> Result<Vec<u8>, Self::Error> {
+        self.key_agreement_key
+            .shared_secret(VERSION, &peer_cose_key)
+            .map(|shared_secret| shared_secret.to_bytes())
+    }
+
+    fn encrypt(&self, shared_secret: &[u8], plaintext: &[u8]) -> Result<Vec<u8>, Self::Error> {
+        SharedSecret::from_bytes(VERSION, shared_secret)?.encrypt(plaintext)
+    }
+
+    fn decrypt(&self, shared_secret: &[u8], ciphertext: &[u8]) -> Result<Vec<u8>, Self::Error> {
+        SharedSecret::from_bytes(VERSION, shared_secret)?.decrypt(ciphertext)
+    }
+
+    fn verify(&self, key: &[u8], message: &[u8], signature: &[u8]) -> Result<(), Self::Error> {
+        if key == self.pin_uv_auth_token && !self.in_use {
+            return Err(Error::PinAuthInvalid);
+        }
+        verify(VERSION, key, message, signature)
+    }
+}
+
+/// A platform's session with an authenticator, using a fresh key agreement
+/// key.
+pub struct PlatformSession<const VERSION: Version> {
+    platform_key_agreement_key: cosey::PublicKey,
+    shared_secret: SharedSecret,
+}
+
+impl<const VERSION: Version> PlatformSession<VERSION> {
+    pub fn shared_secret(&self) -> &SharedSecret {
+        &self.shared_secret
+    }
+}
+
+impl<const VERSION: Version> platform::Session<VERSION> for PlatformSession<VERSION> {
+    type Error = Error;
+
+    fn initialize(peer_cose_key: cosey::PublicKey) -> Result<Self, Self::Error> {
+        let key_agreement_key = KeyAgreementKey::generate();
+        Ok(Self {
+            platform_key_agreement_key: key_agreement_key.public_key(),
+            shared_secret: key_agreement_key.shared_secret(VERSION, &peer_cose_key)?,
+        })
+    }
+
+    fn platform_key_agreement_key(&self) -> &cosey::PublicKey {
+        &self.platform_key_agreement_key
+    }
+
+    fn encrypt(&self, plaintext: &[u8]) -> Result<Vec<u8>, Self::Error> {
+        self.shared_secret.encrypt(plaintext)
+    }
+
+    fn decrypt(&self, ciphertext: &[u8]) -> Result<Vec<u8>, Self::Error> {
+        self.shared_secret.decrypt(ciphertext)
+    }
+
+    fn authenticate(&self, message: &[u8]) -> Result<PinUvAuthParam, Self::Error> {
+        Ok(self.shared_secret.authenticate(message))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use authenticator::Authenticator;
+    use platform::Session;
+
+    // Known answers computed independently with the Python `cryptography`
+    // package, for the key agreement keys with scalars 0x0101...01 and
+    // 0x0202...02.
+
+    const PLATFORM_X: &str = "550f471003f3df97c3df506ac797f6721fb1a1fb7b8f6f83d224498a65c88e24";
+    const PLATFORM_Y: &str = "136093d7012e509a73715cbd0b00a3cc0ff4b5c01b3ffa196ab1fb327036b8e6";
+    const SHARED_SECRET_ONE: &str =
+        "eea14853f0d2996406327d017b3a083c6a5602e7dd43fe71e50d8200f3cc4dc4";
+    const SHARED_SECRET_TWO: &str = "1697f446d0f999cb5c8412e6860ae8d9b1906a602d2d9a29c1e8a82aa0603ff5\
+                                     6d5d9935f17b2b28e5e3d287797094cf27519327f199e2f23f257428f5e559d5";
+    const CIPHERTEXT_ONE: &str = "5f468b3f91d5f68d0182007347ec276da4fdf7d1ea57e733271004b9ccc97a75\
+                                  410fd3fcfe9a16ea03a50cd9b0da842df6765ca94ec9cc34e7b748e75120201a";
+    const CIPHERTEXT_TWO: &str = "a5a5a5a5a5a5a5a5a5a5a5a5a5a5a5a5\
+                                  2eb5cdd7d0560353cfbbc7c75576893ca97513ea5ca23a2a5c9fc51640ac9f4e\
+                                  58562c0e6d9102d97e841e0055a4b43d5d720f159b4601693167001c46df7a15";
+    const MAC_ONE: &str = "ab9456075eaa60a9a351d0bba85beaa5";
+    const MAC_TWO: &str = "701dc0e4fc6bce42964f1b8c5e9eb8a6f78e281e878791cd6405370dce8e5b56";
+
+    fn authenticator_key() -> KeyAgreementKey {
+        KeyAgreementKey::from_bytes(&[0x01; 32]).unwrap()
+    }
+
+    fn platform_key() -> KeyAgreementKey {
+        KeyAgreementKey::from_bytes(&[0x02; 32]).unwrap()
+    }
+
+    fn plaintext() -> Vec<u8> {
+        (0..64).collect()
+    }
+
+    /// A `pinUvAuthParam` message: 32 bytes of 0xFF, a subcommand and a
+    /// protocol version.
+    fn message() -> Vec<u8> {
+        [[0xFF; 32].as_slice(), &[0x06, 0x03]].concat()
+    }
+
+    fn bytes(hex: &str) -> Vec<u8> {
+        hex::decode(hex).unwrap()
+    }
+
+    #[test]
+    fn public_key() {
+        let cosey::PublicKey::EcdhEsHkdf256Key(key) = platform_key().public_key() else {
+            panic!("expected an ECDH-ES+HKDF-256 key");
+        };
+        assert_eq!(&*key.x, bytes(PLATFORM_X));
+        assert_eq!(&*key.y, bytes(PLATFORM_Y));
+    }
+
+    #[test]
+    fn protocol_one() {
+        let shared_secret = authenticator_key()
+            .shared_secret(Version::One, &platform_key().public_key())
+            .unwrap();
+        assert_eq!(shared_secret.to_bytes(), bytes(SHARED_SECRET_ONE));
+
+        // Both sides derive the same secret
+        let peer = platform_key()
+            .shared_secret(Version::One, &authenticator_key().public_key())
+            .unwrap();
+        assert_eq!(peer.to_bytes(), shared_secret.to_bytes());
+
+        let ciphertext = shared_secret.encrypt(&plaintext()).unwrap();
+        assert_eq!(ciphertext, bytes(CIPHERTEXT_ONE));
+        assert_eq!(shared_secret.decrypt(&ciphertext).unwrap(), plaintext());
+
+        let mac = shared_secret.authenticate(&message());
+        assert_eq!(
+            mac,
+            PinUvAuthParam::Short(bytes(MAC_ONE).try_into().unwrap())
+        );
+        assert_eq!(shared_secret.verify(&message(), mac.as_ref()), Ok(()));
+    }
+
+    #[test]
+    fn protocol_two() {
+        let shared_secret = authenticator_key()
+            .shared_secret(Version::Two, &platform_key().public_key())
+            .unwrap();
+        assert_eq!(shared_secret.to_bytes(), bytes(SHARED_SECRET_TWO));
+
+        let ciphertext = shared_secret
+            .encrypt_with_iv([0xA5; BLOCK_SIZE], &plaintext())
+            .unwrap();
+        assert_eq!(ciphertext, bytes(CIPHERTEXT_TWO));
+        assert_eq!(shared_secret.decrypt(&ciphertext).unwrap(), plaintext());
+
+        // A random IV is used otherwise
+        let ciphertext = shared_secret.encrypt(&plaintext()).unwrap();
+        assert_eq!(ciphertext.len(), BLOCK_SIZE + plaintext().len());
+        assert_eq!(shared_secret.decrypt(&ciphertext).unwrap(), plaintext());
+
+        let mac = shared_secret.authenticate(&message());
+        assert_eq!(
+            mac,
+            PinUvAuthParam::Long(bytes(MAC_TWO).try_into().unwrap())
+        );
+        assert_eq!(shared_secret.verify(&message(), mac.as_ref()), Ok(()));
+
+        // Only the HMAC key of the shared secret is used
+        let mac = authenticate(Version::Two, &shared_secret.to_bytes(), &message());
+        assert_eq!(mac.as_ref(), bytes(MAC_TWO));
+    }
+
+    #[test]
+    fn invalid_lengths() {
+        for version in [Version::One, Version::Two] {
+            let shared_secret = authenticator_key()
+                .shared_secret(version, &platform_key().public_key())
+                .unwrap();
+            assert_eq!(
+                shared_secret.encrypt(&[0; 15]),
+                Err(Error::InvalidParameter)
+            );
+            assert_eq!(
+                shared_secret.decrypt(&[0; 17]),
+                Err(Error::InvalidParameter)
+            );
+            assert_eq!(
+                shared_secret.verify(&message(), &[0; 20]),
+                Err(Error::PinAuthInvalid)
+            );
+        }
+
+        let shared_secret = SharedSecret::from_bytes(Version::Two, &bytes(SHARED_SECRET_TWO));
+        assert!(shared_secret.unwrap().decrypt(&[0; 8]).is_err());
+        assert!(SharedSecret::from_bytes(Version::One, &bytes(SHARED_SECRET_TWO)).is_err());
+    }
+
+    fn session<const VERSION: Version>() {
+        let mut authenticator = AuthenticatorProtocol::<VERSION>::new();
+        authenticator.initialize().unwrap();
+
+        let session =
+            PlatformSession::<VERSION>::initialize(authenticator.get_public_key().unwrap())
+                .unwrap();
+        let shared_secret = authenticator
+            .decapsulate(session.platform_key_agreement_key().clone())
+            .unwrap();
+        assert_eq!(shared_secret, session.shared_secret().to_bytes());
+
+        // The authenticator returns the pinUvAuthToken encrypted for the
+        // platform
+        let token = *authenticator.pin_uv_auth_token();
+        let encrypted = authenticator.encrypt(&shared_secret, &token).unwrap();
+        assert_eq!(session.decrypt(&encrypted).unwrap(), token);
+
+        let new_pin = session.encrypt(&[0x31; 64]).unwrap();
+        assert_eq!(
+            authenticator.decrypt(&shared_secret, &new_pin).unwrap(),
+            [0x31; 64]
+        );
+        let pin_uv_auth_param = session.authenticate(&new_pin).unwrap();
+        assert_eq!(
+            authenticator.verify(&shared_secret, &new_pin, pin_uv_auth_param.as_ref()),
+            Ok(())
+        );
+
+        // The token can only be verified while it is in use
+        let signature = authenticate(VERSION, &token, &message());
+        assert_eq!(
+            authenticator.verify(&token, &message(), signature.as_ref()),
+            Err(Error::PinAuthInvalid)
+        );
+        authenticator.begin_using_pin_uv_auth_token();
+        assert_eq!(
+            authenticator.verify(&token, &message(), signature.as_ref()),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn sessions() {
+        session::<{ Version::One }>();
+        session::<{ Version::Two }>();
+    }
+}
@@ -1,4 +1,6 @@
+use crate::status::StatusCode;
 use bounded_integer::BoundedUsize;
+#[cfg(feature = "serde")]
 use serde_with::{Bytes, DeserializeAs, SerializeAs};
 use std::{borrow::Cow, collections::BTreeSet};
 
@@ -8,12 +10,101 @@ use serde::{Deserialize, Serialize};
 pub mod auth_protocol;
 
 #[cfg(feature = "serde")]
-mod raw;
+pub(crate) mod raw;
 
 #[cfg(feature = "serde")]
 use raw::{RawRequest, RawResponse};
 
-pub type PinUvAuthParam = [u8; 16];
+/// The output of `authenticate`: the first 16 bytes of an HMAC-SHA-256 for
+/// PIN/UV auth protocol one, or all 32 bytes for protocol two.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PinUvAuthParam {
+    Short([u8; 16]),
+    Long([u8; 32]),
+}
+
+impl AsRef<[u8]> for PinUvAuthParam {
+    fn as_ref(&self) -> &[u8] {
+        match self {
+            PinUvAuthParam::Short(bytes) => bytes.as_ref(),
+            PinUvAuthParam::Long(bytes) => bytes.as_ref(),
+        }
+    }
+}
+
+impl TryFrom<&[u8]> for PinUvAuthParam {
+    type Error = Error;
+
+    fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
+        match value.len() {
+            16 => {
+                let mut short = [0; 16];
+                short.copy_from_slice(value);
+                Ok(Self::Short(short))
+            }
+            32 => {
+                let mut long = [0; 32];
+                long.copy_from_slice(value);
+                Ok(Self::Long(long))
+            }
+            _ => Err(Error::InvalidParameter),
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> DeserializeAs<'de, PinUvAuthParam> for Bytes {
+    fn deserialize_as<D>(deserializer: D) -> Result<PinUvAuthParam, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let bytes: Vec<u8> = Bytes::deserialize_as(deserializer)?;
+        PinUvAuthParam::try_from(bytes.as_ref()).map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl SerializeAs<PinUvAuthParam> for Bytes {
+    fn serialize_as<S>(source: &PinUvAuthParam, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        Bytes::serialize_as(&source.as_ref(), serializer)
+    }
+}
+
+/// The `pinUvAuthParam` of `authenticatorMakeCredential` and
+/// `authenticatorGetAssertion` requests.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PinUvAuth {
+    /// The request is not authenticated.
+    #[default]
+    None,
+    /// The request is authenticated with a `pinUvAuthToken`.
+    Param(PinUvAuthParam),
+    /// The platform only asks the user to select the authenticator, by
+    /// sending a zero length `pinUvAuthParam`.
+    ///
+    /// > If the platform sends a zero length pinUvAuthParam, the
+    /// > authenticator needs to wait for user touch and then returns either
+    /// > CTAP2_ERR_PIN_NOT_SET if pin is not set or CTAP2_ERR_PIN_INVALID if
+    /// > pin has been set.
+    Selection,
+}
+
+impl PinUvAuth {
+    /// The `pinUvAuthParam` authenticating the request, if any.
+    pub fn param(&self) -> Option<PinUvAuthParam> {
+        match self {
+            PinUvAuth::Param(pin_uv_auth_param) => Some(*pin_uv_auth_param),
+            PinUvAuth::None | PinUvAuth::Selection => None,
+        }
+    }
+
+    pub fn is_none(&self) -> bool {
+        *self == PinUvAuth::None
+    }
+}
 
 #[derive(Clone, Copy, Debug)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
@@ -31,7 +122,6 @@ impl AsRef<[u8]> for PinUvAuthToken {
     }
 }
 
-#[cfg(feature = "serde")]
 impl TryFrom<&[u8]> for PinUvAuthToken {
     type Error = Error;
 
@@ -75,7 +165,8 @@ impl SerializeAs<PinUvAuthToken> for Bytes {
         }
     }
 }
-#[derive(Clone, Debug)]
+
+#[derive(Clone, Debug, PartialEq)]
 #[cfg_attr(
     feature = "serde",
     derive(Serialize, Deserialize),
@@ -89,38 +180,43 @@ pub enum Request<'a> {
     SetPin {
         version: auth_protocol::Version,
         key_agreement: cosey::PublicKey,
-        new_pin_encrypted: [u8; 64],
+        /// The padded PIN, encrypted with the shared secret. 64 bytes for
+        /// protocol one, or 80 bytes including the IV for protocol two.
+        new_pin_encrypted: Vec<u8>,
         pin_uv_auth_param: PinUvAuthParam,
     },
     ChangePin {
         version: auth_protocol::Version,
         key_agreement: cosey::PublicKey,
-        pin_hash_encrypted: [u8; 16],
-        new_pin_encrypted: [u8; 64],
+        pin_hash_encrypted: Vec<u8>,
+        new_pin_encrypted: Vec<u8>,
         pin_uv_auth_param: PinUvAuthParam,
     },
     GetPinToken {
         version: auth_protocol::Version,
         key_agreement: cosey::PublicKey,
-        pin_hash_encrypted: [u8; 16],
+        /// `LEFT(SHA-256(pin), 16)`, encrypted with the shared secret. 16
+        /// bytes for protocol one, or 32 bytes including the IV for protocol
+        /// two.
+        pin_hash_encrypted: Vec<u8>,
     },
     GetPinUvAuthTokenUsingUvWithPermissions {
         version: auth_protocol::Version,
         key_agreement: cosey::PublicKey,
-        permissions: &'a BTreeSet<Permission>, // TODO: Enforce non-empty set?
+        permissions: Cow<'a, BTreeSet<Permission>>, // TODO: Enforce non-empty set?
         relying_party_id: Option<Cow<'a, str>>,
     },
     GetUvRetries,
     GetPinUvAuthTokenUsingPinWithPermissions {
         version: auth_protocol::Version,
         key_agreement: cosey::PublicKey,
-        pin_hash_encrypted: [u8; 16],
-        permissions: &'a BTreeSet<Permission>, // TODO: Enforce non-empty set?
+        pin_hash_encrypted: Vec<u8>,
+        permissions: Cow<'a, BTreeSet<Permission>>, // TODO: Enforce non-empty set?
         relying_party_id: Option<Cow<'a, str>>,
     },
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 #[cfg_attr(
     feature = "serde",
     derive(Serialize, Deserialize),
@@ -129,7 +225,9 @@ pub enum Request<'a> {
 pub enum Response {
     GetPinRetries {
         pin_retries: usize,
-        power_cycle_state: Option<usize>,
+        /// > Present and true if the authenticator requires a power cycle
+        /// > before any future PIN operation, false if no power cycle needed.
+        power_cycle_state: Option<bool>,
     },
     GetKeyAgreement {
         key_agreement: cosey::PublicKey,
@@ -137,12 +235,14 @@ pub enum Response {
     SetPin,
     ChangePin,
     GetPinToken {
-        pin_uv_auth_token: PinUvAuthToken,
+        /// > The pinUvAuthToken, encrypted by calling encrypt with the shared
+        /// > secret as the key.
+        pin_uv_auth_token: Vec<u8>,
     },
     GetPinUvAuthTokenUsingUvWithPermissions {
         /// > The pinUvAuthToken, encrypted by calling encrypt with the shared
         /// > secret as the key.
-        pin_uv_auth_token: PinUvAuthToken,
+        pin_uv_auth_token: Vec<u8>,
     },
     GetUvRetries {
         /// > Number of uv attempts remaining before lockout.
@@ -155,11 +255,55 @@ pub enum Response {
     GetPinUvAuthTokenUsingPinWithPermissions {
         /// > The pinUvAuthToken, encrypted by calling encrypt with the shared
         /// > secret as the key.
-        pin_uv_auth_token: PinUvAuthToken,
+        pin_uv_auth_token: Vec<u8>,
     },
 }
 
-#[derive(Debug, Clone, Copy)]
+impl Response {
+    /// Interprets the response as the one to the given request.
+    ///
+    /// The encoding of a response doesn't identify its subcommand: `setPin`
+    /// and `changePin` both return an empty response, and each subcommand
+    /// that obtains a `pinUvAuthToken` returns only the token. Returns
+    /// [`Error::MissingParameter`] if the response lacks the parameters of the
+    /// request's response.
+    pub fn for_request(self, request: &Request) -> Result<Self, Error> {
+        let pin_uv_auth_token = match &self {
+            Response::GetPinToken { pin_uv_auth_token }
+            | Response::GetPinUvAuthTokenUsingUvWithPermissions { pin_uv_auth_token }
+            | Response::GetPinUvAuthTokenUsingPinWithPermissions { pin_uv_auth_token } => {
+                Some(pin_uv_auth_token.clone())
+            }
+            _ => None,
+        };
+
+        Ok(match (request, self) {
+            (Request::GetPinRetries, response @ Response::GetPinRetries { .. })
+            | (Request::GetKeyAgreement { .. }, response @ Response::GetKeyAgreement { .. })
+            | (Request::GetUvRetries, response @ Response::GetUvRetries { .. }) => response,
+            (Request::SetPin { .. }, Response::SetPin | Response::ChangePin) => Response::SetPin,
+            (Request::ChangePin { .. }, Response::SetPin | Response::ChangePin) => {
+                Response::ChangePin
+            }
+            (Request::GetPinToken { .. }, _) => Response::GetPinToken {
+                pin_uv_auth_token: pin_uv_auth_token.ok_or(Error::MissingParameter)?,
+            },
+            (Request::GetPinUvAuthTokenUsingUvWithPermissions { .. }, _) => {
+                Response::GetPinUvAuthTokenUsingUvWithPermissions {
+                    pin_uv_auth_token: pin_uv_auth_token.ok_or(Error::MissingParameter)?,
+                }
+            }
+            (Request::GetPinUvAuthTokenUsingPinWithPermissions { .. }, _) => {
+                Response::GetPinUvAuthTokenUsingPinWithPermissions {
+                    pin_uv_auth_token: pin_uv_auth_token.ok_or(Error::MissingParameter)?,
+                }
+            }
+            _ => return Err(Error::MissingParameter),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Error {
     MissingParameter,
     InvalidParameter,
@@ -168,12 +312,60 @@ pub enum Error {
     PinBlocked,
     PinAuthBlocked,
     PinInvalid,
+    PinNotSet,
     OperationDenied,
     UnauthorizedPermission,
     NotAllowed,
     UserVerificationBlocked,
     UserActionTimeout,
     UserVerificationInvalid,
+    /// Any other status code, including those defined by extensions and
+    /// vendors.
+    Other(StatusCode),
+}
+
+impl From<Error> for StatusCode {
+    fn from(value: Error) -> Self {
+        match value {
+            Error::MissingParameter => StatusCode::MissingParameter,
+            Error::InvalidParameter => StatusCode::InvalidParameter,
+            Error::PinAuthInvalid => StatusCode::PinAuthInvalid,
+            Error::PinPolicyViolation => StatusCode::PinPolicyViolation,
+            Error::PinBlocked => StatusCode::PinBlocked,
+            Error::PinAuthBlocked => StatusCode::PinAuthBlocked,
+            Error::PinInvalid => StatusCode::PinInvalid,
+            Error::PinNotSet => StatusCode::PinNotSet,
+            Error::OperationDenied => StatusCode::OperationDenied,
+            Error::UnauthorizedPermission => StatusCode::UnauthorizedPermission,
+            Error::NotAllowed => StatusCode::NotAllowed,
+            Error::UserVerificationBlocked => StatusCode::UserVerificationBlocked,
+            Error::UserActionTimeout => StatusCode::UserActionTimeout,
+            Error::UserVerificationInvalid => StatusCode::UserVerificationInvalid,
+            Error::Other(status) => status,
+        }
+    }
+}
+
+impl From<StatusCode> for Error {
+    fn from(value: StatusCode) -> Self {
+        match value {
+            StatusCode::MissingParameter => Error::MissingParameter,
+            StatusCode::InvalidParameter => Error::InvalidParameter,
+            StatusCode::PinAuthInvalid => Error::PinAuthInvalid,
+            StatusCode::PinPolicyViolation => Error::PinPolicyViolation,
+            StatusCode::PinBlocked => Error::PinBlocked,
+            StatusCode::PinAuthBlocked => Error::PinAuthBlocked,
+            StatusCode::PinInvalid => Error::PinInvalid,
+            StatusCode::PinNotSet => Error::PinNotSet,
+            StatusCode::OperationDenied => Error::OperationDenied,
+            StatusCode::UnauthorizedPermission => Error::UnauthorizedPermission,
+            StatusCode::NotAllowed => Error::NotAllowed,
+            StatusCode::UserVerificationBlocked => Error::UserVerificationBlocked,
+            StatusCode::UserActionTimeout => Error::UserActionTimeout,
+            StatusCode::UserVerificationInvalid => Error::UserVerificationInvalid,
+            status => Error::Other(status),
+        }
+    }
 }
 
 /// > When obtaining a `pinUvAuthToken`, the platform requests permissions
@@ -216,12 +408,14 @@ impl std::fmt::Display for Error {
             Error::PinBlocked => write!(f, "PIN blocked"),
             Error::PinAuthBlocked => write!(f, "PIN auth blocked"),
             Error::PinInvalid => write!(f, "PIN invalid"),
+            Error::PinNotSet => write!(f, "PIN not set"),
             Error::OperationDenied => write!(f, "Operation denied"),
             Error::UnauthorizedPermission => write!(f, "Unauthorized permission"),
             Error::NotAllowed => write!(f, "Not allowed"),
             Error::UserVerificationBlocked => write!(f, "User verification blocked"),
             Error::UserActionTimeout => write!(f, "User action timeout"),
             Error::UserVerificationInvalid => write!(f, "User verification invalid"),
+            Error::Other(status) => write!(f, "{}", status),
         }
     }
 }
@@ -5,18 +5,20 @@
 use super::auth_protocol;
 use super::Error;
 use super::Permission;
-use super::{PinUvAuthParam, PinUvAuthToken};
+use super::{PinUvAuth, PinUvAuthParam};
 use super::{Request, Response};
+use crate::status::StatusCode;
+use bounded_integer::BoundedUsize;
 use flagset::flags;
 use flagset::FlagSet;
 use serde::{Deserialize, Serialize};
 use serde_with::{serde_as, Bytes};
-use std::borrow::Cow;
+use std::{borrow::Cow, collections::BTreeSet};
 
 mod public_key;
 
 #[derive(Clone, Serialize, Deserialize)]
-#[serde(into = "u8")]
+#[serde(into = "u8", try_from = "u8")]
 pub(crate) enum RawSubcommand {
     GetPinRetries = 0x01,
     GetKeyAgreement = 0x02,
@@ -34,6 +36,24 @@ impl From<RawSubcommand> for u8 {
     }
 }
 
+impl TryFrom<u8> for RawSubcommand {
+    type Error = Error;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        Ok(match value {
+            0x01 => Self::GetPinRetries,
+            0x02 => Self::GetKeyAgreement,
+            0x03 => Self::SetPin,
+            0x04 => Self::ChangePin,
+            0x05 => Self::GetPinToken,
+            0x06 => Self::GetPinUvAuthTokenUsingUvWithPermissions,
+            0x07 => Self::GetUvRetries,
+            0x09 => Self::GetPinUvAuthTokenUsingPinWithPermissions,
+            _ => return Err(Error::Other(StatusCode::InvalidSubcommand)),
+        })
+    }
+}
+
 #[serde_as]
 #[derive(Clone, Serialize, Deserialize)]
 pub(crate) struct RawRequest<'a> {
@@ -43,6 +63,7 @@ pub(crate) struct RawRequest<'a> {
     pub sub_command: RawSubcommand,
     #[serde(
         rename = 0x03,
+        default,
         deserialize_with = "public_key::deserialize",
         skip_serializing_if = "Option::is_none"
     )]
@@ -52,12 +73,12 @@ pub(crate) struct RawRequest<'a> {
     pub pin_uv_auth_param: Option<PinUvAuthParam>,
     #[serde_as(as = "Option<Bytes>")]
     #[serde(rename = 0x05, skip_serializing_if = "Option::is_none")]
-    pub new_pin_enc: Option<[u8; 64]>,
+    pub new_pin_enc: Option<Vec<u8>>,
     #[serde_as(as = "Option<Bytes>")]
     #[serde(rename = 0x06, skip_serializing_if = "Option::is_none")]
-    pub pin_hash_enc: Option<[u8; 16]>,
+    pub pin_hash_enc: Option<Vec<u8>>,
     #[serde(rename = 0x09, skip_serializing_if = "Option::is_none")]
-    pub permissions: Option<FlagSet<RawPermission>>, // TODO: Deserialize from bitfield
+    pub permissions: Option<FlagSet<RawPermission>>,
     #[serde(rename = 0x0A, skip_serializing_if = "Option::is_none")]
     pub rp_id: Option<Cow<'a, str>>,
 }
@@ -95,7 +116,7 @@ impl<'a> From<Request<'a>> for RawRequest<'a> {
                 sub_command: RawSubcommand::SetPin,
                 key_agreement: Some(key_agreement),
                 pin_uv_auth_param: Some(pin_uv_auth_param),
-                new_pin_enc: Some(new_pin_encrypted.clone()),
+                new_pin_enc: Some(new_pin_encrypted),
                 pin_hash_enc: None,
                 rp_id: None,
                 permissions: None,
@@ -111,8 +132,8 @@ impl<'a> From<Request<'a>> for RawRequest<'a> {
                 sub_command: RawSubcommand::ChangePin,
                 key_agreement: Some(key_agreement),
                 pin_uv_auth_param: Some(pin_uv_auth_param),
-                new_pin_enc: Some(new_pin_encrypted.clone()),
-                pin_hash_enc: Some(pin_hash_encrypted.clone()),
+                new_pin_enc: Some(new_pin_encrypted),
+                pin_hash_enc: Some(pin_hash_encrypted),
                 rp_id: None,
                 permissions: None,
             },
@@ -126,7 +147,7 @@ impl<'a> From<Request<'a>> for RawRequest<'a> {
                 key_agreement: Some(key_agreement),
                 pin_uv_auth_param: None,
                 new_pin_enc: None,
-                pin_hash_enc: Some(pin_hash_encrypted.clone()),
+                pin_hash_enc: Some(pin_hash_encrypted),
                 rp_id: None,
                 permissions: None,
             },
@@ -143,7 +164,7 @@ impl<'a> From<Request<'a>> for RawRequest<'a> {
                 new_pin_enc: None,
                 pin_hash_enc: None,
                 rp_id: relying_party_id,
-                permissions: Some(permissions.iter().map(Clone::clone).collect()),
+                permissions: Some(permissions.iter().copied().collect()),
             },
             Request::GetUvRetries => Self {
                 pin_uv_auth_protocol: None,
@@ -169,7 +190,7 @@ impl<'a> From<Request<'a>> for RawRequest<'a> {
                 new_pin_enc: None,
                 pin_hash_enc: Some(pin_hash_encrypted),
                 rp_id: relying_party_id,
-                permissions: Some(permissions.iter().map(Clone::clone).collect()),
+                permissions: Some(permissions.iter().copied().collect()),
             },
         }
     }
@@ -179,8 +200,86 @@ impl<'a> TryFrom<RawRequest<'a>> for Request<'a> {
     type Error = Error;
 
     fn try_from(value: RawRequest<'a>) -> Result<Self, Self::Error> {
-        todo!()
+        let RawRequest {
+            pin_uv_auth_protocol,
+            sub_command,
+            key_agreement,
+            pin_uv_auth_param,
+            new_pin_enc,
+            pin_hash_enc,
+            permissions,
+            rp_id,
+        } = value;
+
+        Ok(match sub_command {
+            RawSubcommand::GetPinRetries => Request::GetPinRetries,
+            RawSubcommand::GetKeyAgreement => Request::GetKeyAgreement {
+                version: required(pin_uv_auth_protocol)?,
+            },
+            RawSubcommand::SetPin => Request::SetPin {
+                version: required(pin_uv_auth_protocol)?,
+                key_agreement: required(key_agreement)?,
+                new_pin_encrypted: required(new_pin_enc)?,
+                pin_uv_auth_param: required(pin_uv_auth_param)?,
+            },
+            RawSubcommand::ChangePin => Request::ChangePin {
+                version: required(pin_uv_auth_protocol)?,
+                key_agreement: required(key_agreement)?,
+                pin_hash_encrypted: required(pin_hash_enc)?,
+                new_pin_encrypted: required(new_pin_enc)?,
+                pin_uv_auth_param: required(pin_uv_auth_param)?,
+            },
+            RawSubcommand::GetPinToken => {
+                // Tokens obtained without permissions can't be restricted to a
+                // relying party
+                if permissions.is_some() || rp_id.is_some() {
+                    return Err(Error::InvalidParameter);
+                }
+                Request::GetPinToken {
+                    version: required(pin_uv_auth_protocol)?,
+                    key_agreement: required(key_agreement)?,
+                    pin_hash_encrypted: required(pin_hash_enc)?,
+                }
+            }
+            RawSubcommand::GetPinUvAuthTokenUsingUvWithPermissions => {
+                Request::GetPinUvAuthTokenUsingUvWithPermissions {
+                    version: required(pin_uv_auth_protocol)?,
+                    key_agreement: required(key_agreement)?,
+                    permissions: Cow::Owned(permission_set(permissions)?),
+                    relying_party_id: rp_id,
+                }
+            }
+            RawSubcommand::GetUvRetries => Request::GetUvRetries,
+            RawSubcommand::GetPinUvAuthTokenUsingPinWithPermissions => {
+                Request::GetPinUvAuthTokenUsingPinWithPermissions {
+                    version: required(pin_uv_auth_protocol)?,
+                    key_agreement: required(key_agreement)?,
+                    pin_hash_encrypted: required(pin_hash_enc)?,
+                    permissions: Cow::Owned(permission_set(permissions)?),
+                    relying_party_id: rp_id,
+                }
+            }
+        })
+    }
+}
+
+fn required<T>(parameter: Option<T>) -> Result<T, Error> {
+    parameter.ok_or(Error::MissingParameter)
+}
+
+/// Converts the permissions bitfield, ignoring unknown bits. At least one
+/// permission must be requested.
+fn permission_set(
+    permissions: Option<FlagSet<RawPermission>>,
+) -> Result<BTreeSet<Permission>, Error> {
+    let permissions: BTreeSet<Permission> = required(permissions)?
+        .into_iter()
+        .map(Permission::from)
+        .collect();
+    if permissions.is_empty() {
+        return Err(Error::InvalidParameter);
     }
+    Ok(permissions)
 }
 
 #[serde_as]
@@ -196,11 +295,11 @@ pub(crate) struct RawResponse {
     pub key_agreement: Option<cosey::PublicKey>,
     #[serde_as(as = "Option<Bytes>")]
     #[serde(rename = 0x02, skip_serializing_if = "Option::is_none")]
-    pub pin_uv_auth_token: Option<PinUvAuthToken>,
+    pub pin_uv_auth_token: Option<Vec<u8>>,
     #[serde(rename = 0x03, skip_serializing_if = "Option::is_none")]
     pub pin_retries: Option<usize>,
     #[serde(rename = 0x04, skip_serializing_if = "Option::is_none")]
-    pub power_cycle_state: Option<usize>,
+    pub power_cycle_state: Option<bool>,
     #[serde(rename = 0x05, skip_serializing_if = "Option::is_none")]
     pub uv_retries: Option<usize>,
 }
@@ -274,8 +373,18 @@ impl From<Response> for RawResponse {
 impl TryFrom<RawResponse> for Response {
     type Error = Error;
 
+    /// Responses don't identify the subcommand they belong to, so an empty
+    /// response is read as [`Response::SetPin`] and a `pinUvAuthToken` as
+    /// [`Response::GetPinToken`]. See [`Response::for_request`].
     fn try_from(value: RawResponse) -> Result<Self, Self::Error> {
         Ok(match value {
+            RawResponse {
+                key_agreement: None,
+                pin_uv_auth_token: None,
+                pin_retries: None,
+                power_cycle_state: None,
+                uv_retries: None,
+            } => Response::SetPin,
             RawResponse {
                 key_agreement: None,
                 pin_uv_auth_token: None,
@@ -300,7 +409,16 @@ impl TryFrom<RawResponse> for Response {
                 power_cycle_state: None,
                 uv_retries: None,
             } => Response::GetPinToken { pin_uv_auth_token },
-            _ => todo!(),
+            RawResponse {
+                key_agreement: None,
+                pin_uv_auth_token: None,
+                pin_retries: None,
+                power_cycle_state: None,
+                uv_retries: Some(uv_retries),
+            } => Response::GetUvRetries {
+                uv_retries: BoundedUsize::new(uv_retries).ok_or(Error::InvalidParameter)?,
+            },
+            _ => return Err(Error::InvalidParameter),
         })
     }
 }
@@ -353,3 +471,241 @@ impl FromIterator<Permission> for FlagSet<RawPermission> {
             })
     }
 }
+
+/// Serializes the `pinUvAuthParam` of `authenticatorMakeCredential` and
+/// `authenticatorGetAssertion` requests, which is zero length for
+/// authenticator selection.
+pub(crate) fn serialize_pin_uv_auth<S>(value: &PinUvAuth, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+{
+    match value {
+        PinUvAuth::None => serializer.serialize_none(),
+        PinUvAuth::Param(pin_uv_auth_param) => {
+            serializer.serialize_bytes(pin_uv_auth_param.as_ref())
+        }
+        PinUvAuth::Selection => serializer.serialize_bytes(&[]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::authenticator::client_pin::auth_protocol::Version;
+
+    fn key_agreement() -> cosey::PublicKey {
+        cosey::PublicKey::EcdhEsHkdf256Key(cosey::EcdhEsHkdf256PublicKey {
+            x: cosey::Bytes::from_slice(&[0x01; 32]).unwrap(),
+            y: cosey::Bytes::from_slice(&[0x02; 32]).unwrap(),
+        })
+    }
+
+    fn encode<T: Serialize>(value: &T) -> Vec<u8> {
+        let mut data = Vec::new();
+        ciborium::ser::into_writer(value, &mut data).unwrap();
+        data
+    }
+
+    fn request_round_trip(request: Request) {
+        let decoded: Request = ciborium::de::from_reader(encode(&request).as_slice()).unwrap();
+        assert_eq!(decoded, request);
+    }
+
+    fn response_round_trip(request: &Request, response: Response) {
+        let decoded: Response = ciborium::de::from_reader(encode(&response).as_slice()).unwrap();
+        assert_eq!(decoded.for_request(request).unwrap(), response);
+    }
+
+    fn raw(sub_command: RawSubcommand) -> RawRequest<'static> {
+        RawRequest {
+            pin_uv_auth_protocol: None,
+            sub_command,
+            key_agreement: None,
+            pin_uv_auth_param: None,
+            new_pin_enc: None,
+            pin_hash_enc: None,
+            permissions: None,
+            rp_id: None,
+        }
+    }
+
+    #[test]
+    fn get_pin_retries() {
+        let request = Request::GetPinRetries;
+        request_round_trip(request.clone());
+        response_round_trip(
+            &request,
+            Response::GetPinRetries {
+                pin_retries: 8,
+                power_cycle_state: Some(false),
+            },
+        );
+    }
+
+    #[test]
+    fn get_key_agreement() {
+        let request = Request::GetKeyAgreement {
+            version: Version::Two,
+        };
+        request_round_trip(request.clone());
+        response_round_trip(
+            &request,
+            Response::GetKeyAgreement {
+                key_agreement: key_agreement(),
+            },
+        );
+
+        assert_eq!(
+            Request::try_from(raw(RawSubcommand::GetKeyAgreement)).unwrap_err(),
+            Error::MissingParameter
+        );
+    }
+
+    #[test]
+    fn set_pin() {
+        let request = Request::SetPin {
+            version: Version::Two,
+            key_agreement: key_agreement(),
+            new_pin_encrypted: vec![0x03; 80],
+            pin_uv_auth_param: PinUvAuthParam::Long([0x04; 32]),
+        };
+        request_round_trip(request.clone());
+        response_round_trip(&request, Response::SetPin);
+
+        let RawRequest { new_pin_enc, .. } = RawRequest::from(request);
+        let missing = RawRequest {
+            pin_uv_auth_protocol: Some(Version::Two),
+            key_agreement: Some(key_agreement()),
+            new_pin_enc,
+            ..raw(RawSubcommand::SetPin)
+        };
+        assert_eq!(
+            Request::try_from(missing).unwrap_err(),
+            Error::MissingParameter
+        );
+    }
+
+    #[test]
+    fn change_pin() {
+        let request = Request::ChangePin {
+            version: Version::One,
+            key_agreement: key_agreement(),
+            pin_hash_encrypted: vec![0x05; 16],
+            new_pin_encrypted: vec![0x06; 64],
+            pin_uv_auth_param: PinUvAuthParam::Short([0x07; 16]),
+        };
+        request_round_trip(request.clone());
+        response_round_trip(&request, Response::ChangePin);
+    }
+
+    #[test]
+    fn get_pin_token() {
+        let request = Request::GetPinToken {
+            version: Version::One,
+            key_agreement: key_agreement(),
+            pin_hash_encrypted: vec![0x08; 16],
+        };
+        request_round_trip(request.clone());
+        response_round_trip(
+            &request,
+            Response::GetPinToken {
+                pin_uv_auth_token: vec![0x09; 32],
+            },
+        );
+
+        let mut with_permissions = RawRequest::from(request);
+        with_permissions.permissions = Some(RawPermission::MakeCredential.into());
+        assert_eq!(
+            Request::try_from(with_permissions).unwrap_err(),
+            Error::InvalidParameter
+        );
+    }
+
+    #[test]
+    fn get_pin_uv_auth_token_using_uv_with_permissions() {
+        let permissions = BTreeSet::from([Permission::GetAssertion, Permission::LargeBlobWrite]);
+        let request = Request::GetPinUvAuthTokenUsingUvWithPermissions {
+            version: Version::Two,
+            key_agreement: key_agreement(),
+            permissions: Cow::Borrowed(&permissions),
+            relying_party_id: Some(Cow::Borrowed("example.com")),
+        };
+        request_round_trip(request.clone());
+        response_round_trip(
+            &request,
+            Response::GetPinUvAuthTokenUsingUvWithPermissions {
+                pin_uv_auth_token: vec![0x0A; 48],
+            },
+        );
+    }
+
+    #[test]
+    fn get_uv_retries() {
+        let request = Request::GetUvRetries;
+        request_round_trip(request.clone());
+        response_round_trip(
+            &request,
+            Response::GetUvRetries {
+                uv_retries: BoundedUsize::new(3).unwrap(),
+            },
+        );
+
+        let out_of_range = RawResponse {
+            key_agreement: None,
+            pin_uv_auth_token: None,
+            pin_retries: None,
+            power_cycle_state: None,
+            uv_retries: Some(26),
+        };
+        assert_eq!(
+            Response::try_from(out_of_range).unwrap_err(),
+            Error::InvalidParameter
+        );
+    }
+
+    #[test]
+    fn get_pin_uv_auth_token_using_pin_with_permissions() {
+        let permissions = BTreeSet::from([
+            Permission::MakeCredential,
+            Permission::CredentialManagement,
+            Permission::AuthenticatorConfiguration,
+        ]);
+        let request = Request::GetPinUvAuthTokenUsingPinWithPermissions {
+            version: Version::Two,
+            key_agreement: key_agreement(),
+            pin_hash_encrypted: vec![0x0B; 32],
+            permissions: Cow::Borrowed(&permissions),
+            relying_party_id: None,
+        };
+        request_round_trip(request.clone());
+        response_round_trip(
+            &request,
+            Response::GetPinUvAuthTokenUsingPinWithPermissions {
+                pin_uv_auth_token: vec![0x0C; 48],
+            },
+        );
+
+        let mut no_permissions = RawRequest::from(request);
+        no_permissions.permissions = Some(FlagSet::default());
+        assert_eq!(
+            Request::try_from(no_permissions).unwrap_err(),
+            Error::InvalidParameter
+        );
+    }
+
+    #[test]
+    fn unknown_subcommand() {
+        assert_eq!(
+            RawSubcommand::try_from(0x08).err(),
+            Some(Error::Other(StatusCode::InvalidSubcommand))
+        );
+    }
+
+    #[test]
+    fn mismatched_response() {
+        assert_eq!(
+            Response::SetPin.for_request(&Request::GetPinRetries),
+            Err(Error::MissingParameter)
+        );
+    }
+}
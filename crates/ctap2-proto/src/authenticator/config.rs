@@ -1,20 +1,37 @@
-use std::collections::BTreeMap;
+use std::{borrow::Cow, collections::BTreeMap, fmt::Display};
 
-use super::client_pin::auth_protocol;
+use super::client_pin::{auth_protocol, PinUvAuthParam};
+use crate::status::StatusCode;
 
-#[derive(Clone, Copy)]
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "serde")]
+pub(crate) mod raw;
+
+#[cfg(feature = "serde")]
+use raw::RawRequest;
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(into = "RawRequest", try_from = "RawRequest")
+)]
 pub enum Request<'a> {
     /// > This `enableEnterpriseAttestation` subcommand is only implemented if
     /// > the enterprise attestation feature is supported.
     EnableEnterpriseAttestation {
-        pin_uv_auth_protocol: auth_protocol::Version,
-        pin_uv_auth_param: &'a [u8], // TODO: Is using a more specific type possible?
+        pin_uv_auth_protocol: Option<auth_protocol::Version>,
+        /// Absent if the authenticator is not protected by user verification.
+        pin_uv_auth_param: Option<PinUvAuthParam>,
     },
     /// > This `toggleAlwaysUv` subcommand is only implemented if the Always
     /// > Require User Verification feature is supported.
     ToggleAlwaysUserVerification {
-        pin_uv_auth_protocol: auth_protocol::Version,
-        pin_uv_auth_param: &'a [u8], // TODO: Is using a more specific type possible?
+        pin_uv_auth_protocol: Option<auth_protocol::Version>,
+        /// Absent if the authenticator is not protected by user verification.
+        pin_uv_auth_param: Option<PinUvAuthParam>,
     },
     /// > This `setMinPINLength` subcommand is only implemented if the
     /// > `setMinPINLength` option ID is present.
@@ -22,8 +39,19 @@ pub enum Request<'a> {
     /// > This command sets the minimum PIN length in Unicode code points to be
     /// > enforced by the authenticator while changing/setting up a ClientPIN.
     SetMinPinLength {
-        pin_uv_auth_protocol: auth_protocol::Version,
-        pin_uv_auth_param: &'a [u8], // TODO: Is using a more specific type possible?
+        /// > Minimum PIN length in code points
+        ///
+        /// Must not be less than the current minimum PIN length.
+        new_min_pin_length: Option<usize>,
+        /// > RP IDs which are allowed to get this information via the
+        /// > `minPinLength` extension.
+        min_pin_length_rp_ids: Option<Cow<'a, [String]>>,
+        /// > The authenticator returns `CTAP2_ERR_PIN_POLICY_VIOLATION` until
+        /// > changePIN is successful.
+        force_change_pin: Option<bool>,
+        pin_uv_auth_protocol: Option<auth_protocol::Version>,
+        /// Absent if the authenticator is not protected by user verification.
+        pin_uv_auth_param: Option<PinUvAuthParam>,
     },
     /// > This subCommand allows vendors to test authenticator configuration
     /// > features.
@@ -37,16 +65,223 @@ pub enum Request<'a> {
     /// > expected to generally utilize this subCommand.
     VendorPrototype {
         vendor_command_id: usize,
-        params: &'a BTreeMap<Vec<u8>, Vec<u8>>, /* TODO: Is the character space of keys
-                                                 * restricted to UTF-8? */
-        pin_uv_auth_protocol: auth_protocol::Version,
-        pin_uv_auth_param: &'a [u8], // TODO: Is using a more specific type possible?
+        params: Cow<'a, BTreeMap<Vec<u8>, Vec<u8>>>, /* TODO: Is the character space of keys
+                                                      * restricted to UTF-8? */
+        pin_uv_auth_protocol: Option<auth_protocol::Version>,
+        /// Absent if the authenticator is not protected by user verification.
+        pin_uv_auth_param: Option<PinUvAuthParam>,
     },
 }
 
+impl Request<'_> {
+    /// The `pinUvAuthProtocol` and `pinUvAuthParam` of the request, both
+    /// absent if the authenticator is not protected by user verification.
+    pub fn pin_uv_auth(&self) -> (Option<auth_protocol::Version>, Option<PinUvAuthParam>) {
+        match *self {
+            Request::EnableEnterpriseAttestation {
+                pin_uv_auth_protocol,
+                pin_uv_auth_param,
+            }
+            | Request::ToggleAlwaysUserVerification {
+                pin_uv_auth_protocol,
+                pin_uv_auth_param,
+            }
+            | Request::SetMinPinLength {
+                pin_uv_auth_protocol,
+                pin_uv_auth_param,
+                ..
+            }
+            | Request::VendorPrototype {
+                pin_uv_auth_protocol,
+                pin_uv_auth_param,
+                ..
+            } => (pin_uv_auth_protocol, pin_uv_auth_param),
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl Request<'_> {
+    /// The message authenticated by the `pinUvAuthParam`: `32 × 0xff || 0x0d
+    /// || subCommand || subCommandParams`.
+    pub fn authenticated_message(&self) -> Vec<u8> {
+        let (sub_command, params) = self.authenticated_subcommand();
+        raw::authenticated_message(sub_command, params)
+    }
+
+    /// Verifies the `pinUvAuthParam` using the given `pinUvAuthToken`.
+    ///
+    /// Checking whether the token is in use and has the
+    /// [`Permission::AuthenticatorConfiguration`](super::client_pin::Permission::AuthenticatorConfiguration)
+    /// permission is left to the authenticator.
+    #[cfg(feature = "crypto")]
+    pub fn verify(&self, pin_uv_auth_token: &[u8]) -> Result<(), Error> {
+        let (pin_uv_auth_protocol, pin_uv_auth_param) = self.pin_uv_auth();
+        let pin_uv_auth_param = pin_uv_auth_param.ok_or(Error::PinUvAuthTokenRequired)?;
+        let version = pin_uv_auth_protocol.ok_or(Error::MissingParameter)?;
+        auth_protocol::verify(
+            version,
+            pin_uv_auth_token,
+            &self.authenticated_message(),
+            pin_uv_auth_param.as_ref(),
+        )
+        .map_err(|_| Error::PinAuthInvalid)
+    }
+}
+
+#[cfg(all(feature = "serde", feature = "crypto"))]
+impl<'a> Request<'a> {
+    pub fn enable_enterprise_attestation(
+        pin_uv_auth_protocol: auth_protocol::Version,
+        pin_uv_auth_token: &[u8],
+    ) -> Self {
+        Request::EnableEnterpriseAttestation {
+            pin_uv_auth_protocol: None,
+            pin_uv_auth_param: None,
+        }
+        .authenticated(pin_uv_auth_protocol, pin_uv_auth_token)
+    }
+
+    pub fn toggle_always_user_verification(
+        pin_uv_auth_protocol: auth_protocol::Version,
+        pin_uv_auth_token: &[u8],
+    ) -> Self {
+        Request::ToggleAlwaysUserVerification {
+            pin_uv_auth_protocol: None,
+            pin_uv_auth_param: None,
+        }
+        .authenticated(pin_uv_auth_protocol, pin_uv_auth_token)
+    }
+
+    pub fn set_min_pin_length(
+        new_min_pin_length: Option<usize>,
+        min_pin_length_rp_ids: Option<&'a [String]>,
+        force_change_pin: Option<bool>,
+        pin_uv_auth_protocol: auth_protocol::Version,
+        pin_uv_auth_token: &[u8],
+    ) -> Self {
+        Request::SetMinPinLength {
+            new_min_pin_length,
+            min_pin_length_rp_ids: min_pin_length_rp_ids.map(Cow::Borrowed),
+            force_change_pin,
+            pin_uv_auth_protocol: None,
+            pin_uv_auth_param: None,
+        }
+        .authenticated(pin_uv_auth_protocol, pin_uv_auth_token)
+    }
+
+    pub fn vendor_prototype(
+        vendor_command_id: usize,
+        params: &'a BTreeMap<Vec<u8>, Vec<u8>>,
+        pin_uv_auth_protocol: auth_protocol::Version,
+        pin_uv_auth_token: &[u8],
+    ) -> Self {
+        Request::VendorPrototype {
+            vendor_command_id,
+            params: Cow::Borrowed(params),
+            pin_uv_auth_protocol: None,
+            pin_uv_auth_param: None,
+        }
+        .authenticated(pin_uv_auth_protocol, pin_uv_auth_token)
+    }
+
+    /// Sets the `pinUvAuthProtocol`, and the `pinUvAuthParam` computed with
+    /// the given `pinUvAuthToken`.
+    fn authenticated(mut self, version: auth_protocol::Version, pin_uv_auth_token: &[u8]) -> Self {
+        let message = self.authenticated_message();
+        let (Request::EnableEnterpriseAttestation {
+            pin_uv_auth_protocol,
+            pin_uv_auth_param,
+        }
+        | Request::ToggleAlwaysUserVerification {
+            pin_uv_auth_protocol,
+            pin_uv_auth_param,
+        }
+        | Request::SetMinPinLength {
+            pin_uv_auth_protocol,
+            pin_uv_auth_param,
+            ..
+        }
+        | Request::VendorPrototype {
+            pin_uv_auth_protocol,
+            pin_uv_auth_param,
+            ..
+        }) = &mut self;
+        *pin_uv_auth_protocol = Some(version);
+        *pin_uv_auth_param = Some(auth_protocol::authenticate(
+            version,
+            pin_uv_auth_token,
+            &message,
+        ));
+        self
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Error {
     MissingParameter,
     InvalidParameter,
+    InvalidSubcommand,
+    /// The feature configured by the subcommand is not supported.
+    UnsupportedOption,
+    /// The new minimum PIN length is less than the current one.
+    PinPolicyViolation,
     PinUvAuthTokenRequired,
     PinAuthInvalid,
+    /// The `pinUvAuthToken` lacks the
+    /// [`Permission::AuthenticatorConfiguration`](super::client_pin::Permission::AuthenticatorConfiguration)
+    /// permission.
+    UnauthorizedPermission,
+    /// Any other status code, including those defined by extensions and
+    /// vendors.
+    Other(StatusCode),
+}
+
+impl From<Error> for StatusCode {
+    fn from(value: Error) -> Self {
+        match value {
+            Error::MissingParameter => StatusCode::MissingParameter,
+            Error::InvalidParameter => StatusCode::InvalidParameter,
+            Error::InvalidSubcommand => StatusCode::InvalidSubcommand,
+            Error::UnsupportedOption => StatusCode::UnsupportedOption,
+            Error::PinPolicyViolation => StatusCode::PinPolicyViolation,
+            Error::PinUvAuthTokenRequired => StatusCode::PinUvAuthTokenRequired,
+            Error::PinAuthInvalid => StatusCode::PinAuthInvalid,
+            Error::UnauthorizedPermission => StatusCode::UnauthorizedPermission,
+            Error::Other(status) => status,
+        }
+    }
+}
+
+impl From<StatusCode> for Error {
+    fn from(value: StatusCode) -> Self {
+        match value {
+            StatusCode::MissingParameter => Error::MissingParameter,
+            StatusCode::InvalidParameter => Error::InvalidParameter,
+            StatusCode::InvalidSubcommand => Error::InvalidSubcommand,
+            StatusCode::UnsupportedOption => Error::UnsupportedOption,
+            StatusCode::PinPolicyViolation => Error::PinPolicyViolation,
+            StatusCode::PinUvAuthTokenRequired => Error::PinUvAuthTokenRequired,
+            StatusCode::PinAuthInvalid => Error::PinAuthInvalid,
+            StatusCode::UnauthorizedPermission => Error::UnauthorizedPermission,
+            status => Error::Other(status),
+        }
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let message = match self {
+            Error::MissingParameter => "Missing parameter",
+            Error::InvalidParameter => "Invalid parameter",
+            Error::InvalidSubcommand => "Invalid subcommand",
+            Error::UnsupportedOption => "Unsupported option",
+            Error::PinPolicyViolation => "Minimum PIN length cannot be decreased",
+            Error::PinUvAuthTokenRequired => "PIN/UV auth token required",
+            Error::PinAuthInvalid => "PIN auth invalid",
+            Error::UnauthorizedPermission => "Unauthorized permission",
+            Error::Other(status) => return write!(f, "{}", status),
+        };
+        write!(f, "{}", message)
+    }
 }
@@ -0,0 +1,426 @@
+//! Used to make serialization and deserialization of the request possible in
+//! CBOR format while maintaining ergonomic enum variants for public API.
+
+use super::{Error, Request};
+use crate::{
+    authenticator::client_pin::{auth_protocol, PinUvAuthParam},
+    Command,
+};
+use serde::{Deserialize, Serialize};
+use serde_with::{serde_as, skip_serializing_none, Bytes};
+use std::{borrow::Cow, collections::BTreeMap};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(into = "u8", try_from = "u8")]
+pub(crate) enum RawSubcommand {
+    EnableEnterpriseAttestation = 0x01,
+    ToggleAlwaysUv = 0x02,
+    SetMinPinLength = 0x03,
+    VendorPrototype = 0xFF,
+}
+
+impl From<RawSubcommand> for u8 {
+    fn from(val: RawSubcommand) -> Self {
+        val as u8
+    }
+}
+
+impl TryFrom<u8> for RawSubcommand {
+    type Error = Error;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        Ok(match value {
+            0x01 => RawSubcommand::EnableEnterpriseAttestation,
+            0x02 => RawSubcommand::ToggleAlwaysUv,
+            0x03 => RawSubcommand::SetMinPinLength,
+            0xFF => RawSubcommand::VendorPrototype,
+            _ => return Err(Error::InvalidSubcommand),
+        })
+    }
+}
+
+/// The `subCommandParams` of the subcommands that take any.
+#[derive(Clone, Default)]
+pub(crate) struct RawSubcommandParams<'a> {
+    /// `newMinPINLength` of `setMinPINLength`, or `vendorCommandId` of
+    /// `vendorPrototype`: both subcommands use the same key.
+    pub new_min_pin_length_or_vendor_command_id: Option<usize>,
+    pub min_pin_length_rp_ids: Option<Cow<'a, [String]>>,
+    pub force_change_pin: Option<bool>,
+    /// The vendor-defined parameters of `vendorPrototype`, encoded as byte
+    /// string keys and values along with the `vendorCommandId`.
+    pub vendor_params: Option<Cow<'a, BTreeMap<Vec<u8>, Vec<u8>>>>,
+}
+
+impl Serialize for RawSubcommandParams<'_> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        use serde::ser::SerializeMap;
+        use serde_with::ser::SerializeAsWrap;
+
+        // CTAP2 canonical CBOR sorts the integer keys before the byte string
+        // keys, and the byte string keys by length first
+        let mut vendor_params: Vec<_> = self.vendor_params.iter().flat_map(|p| p.iter()).collect();
+        vendor_params.sort_by(|(a, _), (b, _)| a.len().cmp(&b.len()).then_with(|| a.cmp(b)));
+
+        let length = usize::from(self.new_min_pin_length_or_vendor_command_id.is_some())
+            + usize::from(self.min_pin_length_rp_ids.is_some())
+            + usize::from(self.force_change_pin.is_some())
+            + vendor_params.len();
+        let mut map = serializer.serialize_map(Some(length))?;
+        if let Some(value) = &self.new_min_pin_length_or_vendor_command_id {
+            map.serialize_entry(&0x01, value)?;
+        }
+        if let Some(rp_ids) = &self.min_pin_length_rp_ids {
+            map.serialize_entry(&0x02, rp_ids)?;
+        }
+        if let Some(force_change_pin) = &self.force_change_pin {
+            map.serialize_entry(&0x03, force_change_pin)?;
+        }
+        for (key, value) in vendor_params {
+            map.serialize_entry(
+                &SerializeAsWrap::<_, Bytes>::new(key),
+                &SerializeAsWrap::<_, Bytes>::new(value),
+            )?;
+        }
+        map.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for RawSubcommandParams<'_> {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        use ciborium::value::Value;
+        use serde::de::Error;
+
+        let Value::Map(entries) = Value::deserialize(deserializer)? else {
+            return Err(D::Error::custom("expected subCommandParams map"));
+        };
+        let mut params = Self::default();
+        let mut vendor_params = BTreeMap::new();
+        for (key, value) in entries {
+            match key {
+                Value::Integer(key) => match i128::from(key) {
+                    0x01 => {
+                        params.new_min_pin_length_or_vendor_command_id =
+                            Some(value.deserialized().map_err(D::Error::custom)?);
+                    }
+                    0x02 => {
+                        params.min_pin_length_rp_ids =
+                            Some(Cow::Owned(value.deserialized().map_err(D::Error::custom)?));
+                    }
+                    0x03 => {
+                        params.force_change_pin =
+                            Some(value.deserialized().map_err(D::Error::custom)?);
+                    }
+                    _ => {}
+                },
+                Value::Bytes(key) => {
+                    let Value::Bytes(value) = value else {
+                        return Err(D::Error::custom("expected vendor parameter bytes"));
+                    };
+                    vendor_params.insert(key, value);
+                }
+                _ => {}
+            }
+        }
+        if !vendor_params.is_empty() {
+            params.vendor_params = Some(Cow::Owned(vendor_params));
+        }
+        Ok(params)
+    }
+}
+
+#[serde_as]
+#[skip_serializing_none]
+#[derive(Clone, Serialize, Deserialize)]
+pub(crate) struct RawRequest<'a> {
+    #[serde(rename = 0x01)]
+    pub sub_command: RawSubcommand,
+    #[serde(rename = 0x02)]
+    pub sub_command_params: Option<RawSubcommandParams<'a>>,
+    #[serde(rename = 0x03)]
+    pub pin_uv_auth_protocol: Option<auth_protocol::Version>,
+    #[serde_as(as = "Option<Bytes>")]
+    #[serde(rename = 0x04, default)]
+    pub pin_uv_auth_param: Option<PinUvAuthParam>,
+}
+
+impl Request<'_> {
+    pub(super) fn authenticated_subcommand(
+        &self,
+    ) -> (RawSubcommand, Option<RawSubcommandParams<'_>>) {
+        match self {
+            Request::EnableEnterpriseAttestation { .. } => {
+                (RawSubcommand::EnableEnterpriseAttestation, None)
+            }
+            Request::ToggleAlwaysUserVerification { .. } => (RawSubcommand::ToggleAlwaysUv, None),
+            Request::SetMinPinLength {
+                new_min_pin_length,
+                min_pin_length_rp_ids,
+                force_change_pin,
+                ..
+            } => {
+                let params = RawSubcommandParams {
+                    new_min_pin_length_or_vendor_command_id: *new_min_pin_length,
+                    min_pin_length_rp_ids: min_pin_length_rp_ids.as_deref().map(Cow::Borrowed),
+                    force_change_pin: *force_change_pin,
+                    vendor_params: None,
+                };
+                // A request without parameters has no `subCommandParams` map
+                let empty = params.new_min_pin_length_or_vendor_command_id.is_none()
+                    && params.min_pin_length_rp_ids.is_none()
+                    && params.force_change_pin.is_none();
+                (RawSubcommand::SetMinPinLength, (!empty).then_some(params))
+            }
+            Request::VendorPrototype {
+                vendor_command_id,
+                params,
+                ..
+            } => (
+                RawSubcommand::VendorPrototype,
+                Some(RawSubcommandParams {
+                    new_min_pin_length_or_vendor_command_id: Some(*vendor_command_id),
+                    vendor_params: (!params.is_empty()).then_some(Cow::Borrowed(params.as_ref())),
+                    ..Default::default()
+                }),
+            ),
+        }
+    }
+}
+
+impl<'a> From<Request<'a>> for RawRequest<'a> {
+    fn from(value: Request<'a>) -> Self {
+        let (pin_uv_auth_protocol, pin_uv_auth_param) = value.pin_uv_auth();
+        let (sub_command, sub_command_params) = value.authenticated_subcommand();
+        let sub_command_params = sub_command_params.map(|params| RawSubcommandParams {
+            new_min_pin_length_or_vendor_command_id: params.new_min_pin_length_or_vendor_command_id,
+            min_pin_length_rp_ids: params
+                .min_pin_length_rp_ids
+                .map(|rp_ids| Cow::Owned(rp_ids.into_owned())),
+            force_change_pin: params.force_change_pin,
+            vendor_params: params
+                .vendor_params
+                .map(|vendor_params| Cow::Owned(vendor_params.into_owned())),
+        });
+        Self {
+            sub_command,
+            sub_command_params,
+            pin_uv_auth_protocol,
+            pin_uv_auth_param,
+        }
+    }
+}
+
+impl<'a> TryFrom<RawRequest<'a>> for Request<'a> {
+    type Error = Error;
+
+    fn try_from(value: RawRequest<'a>) -> Result<Self, Self::Error> {
+        let RawRequest {
+            sub_command,
+            sub_command_params,
+            pin_uv_auth_protocol,
+            pin_uv_auth_param,
+        } = value;
+        if pin_uv_auth_param.is_some() && pin_uv_auth_protocol.is_none() {
+            return Err(Error::MissingParameter);
+        }
+        let RawSubcommandParams {
+            new_min_pin_length_or_vendor_command_id,
+            min_pin_length_rp_ids,
+            force_change_pin,
+            vendor_params,
+        } = sub_command_params.unwrap_or_default();
+
+        Ok(match sub_command {
+            RawSubcommand::EnableEnterpriseAttestation => Request::EnableEnterpriseAttestation {
+                pin_uv_auth_protocol,
+                pin_uv_auth_param,
+            },
+            RawSubcommand::ToggleAlwaysUv => Request::ToggleAlwaysUserVerification {
+                pin_uv_auth_protocol,
+                pin_uv_auth_param,
+            },
+            RawSubcommand::SetMinPinLength => Request::SetMinPinLength {
+                new_min_pin_length: new_min_pin_length_or_vendor_command_id,
+                min_pin_length_rp_ids,
+                force_change_pin,
+                pin_uv_auth_protocol,
+                pin_uv_auth_param,
+            },
+            RawSubcommand::VendorPrototype => Request::VendorPrototype {
+                vendor_command_id: new_min_pin_length_or_vendor_command_id
+                    .ok_or(Error::MissingParameter)?,
+                params: Cow::Owned(vendor_params.map(Cow::into_owned).unwrap_or_default()),
+                pin_uv_auth_protocol,
+                pin_uv_auth_param,
+            },
+        })
+    }
+}
+
+/// `32 × 0xff || 0x0d || subCommand || subCommandParams`, with the parameters
+/// in their CTAP2 canonical CBOR encoding.
+pub(super) fn authenticated_message(
+    sub_command: RawSubcommand,
+    params: Option<RawSubcommandParams>,
+) -> Vec<u8> {
+    let mut message = vec![0xFF; 32];
+    message.push(Command::AuthenticatorConfig.into());
+    message.push(sub_command.into());
+    if let Some(params) = params {
+        // Neither writing to a `Vec` nor serializing the parameters can fail
+        ciborium::ser::into_writer(&params, &mut message)
+            .expect("subCommandParams are serializable");
+    }
+    message
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode<T: Serialize>(value: &T) -> Vec<u8> {
+        let mut data = Vec::new();
+        ciborium::ser::into_writer(value, &mut data).unwrap();
+        data
+    }
+
+    fn decode(data: &[u8]) -> Result<Request<'static>, Error> {
+        let raw: RawRequest = ciborium::de::from_reader(data).unwrap();
+        raw.try_into()
+    }
+
+    #[test]
+    fn set_min_pin_length_round_trip() {
+        let rp_ids = vec!["example.com".to_owned()];
+        let request = Request::SetMinPinLength {
+            new_min_pin_length: Some(8),
+            min_pin_length_rp_ids: Some(Cow::Borrowed(&rp_ids)),
+            force_change_pin: Some(true),
+            pin_uv_auth_protocol: Some(auth_protocol::Version::Two),
+            pin_uv_auth_param: Some(PinUvAuthParam::Long([0x42; 32])),
+        };
+        assert_eq!(decode(&encode(&request)), Ok(request));
+    }
+
+    #[test]
+    fn set_min_pin_length_message() {
+        let message = authenticated_message(
+            RawSubcommand::SetMinPinLength,
+            Some(RawSubcommandParams {
+                new_min_pin_length_or_vendor_command_id: Some(6),
+                force_change_pin: Some(true),
+                ..Default::default()
+            }),
+        );
+        let expected = [
+            [0xFF; 32].as_slice(),
+            &[0x0D, 0x03, 0xA2, 0x01, 0x06, 0x03, 0xF5],
+        ]
+        .concat();
+        assert_eq!(message, expected);
+    }
+
+    #[test]
+    fn vendor_prototype_round_trip() {
+        let params = BTreeMap::from([(vec![0x02, 0x01], vec![0x03]), (vec![0x04], Vec::new())]);
+        let request = Request::VendorPrototype {
+            vendor_command_id: 0x4242,
+            params: Cow::Borrowed(&params),
+            pin_uv_auth_protocol: Some(auth_protocol::Version::Two),
+            pin_uv_auth_param: Some(PinUvAuthParam::Long([0x42; 32])),
+        };
+        assert_eq!(decode(&encode(&request)), Ok(request));
+    }
+
+    #[test]
+    fn vendor_prototype_message() {
+        let params = BTreeMap::from([(vec![0x02, 0x01], vec![0x03]), (vec![0x04], Vec::new())]);
+        let request = Request::VendorPrototype {
+            vendor_command_id: 7,
+            params: Cow::Borrowed(&params),
+            pin_uv_auth_protocol: Some(auth_protocol::Version::Two),
+            pin_uv_auth_param: None,
+        };
+        // The shorter key comes first, although it is greater
+        let expected = [
+            [0xFF; 32].as_slice(),
+            &[0x0D, 0xFF, 0xA3, 0x01, 0x07],
+            &[0x41, 0x04, 0x40],
+            &[0x42, 0x02, 0x01, 0x41, 0x03],
+        ]
+        .concat();
+        assert_eq!(request.authenticated_message(), expected);
+    }
+
+    #[test]
+    fn vendor_prototype_without_params() {
+        let params = BTreeMap::new();
+        let request = Request::VendorPrototype {
+            vendor_command_id: 7,
+            params: Cow::Borrowed(&params),
+            pin_uv_auth_protocol: Some(auth_protocol::Version::One),
+            pin_uv_auth_param: None,
+        };
+        let expected = [[0xFF; 32].as_slice(), &[0x0D, 0xFF, 0xA1, 0x01, 0x07]].concat();
+        assert_eq!(request.authenticated_message(), expected);
+        assert_eq!(decode(&encode(&request)), Ok(request));
+    }
+
+    #[test]
+    fn unknown_subcommand() {
+        let request = [0xA1, 0x01, 0x04];
+        let raw: Result<RawRequest, _> = ciborium::de::from_reader(request.as_slice());
+        assert!(raw.is_err());
+    }
+
+    #[test]
+    fn without_pin_uv_auth() {
+        let request = Request::ToggleAlwaysUserVerification {
+            pin_uv_auth_protocol: None,
+            pin_uv_auth_param: None,
+        };
+        let encoded = encode(&request);
+        assert_eq!(encoded, [0xA1, 0x01, 0x02]);
+        assert_eq!(decode(&encoded), Ok(request));
+
+        // {1: 2, 4: h'42...'}
+        let encoded = [[0xA2, 0x01, 0x02, 0x04, 0x50].as_slice(), &[0x42; 16]].concat();
+        assert_eq!(decode(&encoded), Err(Error::MissingParameter));
+    }
+
+    #[test]
+    fn toggle_always_uv_message() {
+        let message = authenticated_message(RawSubcommand::ToggleAlwaysUv, None);
+        let expected = [[0xFF; 32].as_slice(), &[0x0D, 0x02]].concat();
+        assert_eq!(message, expected);
+    }
+
+    #[cfg(feature = "crypto")]
+    #[test]
+    fn verify() {
+        use crate::authenticator::{client_pin::auth_protocol::Version, config::Error};
+
+        let token = [0x42; 32];
+        for version in [Version::One, Version::Two] {
+            let request = Request::toggle_always_user_verification(version, &token);
+            assert_eq!(request.verify(&token), Ok(()));
+            assert_eq!(request.verify(&[0x43; 32]), Err(Error::PinAuthInvalid));
+        }
+        let request = Request::ToggleAlwaysUserVerification {
+            pin_uv_auth_protocol: Some(Version::Two),
+            pin_uv_auth_param: None,
+        };
+        assert_eq!(request.verify(&token), Err(Error::PinUvAuthTokenRequired));
+        let request = Request::ToggleAlwaysUserVerification {
+            pin_uv_auth_protocol: None,
+            pin_uv_auth_param: Some(PinUvAuthParam::Long([0x42; 32])),
+        };
+        assert_eq!(request.verify(&token), Err(Error::MissingParameter));
+    }
+}
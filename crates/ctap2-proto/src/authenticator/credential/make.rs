@@ -1,6 +1,8 @@
 use crate::{
     authenticator::{self, client_pin},
-    extensions, Sha256Hash,
+    extensions,
+    status::StatusCode,
+    Sha256Hash,
 };
 use fido_common::{attestation, credential::public_key};
 use std::collections::BTreeMap;
@@ -11,7 +13,10 @@ use serde::{Deserialize, Serialize};
 #[cfg(feature = "serde")]
 use serde_with::{serde_as, skip_serializing_none, Bytes};
 
-#[derive(Debug)]
+#[cfg(feature = "serde")]
+pub(crate) mod raw;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Error {
     OperationDenied,
     PinNotSet,
@@ -27,6 +32,53 @@ pub enum Error {
     PinBlocked,
     CredentialExcluded,
     KeyStoreFull,
+    /// Any other status code, including those defined by extensions and
+    /// vendors.
+    Other(StatusCode),
+}
+
+impl From<Error> for StatusCode {
+    fn from(value: Error) -> Self {
+        match value {
+            Error::OperationDenied => StatusCode::OperationDenied,
+            Error::PinNotSet => StatusCode::PinNotSet,
+            Error::PinInvalid => StatusCode::PinInvalid,
+            Error::InvalidParameter => StatusCode::InvalidParameter,
+            Error::MissingParameter => StatusCode::MissingParameter,
+            Error::UnsupportedAlgorithm => StatusCode::UnsupportedAlgorithm,
+            Error::InvalidOption => StatusCode::InvalidOption,
+            Error::UnsupportedOption => StatusCode::UnsupportedOption,
+            Error::PinUvAuthTokenRequired => StatusCode::PinUvAuthTokenRequired,
+            Error::PinAuthInvalid => StatusCode::PinAuthInvalid,
+            Error::UserActionTimeout => StatusCode::UserActionTimeout,
+            Error::PinBlocked => StatusCode::PinBlocked,
+            Error::CredentialExcluded => StatusCode::CredentialExcluded,
+            Error::KeyStoreFull => StatusCode::KeyStoreFull,
+            Error::Other(status) => status,
+        }
+    }
+}
+
+impl From<StatusCode> for Error {
+    fn from(value: StatusCode) -> Self {
+        match value {
+            StatusCode::OperationDenied => Error::OperationDenied,
+            StatusCode::PinNotSet => Error::PinNotSet,
+            StatusCode::PinInvalid => Error::PinInvalid,
+            StatusCode::InvalidParameter => Error::InvalidParameter,
+            StatusCode::MissingParameter => Error::MissingParameter,
+            StatusCode::UnsupportedAlgorithm => Error::UnsupportedAlgorithm,
+            StatusCode::InvalidOption => Error::InvalidOption,
+            StatusCode::UnsupportedOption => Error::UnsupportedOption,
+            StatusCode::PinUvAuthTokenRequired => Error::PinUvAuthTokenRequired,
+            StatusCode::PinAuthInvalid => Error::PinAuthInvalid,
+            StatusCode::UserActionTimeout => Error::UserActionTimeout,
+            StatusCode::PinBlocked => Error::PinBlocked,
+            StatusCode::CredentialExcluded => Error::CredentialExcluded,
+            StatusCode::KeyStoreFull => Error::KeyStoreFull,
+            status => Error::Other(status),
+        }
+    }
 }
 
 /// > The following option keys are defined for use in
@@ -84,18 +136,24 @@ pub struct Request<'a> {
     /// > Parameters to influence authenticator operation, as specified in
     /// > [WebAuthn]. These parameters might be authenticator specific.
     #[builder(default, setter(strip_option))]
-    #[cfg_attr(feature = "serde", serde(rename = 0x06))]
+    #[cfg_attr(
+        feature = "serde",
+        serde(rename = 0x06, serialize_with = "extensions::raw::serialize")
+    )]
     pub extensions: Option<&'a BTreeMap<extensions::Identifier, Vec<u8>>>,
     #[builder(default, setter(strip_option))]
     #[cfg_attr(feature = "serde", serde(rename = 0x07))]
     pub options: Option<&'a BTreeMap<OptionKey, bool>>,
-    #[builder(default, setter(strip_option))]
+    #[builder(default)]
     #[cfg_attr(
         feature = "serde",
-        serde(rename = 0x08),
-        serde_as(as = "Option<Bytes>")
+        serde(
+            rename = 0x08,
+            serialize_with = "client_pin::raw::serialize_pin_uv_auth",
+            skip_serializing_if = "client_pin::PinUvAuth::is_none"
+        )
     )]
-    pub pin_uv_auth_param: Option<&'a [u8]>,
+    pub pin_uv_auth_param: client_pin::PinUvAuth,
     /// > PIN/UV protocol version selected by platform.
     #[builder(default, setter(strip_option))]
     #[cfg_attr(feature = "serde", serde(rename = 0x09))]
@@ -117,14 +175,79 @@ pub struct Request<'a> {
     pub enterprise_attestation: Option<attestation::enterprise::Kind>,
 }
 
-#[cfg_attr(feature = "serde", derive(Deserialize))]
+impl Request<'_> {
+    /// The message authenticated by the `pinUvAuthParam`, which is just the
+    /// `clientDataHash`.
+    pub fn authenticated_message(&self) -> &[u8] {
+        self.client_data_hash
+    }
+
+    /// Sets the `pinUvAuthParam` computed with the given `pinUvAuthToken`,
+    /// and the PIN/UV protocol version.
+    #[cfg(feature = "crypto")]
+    #[must_use]
+    pub fn authenticated(
+        mut self,
+        pin_uv_auth_protocol_version: client_pin::auth_protocol::Version,
+        pin_uv_auth_token: &[u8],
+    ) -> Self {
+        self.pin_uv_auth_param =
+            client_pin::PinUvAuth::Param(client_pin::auth_protocol::authenticate(
+                pin_uv_auth_protocol_version,
+                pin_uv_auth_token,
+                self.authenticated_message(),
+            ));
+        self.pin_uv_auth_protocol_version = Some(pin_uv_auth_protocol_version);
+        self
+    }
+
+    /// Verifies the `pinUvAuthParam` using the given `pinUvAuthToken`.
+    ///
+    /// Checking whether the token is in use, has the
+    /// [`Permission::MakeCredential`](crate::authenticator::client_pin::Permission::MakeCredential)
+    /// permission and is bound to the request's RP is left to the
+    /// authenticator.
+    #[cfg(feature = "crypto")]
+    pub fn verify(&self, pin_uv_auth_token: &[u8]) -> Result<(), Error> {
+        let pin_uv_auth_param = self
+            .pin_uv_auth_param
+            .param()
+            .ok_or(Error::PinUvAuthTokenRequired)?;
+        let version = self
+            .pin_uv_auth_protocol_version
+            .ok_or(Error::MissingParameter)?;
+        client_pin::auth_protocol::verify(
+            version,
+            pin_uv_auth_token,
+            self.authenticated_message(),
+            pin_uv_auth_param.as_ref(),
+        )
+        .map_err(|_| Error::PinAuthInvalid)
+    }
+}
+
+#[cfg_eval]
+#[cfg_attr(
+    feature = "serde",
+    serde_as,
+    skip_serializing_none,
+    derive(Serialize, Deserialize),
+    serde(try_from = "raw::RawResponse")
+)]
 #[derive(Debug)]
 pub struct Response {
     #[cfg_attr(feature = "serde", serde(rename = 0x01))]
     pub format: fido_common::attestation::FormatIdentifier,
     #[cfg_attr(feature = "serde", serde(rename = 0x02))]
     pub authenticator_data: authenticator::Data,
-    #[cfg_attr(feature = "serde", serde(rename = 0x03))]
+    /// The statement of the `none` attestation format is empty, and is
+    /// represented by `None`. Otherwise, it must be in the attestation's
+    /// `format`.
+    #[cfg_attr(
+        feature = "serde",
+        serialize_always,
+        serde(rename = 0x03, serialize_with = "serialize_statement")
+    )]
     pub attestation_statement: Option<attestation::Statement>,
     /// > Indicates whether an enterprise attestation was returned for this
     /// > credential. If `epAtt` is absent or present and set to false, then an
@@ -134,10 +257,88 @@ pub struct Response {
     pub enterprise_attestation: Option<bool>,
     /// > Contains the `largeBlobKey` for the credential, if requested with the
     /// > `largeBlobKey` extension.
-    #[cfg_attr(feature = "serde", serde(rename = 0x05))]
+    #[cfg_attr(
+        feature = "serde",
+        serde_as(as = "Option<Bytes>"),
+        serde(rename = 0x05)
+    )]
     pub large_blob_key: Option<Vec<u8>>,
     /// > A map, keyed by extension identifiers, to unsigned outputs of
     /// > extensions, if any.
-    #[cfg_attr(feature = "serde", serde(rename = 0x06))]
+    #[cfg_attr(
+        feature = "serde",
+        serde(rename = 0x06, serialize_with = "extensions::raw::serialize_owned")
+    )]
     pub unsigned_extension_outputs: Option<BTreeMap<extensions::Identifier, Vec<u8>>>,
 }
+
+#[cfg(feature = "serde")]
+fn serialize_statement<S>(
+    statement: &Option<attestation::Statement>,
+    serializer: S,
+) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+{
+    use ciborium::value::Value;
+
+    match statement {
+        Some(statement) => statement
+            .to_cbor_value()
+            .map_err(serde::ser::Error::custom)?
+            .serialize(serializer),
+        None => Value::Map(Vec::new()).serialize(serializer),
+    }
+}
+
+#[cfg(all(test, feature = "crypto"))]
+mod tests {
+    use super::*;
+    use client_pin::{auth_protocol::Version, PinUvAuth, PinUvAuthParam};
+    use coset::iana::Algorithm;
+    use fido_common::credential;
+
+    #[test]
+    fn authenticated() {
+        let relying_party = public_key::RelyingPartyEntity {
+            id: "example.com".to_owned(),
+            name: None,
+        };
+        let user = public_key::UserEntity {
+            id: vec![0x01],
+            name: None,
+            display_name: None,
+        };
+        let parameters = [public_key::Parameters {
+            credential_type: credential::Type::PublicKey,
+            algorithm: Algorithm::ES256,
+        }];
+        let request = Request::builder()
+            .client_data_hash(&[0x02; 32])
+            .relying_party(&relying_party)
+            .user(&user)
+            .public_key_credential_params(&parameters)
+            .build();
+        assert_eq!(
+            request.verify(&[0x03; 32]),
+            Err(Error::PinUvAuthTokenRequired)
+        );
+
+        let one = request.authenticated(Version::One, &[0x03; 32]);
+        assert!(matches!(
+            one.pin_uv_auth_param,
+            PinUvAuth::Param(PinUvAuthParam::Short(_))
+        ));
+        assert_eq!(one.pin_uv_auth_protocol_version, Some(Version::One));
+        assert_eq!(one.verify(&[0x03; 32]), Ok(()));
+        assert_eq!(one.verify(&[0x04; 32]), Err(Error::PinAuthInvalid));
+
+        let two = request.authenticated(Version::Two, &[0x03; 32]);
+        assert!(matches!(
+            two.pin_uv_auth_param,
+            PinUvAuth::Param(PinUvAuthParam::Long(_))
+        ));
+        assert_eq!(two.verify(&[0x03; 32]), Ok(()));
+        assert_eq!(two.verify(&[0x04; 32]), Err(Error::PinAuthInvalid));
+    }
+}
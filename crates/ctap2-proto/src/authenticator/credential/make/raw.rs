@@ -0,0 +1,265 @@
+//! Owned request parameters, used to deserialize a [`Request`] received by an
+//! authenticator, and the response parameters, whose attestation statement
+//! can only be decoded once its format is known.
+
+use super::{Error, OptionKey, Request, Response};
+use crate::{
+    authenticator::client_pin::{auth_protocol, PinUvAuth, PinUvAuthParam},
+    extensions, Sha256Hash,
+};
+use ciborium::value::Value;
+use fido_common::{attestation, authenticator, credential::public_key};
+use serde::Deserialize;
+use serde_with::{serde_as, Bytes};
+use std::collections::BTreeMap;
+
+#[serde_as]
+#[derive(Deserialize)]
+pub(crate) struct RawRequest {
+    #[serde_as(as = "Bytes")]
+    #[serde(rename = 0x01)]
+    client_data_hash: Sha256Hash,
+    #[serde(rename = 0x02)]
+    relying_party: public_key::RelyingPartyEntity,
+    #[serde(rename = 0x03)]
+    user: public_key::UserEntity,
+    #[serde(rename = 0x04)]
+    public_key_credential_params: Vec<public_key::Parameters>,
+    #[serde(rename = 0x05)]
+    exclude_list: Option<Vec<public_key::Descriptor>>,
+    #[serde(
+        rename = 0x06,
+        deserialize_with = "extensions::raw::deserialize",
+        default
+    )]
+    extensions: Option<BTreeMap<extensions::Identifier, Vec<u8>>>,
+    #[serde(rename = 0x07)]
+    options: Option<BTreeMap<OptionKey, bool>>,
+    #[serde_as(as = "Option<Bytes>")]
+    #[serde(rename = 0x08)]
+    pin_uv_auth_param: Option<Vec<u8>>,
+    #[serde(rename = 0x09)]
+    pin_uv_auth_protocol_version: Option<auth_protocol::Version>,
+    #[serde(rename = 0x0A)]
+    enterprise_attestation: Option<attestation::enterprise::Kind>,
+}
+
+impl RawRequest {
+    /// Calls `f` with a [`Request`] borrowing the deserialized parameters.
+    ///
+    /// A `pinUvAuthParam` whose length is that of neither PIN/UV auth
+    /// protocol is invalid.
+    pub(crate) fn with_request<T>(
+        &self,
+        f: impl FnOnce(Request<'_>) -> Result<T, Error>,
+    ) -> Result<T, Error> {
+        // A zero length pinUvAuthParam asks for authenticator selection
+        let pin_uv_auth_param = match self.pin_uv_auth_param.as_deref() {
+            None => PinUvAuth::None,
+            Some([]) => PinUvAuth::Selection,
+            Some(pin_uv_auth_param) => PinUvAuth::Param(
+                PinUvAuthParam::try_from(pin_uv_auth_param).map_err(|_| Error::PinAuthInvalid)?,
+            ),
+        };
+        let exclude_list: Option<Vec<&public_key::Descriptor>> = self
+            .exclude_list
+            .as_ref()
+            .map(|exclude_list| exclude_list.iter().collect());
+
+        f(Request {
+            client_data_hash: &self.client_data_hash,
+            relying_party: &self.relying_party,
+            user: &self.user,
+            public_key_credential_params: &self.public_key_credential_params,
+            exclude_list: exclude_list.as_deref(),
+            extensions: self.extensions.as_ref(),
+            options: self.options.as_ref(),
+            pin_uv_auth_param,
+            pin_uv_auth_protocol_version: self.pin_uv_auth_protocol_version,
+            enterprise_attestation: self.enterprise_attestation,
+        })
+    }
+}
+
+#[serde_as]
+#[derive(Deserialize)]
+pub(crate) struct RawResponse {
+    #[serde(rename = 0x01)]
+    format: attestation::FormatIdentifier,
+    #[serde(rename = 0x02)]
+    authenticator_data: authenticator::Data,
+    #[serde(rename = 0x03)]
+    attestation_statement: Value,
+    #[serde(rename = 0x04)]
+    enterprise_attestation: Option<bool>,
+    #[serde_as(as = "Option<Bytes>")]
+    #[serde(rename = 0x05, default)]
+    large_blob_key: Option<Vec<u8>>,
+    #[serde(
+        rename = 0x06,
+        deserialize_with = "extensions::raw::deserialize",
+        default
+    )]
+    unsigned_extension_outputs: Option<BTreeMap<extensions::Identifier, Vec<u8>>>,
+}
+
+impl TryFrom<RawResponse> for Response {
+    type Error = ciborium::value::Error;
+
+    fn try_from(value: RawResponse) -> Result<Self, Self::Error> {
+        Ok(Self {
+            format: value.format,
+            authenticator_data: value.authenticator_data,
+            attestation_statement: attestation::Statement::from_cbor_value(
+                value.format,
+                value.attestation_statement,
+            )?,
+            enterprise_attestation: value.enterprise_attestation,
+            large_blob_key: value.large_blob_key,
+            unsigned_extension_outputs: value.unsigned_extension_outputs,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use coset::iana::Algorithm;
+    use fido_common::credential::{self, BackupEligibility, BackupState};
+
+    fn response(
+        format: attestation::FormatIdentifier,
+        attestation_statement: Option<attestation::Statement>,
+    ) -> Response {
+        Response {
+            format,
+            authenticator_data: authenticator::Data {
+                relying_party_id_hash: [0x01; 32],
+                user_presence: authenticator::UserPresence::Present,
+                user_verification: authenticator::UserVerification::NotVerified,
+                backup_eligibility: BackupEligibility::Ineligible,
+                backup_state: BackupState::NotBackedUp,
+                signature_counter: 0,
+                attested_credential_data: None,
+                extensions: None,
+            },
+            attestation_statement,
+            enterprise_attestation: None,
+            large_blob_key: None,
+            unsigned_extension_outputs: None,
+        }
+    }
+
+    fn encode(response: &Response) -> Vec<u8> {
+        let mut data = Vec::new();
+        ciborium::ser::into_writer(response, &mut data).unwrap();
+        data
+    }
+
+    #[test]
+    fn packed_self_attestation() {
+        let statement = attestation::Statement::Packed {
+            algorithm: Algorithm::ES256,
+            signature: vec![0x02; 70],
+            attestation_certificate_chain: None,
+        };
+        let response = response(attestation::FormatIdentifier::Packed, Some(statement));
+        let decoded: Response = ciborium::de::from_reader(encode(&response).as_slice()).unwrap();
+        assert_eq!(decoded.format, response.format);
+        assert_eq!(
+            decoded.attestation_statement,
+            response.attestation_statement
+        );
+    }
+
+    #[test]
+    fn none_attestation() {
+        let response = response(attestation::FormatIdentifier::None, None);
+        let decoded: Response = ciborium::de::from_reader(encode(&response).as_slice()).unwrap();
+        assert_eq!(decoded.attestation_statement, None);
+    }
+
+    #[test]
+    fn statement_in_another_format() {
+        let statement = attestation::Statement::AndroidSafetyNet {
+            version: "1".to_owned(),
+            response: vec![0x03; 16],
+        };
+        let response = response(attestation::FormatIdentifier::FidoU2f, Some(statement));
+        let decoded: Result<Response, _> = ciborium::de::from_reader(encode(&response).as_slice());
+        assert!(decoded.is_err());
+    }
+
+    /// Encodes the request with the given `pinUvAuthParam`, and decodes it
+    /// as an authenticator would.
+    fn round_trip(pin_uv_auth_param: PinUvAuth) -> Result<PinUvAuth, Error> {
+        let relying_party = public_key::RelyingPartyEntity {
+            id: "example.com".to_owned(),
+            name: None,
+        };
+        let user = public_key::UserEntity {
+            id: vec![0x01],
+            name: None,
+            display_name: None,
+        };
+        let parameters = [public_key::Parameters {
+            credential_type: credential::Type::PublicKey,
+            algorithm: Algorithm::ES256,
+        }];
+        let request = Request {
+            client_data_hash: &[0x02; 32],
+            relying_party: &relying_party,
+            user: &user,
+            public_key_credential_params: &parameters,
+            exclude_list: None,
+            extensions: None,
+            options: None,
+            pin_uv_auth_param,
+            pin_uv_auth_protocol_version: Some(auth_protocol::Version::Two),
+            enterprise_attestation: None,
+        };
+        let mut data = Vec::new();
+        ciborium::ser::into_writer(&request, &mut data).unwrap();
+        let raw: RawRequest = ciborium::de::from_reader(data.as_slice()).unwrap();
+        raw.with_request(|request| {
+            assert_eq!(request.relying_party, &relying_party);
+            Ok(request.pin_uv_auth_param)
+        })
+    }
+
+    #[test]
+    fn pin_uv_auth_param() {
+        let pin_uv_auth_param = PinUvAuth::Param(PinUvAuthParam::Long([0x03; 32]));
+        assert_eq!(round_trip(pin_uv_auth_param), Ok(pin_uv_auth_param));
+        assert_eq!(round_trip(PinUvAuth::None), Ok(PinUvAuth::None));
+    }
+
+    #[test]
+    fn authenticator_selection() {
+        assert_eq!(round_trip(PinUvAuth::Selection), Ok(PinUvAuth::Selection));
+    }
+
+    #[test]
+    fn invalid_pin_uv_auth_param_length() {
+        let raw = RawRequest {
+            client_data_hash: [0x02; 32],
+            relying_party: public_key::RelyingPartyEntity {
+                id: "example.com".to_owned(),
+                name: None,
+            },
+            user: public_key::UserEntity {
+                id: vec![0x01],
+                name: None,
+                display_name: None,
+            },
+            public_key_credential_params: Vec::new(),
+            exclude_list: None,
+            extensions: None,
+            options: None,
+            pin_uv_auth_param: Some(vec![0x03; 20]),
+            pin_uv_auth_protocol_version: Some(auth_protocol::Version::Two),
+            enterprise_attestation: None,
+        };
+        assert_eq!(raw.with_request(|_| Ok(())), Err(Error::PinAuthInvalid));
+    }
+}
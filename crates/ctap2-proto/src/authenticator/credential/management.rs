@@ -1,57 +1,248 @@
-use crate::{authenticator::client_pin, extensions::cred_protect, Sha256Hash};
+use crate::{authenticator::client_pin, extensions::cred_protect, status::StatusCode, Sha256Hash};
 use fido_common::credential::public_key;
-use std::fmt::Display;
+use std::{borrow::Cow, fmt::Display};
 
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+pub use client_pin::PinUvAuthParam;
 
-pub type PinUvAuthParam = [u8; 16];
+#[cfg(feature = "serde")]
+pub(crate) mod raw;
 
 #[cfg(feature = "serde")]
-mod raw;
+use raw::{RawRequest, RawResponse};
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(into = "RawRequest", try_from = "RawRequest")
+)]
 pub enum Request<'a> {
     GetCredentialsMetadata {
         /// > PIN/UV protocol version chosen by the platform.
         pin_uv_auth_protocol: client_pin::auth_protocol::Version,
         /// > First 16 bytes of HMAC-SHA-256 of contents using `pinUvAuthToken`.
-        pin_uv_auth_param: &'a PinUvAuthParam,
+        pin_uv_auth_param: PinUvAuthParam,
     },
     EnumerateRPsBegin {
         /// > PIN/UV protocol version chosen by the platform.
         pin_uv_auth_protocol: client_pin::auth_protocol::Version,
         /// > First 16 bytes of HMAC-SHA-256 of contents using `pinUvAuthToken`.
-        pin_uv_auth_param: &'a PinUvAuthParam,
+        pin_uv_auth_param: PinUvAuthParam,
     },
     EnumerateRPsGetNextRP,
     EnumerateCredentialsBegin {
         /// The ID of the relying party to enumerate credentials for.
-        relying_party_id_hash: &'a Sha256Hash,
+        relying_party_id_hash: Cow<'a, Sha256Hash>,
         /// > PIN/UV protocol version chosen by the platform.
         pin_uv_auth_protocol: client_pin::auth_protocol::Version,
         /// > First 16 bytes of HMAC-SHA-256 of contents using `pinUvAuthToken`.
-        pin_uv_auth_param: &'a PinUvAuthParam,
+        pin_uv_auth_param: PinUvAuthParam,
     },
     EnumerateCredentialsGetNextCredential,
     DeleteCredential {
         /// The ID of the credential to delete.
-        credential_id: &'a public_key::Descriptor,
+        credential_id: Cow<'a, public_key::Descriptor>,
         /// > PIN/UV protocol version chosen by the platform.
         pin_uv_auth_protocol: client_pin::auth_protocol::Version,
         /// > First 16 bytes of HMAC-SHA-256 of contents using `pinUvAuthToken`.
-        pin_uv_auth_param: &'a PinUvAuthParam,
+        pin_uv_auth_param: PinUvAuthParam,
     },
     UpdateUserInformation {
         /// The ID of the credential to update.
-        credential_id: &'a public_key::Descriptor,
+        credential_id: Cow<'a, public_key::Descriptor>,
         /// The updated user information.
-        user: &'a public_key::UserEntity,
+        user: Cow<'a, public_key::UserEntity>,
         /// > PIN/UV protocol version chosen by the platform.
         pin_uv_auth_protocol: client_pin::auth_protocol::Version,
         /// > First 16 bytes of HMAC-SHA-256 of contents using `pinUvAuthToken`.
-        pin_uv_auth_param: &'a PinUvAuthParam,
+        pin_uv_auth_param: PinUvAuthParam,
     },
 }
 
+impl Request<'_> {
+    /// The `pinUvAuthProtocol` and `pinUvAuthParam`, or `None` for the
+    /// subcommands that continue an enumeration, which are not authenticated.
+    pub fn pin_uv_auth(&self) -> Option<(client_pin::auth_protocol::Version, PinUvAuthParam)> {
+        match *self {
+            Request::GetCredentialsMetadata {
+                pin_uv_auth_protocol,
+                pin_uv_auth_param,
+            }
+            | Request::EnumerateRPsBegin {
+                pin_uv_auth_protocol,
+                pin_uv_auth_param,
+            }
+            | Request::EnumerateCredentialsBegin {
+                pin_uv_auth_protocol,
+                pin_uv_auth_param,
+                ..
+            }
+            | Request::DeleteCredential {
+                pin_uv_auth_protocol,
+                pin_uv_auth_param,
+                ..
+            }
+            | Request::UpdateUserInformation {
+                pin_uv_auth_protocol,
+                pin_uv_auth_param,
+                ..
+            } => Some((pin_uv_auth_protocol, pin_uv_auth_param)),
+            Request::EnumerateRPsGetNextRP | Request::EnumerateCredentialsGetNextCredential => None,
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl Request<'_> {
+    /// The message authenticated by the `pinUvAuthParam`: `subCommand ||
+    /// subCommandParams`. Returns `None` for the subcommands that continue an
+    /// enumeration, which are not authenticated.
+    pub fn authenticated_message(&self) -> Option<Vec<u8>> {
+        self.authenticated_subcommand()
+            .map(|(sub_command, params)| raw::authenticated_message(sub_command, params))
+    }
+
+    /// Verifies the `pinUvAuthParam` using the given `pinUvAuthToken`.
+    ///
+    /// Checking whether the token is in use and has the
+    /// [`client_pin::Permission::CredentialManagement`] permission is left to
+    /// the authenticator.
+    #[cfg(feature = "crypto")]
+    pub fn verify(&self, pin_uv_auth_token: &[u8]) -> Result<(), Error> {
+        let (Some((version, pin_uv_auth_param)), Some(message)) =
+            (self.pin_uv_auth(), self.authenticated_message())
+        else {
+            return Ok(());
+        };
+        client_pin::auth_protocol::verify(
+            version,
+            pin_uv_auth_token,
+            &message,
+            pin_uv_auth_param.as_ref(),
+        )
+        .map_err(|_| Error::PinAuthInvalid)
+    }
+}
+
+#[cfg(all(feature = "serde", feature = "crypto"))]
+impl<'a> Request<'a> {
+    pub fn get_credentials_metadata(
+        pin_uv_auth_protocol: client_pin::auth_protocol::Version,
+        pin_uv_auth_token: &[u8],
+    ) -> Self {
+        Request::GetCredentialsMetadata {
+            pin_uv_auth_protocol,
+            pin_uv_auth_param: authenticate(
+                pin_uv_auth_protocol,
+                pin_uv_auth_token,
+                raw::RawSubcommand::GetCredsMetadata,
+                None,
+            ),
+        }
+    }
+
+    pub fn enumerate_rps_begin(
+        pin_uv_auth_protocol: client_pin::auth_protocol::Version,
+        pin_uv_auth_token: &[u8],
+    ) -> Self {
+        Request::EnumerateRPsBegin {
+            pin_uv_auth_protocol,
+            pin_uv_auth_param: authenticate(
+                pin_uv_auth_protocol,
+                pin_uv_auth_token,
+                raw::RawSubcommand::EnumerateRpsBegin,
+                None,
+            ),
+        }
+    }
+
+    pub fn enumerate_credentials_begin(
+        relying_party_id_hash: &'a Sha256Hash,
+        pin_uv_auth_protocol: client_pin::auth_protocol::Version,
+        pin_uv_auth_token: &[u8],
+    ) -> Self {
+        let params = raw::RawSubcommandParams {
+            relying_party_id_hash: Some(*relying_party_id_hash),
+            ..Default::default()
+        };
+        Request::EnumerateCredentialsBegin {
+            relying_party_id_hash: Cow::Borrowed(relying_party_id_hash),
+            pin_uv_auth_protocol,
+            pin_uv_auth_param: authenticate(
+                pin_uv_auth_protocol,
+                pin_uv_auth_token,
+                raw::RawSubcommand::EnumerateCredentialsBegin,
+                Some(params),
+            ),
+        }
+    }
+
+    pub fn delete_credential(
+        credential_id: &'a public_key::Descriptor,
+        pin_uv_auth_protocol: client_pin::auth_protocol::Version,
+        pin_uv_auth_token: &[u8],
+    ) -> Self {
+        let params = raw::RawSubcommandParams {
+            credential_id: Some(Cow::Borrowed(credential_id)),
+            ..Default::default()
+        };
+        Request::DeleteCredential {
+            credential_id: Cow::Borrowed(credential_id),
+            pin_uv_auth_protocol,
+            pin_uv_auth_param: authenticate(
+                pin_uv_auth_protocol,
+                pin_uv_auth_token,
+                raw::RawSubcommand::DeleteCredential,
+                Some(params),
+            ),
+        }
+    }
+
+    pub fn update_user_information(
+        credential_id: &'a public_key::Descriptor,
+        user: &'a public_key::UserEntity,
+        pin_uv_auth_protocol: client_pin::auth_protocol::Version,
+        pin_uv_auth_token: &[u8],
+    ) -> Self {
+        let params = raw::RawSubcommandParams {
+            credential_id: Some(Cow::Borrowed(credential_id)),
+            user: Some(Cow::Borrowed(user)),
+            ..Default::default()
+        };
+        Request::UpdateUserInformation {
+            credential_id: Cow::Borrowed(credential_id),
+            user: Cow::Borrowed(user),
+            pin_uv_auth_protocol,
+            pin_uv_auth_param: authenticate(
+                pin_uv_auth_protocol,
+                pin_uv_auth_token,
+                raw::RawSubcommand::UpdateUserInformation,
+                Some(params),
+            ),
+        }
+    }
+}
+
+#[cfg(all(feature = "serde", feature = "crypto"))]
+fn authenticate(
+    version: client_pin::auth_protocol::Version,
+    pin_uv_auth_token: &[u8],
+    sub_command: raw::RawSubcommand,
+    params: Option<raw::RawSubcommandParams>,
+) -> PinUvAuthParam {
+    let message = raw::authenticated_message(sub_command, params);
+    client_pin::auth_protocol::authenticate(version, pin_uv_auth_token, &message)
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(into = "RawResponse", try_from = "RawResponse")
+)]
 pub enum Response {
     GetCredentialsMetadata {
         /// > Number of existing discoverable credentials present on the
@@ -82,6 +273,49 @@ pub enum Response {
     UpdateUserInformation,
 }
 
+impl Response {
+    /// Interprets the response as the one to the given request.
+    ///
+    /// The encoding of a response doesn't identify its subcommand:
+    /// `deleteCredential` and `updateUserInformation` both return an empty
+    /// response. Returns [`Error::MissingParameter`] if the response lacks the
+    /// parameters of the request's response.
+    pub fn for_request(self, request: &Request) -> Result<Self, Error> {
+        Ok(match (request, self) {
+            (
+                Request::GetCredentialsMetadata { .. },
+                response @ Response::GetCredentialsMetadata { .. },
+            )
+            | (Request::EnumerateRPsBegin { .. }, response @ Response::EnumerateRPsBegin { .. })
+            | (Request::EnumerateRPsGetNextRP, response @ Response::EnumerateRPsGetNextRP { .. })
+            | (
+                Request::EnumerateCredentialsBegin { .. },
+                response @ Response::EnumerateCredentialsBegin { .. },
+            )
+            | (
+                Request::EnumerateCredentialsGetNextCredential,
+                response @ Response::EnumerateCredentialsGetNextCredential { .. },
+            ) => response,
+            (Request::DeleteCredential { .. }, Response::DeleteCredential) => {
+                Response::DeleteCredential
+            }
+            (Request::UpdateUserInformation { .. }, Response::DeleteCredential) => {
+                Response::UpdateUserInformation
+            }
+            _ => return Err(Error::MissingParameter),
+        })
+    }
+
+    /// Whether the response is encoded without any parameters.
+    pub fn is_empty(&self) -> bool {
+        matches!(
+            self,
+            Response::DeleteCredential | Response::UpdateUserInformation
+        )
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct RelyingParty {
     /// The description of the relying party.
     pub relying_party: public_key::RelyingPartyEntity,
@@ -89,20 +323,24 @@ pub struct RelyingParty {
     pub relying_party_id_hash: Sha256Hash,
 }
 
+#[derive(Clone, Debug, PartialEq)]
 pub struct Credential {
     /// The description of the user account associated with the credential.
     pub user: public_key::UserEntity,
     /// A description of the public key associated with the credential.
     pub credential_id: public_key::Descriptor,
     /// The public key associated with the credential.
-    pub public_key: Vec<u8>, // TODO: Replace arbitrary bytes with parsed key type
+    pub public_key: public_key::Key,
     /// Indicates the level of user verification the authenticator requires for
     /// this credential.
     pub credential_protection_policy: cred_protect::Policy,
     /// > Large blob encryption key.
+    ///
+    /// Empty if the credential has none.
     pub large_blob_key: Vec<u8>,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Error {
     PinUvAuthTokenRequired,
     MissingParameter,
@@ -110,6 +348,37 @@ pub enum Error {
     PinAuthInvalid,
     NoCredentials,
     KeyStoreFull,
+    /// Any other status code, including those defined by extensions and
+    /// vendors.
+    Other(StatusCode),
+}
+
+impl From<Error> for StatusCode {
+    fn from(value: Error) -> Self {
+        match value {
+            Error::PinUvAuthTokenRequired => StatusCode::PinUvAuthTokenRequired,
+            Error::MissingParameter => StatusCode::MissingParameter,
+            Error::InvalidParameter => StatusCode::InvalidParameter,
+            Error::PinAuthInvalid => StatusCode::PinAuthInvalid,
+            Error::NoCredentials => StatusCode::NoCredentials,
+            Error::KeyStoreFull => StatusCode::KeyStoreFull,
+            Error::Other(status) => status,
+        }
+    }
+}
+
+impl From<StatusCode> for Error {
+    fn from(value: StatusCode) -> Self {
+        match value {
+            StatusCode::PinUvAuthTokenRequired => Error::PinUvAuthTokenRequired,
+            StatusCode::MissingParameter => Error::MissingParameter,
+            StatusCode::InvalidParameter => Error::InvalidParameter,
+            StatusCode::PinAuthInvalid => Error::PinAuthInvalid,
+            StatusCode::NoCredentials => Error::NoCredentials,
+            StatusCode::KeyStoreFull => Error::KeyStoreFull,
+            status => Error::Other(status),
+        }
+    }
 }
 
 impl Display for Error {
@@ -121,6 +390,7 @@ impl Display for Error {
             Error::PinAuthInvalid => "PIN auth invalid",
             Error::NoCredentials => "No credentials",
             Error::KeyStoreFull => "Key store full",
+            Error::Other(status) => return write!(f, "{}", status),
         };
         write!(f, "{}", message)
     }
@@ -1,9 +1,21 @@
-use super::Error;
+//! Used to make serialization and deserialization of the request and response
+//! possible in CBOR format while maintaining ergonomic enum variants for public
+//! API.
+
+use super::{Credential, Error, RelyingParty, Request, Response};
+use crate::{
+    authenticator::client_pin::{auth_protocol, PinUvAuthParam},
+    extensions::cred_protect,
+    Sha256Hash,
+};
+use fido_common::credential::public_key;
 use serde::{Deserialize, Serialize};
+use serde_with::{serde_as, skip_serializing_none, Bytes};
+use std::borrow::Cow;
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(into = "u8", try_from = "u8")]
-enum RawSubcommand {
+pub(crate) enum RawSubcommand {
     GetCredsMetadata = 0x01,
     EnumerateRpsBegin = 0x02,
     EnumerateRpsGetNextRp = 0x03,
@@ -35,3 +47,515 @@ impl TryFrom<u8> for RawSubcommand {
         })
     }
 }
+
+/// The `subCommandParams` of the subcommands that take any.
+#[serde_as]
+#[skip_serializing_none]
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub(crate) struct RawSubcommandParams<'a> {
+    #[serde_as(as = "Option<Bytes>")]
+    #[serde(rename = 0x01, default)]
+    pub relying_party_id_hash: Option<Sha256Hash>,
+    #[serde(rename = 0x02)]
+    pub credential_id: Option<Cow<'a, public_key::Descriptor>>,
+    #[serde(rename = 0x03)]
+    pub user: Option<Cow<'a, public_key::UserEntity>>,
+}
+
+#[serde_as]
+#[skip_serializing_none]
+#[derive(Clone, Serialize, Deserialize)]
+pub(crate) struct RawRequest<'a> {
+    #[serde(rename = 0x01)]
+    pub sub_command: RawSubcommand,
+    #[serde(rename = 0x02)]
+    pub sub_command_params: Option<RawSubcommandParams<'a>>,
+    #[serde(rename = 0x03)]
+    pub pin_uv_auth_protocol: Option<auth_protocol::Version>,
+    #[serde_as(as = "Option<Bytes>")]
+    #[serde(rename = 0x04, default)]
+    pub pin_uv_auth_param: Option<PinUvAuthParam>,
+}
+
+impl Request<'_> {
+    /// The subcommand and its parameters, if any, or `None` for the
+    /// subcommands that do not take a `pinUvAuthParam`.
+    pub(super) fn authenticated_subcommand(
+        &self,
+    ) -> Option<(RawSubcommand, Option<RawSubcommandParams<'_>>)> {
+        Some(match self {
+            Request::GetCredentialsMetadata { .. } => (RawSubcommand::GetCredsMetadata, None),
+            Request::EnumerateRPsBegin { .. } => (RawSubcommand::EnumerateRpsBegin, None),
+            Request::EnumerateCredentialsBegin {
+                relying_party_id_hash,
+                ..
+            } => (
+                RawSubcommand::EnumerateCredentialsBegin,
+                Some(RawSubcommandParams {
+                    relying_party_id_hash: Some(**relying_party_id_hash),
+                    ..Default::default()
+                }),
+            ),
+            Request::DeleteCredential { credential_id, .. } => (
+                RawSubcommand::DeleteCredential,
+                Some(RawSubcommandParams {
+                    credential_id: Some(Cow::Borrowed(credential_id)),
+                    ..Default::default()
+                }),
+            ),
+            Request::UpdateUserInformation {
+                credential_id,
+                user,
+                ..
+            } => (
+                RawSubcommand::UpdateUserInformation,
+                Some(RawSubcommandParams {
+                    credential_id: Some(Cow::Borrowed(credential_id)),
+                    user: Some(Cow::Borrowed(user)),
+                    ..Default::default()
+                }),
+            ),
+            Request::EnumerateRPsGetNextRP | Request::EnumerateCredentialsGetNextCredential => {
+                return None
+            }
+        })
+    }
+}
+
+impl<'a> From<Request<'a>> for RawRequest<'a> {
+    fn from(value: Request<'a>) -> Self {
+        let (pin_uv_auth_protocol, pin_uv_auth_param) = value.pin_uv_auth().unzip();
+        let (sub_command, sub_command_params) = match value {
+            Request::GetCredentialsMetadata { .. } => (RawSubcommand::GetCredsMetadata, None),
+            Request::EnumerateRPsBegin { .. } => (RawSubcommand::EnumerateRpsBegin, None),
+            Request::EnumerateRPsGetNextRP => (RawSubcommand::EnumerateRpsGetNextRp, None),
+            Request::EnumerateCredentialsBegin {
+                relying_party_id_hash,
+                ..
+            } => (
+                RawSubcommand::EnumerateCredentialsBegin,
+                Some(RawSubcommandParams {
+                    relying_party_id_hash: Some(relying_party_id_hash.into_owned()),
+                    ..Default::default()
+                }),
+            ),
+            Request::EnumerateCredentialsGetNextCredential => {
+                (RawSubcommand::EnumerateCredentialsGetNextCredential, None)
+            }
+            Request::DeleteCredential { credential_id, .. } => (
+                RawSubcommand::DeleteCredential,
+                Some(RawSubcommandParams {
+                    credential_id: Some(credential_id),
+                    ..Default::default()
+                }),
+            ),
+            Request::UpdateUserInformation {
+                credential_id,
+                user,
+                ..
+            } => (
+                RawSubcommand::UpdateUserInformation,
+                Some(RawSubcommandParams {
+                    credential_id: Some(credential_id),
+                    user: Some(user),
+                    ..Default::default()
+                }),
+            ),
+        };
+        Self {
+            sub_command,
+            sub_command_params,
+            pin_uv_auth_protocol,
+            pin_uv_auth_param,
+        }
+    }
+}
+
+impl<'a> TryFrom<RawRequest<'a>> for Request<'a> {
+    type Error = Error;
+
+    fn try_from(value: RawRequest<'a>) -> Result<Self, Self::Error> {
+        let RawRequest {
+            sub_command,
+            sub_command_params,
+            pin_uv_auth_protocol,
+            pin_uv_auth_param,
+        } = value;
+        let RawSubcommandParams {
+            relying_party_id_hash,
+            credential_id,
+            user,
+        } = sub_command_params.unwrap_or_default();
+        let pin_uv_auth = || match (pin_uv_auth_protocol, pin_uv_auth_param) {
+            (Some(protocol), Some(param)) => Ok((protocol, param)),
+            (_, None) => Err(Error::PinUvAuthTokenRequired),
+            (None, Some(_)) => Err(Error::MissingParameter),
+        };
+
+        Ok(match sub_command {
+            RawSubcommand::GetCredsMetadata => {
+                let (pin_uv_auth_protocol, pin_uv_auth_param) = pin_uv_auth()?;
+                Request::GetCredentialsMetadata {
+                    pin_uv_auth_protocol,
+                    pin_uv_auth_param,
+                }
+            }
+            RawSubcommand::EnumerateRpsBegin => {
+                let (pin_uv_auth_protocol, pin_uv_auth_param) = pin_uv_auth()?;
+                Request::EnumerateRPsBegin {
+                    pin_uv_auth_protocol,
+                    pin_uv_auth_param,
+                }
+            }
+            RawSubcommand::EnumerateRpsGetNextRp => Request::EnumerateRPsGetNextRP,
+            RawSubcommand::EnumerateCredentialsBegin => {
+                let relying_party_id_hash = relying_party_id_hash.ok_or(Error::MissingParameter)?;
+                let (pin_uv_auth_protocol, pin_uv_auth_param) = pin_uv_auth()?;
+                Request::EnumerateCredentialsBegin {
+                    relying_party_id_hash: Cow::Owned(relying_party_id_hash),
+                    pin_uv_auth_protocol,
+                    pin_uv_auth_param,
+                }
+            }
+            RawSubcommand::EnumerateCredentialsGetNextCredential => {
+                Request::EnumerateCredentialsGetNextCredential
+            }
+            RawSubcommand::DeleteCredential => {
+                let credential_id = credential_id.ok_or(Error::MissingParameter)?;
+                let (pin_uv_auth_protocol, pin_uv_auth_param) = pin_uv_auth()?;
+                Request::DeleteCredential {
+                    credential_id,
+                    pin_uv_auth_protocol,
+                    pin_uv_auth_param,
+                }
+            }
+            RawSubcommand::UpdateUserInformation => {
+                let (Some(credential_id), Some(user)) = (credential_id, user) else {
+                    return Err(Error::MissingParameter);
+                };
+                let (pin_uv_auth_protocol, pin_uv_auth_param) = pin_uv_auth()?;
+                Request::UpdateUserInformation {
+                    credential_id,
+                    user,
+                    pin_uv_auth_protocol,
+                    pin_uv_auth_param,
+                }
+            }
+        })
+    }
+}
+
+#[serde_as]
+#[skip_serializing_none]
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub(crate) struct RawResponse {
+    #[serde(rename = 0x01)]
+    pub existing_resident_credentials_count: Option<usize>,
+    #[serde(rename = 0x02)]
+    pub max_possible_remaining_resident_credentials_count: Option<usize>,
+    #[serde(rename = 0x03)]
+    pub rp: Option<public_key::RelyingPartyEntity>,
+    #[serde_as(as = "Option<Bytes>")]
+    #[serde(rename = 0x04, default)]
+    pub rp_id_hash: Option<Sha256Hash>,
+    #[serde(rename = 0x05)]
+    pub total_rps: Option<usize>,
+    #[serde(rename = 0x06)]
+    pub user: Option<public_key::UserEntity>,
+    #[serde(rename = 0x07)]
+    pub credential_id: Option<public_key::Descriptor>,
+    #[serde(rename = 0x08)]
+    pub public_key: Option<public_key::Key>,
+    #[serde(rename = 0x09)]
+    pub total_credentials: Option<usize>,
+    #[serde(rename = 0x0A)]
+    pub cred_protect: Option<cred_protect::Policy>,
+    #[serde_as(as = "Option<Bytes>")]
+    #[serde(rename = 0x0B, default)]
+    pub large_blob_key: Option<Vec<u8>>,
+}
+
+impl From<Response> for RawResponse {
+    fn from(value: Response) -> Self {
+        match value {
+            Response::GetCredentialsMetadata {
+                existing_resident_credentials_count,
+                max_possible_remaining_resident_credentials_count,
+            } => Self {
+                existing_resident_credentials_count: Some(existing_resident_credentials_count),
+                max_possible_remaining_resident_credentials_count: Some(
+                    max_possible_remaining_resident_credentials_count,
+                ),
+                ..Default::default()
+            },
+            Response::EnumerateRPsBegin {
+                relying_party,
+                total_relying_parties,
+            } => Self {
+                total_rps: Some(total_relying_parties),
+                ..relying_party.into()
+            },
+            Response::EnumerateRPsGetNextRP { relying_party } => relying_party.into(),
+            Response::EnumerateCredentialsBegin {
+                credential,
+                total_credentials,
+            } => Self {
+                total_credentials: Some(total_credentials),
+                ..credential.into()
+            },
+            Response::EnumerateCredentialsGetNextCredential { credential } => credential.into(),
+            Response::DeleteCredential | Response::UpdateUserInformation => Self::default(),
+        }
+    }
+}
+
+impl From<RelyingParty> for RawResponse {
+    fn from(value: RelyingParty) -> Self {
+        Self {
+            rp: Some(value.relying_party),
+            rp_id_hash: Some(value.relying_party_id_hash),
+            ..Default::default()
+        }
+    }
+}
+
+impl From<Credential> for RawResponse {
+    fn from(value: Credential) -> Self {
+        Self {
+            user: Some(value.user),
+            credential_id: Some(value.credential_id),
+            public_key: Some(value.public_key),
+            cred_protect: Some(value.credential_protection_policy),
+            large_blob_key: (!value.large_blob_key.is_empty()).then_some(value.large_blob_key),
+            ..Default::default()
+        }
+    }
+}
+
+impl TryFrom<RawResponse> for Response {
+    type Error = Error;
+
+    /// Responses don't identify the subcommand they belong to, so an empty
+    /// response is read as [`Response::DeleteCredential`]. See
+    /// [`Response::for_request`].
+    fn try_from(value: RawResponse) -> Result<Self, Self::Error> {
+        if let RawResponse {
+            existing_resident_credentials_count: Some(existing_resident_credentials_count),
+            max_possible_remaining_resident_credentials_count,
+            ..
+        } = value
+        {
+            return Ok(Response::GetCredentialsMetadata {
+                existing_resident_credentials_count,
+                max_possible_remaining_resident_credentials_count:
+                    max_possible_remaining_resident_credentials_count
+                        .ok_or(Error::MissingParameter)?,
+            });
+        }
+
+        if let Some(relying_party) = value.rp {
+            let relying_party = RelyingParty {
+                relying_party,
+                relying_party_id_hash: value.rp_id_hash.ok_or(Error::MissingParameter)?,
+            };
+            return Ok(match value.total_rps {
+                Some(total_relying_parties) => Response::EnumerateRPsBegin {
+                    relying_party,
+                    total_relying_parties,
+                },
+                None => Response::EnumerateRPsGetNextRP { relying_party },
+            });
+        }
+
+        if let Some(user) = value.user {
+            let (Some(credential_id), Some(public_key)) = (value.credential_id, value.public_key)
+            else {
+                return Err(Error::MissingParameter);
+            };
+            let credential = Credential {
+                user,
+                credential_id,
+                public_key,
+                // Credentials created without the credProtect extension have
+                // the default policy
+                credential_protection_policy: value
+                    .cred_protect
+                    .unwrap_or(cred_protect::Policy::UserVerificationOptional),
+                large_blob_key: value.large_blob_key.unwrap_or_default(),
+            };
+            return Ok(match value.total_credentials {
+                Some(total_credentials) => Response::EnumerateCredentialsBegin {
+                    credential,
+                    total_credentials,
+                },
+                None => Response::EnumerateCredentialsGetNextCredential { credential },
+            });
+        }
+
+        Ok(Response::DeleteCredential)
+    }
+}
+
+/// `subCommand || subCommandParams`, with the parameters in their CTAP2
+/// canonical CBOR encoding.
+pub(super) fn authenticated_message(
+    sub_command: RawSubcommand,
+    params: Option<RawSubcommandParams>,
+) -> Vec<u8> {
+    let mut message = vec![sub_command.into()];
+    if let Some(params) = params {
+        // Neither writing to a `Vec` nor serializing the parameters can fail
+        ciborium::ser::into_writer(&params, &mut message)
+            .expect("subCommandParams are serializable");
+    }
+    message
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fido_common::credential;
+
+    #[test]
+    fn enumerate_credentials_begin_message() {
+        let hash = [0xAA; 32];
+        let message = authenticated_message(
+            RawSubcommand::EnumerateCredentialsBegin,
+            Some(RawSubcommandParams {
+                relying_party_id_hash: Some(hash),
+                ..Default::default()
+            }),
+        );
+        let expected = [[0x04, 0xA1, 0x01, 0x58, 0x20].as_slice(), &hash].concat();
+        assert_eq!(message, expected);
+    }
+
+    #[test]
+    fn delete_credential_message() {
+        let credential_id = public_key::Descriptor {
+            id: vec![0x01; 16],
+            credential_type: credential::Type::PublicKey,
+            transports: None,
+        };
+        let message = authenticated_message(
+            RawSubcommand::DeleteCredential,
+            Some(RawSubcommandParams {
+                credential_id: Some(Cow::Borrowed(&credential_id)),
+                ..Default::default()
+            }),
+        );
+        let expected = [
+            [0x06, 0xA1, 0x02, 0xA2].as_slice(),
+            &[0x62, b'i', b'd', 0x50],
+            &[0x01; 16],
+            &[0x64, b't', b'y', b'p', b'e', 0x6A],
+            b"public-key",
+        ]
+        .concat();
+        assert_eq!(message, expected);
+    }
+
+    fn encode<T: Serialize>(value: &T) -> Vec<u8> {
+        let mut data = Vec::new();
+        ciborium::ser::into_writer(value, &mut data).unwrap();
+        data
+    }
+
+    fn decode_response(data: &[u8]) -> Result<Response, Error> {
+        let raw: RawResponse = ciborium::de::from_reader(data).unwrap();
+        raw.try_into()
+    }
+
+    #[test]
+    fn enumerate_rps_get_next_rp() {
+        let encoded = encode(&Request::EnumerateRPsGetNextRP);
+        assert_eq!(encoded, [0xA1, 0x01, 0x03]);
+        let raw: RawRequest = ciborium::de::from_reader(encoded.as_slice()).unwrap();
+        assert_eq!(Request::try_from(raw), Ok(Request::EnumerateRPsGetNextRP));
+    }
+
+    #[test]
+    fn update_user_information_round_trip() {
+        let request = Request::UpdateUserInformation {
+            credential_id: Cow::Owned(public_key::Descriptor {
+                id: vec![0x01; 16],
+                credential_type: credential::Type::PublicKey,
+                transports: None,
+            }),
+            user: Cow::Owned(public_key::UserEntity {
+                id: vec![0x02; 8],
+                name: Some("alex.mueller@example.com".to_owned()),
+                display_name: None,
+            }),
+            pin_uv_auth_protocol: auth_protocol::Version::One,
+            pin_uv_auth_param: PinUvAuthParam::Short([0x42; 16]),
+        };
+        let raw: RawRequest = ciborium::de::from_reader(encode(&request).as_slice()).unwrap();
+        assert_eq!(Request::try_from(raw), Ok(request));
+    }
+
+    #[test]
+    fn enumerate_credentials_begin_response() {
+        let public_key = public_key::Key(
+            coset::CoseKeyBuilder::new_ec2_pub_key(
+                coset::iana::EllipticCurve::P_256,
+                vec![0x03; 32],
+                vec![0x04; 32],
+            )
+            .algorithm(coset::iana::Algorithm::ES256)
+            .build(),
+        );
+        let response = Response::EnumerateCredentialsBegin {
+            credential: Credential {
+                user: public_key::UserEntity {
+                    id: vec![0x02; 8],
+                    name: None,
+                    display_name: None,
+                },
+                credential_id: public_key::Descriptor {
+                    id: vec![0x01; 16],
+                    credential_type: credential::Type::PublicKey,
+                    transports: None,
+                },
+                public_key,
+                credential_protection_policy: cred_protect::Policy::UserVerificationRequired,
+                large_blob_key: Vec::new(),
+            },
+            total_credentials: 2,
+        };
+        assert_eq!(decode_response(&encode(&response)), Ok(response));
+    }
+
+    #[test]
+    fn enumerate_rps_responses() {
+        let relying_party = RelyingParty {
+            relying_party: public_key::RelyingPartyEntity {
+                id: "example.com".to_owned(),
+                name: None,
+            },
+            relying_party_id_hash: [0xAA; 32],
+        };
+        let next = Response::EnumerateRPsGetNextRP {
+            relying_party: relying_party.clone(),
+        };
+        assert_eq!(decode_response(&encode(&next)), Ok(next));
+        let begin = Response::EnumerateRPsBegin {
+            relying_party,
+            total_relying_parties: 3,
+        };
+        assert_eq!(decode_response(&encode(&begin)), Ok(begin));
+    }
+
+    #[cfg(feature = "crypto")]
+    #[test]
+    fn verify() {
+        use crate::authenticator::client_pin::auth_protocol::Version;
+
+        let token = [0x42; 32];
+        for version in [Version::One, Version::Two] {
+            let request = Request::get_credentials_metadata(version, &token);
+            assert_eq!(request.verify(&token), Ok(()));
+            assert_eq!(request.verify(&[0x43; 32]), Err(Error::PinAuthInvalid));
+        }
+    }
+}
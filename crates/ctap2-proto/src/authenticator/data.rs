@@ -0,0 +1,302 @@
+//! Producing and signing the authenticator data of attestations and
+//! assertions, as a software authenticator would.
+
+use crate::{
+    authenticator::{credential::make, Data, Extensions, UserPresence, UserVerification},
+    credential::{BackupEligibility, BackupState},
+    Sha256Hash,
+};
+use coset::iana;
+use fido_common::attestation;
+use sha2::{Digest, Sha256};
+
+#[derive(Debug)]
+pub enum Error {
+    /// A self attestation was requested without attested credential data.
+    MissingCredentialData,
+    /// The signer's algorithm is not that of the credential public key.
+    AlgorithmMismatch,
+    /// The authenticator data could not be encoded.
+    Encoding(coset::CoseError),
+}
+
+impl From<coset::CoseError> for Error {
+    fn from(value: coset::CoseError) -> Self {
+        Self::Encoding(value)
+    }
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::MissingCredentialData => write!(f, "Attested credential data is missing"),
+            Error::AlgorithmMismatch => {
+                write!(f, "Signer algorithm differs from the credential's")
+            }
+            Error::Encoding(error) => write!(f, "Authenticator data encoding failed: {}", error),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// The private key of a credential, signing the authenticator data and the
+/// client data hash.
+pub trait Signer {
+    /// The algorithm the signatures are produced with.
+    fn algorithm(&self) -> iana::Algorithm;
+
+    /// Signs the message, encoding the signature as the algorithm requires
+    /// within attestation statements and assertion signatures.
+    fn sign(&self, message: &[u8]) -> Vec<u8>;
+}
+
+impl Signer for p256::ecdsa::SigningKey {
+    fn algorithm(&self) -> iana::Algorithm {
+        iana::Algorithm::ES256
+    }
+
+    /// > For COSEAlgorithmIdentifier -7 (ES256), and other ECDSA-based
+    /// > algorithms, the sig value MUST be encoded as an ASN.1 DER
+    /// > Ecdsa-Sig-Value, as defined in [RFC3279] section 2.2.3.
+    fn sign(&self, message: &[u8]) -> Vec<u8> {
+        let signature: p256::ecdsa::Signature = p256::ecdsa::signature::Signer::sign(self, message);
+        signature.to_der().as_bytes().to_vec()
+    }
+}
+
+impl Signer for ed25519_dalek::SigningKey {
+    fn algorithm(&self) -> iana::Algorithm {
+        iana::Algorithm::EdDSA
+    }
+
+    /// > For COSEAlgorithmIdentifier -8 (EdDSA), the sig value MUST contain
+    /// > the 64 byte signature as defined in [RFC8032].
+    fn sign(&self, message: &[u8]) -> Vec<u8> {
+        ed25519_dalek::Signer::sign(self, message)
+            .to_bytes()
+            .to_vec()
+    }
+}
+
+/// Encoded authenticator data and its signature, e.g. the `authData` and
+/// `signature` of an `authenticatorGetAssertion` response.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Signed {
+    pub auth_data: Vec<u8>,
+    /// The signature over `authData || clientDataHash`.
+    pub signature: Vec<u8>,
+}
+
+/// Builds [`Data`], starting without user presence, user verification or
+/// backup and with a zero signature counter.
+#[derive(Debug)]
+pub struct Builder {
+    data: Data,
+}
+
+impl Builder {
+    /// Starts the authenticator data of a credential scoped to the given RP
+    /// ID.
+    pub fn new(relying_party_id: &str) -> Self {
+        Self {
+            data: Data {
+                relying_party_id_hash: Sha256::digest(relying_party_id.as_bytes()).into(),
+                user_presence: UserPresence::NotPresent,
+                user_verification: UserVerification::NotVerified,
+                backup_eligibility: BackupEligibility::Ineligible,
+                backup_state: BackupState::NotBackedUp,
+                signature_counter: 0,
+                attested_credential_data: None,
+                extensions: None,
+            },
+        }
+    }
+
+    pub fn user_presence(mut self, user_presence: UserPresence) -> Self {
+        self.data.user_presence = user_presence;
+        self
+    }
+
+    pub fn user_verification(mut self, user_verification: UserVerification) -> Self {
+        self.data.user_verification = user_verification;
+        self
+    }
+
+    pub fn backup_eligibility(mut self, backup_eligibility: BackupEligibility) -> Self {
+        self.data.backup_eligibility = backup_eligibility;
+        self
+    }
+
+    pub fn backup_state(mut self, backup_state: BackupState) -> Self {
+        self.data.backup_state = backup_state;
+        self
+    }
+
+    pub fn signature_counter(mut self, signature_counter: u32) -> Self {
+        self.data.signature_counter = signature_counter;
+        self
+    }
+
+    /// Includes the credential being created, for an attestation.
+    pub fn attested_credential_data(
+        mut self,
+        attested_credential_data: attestation::CredentialData,
+    ) -> Self {
+        self.data.attested_credential_data = Some(attested_credential_data);
+        self
+    }
+
+    /// Sets the authenticator extension outputs.
+    pub fn extensions(mut self, extensions: Extensions) -> Self {
+        self.data.extensions = Some(extensions);
+        self
+    }
+
+    pub fn build(self) -> Data {
+        self.data
+    }
+
+    /// Encodes the authenticator data and signs it along with the client data
+    /// hash, as for an assertion.
+    pub fn sign<S>(self, client_data_hash: &Sha256Hash, signer: &S) -> Result<Signed, Error>
+    where
+        S: Signer + ?Sized,
+    {
+        let auth_data = self.data.to_bytes()?;
+        let signature = signer.sign(&[auth_data.as_slice(), client_data_hash].concat());
+        Ok(Signed {
+            auth_data,
+            signature,
+        })
+    }
+
+    /// Produces a `packed` self attestation, signed with the private key of
+    /// the credential being created.
+    ///
+    /// > In the case of self attestation, the credential public key is also
+    /// > the attestation public key, and no x5c is present.
+    ///
+    /// Fails if the data lacks the attested credential data, or the signer's
+    /// algorithm is not that of the credential public key.
+    pub fn self_attest<S>(
+        self,
+        client_data_hash: &Sha256Hash,
+        signer: &S,
+    ) -> Result<make::Response, Error>
+    where
+        S: Signer + ?Sized,
+    {
+        let credential_data = self
+            .data
+            .attested_credential_data
+            .as_ref()
+            .ok_or(Error::MissingCredentialData)?;
+        if credential_data.public_key.algorithm() != Some(signer.algorithm()) {
+            return Err(Error::AlgorithmMismatch);
+        }
+
+        let signature = signer.sign(&[self.data.to_bytes()?.as_slice(), client_data_hash].concat());
+        Ok(make::Response {
+            format: attestation::FormatIdentifier::Packed,
+            authenticator_data: self.data,
+            attestation_statement: Some(attestation::Statement::Packed {
+                algorithm: signer.algorithm(),
+                signature,
+                attestation_certificate_chain: None,
+            }),
+            enterprise_attestation: None,
+            large_blob_key: None,
+            unsigned_extension_outputs: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fido_common::credential::public_key::Key;
+    use p256::ecdsa::{signature::Verifier, Signature, SigningKey};
+
+    #[test]
+    fn sign() {
+        let key = SigningKey::from_slice(&[0x01; 32]).unwrap();
+        let client_data_hash = [0x02; 32];
+        let signed = Builder::new("example.com")
+            .user_presence(UserPresence::Present)
+            .signature_counter(7)
+            .sign(&client_data_hash, &key)
+            .unwrap();
+
+        assert_eq!(signed.auth_data.len(), 37);
+        assert_eq!(signed.auth_data[..32], Sha256::digest(b"example.com")[..]);
+        // Only the user presence flag is set
+        assert_eq!(signed.auth_data[32], 0b1);
+        assert_eq!(signed.auth_data[33..], 7u32.to_be_bytes());
+
+        let signature = Signature::from_der(&signed.signature).unwrap();
+        let message = [signed.auth_data.as_slice(), &client_data_hash].concat();
+        assert!(key.verifying_key().verify(&message, &signature).is_ok());
+    }
+
+    fn credential_data(public_key: Key) -> attestation::CredentialData {
+        attestation::CredentialData {
+            aaguid: [0x00; 16],
+            id: vec![0x03; 16],
+            public_key,
+        }
+    }
+
+    #[test]
+    fn self_attest() {
+        let key = SigningKey::from_slice(&[0x01; 32]).unwrap();
+        let client_data_hash = [0x02; 32];
+        let public_key = key.verifying_key().into();
+        let response = Builder::new("example.com")
+            .user_presence(UserPresence::Present)
+            .attested_credential_data(credential_data(public_key))
+            .self_attest(&client_data_hash, &key)
+            .unwrap();
+
+        assert_eq!(response.format, attestation::FormatIdentifier::Packed);
+        let Some(attestation::Statement::Packed {
+            algorithm,
+            signature,
+            attestation_certificate_chain: None,
+        }) = response.attestation_statement
+        else {
+            panic!("expected a packed self attestation");
+        };
+        assert_eq!(algorithm, iana::Algorithm::ES256);
+        let signature = Signature::from_der(&signature).unwrap();
+        let auth_data = response.authenticator_data.to_bytes().unwrap();
+        let message = [auth_data.as_slice(), &client_data_hash].concat();
+        assert!(key.verifying_key().verify(&message, &signature).is_ok());
+    }
+
+    #[test]
+    fn self_attest_without_credential() {
+        let key = SigningKey::from_slice(&[0x01; 32]).unwrap();
+        let result = Builder::new("example.com").self_attest(&[0x02; 32], &key);
+        assert!(matches!(result, Err(Error::MissingCredentialData)));
+    }
+
+    #[test]
+    fn self_attest_with_another_algorithm() {
+        let key = SigningKey::from_slice(&[0x01; 32]).unwrap();
+        let ed25519 = ed25519_dalek::SigningKey::from_bytes(&[0x01; 32]);
+        let public_key = (&ed25519.verifying_key()).into();
+        let result = Builder::new("example.com")
+            .attested_credential_data(credential_data(public_key))
+            .self_attest(&[0x02; 32], &key);
+        assert!(matches!(result, Err(Error::AlgorithmMismatch)));
+
+        // A key without an algorithm is not that of any signer
+        let mut public_key: Key = key.verifying_key().into();
+        public_key.0.alg = None;
+        let result = Builder::new("example.com")
+            .attested_credential_data(credential_data(public_key))
+            .self_attest(&[0x02; 32], &key);
+        assert!(matches!(result, Err(Error::AlgorithmMismatch)));
+    }
+}
@@ -11,10 +11,21 @@ use std::usize;
 use serde::{Deserialize, Serialize};
 
 /// A usize with a minimum value of N
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct UsizeN<const N: usize>(bounded_integer::BoundedUsize<N, { usize::MAX }>);
 
+impl<const N: usize> UsizeN<N> {
+    /// Returns `None` if the value is less than `N`.
+    pub fn new(value: usize) -> Option<Self> {
+        bounded_integer::BoundedUsize::new(value).map(Self)
+    }
+
+    pub fn get(&self) -> usize {
+        self.0.get()
+    }
+}
+
 /// > data type byte string and identifying the authenticator model, i.e.
 /// > identical values mean that they refer to the same authenticator model and
 /// > different values mean they refer to different authenticator models.
@@ -41,7 +52,7 @@ pub enum Version {
 /// > based on these hints such as selecting a PIN protocol or `credProtect`
 /// > level.
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
-#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub enum Certification {
     /// > The [FIPS140-2] Cryptographic-Module-Validation-Program overall
     /// > certification level.
@@ -55,7 +66,7 @@ pub enum Certification {
 
 #[repr(usize)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
-#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub enum FipsCryptoValidation2Level {
     Level1 = 1,
     Level2 = 2,
@@ -65,7 +76,7 @@ pub enum FipsCryptoValidation2Level {
 
 #[repr(usize)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
-#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub enum FipsCryptoValidation3Level {
     Level1 = 1,
     Level2 = 2,
@@ -75,7 +86,7 @@ pub enum FipsCryptoValidation3Level {
 
 #[repr(usize)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
-#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub enum FipsPhysicalCryptoValidation2Level {
     Level1 = 1,
     Level2 = 2,
@@ -85,7 +96,7 @@ pub enum FipsPhysicalCryptoValidation2Level {
 
 #[repr(usize)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
-#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub enum FipsPhysicalCryptoValidation3Level {
     Level1 = 1,
     Level2 = 2,
@@ -97,7 +108,7 @@ pub enum FipsPhysicalCryptoValidation3Level {
 /// > from 1 to 7. The intermediate-plus levels are not represented.
 #[repr(usize)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
-#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub enum CommonCriterialLevel {
     EAL1 = 1,
     EAL2 = 2,
@@ -113,7 +124,7 @@ pub enum CommonCriterialLevel {
 /// > to the even numbers e.g., level 3+ is mapped to 6.
 #[repr(usize)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
-#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub enum FidoLevel {
     L1 = 1,
     L1Plus = 2,
@@ -226,7 +237,7 @@ pub enum OptionId {
 /// > other aspects of its overall capabilities. Platforms should use this
 /// > information to tailor their command parameters choices.
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Info {
     /// > List of supported CTAP versions.
     #[cfg_attr(feature = "serde", serde(rename = 0x01))]
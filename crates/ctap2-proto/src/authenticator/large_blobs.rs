@@ -0,0 +1,268 @@
+use super::client_pin::{auth_protocol, PinUvAuthParam};
+use crate::status::StatusCode;
+use std::{borrow::Cow, fmt::Display};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "serde")]
+use serde_with::{serde_as, skip_serializing_none, Bytes};
+
+pub mod array;
+
+#[cfg(feature = "serde")]
+pub(crate) mod raw;
+
+/// The number of bytes of a message taken up by everything but the fragment
+/// of the serialized large-blob array.
+///
+/// > `maxFragmentLength` = `maxMsgSize` - 64
+pub const MESSAGE_OVERHEAD: usize = 64;
+
+/// Request parameters for the `authenticatorLargeBlobs` command, which reads
+/// and writes the serialized large-blob array in fragments.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(into = "raw::RawRequest", try_from = "raw::RawRequest")
+)]
+pub enum Request<'a> {
+    /// Reads a fragment of the serialized large-blob array.
+    Get {
+        /// > The byte offset at which to read/write.
+        offset: usize,
+        /// > The number of bytes requested to read.
+        length: usize,
+    },
+    /// Writes a fragment of a new serialized large-blob array, which replaces
+    /// the stored one once all fragments are written.
+    Set {
+        /// > A fragment to write.
+        fragment: Cow<'a, [u8]>,
+        /// > The byte offset at which to read/write.
+        offset: usize,
+        /// > The total length of a write operation. Present if, and only if,
+        /// > `offset` is zero.
+        length: Option<usize>,
+        /// > PIN/UV protocol version chosen by the platform.
+        pin_uv_auth_protocol: Option<auth_protocol::Version>,
+        /// Absent if the authenticator is not protected by user verification.
+        pin_uv_auth_param: Option<PinUvAuthParam>,
+    },
+}
+
+#[cfg(feature = "crypto")]
+impl Request<'_> {
+    /// The message authenticated by the `pinUvAuthParam`, or `None` for
+    /// `get`, which is not authenticated.
+    ///
+    /// See [`authenticated_message`] for when this fails.
+    pub fn authenticated_message(&self) -> Result<Option<Vec<u8>>, Error> {
+        match self {
+            Request::Get { .. } => Ok(None),
+            Request::Set {
+                fragment, offset, ..
+            } => authenticated_message(*offset, fragment).map(Some),
+        }
+    }
+
+    /// Verifies the `pinUvAuthParam` using the given `pinUvAuthToken`.
+    ///
+    /// Checking whether the token is in use and has the
+    /// [`Permission::LargeBlobWrite`](super::client_pin::Permission::LargeBlobWrite)
+    /// permission is left to the authenticator.
+    pub fn verify(&self, pin_uv_auth_token: &[u8]) -> Result<(), Error> {
+        let Request::Set {
+            fragment,
+            offset,
+            pin_uv_auth_protocol,
+            pin_uv_auth_param,
+            ..
+        } = self
+        else {
+            return Ok(());
+        };
+        let pin_uv_auth_param = pin_uv_auth_param.ok_or(Error::PinUvAuthTokenRequired)?;
+        let version = pin_uv_auth_protocol.ok_or(Error::MissingParameter)?;
+        auth_protocol::verify(
+            version,
+            pin_uv_auth_token,
+            &authenticated_message(*offset, fragment)?,
+            pin_uv_auth_param.as_ref(),
+        )
+        .map_err(|_| Error::PinAuthInvalid)
+    }
+}
+
+#[cfg(feature = "crypto")]
+impl<'a> Request<'a> {
+    /// A `set` request authenticated with the given `pinUvAuthToken`.
+    ///
+    /// The total `length` of the serialized large-blob array must be given
+    /// with the first fragment, at offset zero, and only then.
+    ///
+    /// See [`authenticated_message`] for when this fails.
+    pub fn set(
+        fragment: &'a [u8],
+        offset: usize,
+        length: Option<usize>,
+        pin_uv_auth_protocol: auth_protocol::Version,
+        pin_uv_auth_token: &[u8],
+    ) -> Result<Self, Error> {
+        Ok(Request::Set {
+            fragment: Cow::Borrowed(fragment),
+            offset,
+            length,
+            pin_uv_auth_protocol: Some(pin_uv_auth_protocol),
+            pin_uv_auth_param: Some(auth_protocol::authenticate(
+                pin_uv_auth_protocol,
+                pin_uv_auth_token,
+                &authenticated_message(offset, fragment)?,
+            )),
+        })
+    }
+}
+
+/// `32 × 0xff || h'0c00' || uint32LittleEndian(offset) || SHA-256(fragment)`
+///
+/// Fails with [`Error::InvalidParameter`] if the offset does not fit in 32
+/// bits, which is far beyond the size of any serialized large-blob array.
+#[cfg(feature = "crypto")]
+pub fn authenticated_message(offset: usize, fragment: &[u8]) -> Result<Vec<u8>, Error> {
+    use sha2::{Digest, Sha256};
+
+    let offset = u32::try_from(offset).map_err(|_| Error::InvalidParameter)?;
+    let mut message = vec![0xFF; 32];
+    message.extend_from_slice(&[crate::Command::AuthenticatorLargeBlobs.into(), 0x00]);
+    message.extend_from_slice(&offset.to_le_bytes());
+    message.extend_from_slice(&Sha256::digest(fragment));
+    Ok(message)
+}
+
+/// Response structure for the `authenticatorLargeBlobs` command.
+#[cfg_eval]
+#[derive(Clone, Debug, PartialEq, Eq, Default)]
+#[cfg_attr(
+    feature = "serde",
+    serde_as,
+    skip_serializing_none,
+    derive(Serialize, Deserialize)
+)]
+pub struct Response {
+    /// > The fragment of the serialized large-blob array read.
+    ///
+    /// Only present in the response to [`Request::Get`]. A fragment shorter
+    /// than the requested length means the end of the array was reached.
+    #[cfg_attr(
+        feature = "serde",
+        serde_as(as = "Option<Bytes>"),
+        serde(rename = 0x01)
+    )]
+    pub config: Option<Vec<u8>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    MissingParameter,
+    InvalidParameter,
+    /// The fragment is longer than `maxFragmentLength`.
+    InvalidLength,
+    /// The `offset` of a `set` request is not where the previous one ended.
+    InvalidSequence,
+    PinUvAuthTokenRequired,
+    PinAuthInvalid,
+    /// The checksum of the written serialized large-blob array did not match.
+    IntegrityFailure,
+    /// The serialized large-blob array is longer than
+    /// `maxSerializedLargeBlobArray`.
+    LargeBlobStorageFull,
+    /// Any other status code, including those defined by extensions and
+    /// vendors.
+    Other(StatusCode),
+}
+
+impl From<Error> for StatusCode {
+    fn from(value: Error) -> Self {
+        match value {
+            Error::MissingParameter => StatusCode::MissingParameter,
+            Error::InvalidParameter => StatusCode::InvalidParameter,
+            Error::InvalidLength => StatusCode::InvalidLength,
+            Error::InvalidSequence => StatusCode::InvalidSequence,
+            Error::PinUvAuthTokenRequired => StatusCode::PinUvAuthTokenRequired,
+            Error::PinAuthInvalid => StatusCode::PinAuthInvalid,
+            Error::IntegrityFailure => StatusCode::IntegrityFailure,
+            Error::LargeBlobStorageFull => StatusCode::LargeBlobStorageFull,
+            Error::Other(status) => status,
+        }
+    }
+}
+
+impl From<StatusCode> for Error {
+    fn from(value: StatusCode) -> Self {
+        match value {
+            StatusCode::MissingParameter => Error::MissingParameter,
+            StatusCode::InvalidParameter => Error::InvalidParameter,
+            StatusCode::InvalidLength => Error::InvalidLength,
+            StatusCode::InvalidSequence => Error::InvalidSequence,
+            StatusCode::PinUvAuthTokenRequired => Error::PinUvAuthTokenRequired,
+            StatusCode::PinAuthInvalid => Error::PinAuthInvalid,
+            StatusCode::IntegrityFailure => Error::IntegrityFailure,
+            StatusCode::LargeBlobStorageFull => Error::LargeBlobStorageFull,
+            status => Error::Other(status),
+        }
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let message = match self {
+            Error::MissingParameter => "Missing parameter",
+            Error::InvalidParameter => "Invalid parameter",
+            Error::InvalidLength => "Invalid length",
+            Error::InvalidSequence => "Invalid sequence",
+            Error::PinUvAuthTokenRequired => "PIN/UV auth token required",
+            Error::PinAuthInvalid => "PIN auth invalid",
+            Error::IntegrityFailure => "Integrity failure",
+            Error::LargeBlobStorageFull => "Large blob storage full",
+            Error::Other(status) => return write!(f, "{}", status),
+        };
+        write!(f, "{}", message)
+    }
+}
+
+#[cfg(all(test, feature = "crypto", target_pointer_width = "64"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn offset_beyond_32_bits() {
+        let offset = u32::MAX as usize + 1;
+        assert_eq!(
+            authenticated_message(offset, &[0x80]),
+            Err(Error::InvalidParameter)
+        );
+        assert_eq!(
+            Request::set(
+                &[0x80],
+                offset,
+                None,
+                auth_protocol::Version::Two,
+                &[0x03; 32]
+            ),
+            Err(Error::InvalidParameter)
+        );
+
+        let request = Request::Set {
+            fragment: Cow::Borrowed(&[0x80]),
+            offset,
+            length: None,
+            pin_uv_auth_protocol: Some(auth_protocol::Version::Two),
+            pin_uv_auth_param: Some(PinUvAuthParam::Long([0x42; 32])),
+        };
+        assert_eq!(
+            request.authenticated_message(),
+            Err(Error::InvalidParameter)
+        );
+        assert_eq!(request.verify(&[0x03; 32]), Err(Error::InvalidParameter));
+    }
+}
@@ -0,0 +1,185 @@
+//! The serialized large-blob array: a CBOR array of large-blob maps followed by
+//! a truncated SHA-256 checksum of the array.
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "serde")]
+use serde_with::{serde_as, Bytes};
+
+#[cfg(feature = "crypto")]
+use super::Error;
+
+/// The length of the checksum following the CBOR array.
+pub const CHECKSUM_LENGTH: usize = 16;
+
+/// The initial serialized large-blob array: an empty CBOR array followed by
+/// `LEFT(SHA-256(h'80'), 16)`. This is also the shortest valid serialized
+/// large-blob array.
+pub const EMPTY: [u8; 17] = [
+    0x80, 0x76, 0xBE, 0x8B, 0x52, 0x8D, 0x00, 0x75, 0xF7, 0xAA, 0xE9, 0x8D, 0x6F, 0xA5, 0x7A, 0x6D,
+    0x3C,
+];
+
+/// The length of the AEAD_AES_256_GCM nonce of each blob.
+pub const NONCE_LENGTH: usize = 12;
+
+/// An element of the large-blob array, holding a blob encrypted with the
+/// `largeBlobKey` of the credential it belongs to.
+#[cfg_eval]
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", serde_as, derive(Serialize, Deserialize))]
+pub struct Blob {
+    /// > AEAD_AES_256_GCM ciphertext, implicitly including the AEAD
+    /// > "authentication tag" at the end.
+    #[cfg_attr(feature = "serde", serde_as(as = "Bytes"), serde(rename = 0x01))]
+    pub ciphertext: Vec<u8>,
+    /// > AEAD_AES_256_GCM nonce. MUST be exactly 12 bytes long.
+    #[cfg_attr(feature = "serde", serde_as(as = "Bytes"), serde(rename = 0x02))]
+    pub nonce: [u8; NONCE_LENGTH],
+    /// > Contains the length, in bytes, of the uncompressed data.
+    #[cfg_attr(feature = "serde", serde(rename = 0x03))]
+    pub orig_size: usize,
+}
+
+/// An element of the large-blob array.
+#[cfg(feature = "serde")]
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(untagged)]
+pub enum Element {
+    Blob(Blob),
+    /// Any element that is not a large-blob map, e.g. one written by a newer
+    /// version of the specification. It is kept as-is so that it survives
+    /// the array being rewritten.
+    Unknown(ciborium::value::Value),
+}
+
+#[cfg(feature = "serde")]
+impl Element {
+    /// The blob, if the element is a large-blob map.
+    pub fn blob(&self) -> Option<&Blob> {
+        match self {
+            Element::Blob(blob) => Some(blob),
+            Element::Unknown(_) => None,
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl From<Blob> for Element {
+    fn from(value: Blob) -> Self {
+        Element::Blob(value)
+    }
+}
+
+/// `LEFT(SHA-256(array), 16)`
+#[cfg(feature = "crypto")]
+pub fn checksum(array: &[u8]) -> [u8; CHECKSUM_LENGTH] {
+    use sha2::{Digest, Sha256};
+
+    let mut checksum = [0; CHECKSUM_LENGTH];
+    checksum.copy_from_slice(&Sha256::digest(array)[..CHECKSUM_LENGTH]);
+    checksum
+}
+
+/// Verifies the checksum of a serialized large-blob array, returning the CBOR
+/// encoded array that precedes it.
+#[cfg(feature = "crypto")]
+pub fn verify(serialized: &[u8]) -> Result<&[u8], Error> {
+    if serialized.len() < EMPTY.len() {
+        return Err(Error::IntegrityFailure);
+    }
+    let (array, expected) = serialized.split_at(serialized.len() - CHECKSUM_LENGTH);
+    if checksum(array) == expected {
+        Ok(array)
+    } else {
+        Err(Error::IntegrityFailure)
+    }
+}
+
+/// Serializes the elements as a CBOR array followed by its checksum.
+#[cfg(all(feature = "serde", feature = "crypto"))]
+pub fn serialize(elements: &[Element]) -> Vec<u8> {
+    let mut serialized = Vec::new();
+    // Neither writing to a `Vec` nor serializing the elements can fail
+    ciborium::ser::into_writer(elements, &mut serialized).expect("elements are serializable");
+    let checksum = checksum(&serialized);
+    serialized.extend_from_slice(&checksum);
+    serialized
+}
+
+/// Verifies the checksum of a serialized large-blob array and deserializes
+/// its elements.
+///
+/// Elements that are not large-blob maps are returned as
+/// [`Element::Unknown`].
+#[cfg(all(feature = "serde", feature = "crypto"))]
+pub fn deserialize(serialized: &[u8]) -> Result<Vec<Element>, Error> {
+    let array = verify(serialized)?;
+    let elements: Vec<ciborium::value::Value> =
+        ciborium::de::from_reader(array).map_err(|_| Error::InvalidParameter)?;
+    Ok(elements
+        .into_iter()
+        .map(|element| match element.deserialized() {
+            Ok(blob) => Element::Blob(blob),
+            Err(_) => Element::Unknown(element),
+        })
+        .collect())
+}
+
+#[cfg(all(test, feature = "serde", feature = "crypto"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty() {
+        assert_eq!(checksum(&[0x80]), EMPTY[1..]);
+        assert_eq!(serialize(&[]), EMPTY);
+        assert_eq!(deserialize(&EMPTY), Ok(Vec::new()));
+    }
+
+    #[test]
+    fn round_trip() {
+        let elements = vec![
+            Element::Blob(Blob {
+                ciphertext: vec![0x01; 32],
+                nonce: [0x02; NONCE_LENGTH],
+                orig_size: 16,
+            }),
+            Element::Blob(Blob {
+                ciphertext: vec![0x03; 48],
+                nonce: [0x04; NONCE_LENGTH],
+                orig_size: 64,
+            }),
+        ];
+        assert_eq!(deserialize(&serialize(&elements)), Ok(elements));
+    }
+
+    #[test]
+    fn checksum_mismatch() {
+        let mut serialized = EMPTY;
+        serialized[16] ^= 0x01;
+        assert_eq!(deserialize(&serialized), Err(Error::IntegrityFailure));
+        assert_eq!(deserialize(&EMPTY[1..]), Err(Error::IntegrityFailure));
+    }
+
+    #[test]
+    fn keeps_unknown_elements() {
+        // [1, {4: h'FF'}]
+        let array = [0x82, 0x01, 0xA1, 0x04, 0x41, 0xFF];
+        let serialized = [array.as_slice(), &checksum(&array)].concat();
+        let mut elements = deserialize(&serialized).unwrap();
+        assert!(elements.iter().all(|element| element.blob().is_none()));
+
+        let blob = Blob {
+            ciphertext: vec![0x01; 32],
+            nonce: [0x02; NONCE_LENGTH],
+            orig_size: 16,
+        };
+        elements.push(blob.clone().into());
+        let rewritten = serialize(&elements);
+        // The foreign elements are written back untouched, ahead of the blob
+        assert_eq!(rewritten[0], 0x83);
+        assert_eq!(rewritten[1..array.len()], array[1..]);
+        assert_eq!(deserialize(&rewritten).unwrap()[2..], [Element::Blob(blob)]);
+    }
+}
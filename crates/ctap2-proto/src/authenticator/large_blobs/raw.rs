@@ -0,0 +1,173 @@
+//! Used to make serialization and deserialization of the request possible in
+//! CBOR format while maintaining ergonomic enum variants for public API.
+
+use super::{Error, Request};
+use crate::authenticator::client_pin::{auth_protocol, PinUvAuthParam};
+use serde::{Deserialize, Serialize};
+use serde_with::{serde_as, skip_serializing_none, Bytes};
+use std::borrow::Cow;
+
+#[serde_as]
+#[skip_serializing_none]
+#[derive(Clone, Serialize, Deserialize)]
+pub(crate) struct RawRequest {
+    #[serde(rename = 0x01)]
+    pub get: Option<usize>,
+    #[serde_as(as = "Option<Bytes>")]
+    #[serde(rename = 0x02, default)]
+    pub set: Option<Vec<u8>>,
+    #[serde(rename = 0x03)]
+    pub offset: Option<usize>,
+    #[serde(rename = 0x04)]
+    pub length: Option<usize>,
+    #[serde_as(as = "Option<Bytes>")]
+    #[serde(rename = 0x05, default)]
+    pub pin_uv_auth_param: Option<PinUvAuthParam>,
+    #[serde(rename = 0x06)]
+    pub pin_uv_auth_protocol: Option<auth_protocol::Version>,
+}
+
+impl From<Request<'_>> for RawRequest {
+    fn from(value: Request<'_>) -> Self {
+        match value {
+            Request::Get { offset, length } => Self {
+                get: Some(length),
+                set: None,
+                offset: Some(offset),
+                length: None,
+                pin_uv_auth_param: None,
+                pin_uv_auth_protocol: None,
+            },
+            Request::Set {
+                fragment,
+                offset,
+                length,
+                pin_uv_auth_protocol,
+                pin_uv_auth_param,
+            } => Self {
+                get: None,
+                set: Some(fragment.into_owned()),
+                offset: Some(offset),
+                length,
+                pin_uv_auth_param,
+                pin_uv_auth_protocol,
+            },
+        }
+    }
+}
+
+impl TryFrom<RawRequest> for Request<'_> {
+    type Error = Error;
+
+    fn try_from(value: RawRequest) -> Result<Self, Self::Error> {
+        let RawRequest {
+            get,
+            set,
+            offset,
+            length,
+            pin_uv_auth_param,
+            pin_uv_auth_protocol,
+        } = value;
+        let offset = offset.ok_or(Error::MissingParameter)?;
+
+        match (get, set) {
+            (Some(get), None) => {
+                if length.is_some() {
+                    return Err(Error::InvalidParameter);
+                }
+                Ok(Request::Get {
+                    offset,
+                    length: get,
+                })
+            }
+            (None, Some(set)) => {
+                // The total length is only sent along with the first fragment
+                match (offset, length) {
+                    (0, None) => return Err(Error::MissingParameter),
+                    (1.., Some(_)) => return Err(Error::InvalidParameter),
+                    _ => {}
+                }
+                if pin_uv_auth_param.is_some() && pin_uv_auth_protocol.is_none() {
+                    return Err(Error::MissingParameter);
+                }
+                Ok(Request::Set {
+                    fragment: Cow::Owned(set),
+                    offset,
+                    length,
+                    pin_uv_auth_protocol,
+                    pin_uv_auth_param,
+                })
+            }
+            _ => Err(Error::InvalidParameter),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode<T: Serialize>(value: &T) -> Vec<u8> {
+        let mut data = Vec::new();
+        ciborium::ser::into_writer(value, &mut data).unwrap();
+        data
+    }
+
+    fn decode(data: &[u8]) -> Result<Request<'static>, Error> {
+        let raw: RawRequest = ciborium::de::from_reader(data).unwrap();
+        raw.try_into()
+    }
+
+    #[test]
+    fn get() {
+        let request = Request::Get {
+            offset: 0,
+            length: 960,
+        };
+        let encoded = encode(&request);
+        assert_eq!(encoded, [0xA2, 0x01, 0x19, 0x03, 0xC0, 0x03, 0x00]);
+        assert_eq!(decode(&encoded), Ok(request));
+    }
+
+    #[test]
+    fn set_round_trip() {
+        let request = Request::Set {
+            fragment: Cow::Borrowed(&[0x80]),
+            offset: 0,
+            length: Some(17),
+            pin_uv_auth_protocol: Some(auth_protocol::Version::Two),
+            pin_uv_auth_param: Some(PinUvAuthParam::Long([0x42; 32])),
+        };
+        assert_eq!(decode(&encode(&request)), Ok(request));
+    }
+
+    #[test]
+    fn set_length() {
+        let set = |offset, length| {
+            encode(&RawRequest {
+                get: None,
+                set: Some(vec![0x80]),
+                offset: Some(offset),
+                length,
+                pin_uv_auth_param: None,
+                pin_uv_auth_protocol: None,
+            })
+        };
+        assert_eq!(decode(&set(0, None)), Err(Error::MissingParameter));
+        assert_eq!(decode(&set(16, Some(17))), Err(Error::InvalidParameter));
+        assert!(decode(&set(16, None)).is_ok());
+    }
+
+    #[test]
+    fn get_and_set() {
+        let request = encode(&RawRequest {
+            get: Some(16),
+            set: Some(vec![0x80]),
+            offset: Some(0),
+            length: None,
+            pin_uv_auth_param: None,
+            pin_uv_auth_protocol: None,
+        });
+        assert_eq!(decode(&request), Err(Error::InvalidParameter));
+    }
+}
@@ -5,6 +5,9 @@ pub mod bio_enrollment;
 pub mod client_pin;
 pub mod config;
 pub mod credential;
+#[cfg(all(feature = "serde", feature = "crypto"))]
+pub mod data;
 pub mod device;
+pub mod large_blobs;
 pub mod reset;
 pub mod selection;
@@ -1,8 +1,9 @@
+use crate::status::StatusCode;
 #[cfg(feature = "serde")]
 use serde::{Deserialize, Serialize};
 
 /// Possible errors for the [`Ctap2Device::reset`] command.
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum Error {
     /// Returned if the `reset` operation is disabled for the transport used or
@@ -19,4 +20,29 @@ pub enum Error {
     /// Returned when the `reset` request is received by the authenticator more
     /// than ten seconds after powering up.
     NotAllowed,
+    /// Any other status code, including those defined by extensions and
+    /// vendors.
+    Other(StatusCode),
+}
+
+impl From<Error> for StatusCode {
+    fn from(value: Error) -> Self {
+        match value {
+            Error::OperationDenied => StatusCode::OperationDenied,
+            Error::UserActionTimeout => StatusCode::UserActionTimeout,
+            Error::NotAllowed => StatusCode::NotAllowed,
+            Error::Other(status) => status,
+        }
+    }
+}
+
+impl From<StatusCode> for Error {
+    fn from(value: StatusCode) -> Self {
+        match value {
+            StatusCode::OperationDenied => Error::OperationDenied,
+            StatusCode::UserActionTimeout => Error::UserActionTimeout,
+            StatusCode::NotAllowed => Error::NotAllowed,
+            status => Error::Other(status),
+        }
+    }
 }
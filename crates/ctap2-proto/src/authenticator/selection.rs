@@ -1,4 +1,30 @@
+use crate::status::StatusCode;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Error {
     OperationDenied,
     UserActionTimeout,
+    /// Any other status code, including those defined by extensions and
+    /// vendors.
+    Other(StatusCode),
+}
+
+impl From<Error> for StatusCode {
+    fn from(value: Error) -> Self {
+        match value {
+            Error::OperationDenied => StatusCode::OperationDenied,
+            Error::UserActionTimeout => StatusCode::UserActionTimeout,
+            Error::Other(status) => status,
+        }
+    }
+}
+
+impl From<StatusCode> for Error {
+    fn from(value: StatusCode) -> Self {
+        match value {
+            StatusCode::OperationDenied => Error::OperationDenied,
+            StatusCode::UserActionTimeout => Error::UserActionTimeout,
+            status => Error::Other(status),
+        }
+    }
 }
@@ -0,0 +1,112 @@
+//! The authenticator side of the CTAP2 message encoding: decodes the requests
+//! received from a platform, routes them to a [`Ctap2_2Authenticator`] and
+//! encodes its response.
+
+use crate::{
+    authenticator::{
+        assertion::get::raw as get_raw, bio_enrollment::raw as bio_enrollment_raw,
+        client_pin::raw as client_pin_raw, config::raw as config_raw,
+        credential::make::raw as make_raw, credential::management::raw as management_raw,
+        large_blobs::raw as large_blobs_raw,
+    },
+    prelude::*,
+};
+use serde::{de::DeserializeOwned, Serialize};
+
+/// Processes a CTAP2 request, the command byte followed by its CBOR encoded
+/// parameters, returning the status byte followed by the CBOR encoded
+/// response data, if any.
+pub fn dispatch<A>(authenticator: &mut A, request: &[u8]) -> Vec<u8>
+where
+    A: Ctap2_2Authenticator + ?Sized,
+{
+    let Some((&command, parameters)) = request.split_first() else {
+        return vec![StatusCode::InvalidLength.into()];
+    };
+
+    match process(authenticator, command, parameters) {
+        Ok(data) => [&[StatusCode::Ok.into()], data.as_slice()].concat(),
+        Err(status) => vec![status.into()],
+    }
+}
+
+/// Returns the encoded response data, or the status code of the error.
+fn process<A>(authenticator: &mut A, command: u8, parameters: &[u8]) -> Result<Vec<u8>, StatusCode>
+where
+    A: Ctap2_2Authenticator + ?Sized,
+{
+    match Command::try_from(command)? {
+        Command::AuthenticatorMakeCredential => {
+            let request: make_raw::RawRequest = decode(parameters)?;
+            let response =
+                request.with_request(|request| authenticator.make_credential(request))?;
+            encode(&response)
+        }
+        Command::AuthenticatorGetAssertion => {
+            let request: get_raw::RawRequest = decode(parameters)?;
+            let response = request.with_request(|request| authenticator.get_assertion(request))?;
+            encode(&response)
+        }
+        Command::AuthenticatorGetNextAssertion => encode(&authenticator.get_next_assertion()?),
+        Command::AuthenticatorGetInfo => encode(&authenticator.get_info()),
+        Command::AuthenticatorClientPin => {
+            let request: client_pin_raw::RawRequest = decode(parameters)?;
+            let response = authenticator.client_pin(request.try_into()?)?;
+            encode(&response)
+        }
+        Command::AuthenticatorReset => {
+            authenticator.reset()?;
+            Ok(Vec::new())
+        }
+        Command::AuthenticatorSelection => {
+            authenticator.selection()?;
+            Ok(Vec::new())
+        }
+        Command::AuthenticatorLargeBlobs => {
+            let request: large_blobs_raw::RawRequest = decode(parameters)?;
+            let response = authenticator.large_blobs(request.try_into()?)?;
+            // Only `get` returns any data
+            match response.config {
+                Some(_) => encode(&response),
+                None => Ok(Vec::new()),
+            }
+        }
+        Command::AuthenticatorBioEnrollment | Command::PrototypeAuthenticatorBioEnrollment => {
+            let request: bio_enrollment_raw::RawRequest = decode(parameters)?;
+            let response = authenticator.bio_enrollment(request.try_into()?)?;
+            if response.is_empty() {
+                Ok(Vec::new())
+            } else {
+                encode(&response)
+            }
+        }
+        Command::AuthenticatorConfig => {
+            let request: config_raw::RawRequest = decode(parameters)?;
+            authenticator.authenticator_config(request.try_into()?)?;
+            Ok(Vec::new())
+        }
+        Command::AuthenticatorCredentialManagement
+        | Command::PrototypeAuthenticatorCredentialManagement => {
+            let request: management_raw::RawRequest = decode(parameters)?;
+            let response = authenticator.credential_management(request.try_into()?)?;
+            if response.is_empty() {
+                Ok(Vec::new())
+            } else {
+                encode(&response)
+            }
+        }
+    }
+}
+
+fn decode<T: DeserializeOwned>(parameters: &[u8]) -> Result<T, StatusCode> {
+    if parameters.is_empty() {
+        return Err(StatusCode::MissingParameter);
+    }
+    ciborium::de::from_reader(parameters).map_err(|_| StatusCode::InvalidCbor)
+}
+
+fn encode<T: Serialize>(response: &T) -> Result<Vec<u8>, StatusCode> {
+    let mut data = Vec::new();
+    ciborium::ser::into_writer(response, &mut data).map_err(|_| StatusCode::Other)?;
+    Ok(data)
+}
@@ -1,10 +1,35 @@
+use crate::status::StatusCode;
+
 #[cfg(feature = "serde")]
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, Copy)]
-#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(into = "u8", try_from = "u8")
+)]
 pub enum Policy {
     UserVerificationOptional = 0x01,
     UserVerificationOptionalWithCredentialIdList = 0x02,
     UserVerificationRequired = 0x03,
 }
+
+impl From<Policy> for u8 {
+    fn from(value: Policy) -> Self {
+        value as u8
+    }
+}
+
+impl TryFrom<u8> for Policy {
+    type Error = StatusCode;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0x01 => Ok(Policy::UserVerificationOptional),
+            0x02 => Ok(Policy::UserVerificationOptionalWithCredentialIdList),
+            0x03 => Ok(Policy::UserVerificationRequired),
+            _ => Err(StatusCode::InvalidParameter),
+        }
+    }
+}
@@ -2,6 +2,9 @@ pub use fido_common::extensions::*;
 
 pub mod cred_protect;
 
+#[cfg(feature = "serde")]
+pub(crate) mod raw;
+
 /// The extension input parameters passed to the authenticator during a call to
 /// `make_credential` call. Defined by the extension author.
 ///
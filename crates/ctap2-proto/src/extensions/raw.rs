@@ -0,0 +1,80 @@
+//! Extension inputs and outputs are kept as the CBOR encoding of their value,
+//! since each extension defines its own types.
+
+use super::Identifier;
+use ciborium::value::Value;
+use serde::{
+    de::{value::StrDeserializer, IntoDeserializer},
+    ser::{Error, SerializeMap},
+    Deserialize,
+};
+use std::collections::BTreeMap;
+
+pub(crate) fn serialize<S, V>(
+    extensions: &Option<&BTreeMap<Identifier, V>>,
+    serializer: S,
+) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+    V: AsRef<[u8]>,
+{
+    match extensions {
+        Some(extensions) => serialize_map(extensions, serializer),
+        None => serializer.serialize_none(),
+    }
+}
+
+pub(crate) fn serialize_owned<S>(
+    extensions: &Option<BTreeMap<Identifier, Vec<u8>>>,
+    serializer: S,
+) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+{
+    serialize(&extensions.as_ref(), serializer)
+}
+
+fn serialize_map<S, V>(
+    extensions: &BTreeMap<Identifier, V>,
+    serializer: S,
+) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+    V: AsRef<[u8]>,
+{
+    let mut map = serializer.serialize_map(Some(extensions.len()))?;
+    for (identifier, value) in extensions {
+        let value: Value = ciborium::de::from_reader(value.as_ref()).map_err(S::Error::custom)?;
+        map.serialize_entry(identifier, &value)?;
+    }
+    map.end()
+}
+
+/// Deserializes a map of extension identifiers to values, ignoring
+/// unrecognized extensions.
+pub(crate) fn deserialize<'de, D>(
+    deserializer: D,
+) -> Result<Option<BTreeMap<Identifier, Vec<u8>>>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    use serde::de::Error;
+
+    let Some(extensions) = Option::<BTreeMap<String, Value>>::deserialize(deserializer)? else {
+        return Ok(None);
+    };
+
+    let mut map = BTreeMap::new();
+    for (identifier, value) in extensions {
+        let identifier: StrDeserializer<serde::de::value::Error> =
+            identifier.as_str().into_deserializer();
+        let Ok(identifier) = Identifier::deserialize(identifier) else {
+            continue;
+        };
+
+        let mut bytes = Vec::new();
+        ciborium::ser::into_writer(&value, &mut bytes).map_err(D::Error::custom)?;
+        map.insert(identifier, bytes);
+    }
+    Ok(Some(map))
+}
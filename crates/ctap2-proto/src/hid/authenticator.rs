@@ -0,0 +1,589 @@
+//! The authenticator side of a CTAPHID connection: channel allocation,
+//! reassembly of incoming requests and fragmentation of responses.
+
+use super::{
+    packet::{Message, Packet, Reassembler},
+    Capability, ChannelId, Command, Device, ErrorCode, InitResponse, Report, BROADCAST_CHANNEL,
+    NONCE_SIZE, PROTOCOL_VERSION,
+};
+use flagset::FlagSet;
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+/// The number of channels kept allocated at once. Allocating another one
+/// releases the least recently allocated channel, so that platforms opening a
+/// channel per connection don't grow the list without bound.
+pub const MAX_CHANNELS: usize = 32;
+
+/// How long a transaction may take to be fully received before it is aborted
+/// with ERR_MSG_TIMEOUT, as recommended by the spec. Until then, it keeps
+/// every other channel busy.
+pub const TRANSACTION_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Processes the complete messages received by a [`Server`].
+pub trait Handler {
+    /// Processes an encapsulated CTAP2 request (command byte followed by CBOR
+    /// parameters), returning the response (status byte followed by CBOR
+    /// data).
+    fn cbor(&mut self, request: &[u8]) -> Vec<u8>;
+
+    /// Processes an encapsulated CTAP1/U2F message.
+    fn msg(&mut self, request: &[u8]) -> Result<Vec<u8>, ErrorCode> {
+        let _ = request;
+        Err(ErrorCode::InvalidCommand)
+    }
+
+    /// Performs a vendor-defined action to identify the authenticator to the
+    /// user.
+    fn wink(&mut self) {}
+}
+
+/// Tracks the channels allocated on an authenticator and the transaction
+/// currently being received.
+pub struct Server {
+    major_version: u8,
+    minor_version: u8,
+    build_version: u8,
+    capabilities: FlagSet<Capability>,
+    next_channel: ChannelId,
+    /// Ordered from the least to the most recently allocated.
+    channels: VecDeque<ChannelId>,
+    /// Only a single transaction is processed at a time, across all channels.
+    transaction: Option<Transaction>,
+}
+
+/// A request that has not been fully received yet.
+struct Transaction {
+    reassembler: Reassembler,
+    started: Instant,
+}
+
+impl Server {
+    pub fn new(
+        (major_version, minor_version, build_version): (u8, u8, u8),
+        capabilities: impl Into<FlagSet<Capability>>,
+    ) -> Self {
+        Self {
+            major_version,
+            minor_version,
+            build_version,
+            capabilities: capabilities.into(),
+            next_channel: 1,
+            channels: VecDeque::new(),
+            transaction: None,
+        }
+    }
+
+    /// Processes a single report from the platform, returning the reports to
+    /// be sent back in response, if any.
+    pub fn handle_report<H: Handler>(&mut self, report: &Report, handler: &mut H) -> Vec<Report> {
+        let mut reports = self.expire();
+        reports.extend(match self.receive(report) {
+            Ok(Some(message)) => self.process(message, handler),
+            Ok(None) => Vec::new(),
+            Err((channel, code)) => error(channel, code),
+        });
+        reports
+    }
+
+    /// Aborts the current transaction if it has not been fully received
+    /// within [`TRANSACTION_TIMEOUT`], returning the error to be sent on its
+    /// channel, if any.
+    ///
+    /// This is done whenever a report is received, but can also be called
+    /// periodically so that the platform is told about the timeout without
+    /// sending another report first.
+    pub fn expire(&mut self) -> Vec<Report> {
+        match &self.transaction {
+            Some(pending) if pending.started.elapsed() >= TRANSACTION_TIMEOUT => {
+                let channel = pending.reassembler.channel();
+                self.transaction = None;
+                error(channel, ErrorCode::MessageTimeout)
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    /// Feeds a packet into the current transaction, returning the complete
+    /// message once it has been fully received.
+    fn receive(&mut self, report: &Report) -> Result<Option<Message>, (ChannelId, ErrorCode)> {
+        let packet = Packet::parse(report);
+        let channel = packet.channel();
+
+        if channel == 0 {
+            return Err((channel, ErrorCode::InvalidChannel));
+        }
+
+        let reassembler = match packet {
+            Packet::Initialization {
+                command,
+                length,
+                data,
+                ..
+            } => {
+                let command = Command::try_from(command).map_err(|code| (channel, code))?;
+
+                match &self.transaction {
+                    // INIT on a channel with a pending transaction discards it
+                    Some(pending)
+                        if pending.reassembler.channel() == channel && command == Command::Init =>
+                    {
+                        self.transaction = None;
+                    }
+                    Some(pending) if pending.reassembler.channel() == channel => {
+                        self.transaction = None;
+                        return Err((channel, ErrorCode::InvalidSequence));
+                    }
+                    Some(_) => return Err((channel, ErrorCode::ChannelBusy)),
+                    None => {}
+                }
+
+                // Only INIT may be sent on the broadcast channel, to allocate
+                // a channel
+                let allocating = channel == BROADCAST_CHANNEL && command == Command::Init;
+                if !allocating && !self.channels.contains(&channel) {
+                    return Err((channel, ErrorCode::InvalidChannel));
+                }
+
+                let reassembler = Reassembler::new(channel, command, length, data)
+                    .map_err(|code| (channel, code))?;
+                &mut self
+                    .transaction
+                    .insert(Transaction {
+                        reassembler,
+                        started: Instant::now(),
+                    })
+                    .reassembler
+            }
+            Packet::Continuation { sequence, data, .. } => match self.transaction.as_mut() {
+                Some(pending) if pending.reassembler.channel() == channel => {
+                    if let Err(code) = pending.reassembler.push(sequence, data) {
+                        self.transaction = None;
+                        return Err((channel, code));
+                    }
+                    &mut pending.reassembler
+                }
+                Some(_) => return Err((channel, ErrorCode::ChannelBusy)),
+                // Spurious continuation packets are ignored
+                None => return Ok(None),
+            },
+        };
+
+        if reassembler.is_complete() {
+            Ok(self
+                .transaction
+                .take()
+                .and_then(|pending| pending.reassembler.into_message()))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn process<H: Handler>(&mut self, message: Message, handler: &mut H) -> Vec<Report> {
+        let channel = message.channel;
+
+        let response = match message.command {
+            Command::Init => return self.init(channel, &message.payload),
+            Command::Ping => Ok(message.payload),
+            Command::Cbor if self.capabilities.contains(Capability::Cbor) => {
+                Ok(handler.cbor(&message.payload))
+            }
+            Command::Msg if !self.capabilities.contains(Capability::NoMsg) => {
+                handler.msg(&message.payload)
+            }
+            Command::Wink if self.capabilities.contains(Capability::Wink) => {
+                handler.wink();
+                Ok(Vec::new())
+            }
+            // Requests are processed synchronously, so there is never an
+            // outstanding request to cancel. No response is sent.
+            Command::Cancel => return Vec::new(),
+            _ => Err(ErrorCode::InvalidCommand),
+        };
+
+        match response {
+            Ok(payload) => respond(channel, message.command, payload),
+            Err(code) => error(channel, code),
+        }
+    }
+
+    fn init(&mut self, channel: ChannelId, payload: &[u8]) -> Vec<Report> {
+        let Ok(nonce) = <[u8; NONCE_SIZE]>::try_from(payload) else {
+            return error(channel, ErrorCode::InvalidLength);
+        };
+
+        // Allocation requests are answered on the broadcast channel, while an
+        // INIT on an allocated channel resynchronizes it and keeps its CID.
+        // INIT on any other channel was rejected by `receive`.
+        let allocated = if channel == BROADCAST_CHANNEL {
+            self.allocate()
+        } else {
+            channel
+        };
+
+        let response = InitResponse {
+            nonce,
+            channel: allocated,
+            protocol_version: PROTOCOL_VERSION,
+            major_version: self.major_version,
+            minor_version: self.minor_version,
+            build_version: self.build_version,
+            capabilities: self.capabilities,
+        };
+        respond(channel, Command::Init, response.to_bytes().to_vec())
+    }
+
+    fn allocate(&mut self) -> ChannelId {
+        let channel = self.next_channel;
+        self.next_channel = match self.next_channel.wrapping_add(1) {
+            0 | BROADCAST_CHANNEL => 1,
+            next => next,
+        };
+        if self.channels.len() == MAX_CHANNELS {
+            self.channels.pop_front();
+        }
+        self.channels.push_back(channel);
+        channel
+    }
+}
+
+fn respond(channel: ChannelId, command: Command, payload: Vec<u8>) -> Vec<Report> {
+    let message = Message {
+        channel,
+        command,
+        payload,
+    };
+    message
+        .fragment()
+        .unwrap_or_else(|code| error(channel, code))
+}
+
+fn error(channel: ChannelId, code: ErrorCode) -> Vec<Report> {
+    let message = Message {
+        channel,
+        command: Command::Error,
+        payload: vec![code.into()],
+    };
+    // A single byte payload always fits in one packet
+    message.fragment().unwrap_or_default()
+}
+
+/// An in-memory [`Device`] that delivers the reports sent by the platform
+/// directly to a [`Server`].
+pub struct Loopback<H: Handler> {
+    pub server: Server,
+    pub handler: H,
+    responses: VecDeque<Report>,
+}
+
+/// Returned by [`Loopback`] when a report is read before the authenticator
+/// has sent one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NoPendingReport;
+
+impl<H: Handler> Loopback<H> {
+    pub fn new(server: Server, handler: H) -> Self {
+        Self {
+            server,
+            handler,
+            responses: VecDeque::new(),
+        }
+    }
+}
+
+impl<H: Handler> Device for Loopback<H> {
+    type Error = NoPendingReport;
+
+    fn send_report(&mut self, report: &Report) -> Result<(), Self::Error> {
+        let responses = self.server.handle_report(report, &mut self.handler);
+        self.responses.extend(responses);
+        Ok(())
+    }
+
+    fn receive_report(&mut self) -> Result<Report, Self::Error> {
+        self.responses.pop_front().ok_or(NoPendingReport)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hid::packet::{CONTINUATION_DATA_SIZE, INITIALIZATION_DATA_SIZE};
+
+    /// Responds to CTAP2 requests with their reversed bytes.
+    struct Reverse;
+
+    impl Handler for Reverse {
+        fn cbor(&mut self, request: &[u8]) -> Vec<u8> {
+            request.iter().rev().copied().collect()
+        }
+    }
+
+    fn server() -> Server {
+        Server::new((1, 2, 3), Capability::Cbor | Capability::NoMsg)
+    }
+
+    fn send(server: &mut Server, channel: ChannelId, command: Command, payload: &[u8]) -> Message {
+        let message = Message {
+            channel,
+            command,
+            payload: payload.to_vec(),
+        };
+        let reports = message.fragment().unwrap();
+        let mut responses = Vec::new();
+        for report in &reports {
+            responses.extend(server.handle_report(report, &mut Reverse));
+        }
+        decode(&responses)
+    }
+
+    fn decode(reports: &[Report]) -> Message {
+        let (first, rest) = reports.split_first().unwrap();
+        let Packet::Initialization {
+            channel,
+            command,
+            length,
+            data,
+        } = Packet::parse(first)
+        else {
+            panic!("expected an initialization packet");
+        };
+        let mut reassembler =
+            Reassembler::new(channel, Command::try_from(command).unwrap(), length, data).unwrap();
+        for report in rest {
+            let Packet::Continuation { sequence, data, .. } = Packet::parse(report) else {
+                panic!("expected a continuation packet");
+            };
+            reassembler.push(sequence, data).unwrap();
+        }
+        reassembler.into_message().unwrap()
+    }
+
+    fn error(channel: ChannelId, code: ErrorCode) -> Message {
+        Message {
+            channel,
+            command: Command::Error,
+            payload: vec![code.into()],
+        }
+    }
+
+    /// Allocates a channel, returning its ID.
+    fn allocate(server: &mut Server) -> ChannelId {
+        let nonce = [0x42; NONCE_SIZE];
+        let response = send(server, BROADCAST_CHANNEL, Command::Init, &nonce);
+        assert_eq!(response.channel, BROADCAST_CHANNEL);
+        let response = InitResponse::try_from(response.payload.as_slice()).unwrap();
+        assert_eq!(response.nonce, nonce);
+        response.channel
+    }
+
+    #[test]
+    fn allocates_channels() {
+        let mut server = server();
+        let nonce = [0x42; NONCE_SIZE];
+        let response = send(&mut server, BROADCAST_CHANNEL, Command::Init, &nonce);
+        let response = InitResponse::try_from(response.payload.as_slice()).unwrap();
+        assert_eq!(
+            response,
+            InitResponse {
+                nonce,
+                channel: 1,
+                protocol_version: PROTOCOL_VERSION,
+                major_version: 1,
+                minor_version: 2,
+                build_version: 3,
+                capabilities: Capability::Cbor | Capability::NoMsg,
+            }
+        );
+        assert_eq!(allocate(&mut server), 2);
+
+        // Resynchronizing keeps the channel
+        let response = send(&mut server, 1, Command::Init, &nonce);
+        assert_eq!(response.channel, 1);
+        assert_eq!(
+            InitResponse::try_from(response.payload.as_slice())
+                .unwrap()
+                .channel,
+            1
+        );
+    }
+
+    #[test]
+    fn releases_least_recently_allocated_channel() {
+        let mut server = server();
+        let channels: Vec<_> = (0..=MAX_CHANNELS).map(|_| allocate(&mut server)).collect();
+        assert_eq!(server.channels.len(), MAX_CHANNELS);
+        assert_eq!(
+            send(&mut server, channels[0], Command::Ping, &[0x01]),
+            error(channels[0], ErrorCode::InvalidChannel)
+        );
+        for &channel in &channels[1..] {
+            assert_eq!(
+                send(&mut server, channel, Command::Ping, &[0x01]).payload,
+                [0x01]
+            );
+        }
+    }
+
+    #[test]
+    fn processes_messages() {
+        let mut server = server();
+        let channel = allocate(&mut server);
+        let payload: Vec<u8> = (0..=255).collect();
+
+        let response = send(&mut server, channel, Command::Ping, &payload);
+        assert_eq!(response.command, Command::Ping);
+        assert_eq!(response.payload, payload);
+
+        let response = send(&mut server, channel, Command::Cbor, &payload);
+        assert_eq!(response.command, Command::Cbor);
+        assert_eq!(
+            response.payload,
+            payload.iter().rev().copied().collect::<Vec<_>>()
+        );
+
+        // Commands without the capability
+        assert_eq!(
+            send(&mut server, channel, Command::Msg, &payload),
+            error(channel, ErrorCode::InvalidCommand)
+        );
+        assert_eq!(
+            send(&mut server, channel, Command::Wink, &[]),
+            error(channel, ErrorCode::InvalidCommand)
+        );
+
+        let cancel = Message {
+            channel,
+            command: Command::Cancel,
+            payload: Vec::new(),
+        };
+        let report = cancel.fragment().unwrap()[0];
+        assert!(server.handle_report(&report, &mut Reverse).is_empty());
+    }
+
+    #[test]
+    fn invalid_channel() {
+        let mut server = server();
+        let channel = allocate(&mut server);
+        assert_eq!(
+            send(&mut server, channel + 1, Command::Cbor, &[0x04]),
+            error(channel + 1, ErrorCode::InvalidChannel)
+        );
+        // Only allocated channels can be resynchronized
+        assert_eq!(
+            send(&mut server, channel + 1, Command::Init, &[0x42; NONCE_SIZE]),
+            error(channel + 1, ErrorCode::InvalidChannel)
+        );
+        assert_eq!(
+            send(&mut server, BROADCAST_CHANNEL, Command::Ping, &[0x01]),
+            error(BROADCAST_CHANNEL, ErrorCode::InvalidChannel)
+        );
+        assert_eq!(
+            send(&mut server, 0, Command::Init, &[0x42; NONCE_SIZE]),
+            error(0, ErrorCode::InvalidChannel)
+        );
+        assert_eq!(
+            send(&mut server, BROADCAST_CHANNEL, Command::Init, &[0x42; 4]),
+            error(BROADCAST_CHANNEL, ErrorCode::InvalidLength)
+        );
+    }
+
+    #[test]
+    fn invalid_sequence() {
+        let mut server = server();
+        let channel = allocate(&mut server);
+        let message = Message {
+            channel,
+            command: Command::Cbor,
+            payload: vec![0x42; INITIALIZATION_DATA_SIZE + 2 * CONTINUATION_DATA_SIZE],
+        };
+        let reports = message.fragment().unwrap();
+        assert!(server.handle_report(&reports[0], &mut Reverse).is_empty());
+        assert_eq!(
+            decode(&server.handle_report(&reports[2], &mut Reverse)),
+            error(channel, ErrorCode::InvalidSequence)
+        );
+
+        // The transaction is discarded, so its remaining packets are ignored
+        assert!(server.handle_report(&reports[1], &mut Reverse).is_empty());
+        let response = send(&mut server, channel, Command::Ping, &[0x01]);
+        assert_eq!(response.payload, [0x01]);
+    }
+
+    #[test]
+    fn busy_channel() {
+        let mut server = server();
+        let first = allocate(&mut server);
+        let second = allocate(&mut server);
+        let message = Message {
+            channel: first,
+            command: Command::Cbor,
+            payload: vec![0x42; INITIALIZATION_DATA_SIZE + 1],
+        };
+        let reports = message.fragment().unwrap();
+        assert!(server.handle_report(&reports[0], &mut Reverse).is_empty());
+        assert_eq!(
+            send(&mut server, second, Command::Ping, &[0x01]),
+            error(second, ErrorCode::ChannelBusy)
+        );
+
+        // The pending transaction is unaffected
+        let response = decode(&server.handle_report(&reports[1], &mut Reverse));
+        assert_eq!(response.channel, first);
+        assert_eq!(response.payload, message.payload);
+    }
+
+    #[test]
+    fn init_discards_transaction() {
+        let mut server = server();
+        let channel = allocate(&mut server);
+        let message = Message {
+            channel,
+            command: Command::Cbor,
+            payload: vec![0x42; INITIALIZATION_DATA_SIZE + 1],
+        };
+        let reports = message.fragment().unwrap();
+        assert!(server.handle_report(&reports[0], &mut Reverse).is_empty());
+        let response = send(&mut server, channel, Command::Init, &[0x42; NONCE_SIZE]);
+        assert_eq!(response.command, Command::Init);
+        assert!(server.handle_report(&reports[1], &mut Reverse).is_empty());
+    }
+
+    #[test]
+    fn stalled_transaction_times_out() {
+        let mut server = server();
+        let first = allocate(&mut server);
+        let second = allocate(&mut server);
+        let message = Message {
+            channel: first,
+            command: Command::Cbor,
+            payload: vec![0x42; INITIALIZATION_DATA_SIZE + 1],
+        };
+        let reports = message.fragment().unwrap();
+        assert!(server.handle_report(&reports[0], &mut Reverse).is_empty());
+
+        let elapsed = TRANSACTION_TIMEOUT - Duration::from_secs(1);
+        server.transaction.as_mut().unwrap().started = Instant::now() - elapsed;
+        assert!(server.expire().is_empty());
+
+        // The next report releases the other channels
+        server.transaction.as_mut().unwrap().started = Instant::now() - TRANSACTION_TIMEOUT;
+        let ping = Message {
+            channel: second,
+            command: Command::Ping,
+            payload: vec![0x01],
+        };
+        let responses = server.handle_report(&ping.fragment().unwrap()[0], &mut Reverse);
+        assert_eq!(responses.len(), 2);
+        assert_eq!(
+            decode(&responses[..1]),
+            error(first, ErrorCode::MessageTimeout)
+        );
+        assert_eq!(decode(&responses[1..]), ping);
+
+        // The rest of the stalled transaction is ignored
+        assert!(server.handle_report(&reports[1], &mut Reverse).is_empty());
+    }
+}
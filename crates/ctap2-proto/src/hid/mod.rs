@@ -0,0 +1,289 @@
+//! The CTAPHID transport binding, used to exchange CTAP messages with an
+//! authenticator over USB HID.
+//!
+//! > The HID protocol has been designed with the goal to be driver-less on all
+//! > major
+//! > host platforms.
+//!
+//! This module is transport-agnostic: it only requires a [`Device`] capable of
+//! sending and receiving raw, fixed-size HID reports.
+
+use flagset::{flags, FlagSet};
+use std::fmt::Display;
+
+pub mod authenticator;
+pub mod packet;
+pub mod platform;
+
+/// The size of a full-speed USB HID report, in bytes.
+pub const REPORT_SIZE: usize = 64;
+
+/// A single raw HID report.
+pub type Report = [u8; REPORT_SIZE];
+
+/// > Channel identifier. Value 0 is reserved and 0xffffffff is reserved for
+/// > broadcast commands, i.e. at the time of channel allocation.
+pub type ChannelId = u32;
+
+/// The channel used to allocate a new channel with [`Command::Init`].
+pub const BROADCAST_CHANNEL: ChannelId = 0xFFFF_FFFF;
+
+/// The size of the nonce sent with a [`Command::Init`] request.
+pub const NONCE_SIZE: usize = 8;
+
+/// The version of the CTAPHID protocol implemented by this module.
+pub const PROTOCOL_VERSION: u8 = 2;
+
+/// A raw HID report based transport to an authenticator.
+pub trait Device {
+    type Error;
+
+    /// Writes a single report to the device.
+    fn send_report(&mut self, report: &Report) -> Result<(), Self::Error>;
+
+    /// Blocks until a single report is read from the device.
+    fn receive_report(&mut self) -> Result<Report, Self::Error>;
+}
+
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    /// > Sends a transaction to the device, which immediately echoes the same
+    /// > data back.
+    Ping = 0x01,
+    /// > This command sends an encapsulated CTAP1/U2F message to the device.
+    Msg = 0x03,
+    /// > The lock command places an exclusive lock for one channel to
+    /// > communicate with the device.
+    Lock = 0x04,
+    /// > This command has two functions. If sent on an allocated CID, it
+    /// > synchronizes a channel, discarding the current transaction, buffers
+    /// > and state as quickly as possible. ... If sent on the broadcast CID,
+    /// > it requests the device to allocate a unique 32-bit channel identifier
+    /// > (CID) that can be used by the requesting application during its
+    /// > lifetime.
+    Init = 0x06,
+    /// > The wink command performs a vendor-defined action that provides some
+    /// > visual or audible identification of a particular authenticator.
+    Wink = 0x08,
+    /// > This command sends an encapsulated CTAP CBOR encoded message.
+    Cbor = 0x10,
+    /// > Cancel any outstanding requests on this CID.
+    Cancel = 0x11,
+    /// > This command code is sent while processing a CTAPHID_MSG. It should
+    /// > be sent at least every 100ms and whenever the status changes.
+    Keepalive = 0x3B,
+    /// > This command code is used in response messages only.
+    Error = 0x3F,
+}
+
+impl From<Command> for u8 {
+    fn from(value: Command) -> Self {
+        value as u8
+    }
+}
+
+impl TryFrom<u8> for Command {
+    type Error = ErrorCode;
+
+    fn try_from(value: u8) -> Result<Self, ErrorCode> {
+        Ok(match value {
+            0x01 => Command::Ping,
+            0x03 => Command::Msg,
+            0x04 => Command::Lock,
+            0x06 => Command::Init,
+            0x08 => Command::Wink,
+            0x10 => Command::Cbor,
+            0x11 => Command::Cancel,
+            0x3B => Command::Keepalive,
+            0x3F => Command::Error,
+            _ => return Err(ErrorCode::InvalidCommand),
+        })
+    }
+}
+
+/// Error codes carried by a [`Command::Error`] response.
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorCode {
+    /// > The command in the request is invalid
+    InvalidCommand = 0x01,
+    /// > The parameter(s) in the request is invalid
+    InvalidParameter = 0x02,
+    /// > The length field (BCNT) is invalid for the request
+    InvalidLength = 0x03,
+    /// > The sequence does not match expected value
+    InvalidSequence = 0x04,
+    /// > The message has timed out
+    MessageTimeout = 0x05,
+    /// > The device is busy for the requesting channel. The client SHOULD
+    /// > retry the request after a short delay.
+    ChannelBusy = 0x06,
+    /// > Command requires channel lock
+    LockRequired = 0x0A,
+    /// > CID is not valid.
+    InvalidChannel = 0x0B,
+    /// > Unspecified error
+    Other = 0x7F,
+}
+
+impl From<ErrorCode> for u8 {
+    fn from(value: ErrorCode) -> Self {
+        value as u8
+    }
+}
+
+impl From<u8> for ErrorCode {
+    fn from(value: u8) -> Self {
+        match value {
+            0x01 => ErrorCode::InvalidCommand,
+            0x02 => ErrorCode::InvalidParameter,
+            0x03 => ErrorCode::InvalidLength,
+            0x04 => ErrorCode::InvalidSequence,
+            0x05 => ErrorCode::MessageTimeout,
+            0x06 => ErrorCode::ChannelBusy,
+            0x0A => ErrorCode::LockRequired,
+            0x0B => ErrorCode::InvalidChannel,
+            _ => ErrorCode::Other,
+        }
+    }
+}
+
+impl Display for ErrorCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let message = match self {
+            ErrorCode::InvalidCommand => "Invalid command",
+            ErrorCode::InvalidParameter => "Invalid parameter",
+            ErrorCode::InvalidLength => "Invalid length",
+            ErrorCode::InvalidSequence => "Invalid sequence",
+            ErrorCode::MessageTimeout => "Message timeout",
+            ErrorCode::ChannelBusy => "Channel busy",
+            ErrorCode::LockRequired => "Lock required",
+            ErrorCode::InvalidChannel => "Invalid channel",
+            ErrorCode::Other => "Other",
+        };
+        write!(f, "{}", message)
+    }
+}
+
+/// The status sent by the authenticator in a [`Command::Keepalive`] message.
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeepaliveStatus {
+    /// > The authenticator is still processing the current request.
+    Processing = 0x01,
+    /// > The authenticator is waiting for user presence.
+    UserPresenceNeeded = 0x02,
+}
+
+impl TryFrom<u8> for KeepaliveStatus {
+    type Error = ErrorCode;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0x01 => Ok(KeepaliveStatus::Processing),
+            0x02 => Ok(KeepaliveStatus::UserPresenceNeeded),
+            _ => Err(ErrorCode::InvalidParameter),
+        }
+    }
+}
+
+flags! {
+    /// Capabilities advertised by the authenticator in its response to
+    /// [`Command::Init`].
+    pub enum Capability: u8 {
+        /// > If set to 1, authenticator implements CTAPHID_WINK function
+        Wink = 0x01,
+        /// > If set to 1, authenticator implements CTAPHID_CBOR function
+        Cbor = 0x04,
+        /// > If set to 1, authenticator DOES NOT implement CTAPHID_MSG
+        /// > function
+        NoMsg = 0x08,
+    }
+}
+
+/// The payload of the authenticator's response to [`Command::Init`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InitResponse {
+    /// > 8-byte nonce
+    pub nonce: [u8; NONCE_SIZE],
+    /// > 4-byte channel ID
+    pub channel: ChannelId,
+    /// > CTAPHID protocol version identifier
+    pub protocol_version: u8,
+    /// > Major device version number
+    pub major_version: u8,
+    /// > Minor device version number
+    pub minor_version: u8,
+    /// > Build device version number
+    pub build_version: u8,
+    /// > Capabilities flags
+    pub capabilities: FlagSet<Capability>,
+}
+
+impl InitResponse {
+    /// The length of the encoded response payload.
+    pub const SIZE: usize = 17;
+
+    pub fn to_bytes(&self) -> [u8; Self::SIZE] {
+        let mut bytes = [0; Self::SIZE];
+        bytes[..8].copy_from_slice(&self.nonce);
+        bytes[8..12].copy_from_slice(&self.channel.to_be_bytes());
+        bytes[12] = self.protocol_version;
+        bytes[13] = self.major_version;
+        bytes[14] = self.minor_version;
+        bytes[15] = self.build_version;
+        bytes[16] = self.capabilities.bits();
+        bytes
+    }
+}
+
+impl TryFrom<&[u8]> for InitResponse {
+    type Error = ErrorCode;
+
+    fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
+        if value.len() < Self::SIZE {
+            return Err(ErrorCode::InvalidLength);
+        }
+
+        let mut nonce = [0; NONCE_SIZE];
+        nonce.copy_from_slice(&value[..8]);
+        let mut channel = [0; 4];
+        channel.copy_from_slice(&value[8..12]);
+
+        Ok(Self {
+            nonce,
+            channel: ChannelId::from_be_bytes(channel),
+            protocol_version: value[12],
+            major_version: value[13],
+            minor_version: value[14],
+            build_version: value[15],
+            capabilities: FlagSet::new_truncated(value[16]),
+        })
+    }
+}
+
+/// Errors encountered while exchanging messages over CTAPHID.
+#[derive(Debug)]
+pub enum Error<E> {
+    /// The underlying [`Device`] failed to send or receive a report.
+    Transport(E),
+    /// The authenticator responded with a [`Command::Error`] message.
+    Device(ErrorCode),
+    /// The message is larger than can be represented by a single CTAPHID
+    /// transaction.
+    MessageTooLarge,
+    /// The authenticator sent a malformed or unexpected message.
+    InvalidResponse,
+}
+
+impl<E: Display> Display for Error<E> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Transport(error) => write!(f, "Transport error: {}", error),
+            Error::Device(code) => write!(f, "Device error: {}", code),
+            Error::MessageTooLarge => write!(f, "Message too large"),
+            Error::InvalidResponse => write!(f, "Invalid response"),
+        }
+    }
+}
@@ -0,0 +1,304 @@
+//! Fragmentation of CTAPHID messages into HID reports and their reassembly.
+//!
+//! > A message is transferred as one initialization packet, optionally
+//! > followed by one or more continuation packets.
+
+use super::{ChannelId, Command, ErrorCode, Report, REPORT_SIZE};
+
+/// The number of payload bytes carried by an initialization packet.
+pub const INITIALIZATION_DATA_SIZE: usize = REPORT_SIZE - 7;
+
+/// The number of payload bytes carried by a continuation packet.
+pub const CONTINUATION_DATA_SIZE: usize = REPORT_SIZE - 5;
+
+/// > With a packet size of 64 bytes (max for full-speed devices), this means
+/// > that the maximum message payload length is 64 - 7 + 128 * (64 - 5) = 7609
+/// > bytes.
+pub const MAX_MESSAGE_SIZE: usize = INITIALIZATION_DATA_SIZE + 128 * CONTINUATION_DATA_SIZE;
+
+/// The bit set in the command byte of every initialization packet.
+const INITIALIZATION_BIT: u8 = 0x80;
+
+/// A single HID report, interpreted as a CTAPHID packet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Packet<'a> {
+    Initialization {
+        channel: ChannelId,
+        /// The raw command byte, without the initialization bit.
+        command: u8,
+        /// > Payload length
+        length: u16,
+        data: &'a [u8],
+    },
+    Continuation {
+        channel: ChannelId,
+        /// > Packet sequence 0x00..0x7f (high bit always cleared)
+        sequence: u8,
+        data: &'a [u8],
+    },
+}
+
+impl<'a> Packet<'a> {
+    pub fn parse(report: &'a Report) -> Self {
+        let channel = ChannelId::from_be_bytes([report[0], report[1], report[2], report[3]]);
+
+        if report[4] & INITIALIZATION_BIT != 0 {
+            Packet::Initialization {
+                channel,
+                command: report[4] & !INITIALIZATION_BIT,
+                length: u16::from_be_bytes([report[5], report[6]]),
+                data: &report[7..],
+            }
+        } else {
+            Packet::Continuation {
+                channel,
+                sequence: report[4],
+                data: &report[5..],
+            }
+        }
+    }
+
+    pub fn channel(&self) -> ChannelId {
+        match self {
+            Packet::Initialization { channel, .. } | Packet::Continuation { channel, .. } => {
+                *channel
+            }
+        }
+    }
+}
+
+/// A complete CTAPHID message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Message {
+    pub channel: ChannelId,
+    pub command: Command,
+    pub payload: Vec<u8>,
+}
+
+impl Message {
+    /// Splits the message into an initialization packet followed by as many
+    /// continuation packets as required. Unused bytes are zero-padded.
+    pub fn fragment(&self) -> Result<Vec<Report>, ErrorCode> {
+        if self.payload.len() > MAX_MESSAGE_SIZE {
+            return Err(ErrorCode::InvalidLength);
+        }
+
+        let channel = self.channel.to_be_bytes();
+        let (first, rest) = self
+            .payload
+            .split_at(self.payload.len().min(INITIALIZATION_DATA_SIZE));
+
+        let mut report = [0; REPORT_SIZE];
+        report[..4].copy_from_slice(&channel);
+        report[4] = u8::from(self.command) | INITIALIZATION_BIT;
+        report[5..7].copy_from_slice(&(self.payload.len() as u16).to_be_bytes());
+        report[7..7 + first.len()].copy_from_slice(first);
+
+        let mut reports = vec![report];
+        for (sequence, chunk) in rest.chunks(CONTINUATION_DATA_SIZE).enumerate() {
+            let mut report = [0; REPORT_SIZE];
+            report[..4].copy_from_slice(&channel);
+            // Cannot exceed 0x7F since the payload length was checked above
+            report[4] = sequence as u8;
+            report[5..5 + chunk.len()].copy_from_slice(chunk);
+            reports.push(report);
+        }
+
+        Ok(reports)
+    }
+}
+
+/// Collects the packets of a single message until the full payload has been
+/// received.
+#[derive(Debug, Clone)]
+pub struct Reassembler {
+    channel: ChannelId,
+    command: Command,
+    length: usize,
+    payload: Vec<u8>,
+    next_sequence: u8,
+}
+
+impl Reassembler {
+    /// Begins reassembly from an initialization packet.
+    pub fn new(
+        channel: ChannelId,
+        command: Command,
+        length: u16,
+        data: &[u8],
+    ) -> Result<Self, ErrorCode> {
+        let length = length as usize;
+        if length > MAX_MESSAGE_SIZE {
+            return Err(ErrorCode::InvalidLength);
+        }
+
+        let mut payload = Vec::with_capacity(length);
+        payload.extend_from_slice(&data[..length.min(data.len())]);
+
+        Ok(Self {
+            channel,
+            command,
+            length,
+            payload,
+            next_sequence: 0,
+        })
+    }
+
+    pub fn channel(&self) -> ChannelId {
+        self.channel
+    }
+
+    /// Appends the data of a continuation packet, which must carry the next
+    /// expected sequence number.
+    pub fn push(&mut self, sequence: u8, data: &[u8]) -> Result<(), ErrorCode> {
+        if self.is_complete() || sequence != self.next_sequence {
+            return Err(ErrorCode::InvalidSequence);
+        }
+
+        let remaining = self.length - self.payload.len();
+        self.payload
+            .extend_from_slice(&data[..remaining.min(data.len())]);
+        self.next_sequence += 1;
+
+        Ok(())
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.payload.len() == self.length
+    }
+
+    /// Returns the reassembled message, or [`None`] if packets are still
+    /// outstanding.
+    pub fn into_message(self) -> Option<Message> {
+        self.is_complete().then_some(Message {
+            channel: self.channel,
+            command: self.command,
+            payload: self.payload,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(length: usize) -> Message {
+        Message {
+            channel: 0x0102_0304,
+            command: Command::Cbor,
+            payload: (0..length).map(|i| i as u8).collect(),
+        }
+    }
+
+    /// Reassembles the reports produced by [`Message::fragment`].
+    fn reassemble(reports: &[Report]) -> Result<Message, ErrorCode> {
+        let (first, rest) = reports.split_first().unwrap();
+        let Packet::Initialization {
+            channel,
+            command,
+            length,
+            data,
+        } = Packet::parse(first)
+        else {
+            panic!("expected an initialization packet");
+        };
+        let command = Command::try_from(command)?;
+        let mut reassembler = Reassembler::new(channel, command, length, data)?;
+        for report in rest {
+            let Packet::Continuation { sequence, data, .. } = Packet::parse(report) else {
+                panic!("expected a continuation packet");
+            };
+            reassembler.push(sequence, data)?;
+        }
+        Ok(reassembler.into_message().unwrap())
+    }
+
+    #[test]
+    fn initialization_packet() {
+        let reports = message(3).fragment().unwrap();
+        assert_eq!(reports.len(), 1);
+        assert_eq!(reports[0][..10], [1, 2, 3, 4, 0x90, 0, 3, 0, 1, 2]);
+        assert!(reports[0][10..].iter().all(|&byte| byte == 0));
+        assert_eq!(
+            Packet::parse(&reports[0]),
+            Packet::Initialization {
+                channel: 0x0102_0304,
+                command: 0x10,
+                length: 3,
+                data: &reports[0][7..],
+            }
+        );
+    }
+
+    #[test]
+    fn fragment_boundaries() {
+        let cases = [
+            (0, 1),
+            (INITIALIZATION_DATA_SIZE, 1),
+            (INITIALIZATION_DATA_SIZE + 1, 2),
+            (INITIALIZATION_DATA_SIZE + CONTINUATION_DATA_SIZE, 2),
+            (INITIALIZATION_DATA_SIZE + CONTINUATION_DATA_SIZE + 1, 3),
+            (MAX_MESSAGE_SIZE, 129),
+        ];
+        for (length, packets) in cases {
+            let message = message(length);
+            let reports = message.fragment().unwrap();
+            assert_eq!(reports.len(), packets, "{length} bytes");
+            for (sequence, report) in reports[1..].iter().enumerate() {
+                assert_eq!(
+                    Packet::parse(report),
+                    Packet::Continuation {
+                        channel: 0x0102_0304,
+                        sequence: sequence as u8,
+                        data: &report[5..],
+                    }
+                );
+            }
+            assert_eq!(reassemble(&reports), Ok(message));
+        }
+    }
+
+    #[test]
+    fn message_too_large() {
+        assert_eq!(
+            message(MAX_MESSAGE_SIZE + 1).fragment(),
+            Err(ErrorCode::InvalidLength)
+        );
+        let length = MAX_MESSAGE_SIZE as u16 + 1;
+        assert!(Reassembler::new(1, Command::Cbor, length, &[]).is_err());
+    }
+
+    #[test]
+    fn out_of_order_sequence() {
+        let reports = message(INITIALIZATION_DATA_SIZE + 3 * CONTINUATION_DATA_SIZE)
+            .fragment()
+            .unwrap();
+        assert_eq!(
+            reassemble(&[reports[0], reports[2], reports[1], reports[3]]),
+            Err(ErrorCode::InvalidSequence)
+        );
+        assert_eq!(
+            reassemble(&[reports[0], reports[1], reports[1], reports[2]]),
+            Err(ErrorCode::InvalidSequence)
+        );
+
+        // No packets are accepted once the message is complete
+        let mut reassembler = Reassembler::new(1, Command::Cbor, 1, &[0x42]).unwrap();
+        assert!(reassembler.is_complete());
+        assert_eq!(
+            reassembler.push(0, &[0x43]),
+            Err(ErrorCode::InvalidSequence)
+        );
+    }
+
+    #[test]
+    fn incomplete_message() {
+        let reports = message(INITIALIZATION_DATA_SIZE + 1).fragment().unwrap();
+        let Packet::Initialization { length, data, .. } = Packet::parse(&reports[0]) else {
+            panic!("expected an initialization packet");
+        };
+        let reassembler = Reassembler::new(1, Command::Cbor, length, data).unwrap();
+        assert!(!reassembler.is_complete());
+        assert_eq!(reassembler.into_message(), None);
+    }
+}
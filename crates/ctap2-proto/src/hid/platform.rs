@@ -0,0 +1,396 @@
+//! The platform (host) side of a CTAPHID connection.
+
+use super::{
+    packet::{Message, Packet, Reassembler},
+    ChannelId, Command, Device, Error, ErrorCode, InitResponse, KeepaliveStatus, Report,
+    BROADCAST_CHANNEL, NONCE_SIZE,
+};
+
+/// A channel allocated on an authenticator, over which transactions are
+/// performed.
+///
+/// > A transaction is defined as a request message, followed by a response
+/// > message. ... Only one (1) transaction can be outstanding per channel at a
+/// > time.
+pub struct Channel<D: Device> {
+    device: D,
+    info: InitResponse,
+}
+
+impl<D: Device> Channel<D> {
+    /// Allocates a new channel on the device using a `CTAPHID_INIT` request
+    /// on the broadcast channel.
+    ///
+    /// The nonce should be freshly generated for each allocation so that
+    /// responses to other applications' requests are not mistaken for our
+    /// own.
+    pub fn open(mut device: D, nonce: [u8; NONCE_SIZE]) -> Result<Self, Error<D::Error>> {
+        send(&mut device, BROADCAST_CHANNEL, Command::Init, &nonce)?;
+
+        loop {
+            let message = receive(&mut device, BROADCAST_CHANNEL)?;
+            match message.command {
+                Command::Init => {
+                    let info = InitResponse::try_from(message.payload.as_slice())
+                        .map_err(|_| Error::InvalidResponse)?;
+                    // Other applications may be allocating channels at the
+                    // same time, so only the response to our nonce counts
+                    if info.nonce == nonce {
+                        return Ok(Self { device, info });
+                    }
+                }
+                Command::Error => return Err(device_error(&message.payload)),
+                _ => continue,
+            }
+        }
+    }
+
+    /// The identifier of the allocated channel.
+    pub fn id(&self) -> ChannelId {
+        self.info.channel
+    }
+
+    /// The authenticator's response to the channel allocation, including its
+    /// version and capabilities.
+    pub fn info(&self) -> &InitResponse {
+        &self.info
+    }
+
+    pub fn into_inner(self) -> D {
+        self.device
+    }
+
+    /// Sends a request message and waits for its response, invoking
+    /// `keepalive` for each `CTAPHID_KEEPALIVE` received in the meantime.
+    pub fn transact(
+        &mut self,
+        command: Command,
+        payload: &[u8],
+        mut keepalive: impl FnMut(KeepaliveStatus),
+    ) -> Result<Vec<u8>, Error<D::Error>> {
+        let channel = self.id();
+        send(&mut self.device, channel, command, payload)?;
+
+        loop {
+            let message = receive(&mut self.device, channel)?;
+            match message.command {
+                Command::Keepalive => {
+                    let status = message
+                        .payload
+                        .first()
+                        .and_then(|&status| KeepaliveStatus::try_from(status).ok())
+                        .ok_or(Error::InvalidResponse)?;
+                    keepalive(status);
+                }
+                Command::Error => return Err(device_error(&message.payload)),
+                response if response == command => return Ok(message.payload),
+                _ => return Err(Error::InvalidResponse),
+            }
+        }
+    }
+
+    /// Sends an encapsulated CTAP2 request (command byte followed by CBOR
+    /// parameters) and returns the response (status byte followed by CBOR
+    /// data).
+    pub fn cbor(
+        &mut self,
+        request: &[u8],
+        keepalive: impl FnMut(KeepaliveStatus),
+    ) -> Result<Vec<u8>, Error<D::Error>> {
+        self.transact(Command::Cbor, request, keepalive)
+    }
+
+    /// Sends an encapsulated CTAP1/U2F message.
+    pub fn msg(
+        &mut self,
+        request: &[u8],
+        keepalive: impl FnMut(KeepaliveStatus),
+    ) -> Result<Vec<u8>, Error<D::Error>> {
+        self.transact(Command::Msg, request, keepalive)
+    }
+
+    /// Sends data which the authenticator echoes back unchanged.
+    pub fn ping(&mut self, data: &[u8]) -> Result<(), Error<D::Error>> {
+        let response = self.transact(Command::Ping, data, |_| {})?;
+        if response == data {
+            Ok(())
+        } else {
+            Err(Error::InvalidResponse)
+        }
+    }
+
+    /// Requests that the authenticator identify itself to the user.
+    pub fn wink(&mut self) -> Result<(), Error<D::Error>> {
+        self.transact(Command::Wink, &[], |_| {}).map(|_| ())
+    }
+
+    /// > Cancel any outstanding requests on this CID. If there is an
+    /// > outstanding request that can be cancelled, the authenticator MUST
+    /// > cancel it and that cancelled request will reply with the error
+    /// > CTAP2_ERR_KEEPALIVE_CANCEL.
+    ///
+    /// The authenticator does not respond to the cancel request itself.
+    pub fn cancel(&mut self) -> Result<(), Error<D::Error>> {
+        let channel = self.id();
+        send(&mut self.device, channel, Command::Cancel, &[])
+    }
+}
+
+fn send<D: Device>(
+    device: &mut D,
+    channel: ChannelId,
+    command: Command,
+    payload: &[u8],
+) -> Result<(), Error<D::Error>> {
+    let message = Message {
+        channel,
+        command,
+        payload: payload.to_vec(),
+    };
+    let reports = message.fragment().map_err(|_| Error::MessageTooLarge)?;
+    for report in &reports {
+        device.send_report(report).map_err(Error::Transport)?;
+    }
+    Ok(())
+}
+
+/// Reads reports until a complete message has been received on `channel`.
+/// Packets addressed to other channels are discarded.
+fn receive<D: Device>(device: &mut D, channel: ChannelId) -> Result<Message, Error<D::Error>> {
+    let mut reassembler: Option<Reassembler> = None;
+
+    loop {
+        let report: Report = device.receive_report().map_err(Error::Transport)?;
+        let packet = Packet::parse(&report);
+        if packet.channel() != channel {
+            continue;
+        }
+
+        match (packet, reassembler.as_mut()) {
+            (
+                Packet::Initialization {
+                    command,
+                    length,
+                    data,
+                    ..
+                },
+                _,
+            ) => {
+                let command = Command::try_from(command).map_err(|_| Error::InvalidResponse)?;
+                reassembler = Some(
+                    Reassembler::new(channel, command, length, data)
+                        .map_err(|_| Error::InvalidResponse)?,
+                );
+            }
+            (Packet::Continuation { sequence, data, .. }, Some(reassembler)) => {
+                reassembler
+                    .push(sequence, data)
+                    .map_err(|_| Error::InvalidResponse)?;
+            }
+            // A continuation packet without a preceding initialization packet
+            (Packet::Continuation { .. }, None) => return Err(Error::InvalidResponse),
+        }
+
+        if reassembler.as_ref().is_some_and(Reassembler::is_complete) {
+            // Checked to be complete immediately above
+            return Ok(reassembler.and_then(Reassembler::into_message).unwrap());
+        }
+    }
+}
+
+fn device_error<E>(payload: &[u8]) -> Error<E> {
+    payload
+        .first()
+        .map(|&code| Error::Device(ErrorCode::from(code)))
+        .unwrap_or(Error::InvalidResponse)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hid::{
+        authenticator::{Handler, Loopback, NoPendingReport, Server},
+        packet::MAX_MESSAGE_SIZE,
+        Capability, REPORT_SIZE,
+    };
+    use std::collections::VecDeque;
+
+    const NONCE: [u8; NONCE_SIZE] = [0x42; NONCE_SIZE];
+
+    /// Responds to CTAP2 requests with their reversed bytes, and counts
+    /// winks.
+    #[derive(Default)]
+    struct Reverse {
+        winks: usize,
+    }
+
+    impl Handler for Reverse {
+        fn cbor(&mut self, request: &[u8]) -> Vec<u8> {
+            request.iter().rev().copied().collect()
+        }
+
+        fn wink(&mut self) {
+            self.winks += 1;
+        }
+    }
+
+    /// A device replaying the given messages, regardless of the requests.
+    #[derive(Default)]
+    struct Script {
+        responses: VecDeque<Report>,
+        sent: Vec<Report>,
+    }
+
+    impl Script {
+        fn respond(mut self, channel: ChannelId, command: Command, payload: &[u8]) -> Self {
+            let message = Message {
+                channel,
+                command,
+                payload: payload.to_vec(),
+            };
+            self.responses.extend(message.fragment().unwrap());
+            self
+        }
+
+        fn init(self, nonce: [u8; NONCE_SIZE], channel: ChannelId) -> Self {
+            let response = InitResponse {
+                nonce,
+                channel,
+                protocol_version: 2,
+                major_version: 0,
+                minor_version: 0,
+                build_version: 0,
+                capabilities: Capability::Cbor.into(),
+            };
+            self.respond(BROADCAST_CHANNEL, Command::Init, &response.to_bytes())
+        }
+    }
+
+    impl Device for Script {
+        type Error = NoPendingReport;
+
+        fn send_report(&mut self, report: &Report) -> Result<(), Self::Error> {
+            self.sent.push(*report);
+            Ok(())
+        }
+
+        fn receive_report(&mut self) -> Result<Report, Self::Error> {
+            self.responses.pop_front().ok_or(NoPendingReport)
+        }
+    }
+
+    #[test]
+    fn loopback_round_trip() {
+        let server = Server::new(
+            (1, 2, 3),
+            Capability::Cbor | Capability::Wink | Capability::NoMsg,
+        );
+        let mut channel = Channel::open(Loopback::new(server, Reverse::default()), NONCE).unwrap();
+        assert_eq!(channel.id(), 1);
+        assert_eq!(channel.info().major_version, 1);
+        assert!(channel.info().capabilities.contains(Capability::Wink));
+
+        for length in [0, 1, 57, 58, 116, 117, MAX_MESSAGE_SIZE] {
+            let request: Vec<u8> = (0..length).map(|i| i as u8).collect();
+            let response = channel.cbor(&request, |_| {}).unwrap();
+            assert_eq!(response, request.iter().rev().copied().collect::<Vec<_>>());
+            channel.ping(&request).unwrap();
+        }
+        assert!(matches!(
+            channel.cbor(&[0; MAX_MESSAGE_SIZE + 1], |_| {}),
+            Err(Error::MessageTooLarge)
+        ));
+
+        channel.wink().unwrap();
+        assert!(matches!(
+            channel.msg(&[0x00], |_| {}),
+            Err(Error::Device(ErrorCode::InvalidCommand))
+        ));
+        channel.cancel().unwrap();
+        assert_eq!(channel.into_inner().handler.winks, 1);
+    }
+
+    #[test]
+    fn loopback_channels() {
+        let server = Server::new((1, 2, 3), Capability::Cbor);
+        let first = Channel::open(Loopback::new(server, Reverse::default()), NONCE).unwrap();
+        assert_eq!(first.id(), 1);
+        let mut second = Channel::open(first.into_inner(), [0x43; NONCE_SIZE]).unwrap();
+        assert_eq!(second.id(), 2);
+        second.ping(&[0x01]).unwrap();
+        assert!(matches!(
+            second.wink(),
+            Err(Error::Device(ErrorCode::InvalidCommand))
+        ));
+    }
+
+    #[test]
+    fn ignores_other_nonces() {
+        let device = Script::default().init([0x43; NONCE_SIZE], 7).init(NONCE, 8);
+        let channel = Channel::open(device, NONCE).unwrap();
+        assert_eq!(channel.id(), 8);
+
+        let device = Script::default().respond(BROADCAST_CHANNEL, Command::Error, &[0x06]);
+        assert!(matches!(
+            Channel::open(device, NONCE),
+            Err(Error::Device(ErrorCode::ChannelBusy))
+        ));
+    }
+
+    #[test]
+    fn keepalive() {
+        let device = Script::default()
+            .init(NONCE, 8)
+            .respond(8, Command::Keepalive, &[0x01])
+            .respond(9, Command::Cbor, &[0x01])
+            .respond(8, Command::Keepalive, &[0x02])
+            .respond(8, Command::Cbor, &[0x00; 100]);
+        let mut channel = Channel::open(device, NONCE).unwrap();
+        let mut statuses = Vec::new();
+        let response = channel
+            .cbor(&[0x04], |status| statuses.push(status))
+            .unwrap();
+        assert_eq!(response, [0x00; 100]);
+        assert_eq!(
+            statuses,
+            [
+                KeepaliveStatus::Processing,
+                KeepaliveStatus::UserPresenceNeeded
+            ]
+        );
+        assert_eq!(
+            channel.into_inner().sent[1][..8],
+            [0x00, 0x00, 0x00, 0x08, 0x90, 0x00, 0x01, 0x04]
+        );
+    }
+
+    #[test]
+    fn invalid_responses() {
+        let responses: [(Command, &[u8]); 3] = [
+            (Command::Keepalive, &[0x03]),
+            (Command::Ping, &[]),
+            (Command::Error, &[]),
+        ];
+        for (command, payload) in responses {
+            let device = Script::default()
+                .init(NONCE, 8)
+                .respond(8, command, payload);
+            let mut channel = Channel::open(device, NONCE).unwrap();
+            assert!(matches!(
+                channel.cbor(&[0x04], |_| {}),
+                Err(Error::InvalidResponse)
+            ));
+        }
+
+        // A continuation packet must follow an initialization packet
+        let mut device = Script::default().init(NONCE, 8);
+        let mut report = [0; REPORT_SIZE];
+        report[..4].copy_from_slice(&8u32.to_be_bytes());
+        device.responses.push_back(report);
+        let mut channel = Channel::open(device, NONCE).unwrap();
+        assert!(matches!(
+            channel.cbor(&[0x04], |_| {}),
+            Err(Error::InvalidResponse)
+        ));
+    }
+}
@@ -6,8 +6,9 @@ pub mod prelude {
             assertion::get,
             bio_enrollment, client_pin, config,
             credential::{make, management},
-            device, reset, selection,
+            device, large_blobs, reset, selection,
         },
+        status::StatusCode,
         Command, Ctap2_2Authenticator,
     };
     pub use fido_common::*;
@@ -15,7 +16,11 @@ pub mod prelude {
 use prelude::*;
 
 pub mod authenticator;
+#[cfg(feature = "serde")]
+pub mod dispatch;
 pub mod extensions;
+pub mod hid;
+pub mod status;
 
 /// Defines the raw CTAP operations
 pub trait Ctap2_2Authenticator {
@@ -29,6 +34,13 @@ pub trait Ctap2_2Authenticator {
     /// > relying party identifier.
     fn get_assertion(&mut self, request: get::Request) -> Result<get::Response, get::Error>;
 
+    /// > The client calls this method when the `authenticatorGetAssertion`
+    /// > response contains the `numberOfCredentials` member and the number of
+    /// > credentials exceeds 1. This method is used to obtain the next
+    /// > per-credential signature for a given `authenticatorGetAssertion`
+    /// > request.
+    fn get_next_assertion(&mut self) -> Result<get::Response, get::Error>;
+
     /// > Using this method, platforms can request that the authenticator report
     /// > a list of its supported protocol versions and extensions, its AAGUID,
     /// > and other aspects of its overall capabilities. Platforms should use
@@ -49,6 +61,8 @@ pub trait Ctap2_2Authenticator {
     /// > factory default state.
     fn reset(&mut self) -> Result<(), reset::Error>;
 
+    /// > This command is used by the platform to provision/enumerate/delete
+    /// > bio enrollments in the authenticator.
     fn bio_enrollment(
         &mut self,
         request: bio_enrollment::Request,
@@ -65,7 +79,12 @@ pub trait Ctap2_2Authenticator {
     /// > authenticator by asking for user presence.
     fn selection(&mut self) -> Result<(), authenticator::selection::Error>;
 
-    // fn large_blobs() -> Result<(), ()>;
+    /// > This command is used by the platform to read and write the large-blob
+    /// > array.
+    fn large_blobs(
+        &mut self,
+        request: large_blobs::Request,
+    ) -> Result<large_blobs::Response, large_blobs::Error>;
 
     // > This command is used to configure various authenticator features
     // > through the use of its subcommands.
@@ -73,6 +92,7 @@ pub trait Ctap2_2Authenticator {
 }
 
 #[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Command {
     AuthenticatorMakeCredential = 0x01,
     AuthenticatorGetAssertion = 0x02,
@@ -88,3 +108,32 @@ pub enum Command {
     PrototypeAuthenticatorBioEnrollment = 0x40,
     PrototypeAuthenticatorCredentialManagement = 0x41,
 }
+
+impl From<Command> for u8 {
+    fn from(value: Command) -> Self {
+        value as u8
+    }
+}
+
+impl TryFrom<u8> for Command {
+    type Error = StatusCode;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        Ok(match value {
+            0x01 => Command::AuthenticatorMakeCredential,
+            0x02 => Command::AuthenticatorGetAssertion,
+            0x04 => Command::AuthenticatorGetInfo,
+            0x06 => Command::AuthenticatorClientPin,
+            0x07 => Command::AuthenticatorReset,
+            0x08 => Command::AuthenticatorGetNextAssertion,
+            0x09 => Command::AuthenticatorBioEnrollment,
+            0x0A => Command::AuthenticatorCredentialManagement,
+            0x0B => Command::AuthenticatorSelection,
+            0x0C => Command::AuthenticatorLargeBlobs,
+            0x0D => Command::AuthenticatorConfig,
+            0x40 => Command::PrototypeAuthenticatorBioEnrollment,
+            0x41 => Command::PrototypeAuthenticatorCredentialManagement,
+            _ => return Err(StatusCode::InvalidCommand),
+        })
+    }
+}
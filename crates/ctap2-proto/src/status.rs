@@ -0,0 +1,334 @@
+//! The status byte that prefixes every CTAP2 response.
+
+use std::fmt::Display;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// > The error response values range from 0x01 - 0xff. This range is split
+/// > based on error type.
+///
+/// Every byte maps to a status code, so conversions between the two are
+/// lossless.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(from = "u8", into = "u8")
+)]
+pub enum StatusCode {
+    /// > Indicates successful response.
+    Ok,
+    /// > The command is not a valid CTAP command.
+    InvalidCommand,
+    /// > The command included an invalid parameter.
+    InvalidParameter,
+    /// > Invalid message or item length.
+    InvalidLength,
+    /// > Invalid message sequencing.
+    InvalidSequence,
+    /// > Message timed out.
+    Timeout,
+    /// > Channel busy. Client SHOULD retry the request after a short delay.
+    /// > Note that the client MAY abort the transaction if the command is no
+    /// > longer relevant.
+    ChannelBusy,
+    /// > Command requires channel lock.
+    LockRequired,
+    /// > Command not allowed on this cid.
+    InvalidChannel,
+    /// > Invalid/unexpected CBOR error.
+    CborUnexpectedType,
+    /// > Error when parsing CBOR.
+    InvalidCbor,
+    /// > Missing non-optional parameter.
+    MissingParameter,
+    /// > Limit for number of items exceeded.
+    LimitExceeded,
+    /// > Fingerprint data base is full, e.g., during enrollment.
+    FingerprintDatabaseFull,
+    /// > Large blob storage is full.
+    LargeBlobStorageFull,
+    /// > Valid credential found in the exclude list.
+    CredentialExcluded,
+    /// > Processing (Lengthy operation is in progress).
+    Processing,
+    /// > Credential not valid for the authenticator.
+    InvalidCredential,
+    /// > Authentication is waiting for user interaction.
+    UserActionPending,
+    /// > Processing, lengthy operation is in progress.
+    OperationPending,
+    /// > No request is pending.
+    NoOperations,
+    /// > Authenticator does not support requested algorithm.
+    UnsupportedAlgorithm,
+    /// > Not authorized for requested operation.
+    OperationDenied,
+    /// > Internal key storage is full.
+    KeyStoreFull,
+    /// > Unsupported option.
+    UnsupportedOption,
+    /// > Not a valid option for current operation.
+    InvalidOption,
+    /// > Pending keep alive was cancelled.
+    KeepaliveCancel,
+    /// > No valid credentials provided.
+    NoCredentials,
+    /// > A user action timeout occurred.
+    UserActionTimeout,
+    /// > Continuation command, such as, `authenticatorGetNextAssertion` not
+    /// > allowed.
+    NotAllowed,
+    /// > PIN Invalid.
+    PinInvalid,
+    /// > PIN Blocked.
+    PinBlocked,
+    /// > PIN authentication,`pinUvAuthParam`, verification failed.
+    PinAuthInvalid,
+    /// > PIN authentication using `pinUvAuthToken` blocked. Requires power
+    /// > cycle to reset.
+    PinAuthBlocked,
+    /// > No PIN has been set.
+    PinNotSet,
+    /// > A `pinUvAuthToken` is required for the selected operation.
+    PinUvAuthTokenRequired,
+    /// > PIN policy violation.
+    PinPolicyViolation,
+    /// > Authenticator cannot handle this request due to memory constraints.
+    RequestTooLarge,
+    /// > The current operation has timed out.
+    ActionTimeout,
+    /// > User presence is required for the requested operation.
+    UserPresenceRequired,
+    /// > built-in user verification is disabled.
+    UserVerificationBlocked,
+    /// > A checksum did not match.
+    IntegrityFailure,
+    /// > The requested subcommand is either invalid or not implemented.
+    InvalidSubcommand,
+    /// > built-in user verification unsuccessful. The platform SHOULD retry.
+    UserVerificationInvalid,
+    /// > The permissions parameter contains an unauthorized permission.
+    UnauthorizedPermission,
+    /// > Other unspecified error.
+    Other,
+    /// > Extension specific error.
+    ///
+    /// Holds the status byte, in the range `0xE0..=0xEF`.
+    Extension(u8),
+    /// > Vendor specific error.
+    ///
+    /// Holds the status byte, in the range `0xF0..=0xFF`.
+    Vendor(u8),
+    /// A status byte that is reserved or not defined by CTAP 2.1.
+    Unknown(u8),
+}
+
+impl From<u8> for StatusCode {
+    fn from(value: u8) -> Self {
+        match value {
+            0x00 => StatusCode::Ok,
+            0x01 => StatusCode::InvalidCommand,
+            0x02 => StatusCode::InvalidParameter,
+            0x03 => StatusCode::InvalidLength,
+            0x04 => StatusCode::InvalidSequence,
+            0x05 => StatusCode::Timeout,
+            0x06 => StatusCode::ChannelBusy,
+            0x0A => StatusCode::LockRequired,
+            0x0B => StatusCode::InvalidChannel,
+            0x11 => StatusCode::CborUnexpectedType,
+            0x12 => StatusCode::InvalidCbor,
+            0x14 => StatusCode::MissingParameter,
+            0x15 => StatusCode::LimitExceeded,
+            0x17 => StatusCode::FingerprintDatabaseFull,
+            0x18 => StatusCode::LargeBlobStorageFull,
+            0x19 => StatusCode::CredentialExcluded,
+            0x21 => StatusCode::Processing,
+            0x22 => StatusCode::InvalidCredential,
+            0x23 => StatusCode::UserActionPending,
+            0x24 => StatusCode::OperationPending,
+            0x25 => StatusCode::NoOperations,
+            0x26 => StatusCode::UnsupportedAlgorithm,
+            0x27 => StatusCode::OperationDenied,
+            0x28 => StatusCode::KeyStoreFull,
+            0x2B => StatusCode::UnsupportedOption,
+            0x2C => StatusCode::InvalidOption,
+            0x2D => StatusCode::KeepaliveCancel,
+            0x2E => StatusCode::NoCredentials,
+            0x2F => StatusCode::UserActionTimeout,
+            0x30 => StatusCode::NotAllowed,
+            0x31 => StatusCode::PinInvalid,
+            0x32 => StatusCode::PinBlocked,
+            0x33 => StatusCode::PinAuthInvalid,
+            0x34 => StatusCode::PinAuthBlocked,
+            0x35 => StatusCode::PinNotSet,
+            0x36 => StatusCode::PinUvAuthTokenRequired,
+            0x37 => StatusCode::PinPolicyViolation,
+            0x39 => StatusCode::RequestTooLarge,
+            0x3A => StatusCode::ActionTimeout,
+            0x3B => StatusCode::UserPresenceRequired,
+            0x3C => StatusCode::UserVerificationBlocked,
+            0x3D => StatusCode::IntegrityFailure,
+            0x3E => StatusCode::InvalidSubcommand,
+            0x3F => StatusCode::UserVerificationInvalid,
+            0x40 => StatusCode::UnauthorizedPermission,
+            0x7F => StatusCode::Other,
+            0xE0..=0xEF => StatusCode::Extension(value),
+            0xF0..=0xFF => StatusCode::Vendor(value),
+            _ => StatusCode::Unknown(value),
+        }
+    }
+}
+
+impl From<StatusCode> for u8 {
+    fn from(value: StatusCode) -> Self {
+        match value {
+            StatusCode::Ok => 0x00,
+            StatusCode::InvalidCommand => 0x01,
+            StatusCode::InvalidParameter => 0x02,
+            StatusCode::InvalidLength => 0x03,
+            StatusCode::InvalidSequence => 0x04,
+            StatusCode::Timeout => 0x05,
+            StatusCode::ChannelBusy => 0x06,
+            StatusCode::LockRequired => 0x0A,
+            StatusCode::InvalidChannel => 0x0B,
+            StatusCode::CborUnexpectedType => 0x11,
+            StatusCode::InvalidCbor => 0x12,
+            StatusCode::MissingParameter => 0x14,
+            StatusCode::LimitExceeded => 0x15,
+            StatusCode::FingerprintDatabaseFull => 0x17,
+            StatusCode::LargeBlobStorageFull => 0x18,
+            StatusCode::CredentialExcluded => 0x19,
+            StatusCode::Processing => 0x21,
+            StatusCode::InvalidCredential => 0x22,
+            StatusCode::UserActionPending => 0x23,
+            StatusCode::OperationPending => 0x24,
+            StatusCode::NoOperations => 0x25,
+            StatusCode::UnsupportedAlgorithm => 0x26,
+            StatusCode::OperationDenied => 0x27,
+            StatusCode::KeyStoreFull => 0x28,
+            StatusCode::UnsupportedOption => 0x2B,
+            StatusCode::InvalidOption => 0x2C,
+            StatusCode::KeepaliveCancel => 0x2D,
+            StatusCode::NoCredentials => 0x2E,
+            StatusCode::UserActionTimeout => 0x2F,
+            StatusCode::NotAllowed => 0x30,
+            StatusCode::PinInvalid => 0x31,
+            StatusCode::PinBlocked => 0x32,
+            StatusCode::PinAuthInvalid => 0x33,
+            StatusCode::PinAuthBlocked => 0x34,
+            StatusCode::PinNotSet => 0x35,
+            StatusCode::PinUvAuthTokenRequired => 0x36,
+            StatusCode::PinPolicyViolation => 0x37,
+            StatusCode::RequestTooLarge => 0x39,
+            StatusCode::ActionTimeout => 0x3A,
+            StatusCode::UserPresenceRequired => 0x3B,
+            StatusCode::UserVerificationBlocked => 0x3C,
+            StatusCode::IntegrityFailure => 0x3D,
+            StatusCode::InvalidSubcommand => 0x3E,
+            StatusCode::UserVerificationInvalid => 0x3F,
+            StatusCode::UnauthorizedPermission => 0x40,
+            StatusCode::Other => 0x7F,
+            StatusCode::Extension(value)
+            | StatusCode::Vendor(value)
+            | StatusCode::Unknown(value) => value,
+        }
+    }
+}
+
+impl Display for StatusCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let message = match self {
+            StatusCode::Ok => "Ok",
+            StatusCode::InvalidCommand => "Invalid command",
+            StatusCode::InvalidParameter => "Invalid parameter",
+            StatusCode::InvalidLength => "Invalid length",
+            StatusCode::InvalidSequence => "Invalid sequence",
+            StatusCode::Timeout => "Timeout",
+            StatusCode::ChannelBusy => "Channel busy",
+            StatusCode::LockRequired => "Lock required",
+            StatusCode::InvalidChannel => "Invalid channel",
+            StatusCode::CborUnexpectedType => "Unexpected CBOR type",
+            StatusCode::InvalidCbor => "Invalid CBOR",
+            StatusCode::MissingParameter => "Missing parameter",
+            StatusCode::LimitExceeded => "Limit exceeded",
+            StatusCode::FingerprintDatabaseFull => "Fingerprint database full",
+            StatusCode::LargeBlobStorageFull => "Large blob storage full",
+            StatusCode::CredentialExcluded => "Credential excluded",
+            StatusCode::Processing => "Processing",
+            StatusCode::InvalidCredential => "Invalid credential",
+            StatusCode::UserActionPending => "User action pending",
+            StatusCode::OperationPending => "Operation pending",
+            StatusCode::NoOperations => "No operations",
+            StatusCode::UnsupportedAlgorithm => "Unsupported algorithm",
+            StatusCode::OperationDenied => "Operation denied",
+            StatusCode::KeyStoreFull => "Key store full",
+            StatusCode::UnsupportedOption => "Unsupported option",
+            StatusCode::InvalidOption => "Invalid option",
+            StatusCode::KeepaliveCancel => "Keepalive cancelled",
+            StatusCode::NoCredentials => "No credentials",
+            StatusCode::UserActionTimeout => "User action timeout",
+            StatusCode::NotAllowed => "Not allowed",
+            StatusCode::PinInvalid => "PIN invalid",
+            StatusCode::PinBlocked => "PIN blocked",
+            StatusCode::PinAuthInvalid => "PIN auth invalid",
+            StatusCode::PinAuthBlocked => "PIN auth blocked",
+            StatusCode::PinNotSet => "PIN not set",
+            StatusCode::PinUvAuthTokenRequired => "PIN/UV auth token required",
+            StatusCode::PinPolicyViolation => "PIN policy violation",
+            StatusCode::RequestTooLarge => "Request too large",
+            StatusCode::ActionTimeout => "Action timeout",
+            StatusCode::UserPresenceRequired => "User presence required",
+            StatusCode::UserVerificationBlocked => "User verification blocked",
+            StatusCode::IntegrityFailure => "Integrity failure",
+            StatusCode::InvalidSubcommand => "Invalid subcommand",
+            StatusCode::UserVerificationInvalid => "User verification invalid",
+            StatusCode::UnauthorizedPermission => "Unauthorized permission",
+            StatusCode::Other => "Other",
+            StatusCode::Extension(value) => {
+                return write!(f, "Extension error {:#04x}", value);
+            }
+            StatusCode::Vendor(value) => return write!(f, "Vendor error {:#04x}", value),
+            StatusCode::Unknown(value) => return write!(f, "Unknown status {:#04x}", value),
+        };
+        write!(f, "{}", message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DEFINED: [u8; 46] = [
+        0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x0A, 0x0B, 0x11, 0x12, 0x14, 0x15, 0x17, 0x18,
+        0x19, 0x21, 0x22, 0x23, 0x24, 0x25, 0x26, 0x27, 0x28, 0x2B, 0x2C, 0x2D, 0x2E, 0x2F, 0x30,
+        0x31, 0x32, 0x33, 0x34, 0x35, 0x36, 0x37, 0x39, 0x3A, 0x3B, 0x3C, 0x3D, 0x3E, 0x3F, 0x40,
+        0x7F,
+    ];
+
+    #[test]
+    fn defined_codes() {
+        for byte in DEFINED {
+            let code = StatusCode::from(byte);
+            assert!(!matches!(code, StatusCode::Unknown(_)), "{byte:#04x}");
+            assert_eq!(u8::from(code), byte);
+        }
+        assert_eq!(StatusCode::from(0x27), StatusCode::OperationDenied);
+        assert_eq!(StatusCode::from(0xE3), StatusCode::Extension(0xE3));
+        assert_eq!(StatusCode::from(0xF3), StatusCode::Vendor(0xF3));
+    }
+
+    #[test]
+    fn unknown_code() {
+        assert_eq!(StatusCode::from(0x07), StatusCode::Unknown(0x07));
+        assert_eq!(u8::from(StatusCode::Unknown(0x07)), 0x07);
+    }
+
+    #[test]
+    fn every_byte_round_trips() {
+        for byte in u8::MIN..=u8::MAX {
+            assert_eq!(u8::from(StatusCode::from(byte)), byte);
+        }
+    }
+}
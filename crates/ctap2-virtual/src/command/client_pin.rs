@@ -0,0 +1,516 @@
+//! `authenticatorClientPIN`.
+
+use crate::{
+    pin::{self, SharedSecret},
+    storage::{Storage, MAX_PIN_RETRIES},
+    Authenticator, UserPresence,
+};
+use ctap2_proto::prelude::{
+    client_pin::{auth_protocol::Version, Error, Permission, Request, Response},
+    StatusCode,
+};
+use std::{borrow::Cow, collections::BTreeSet};
+
+/// The length of the decrypted `newPinEnc`, a PIN padded with zero bytes.
+const PADDED_PIN_LENGTH: usize = 64;
+
+/// The number of consecutive PIN mismatches after which the authenticator
+/// must be power cycled before the PIN can be tried again.
+const MAX_PIN_MISMATCHES: u8 = 3;
+
+impl<S: Storage, P: UserPresence> Authenticator<S, P> {
+    pub(crate) fn handle_client_pin(&mut self, request: Request) -> Result<Response, Error> {
+        match request {
+            Request::GetPinRetries => Ok(Response::GetPinRetries {
+                pin_retries: self.storage.state().pin_retries.into(),
+                power_cycle_state: Some(self.pin_mismatches >= MAX_PIN_MISMATCHES),
+            }),
+            Request::GetKeyAgreement { version } => Ok(Response::GetKeyAgreement {
+                key_agreement: self.key_agreement(version).public_key(),
+            }),
+            Request::SetPin {
+                version,
+                key_agreement,
+                new_pin_encrypted,
+                pin_uv_auth_param,
+            } => {
+                let mut state = self.storage.state();
+                if state.pin_hash.is_some() {
+                    return Err(Error::NotAllowed);
+                }
+
+                let shared_secret = self.shared_secret(version, &key_agreement)?;
+                if shared_secret
+                    .verify(&new_pin_encrypted, pin_uv_auth_param.as_ref())
+                    .is_err()
+                {
+                    return Err(Error::PinAuthInvalid);
+                }
+                let pin = decrypt_pin(&shared_secret, &new_pin_encrypted, state.min_pin_length)?;
+
+                state.pin_hash = Some(pin::pin_hash(&pin));
+                state.pin_retries = MAX_PIN_RETRIES;
+                state.force_pin_change = false;
+                self.storage.set_state(state);
+                Ok(Response::SetPin)
+            }
+            Request::ChangePin {
+                version,
+                key_agreement,
+                pin_hash_encrypted,
+                new_pin_encrypted,
+                pin_uv_auth_param,
+            } => {
+                let shared_secret = self.shared_secret(version, &key_agreement)?;
+                let message = [new_pin_encrypted.as_slice(), &pin_hash_encrypted].concat();
+                if shared_secret
+                    .verify(&message, pin_uv_auth_param.as_ref())
+                    .is_err()
+                {
+                    return Err(Error::PinAuthInvalid);
+                }
+
+                self.verify_pin_hash(&shared_secret, &pin_hash_encrypted)?;
+
+                let mut state = self.storage.state();
+                let pin = decrypt_pin(&shared_secret, &new_pin_encrypted, state.min_pin_length)?;
+                state.pin_hash = Some(pin::pin_hash(&pin));
+                state.force_pin_change = false;
+                self.storage.set_state(state);
+
+                self.pin_uv_auth_token.reset();
+                Ok(Response::ChangePin)
+            }
+            Request::GetPinToken {
+                version,
+                key_agreement,
+                pin_hash_encrypted,
+            } => {
+                let pin_uv_auth_token = self.pin_uv_auth_token_using_pin(
+                    version,
+                    &key_agreement,
+                    &pin_hash_encrypted,
+                    BTreeSet::from([Permission::MakeCredential, Permission::GetAssertion]),
+                    None,
+                )?;
+                Ok(Response::GetPinToken { pin_uv_auth_token })
+            }
+            Request::GetPinUvAuthTokenUsingPinWithPermissions {
+                version,
+                key_agreement,
+                pin_hash_encrypted,
+                permissions,
+                relying_party_id,
+            } => {
+                if permissions.is_empty() {
+                    return Err(Error::InvalidParameter);
+                }
+                let scoped = permissions.contains(&Permission::MakeCredential)
+                    || permissions.contains(&Permission::GetAssertion);
+                if scoped && relying_party_id.is_none() {
+                    return Err(Error::MissingParameter);
+                }
+                // Biometric enrollment is not supported
+                if permissions.contains(&Permission::BiometricEnrollment) {
+                    return Err(Error::UnauthorizedPermission);
+                }
+
+                let pin_uv_auth_token = self.pin_uv_auth_token_using_pin(
+                    version,
+                    &key_agreement,
+                    &pin_hash_encrypted,
+                    permissions.into_owned(),
+                    relying_party_id.map(Cow::into_owned),
+                )?;
+                Ok(Response::GetPinUvAuthTokenUsingPinWithPermissions { pin_uv_auth_token })
+            }
+            // There is no built-in user verification method
+            Request::GetPinUvAuthTokenUsingUvWithPermissions { .. } | Request::GetUvRetries => {
+                Err(Error::Other(StatusCode::InvalidSubcommand))
+            }
+        }
+    }
+
+    fn shared_secret(
+        &self,
+        version: Version,
+        key_agreement: &cosey::PublicKey,
+    ) -> Result<SharedSecret, Error> {
+        self.key_agreement(version)
+            .shared_secret(version, key_agreement)
+    }
+
+    /// Compares the platform's PIN hash with the stored hash, consuming a PIN
+    /// retry unless they match.
+    fn verify_pin_hash(
+        &mut self,
+        shared_secret: &SharedSecret,
+        pin_hash_encrypted: &[u8],
+    ) -> Result<(), Error> {
+        let mut state = self.storage.state();
+        let Some(pin_hash) = state.pin_hash else {
+            return Err(Error::PinNotSet);
+        };
+        if state.pin_retries == 0 {
+            return Err(Error::PinBlocked);
+        }
+        if self.pin_mismatches >= MAX_PIN_MISMATCHES {
+            return Err(Error::PinAuthBlocked);
+        }
+
+        state.pin_retries -= 1;
+        self.storage.set_state(state.clone());
+
+        if shared_secret.decrypt(pin_hash_encrypted).as_deref() != Ok(pin_hash.as_slice()) {
+            self.regenerate_key_agreement();
+            self.pin_mismatches += 1;
+            return Err(if state.pin_retries == 0 {
+                Error::PinBlocked
+            } else if self.pin_mismatches >= MAX_PIN_MISMATCHES {
+                Error::PinAuthBlocked
+            } else {
+                Error::PinInvalid
+            });
+        }
+
+        state.pin_retries = MAX_PIN_RETRIES;
+        self.storage.set_state(state);
+        self.pin_mismatches = 0;
+        Ok(())
+    }
+
+    /// Issues a fresh `pinUvAuthToken` with the given permissions, encrypted
+    /// for the platform.
+    fn pin_uv_auth_token_using_pin(
+        &mut self,
+        version: Version,
+        key_agreement: &cosey::PublicKey,
+        pin_hash_encrypted: &[u8],
+        permissions: BTreeSet<Permission>,
+        relying_party_id: Option<String>,
+    ) -> Result<Vec<u8>, Error> {
+        let shared_secret = self.shared_secret(version, key_agreement)?;
+        self.verify_pin_hash(&shared_secret, pin_hash_encrypted)?;
+        if self.storage.state().force_pin_change {
+            return Err(Error::PinPolicyViolation);
+        }
+
+        self.pin_uv_auth_token.reset();
+        self.pin_uv_auth_token
+            .begin_using(permissions, relying_party_id);
+        shared_secret.encrypt(self.pin_uv_auth_token.as_bytes())
+    }
+}
+
+/// Decrypts `newPinEnc` and checks the PIN against the PIN policy.
+fn decrypt_pin(
+    shared_secret: &SharedSecret,
+    new_pin_encrypted: &[u8],
+    min_pin_length: usize,
+) -> Result<Vec<u8>, Error> {
+    let padded = shared_secret.decrypt(new_pin_encrypted)?;
+    if padded.len() != PADDED_PIN_LENGTH {
+        return Err(Error::InvalidParameter);
+    }
+
+    // At least one byte of padding is required, limiting PINs to 63 bytes
+    let Some(length) = padded.iter().position(|&byte| byte == 0) else {
+        return Err(Error::PinPolicyViolation);
+    };
+    let pin = &padded[..length];
+    match std::str::from_utf8(pin) {
+        Ok(pin) if pin.chars().count() >= min_pin_length => {}
+        _ => return Err(Error::PinPolicyViolation),
+    }
+    Ok(pin.to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        command::tests::{
+            authenticator, key_agreement, padded, pin_hash_encrypted, set_pin, token, PIN,
+            RELYING_PARTY_ID,
+        },
+        storage::Memory,
+    };
+    use ctap2_proto::prelude::{client_pin::auth_protocol, Ctap2_2Authenticator};
+
+    fn retries(authenticator: &mut Authenticator<Memory>) -> (usize, Option<bool>) {
+        match authenticator.client_pin(Request::GetPinRetries) {
+            Ok(Response::GetPinRetries {
+                pin_retries,
+                power_cycle_state,
+            }) => (pin_retries, power_cycle_state),
+            response => panic!("unexpected response: {response:?}"),
+        }
+    }
+
+    fn change_pin(
+        authenticator: &mut Authenticator<Memory>,
+        current_pin: &str,
+        new_pin: &str,
+    ) -> Result<Response, Error> {
+        let (key_agreement, shared_secret) = key_agreement(authenticator, Version::One);
+        let pin_hash_encrypted = pin_hash_encrypted(&shared_secret, current_pin);
+        let new_pin_encrypted = shared_secret.encrypt(&padded(new_pin)).unwrap();
+        let message = [new_pin_encrypted.as_slice(), &pin_hash_encrypted].concat();
+        authenticator.client_pin(Request::ChangePin {
+            version: Version::One,
+            key_agreement,
+            pin_hash_encrypted,
+            new_pin_encrypted,
+            pin_uv_auth_param: shared_secret.authenticate(&message),
+        })
+    }
+
+    fn get_pin_token(
+        authenticator: &mut Authenticator<Memory>,
+        pin: &str,
+    ) -> Result<Vec<u8>, Error> {
+        let (key_agreement, shared_secret) = key_agreement(authenticator, Version::Two);
+        let response = authenticator.client_pin(Request::GetPinToken {
+            version: Version::Two,
+            key_agreement,
+            pin_hash_encrypted: pin_hash_encrypted(&shared_secret, pin),
+        })?;
+        let Response::GetPinToken { pin_uv_auth_token } = response else {
+            panic!("unexpected response: {response:?}");
+        };
+        Ok(shared_secret.decrypt(&pin_uv_auth_token).unwrap())
+    }
+
+    #[test]
+    fn set_pin_once() {
+        let mut authenticator = authenticator();
+        assert_eq!(set_pin(&mut authenticator, PIN), Ok(Response::SetPin));
+        assert_eq!(
+            authenticator.storage().state().pin_hash,
+            Some(pin::pin_hash(PIN.as_bytes()))
+        );
+        assert_eq!(set_pin(&mut authenticator, "5678"), Err(Error::NotAllowed));
+    }
+
+    #[test]
+    fn set_pin_policy() {
+        let mut authenticator = authenticator();
+        assert_eq!(
+            set_pin(&mut authenticator, "123"),
+            Err(Error::PinPolicyViolation)
+        );
+        // At least one byte of padding is required
+        assert_eq!(
+            set_pin(&mut authenticator, &"1".repeat(64)),
+            Err(Error::PinPolicyViolation)
+        );
+        // The minimum length counts code points rather than bytes
+        assert_eq!(
+            set_pin(&mut authenticator, "ééé"),
+            Err(Error::PinPolicyViolation)
+        );
+        assert_eq!(set_pin(&mut authenticator, "éééé"), Ok(Response::SetPin));
+    }
+
+    #[test]
+    fn set_pin_invalid_auth_param() {
+        let mut authenticator = authenticator();
+        let (key_agreement, shared_secret) = key_agreement(&mut authenticator, Version::Two);
+        let new_pin_encrypted = shared_secret.encrypt(&padded(PIN)).unwrap();
+        let request = Request::SetPin {
+            version: Version::Two,
+            key_agreement,
+            new_pin_encrypted,
+            pin_uv_auth_param: shared_secret.authenticate(b"another message"),
+        };
+        assert_eq!(
+            authenticator.client_pin(request),
+            Err(Error::PinAuthInvalid)
+        );
+        assert_eq!(authenticator.storage().state().pin_hash, None);
+    }
+
+    #[test]
+    fn change_pin_with_current_pin() {
+        let mut authenticator = authenticator();
+        assert_eq!(
+            change_pin(&mut authenticator, PIN, "5678"),
+            Err(Error::PinNotSet)
+        );
+        set_pin(&mut authenticator, PIN).unwrap();
+        let token = get_pin_token(&mut authenticator, PIN).unwrap();
+
+        assert_eq!(
+            change_pin(&mut authenticator, "0000", "5678"),
+            Err(Error::PinInvalid)
+        );
+        assert_eq!(
+            retries(&mut authenticator).0,
+            usize::from(MAX_PIN_RETRIES) - 1
+        );
+        assert_eq!(
+            change_pin(&mut authenticator, PIN, "5678"),
+            Ok(Response::ChangePin)
+        );
+        assert_eq!(retries(&mut authenticator).0, usize::from(MAX_PIN_RETRIES));
+
+        // Changing the PIN invalidates tokens issued with the previous one
+        assert_ne!(get_pin_token(&mut authenticator, "5678").unwrap(), token);
+        assert_eq!(
+            get_pin_token(&mut authenticator, PIN),
+            Err(Error::PinInvalid)
+        );
+    }
+
+    #[test]
+    fn pin_mismatches_require_power_cycle() {
+        let mut authenticator = authenticator();
+        set_pin(&mut authenticator, PIN).unwrap();
+        assert_eq!(retries(&mut authenticator), (8, Some(false)));
+
+        for _ in 0..2 {
+            assert_eq!(
+                get_pin_token(&mut authenticator, "0000"),
+                Err(Error::PinInvalid)
+            );
+        }
+        assert_eq!(
+            get_pin_token(&mut authenticator, "0000"),
+            Err(Error::PinAuthBlocked)
+        );
+        assert_eq!(retries(&mut authenticator), (5, Some(true)));
+        // Even the right PIN is not tried before a power cycle
+        assert_eq!(
+            get_pin_token(&mut authenticator, PIN),
+            Err(Error::PinAuthBlocked)
+        );
+        assert_eq!(retries(&mut authenticator), (5, Some(true)));
+
+        authenticator.power_cycle();
+        assert!(get_pin_token(&mut authenticator, PIN).is_ok());
+        assert_eq!(retries(&mut authenticator), (8, Some(false)));
+    }
+
+    #[test]
+    fn pin_blocked() {
+        let mut authenticator = authenticator();
+        set_pin(&mut authenticator, PIN).unwrap();
+        for attempt in 1..=MAX_PIN_RETRIES {
+            if attempt % MAX_PIN_MISMATCHES == 1 {
+                authenticator.power_cycle();
+            }
+            let expected = match attempt {
+                MAX_PIN_RETRIES => Error::PinBlocked,
+                _ if attempt % MAX_PIN_MISMATCHES == 0 => Error::PinAuthBlocked,
+                _ => Error::PinInvalid,
+            };
+            assert_eq!(get_pin_token(&mut authenticator, "0000"), Err(expected));
+        }
+
+        authenticator.power_cycle();
+        assert_eq!(
+            get_pin_token(&mut authenticator, PIN),
+            Err(Error::PinBlocked)
+        );
+        assert_eq!(retries(&mut authenticator).0, 0);
+    }
+
+    #[test]
+    fn get_pin_token_permissions() {
+        let mut authenticator = authenticator();
+        set_pin(&mut authenticator, PIN).unwrap();
+        let token = get_pin_token(&mut authenticator, PIN).unwrap();
+
+        let param = auth_protocol::authenticate(Version::Two, &token, b"message");
+        let mut verify = |permission, relying_party_id| {
+            authenticator.pin_uv_auth_token.verify(
+                Version::Two,
+                b"message",
+                param.as_ref(),
+                permission,
+                relying_party_id,
+            )
+        };
+
+        assert!(!verify(Permission::CredentialManagement, None));
+        // Tokens from `getPinToken` become bound to the RP they are first used
+        // for
+        assert!(verify(Permission::MakeCredential, Some("example.com")));
+        assert!(verify(Permission::GetAssertion, Some("example.com")));
+        assert!(!verify(Permission::GetAssertion, Some("example.org")));
+    }
+
+    #[test]
+    fn get_pin_uv_auth_token_using_pin_with_permissions() {
+        let mut authenticator = authenticator();
+        assert_eq!(
+            token(
+                &mut authenticator,
+                Version::Two,
+                &[Permission::CredentialManagement],
+                None
+            ),
+            Err(Error::PinNotSet)
+        );
+        set_pin(&mut authenticator, PIN).unwrap();
+
+        assert_eq!(
+            token(&mut authenticator, Version::Two, &[], None),
+            Err(Error::InvalidParameter)
+        );
+        assert_eq!(
+            token(
+                &mut authenticator,
+                Version::Two,
+                &[Permission::GetAssertion],
+                None
+            ),
+            Err(Error::MissingParameter)
+        );
+        assert_eq!(
+            token(
+                &mut authenticator,
+                Version::Two,
+                &[Permission::BiometricEnrollment],
+                None
+            ),
+            Err(Error::UnauthorizedPermission)
+        );
+        for version in [Version::One, Version::Two] {
+            let token = token(
+                &mut authenticator,
+                version,
+                &[Permission::GetAssertion],
+                Some(RELYING_PARTY_ID),
+            )
+            .unwrap();
+            assert_eq!(token.len(), 32);
+        }
+    }
+
+    #[test]
+    fn force_pin_change() {
+        let mut authenticator = authenticator();
+        set_pin(&mut authenticator, PIN).unwrap();
+        let mut state = authenticator.storage().state();
+        state.force_pin_change = true;
+        authenticator.storage_mut().set_state(state);
+
+        assert_eq!(
+            get_pin_token(&mut authenticator, PIN),
+            Err(Error::PinPolicyViolation)
+        );
+        change_pin(&mut authenticator, PIN, "5678").unwrap();
+        assert!(!authenticator.storage().state().force_pin_change);
+        assert!(get_pin_token(&mut authenticator, "5678").is_ok());
+    }
+
+    #[test]
+    fn user_verification_unsupported() {
+        let mut authenticator = authenticator();
+        assert_eq!(
+            authenticator.client_pin(Request::GetUvRetries),
+            Err(Error::Other(StatusCode::InvalidSubcommand))
+        );
+    }
+}
@@ -0,0 +1,228 @@
+//! `authenticatorConfig`.
+
+use crate::{storage::Storage, Authenticator, UserPresence};
+use ctap2_proto::prelude::{
+    client_pin::Permission,
+    config::{Error, Request},
+    StatusCode,
+};
+
+impl<S: Storage, P: UserPresence> Authenticator<S, P> {
+    pub(crate) fn handle_authenticator_config(&mut self, request: Request) -> Result<(), Error> {
+        let mut state = self.storage.state();
+        match request {
+            Request::EnableEnterpriseAttestation { .. } => {
+                self.verify_config(&request)?;
+                state.enterprise_attestation = true;
+            }
+            Request::ToggleAlwaysUserVerification { .. } => {
+                self.verify_config(&request)?;
+                state.always_uv = !state.always_uv;
+            }
+            Request::SetMinPinLength {
+                new_min_pin_length,
+                ref min_pin_length_rp_ids,
+                force_change_pin,
+                ..
+            } => {
+                self.verify_config(&request)?;
+                let new_min_pin_length = new_min_pin_length.unwrap_or(state.min_pin_length);
+                if new_min_pin_length < state.min_pin_length {
+                    return Err(Error::PinPolicyViolation);
+                }
+                // The minimum PIN length is not sent to any RP
+                if min_pin_length_rp_ids.is_some() {
+                    return Err(Error::InvalidParameter);
+                }
+                let force_change_pin = force_change_pin.unwrap_or(false);
+                if force_change_pin && state.pin_hash.is_none() {
+                    return Err(Error::Other(StatusCode::PinNotSet));
+                }
+                // The current PIN may be too short for the new minimum
+                if state.pin_hash.is_some()
+                    && (force_change_pin || new_min_pin_length > state.min_pin_length)
+                {
+                    state.force_pin_change = true;
+                }
+                state.min_pin_length = new_min_pin_length;
+            }
+            // No vendor commands are supported
+            Request::VendorPrototype { .. } => {
+                return Err(Error::Other(StatusCode::InvalidSubcommand))
+            }
+        }
+        self.storage.set_state(state);
+        Ok(())
+    }
+
+    /// Verifies the `pinUvAuthParam` over `32 × 0xff || 0x0d || subCommand ||
+    /// subCommandParams`.
+    ///
+    /// An authenticator without a PIN is not protected by user verification,
+    /// so the parameter is not required.
+    fn verify_config(&mut self, request: &Request) -> Result<(), Error> {
+        if self.storage.state().pin_hash.is_none() {
+            return Ok(());
+        }
+        let (pin_uv_auth_protocol, pin_uv_auth_param) = request.pin_uv_auth();
+        let Some(pin_uv_auth_param) = pin_uv_auth_param else {
+            return Err(Error::PinUvAuthTokenRequired);
+        };
+        let Some(pin_uv_auth_protocol) = pin_uv_auth_protocol else {
+            return Err(Error::MissingParameter);
+        };
+
+        if self.pin_uv_auth_token.verify(
+            pin_uv_auth_protocol,
+            &request.authenticated_message(),
+            pin_uv_auth_param.as_ref(),
+            Permission::AuthenticatorConfiguration,
+            None,
+        ) {
+            Ok(())
+        } else {
+            Err(Error::PinAuthInvalid)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        command::tests::{authenticator, set_pin, token, PIN},
+        storage::Memory,
+    };
+    use ctap2_proto::prelude::{client_pin::auth_protocol::Version, Ctap2_2Authenticator};
+    use std::{borrow::Cow, collections::BTreeMap};
+
+    fn config_token(authenticator: &mut Authenticator<Memory>) -> Vec<u8> {
+        token(
+            authenticator,
+            Version::Two,
+            &[Permission::AuthenticatorConfiguration],
+            None,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn without_pin() {
+        let mut authenticator = authenticator();
+        let request = Request::ToggleAlwaysUserVerification {
+            pin_uv_auth_protocol: None,
+            pin_uv_auth_param: None,
+        };
+        assert_eq!(authenticator.authenticator_config(request.clone()), Ok(()));
+        assert!(authenticator.storage().state().always_uv);
+        assert_eq!(authenticator.authenticator_config(request), Ok(()));
+        assert!(!authenticator.storage().state().always_uv);
+    }
+
+    #[test]
+    fn with_pin() {
+        let mut authenticator = authenticator();
+        set_pin(&mut authenticator, PIN).unwrap();
+        let request = Request::EnableEnterpriseAttestation {
+            pin_uv_auth_protocol: None,
+            pin_uv_auth_param: None,
+        };
+        assert_eq!(
+            authenticator.authenticator_config(request),
+            Err(Error::PinUvAuthTokenRequired)
+        );
+
+        let token = token(
+            &mut authenticator,
+            Version::Two,
+            &[Permission::CredentialManagement],
+            None,
+        )
+        .unwrap();
+        let request = Request::enable_enterprise_attestation(Version::Two, &token);
+        assert_eq!(
+            authenticator.authenticator_config(request),
+            Err(Error::PinAuthInvalid)
+        );
+
+        let token = config_token(&mut authenticator);
+        let request = Request::enable_enterprise_attestation(Version::Two, &token);
+        assert_eq!(authenticator.authenticator_config(request), Ok(()));
+        assert!(authenticator.storage().state().enterprise_attestation);
+    }
+
+    #[test]
+    fn set_min_pin_length() {
+        let mut authenticator = authenticator();
+        let request = |new_min_pin_length, force_change_pin| Request::SetMinPinLength {
+            new_min_pin_length,
+            min_pin_length_rp_ids: None,
+            force_change_pin,
+            pin_uv_auth_protocol: None,
+            pin_uv_auth_param: None,
+        };
+
+        assert_eq!(
+            authenticator.authenticator_config(request(Some(3), None)),
+            Err(Error::PinPolicyViolation)
+        );
+        assert_eq!(
+            authenticator.authenticator_config(request(None, Some(true))),
+            Err(Error::Other(StatusCode::PinNotSet))
+        );
+        assert_eq!(
+            authenticator.authenticator_config(request(Some(6), None)),
+            Ok(())
+        );
+        let state = authenticator.storage().state();
+        assert_eq!(state.min_pin_length, 6);
+        assert!(!state.force_pin_change);
+        assert_eq!(authenticator.get_info().min_pin_length, Some(6));
+
+        let rp_ids = [String::from("example.com")];
+        let request = Request::SetMinPinLength {
+            new_min_pin_length: None,
+            min_pin_length_rp_ids: Some(Cow::Borrowed(&rp_ids)),
+            force_change_pin: None,
+            pin_uv_auth_protocol: None,
+            pin_uv_auth_param: None,
+        };
+        assert_eq!(
+            authenticator.authenticator_config(request),
+            Err(Error::InvalidParameter)
+        );
+    }
+
+    #[test]
+    fn set_min_pin_length_forces_pin_change() {
+        let mut authenticator = authenticator();
+        set_pin(&mut authenticator, PIN).unwrap();
+
+        // The current PIN is long enough for an unchanged minimum, but its
+        // length is unknown once it is hashed
+        let token = config_token(&mut authenticator);
+        let request = Request::set_min_pin_length(Some(4), None, None, Version::Two, &token);
+        assert_eq!(authenticator.authenticator_config(request), Ok(()));
+        assert!(!authenticator.storage().state().force_pin_change);
+
+        let request = Request::set_min_pin_length(Some(6), None, None, Version::Two, &token);
+        assert_eq!(authenticator.authenticator_config(request), Ok(()));
+        assert!(authenticator.storage().state().force_pin_change);
+    }
+
+    #[test]
+    fn vendor_prototype_unsupported() {
+        let mut authenticator = authenticator();
+        let params = BTreeMap::new();
+        let request = Request::VendorPrototype {
+            vendor_command_id: 1,
+            params: Cow::Borrowed(&params),
+            pin_uv_auth_protocol: None,
+            pin_uv_auth_param: None,
+        };
+        assert_eq!(
+            authenticator.authenticator_config(request),
+            Err(Error::Other(StatusCode::InvalidSubcommand))
+        );
+    }
+}
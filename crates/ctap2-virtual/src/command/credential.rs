@@ -0,0 +1,992 @@
+//! `authenticatorMakeCredential` and `authenticatorGetAssertion`.
+
+use super::extension_requested;
+use crate::{
+    key::{self, PrivateKey},
+    storage::{self, Credential, Storage},
+    Authenticator, UserPresence,
+};
+use ctap2_proto::{
+    authenticator::{self, data},
+    extensions::cred_protect,
+    prelude::{
+        attestation,
+        client_pin::{Permission, PinUvAuth},
+        credential,
+        credential::public_key,
+        extensions, get, make, Sha256Hash, StatusCode,
+    },
+};
+use rand::{rngs::OsRng, RngCore};
+use std::{
+    collections::{BTreeMap, VecDeque},
+    time::{Duration, Instant},
+};
+
+/// The length of generated credential IDs.
+const CREDENTIAL_ID_LENGTH: usize = 32;
+
+/// The identifier of the `credProtect` extension in authenticator data.
+const CRED_PROTECT: &str = "credProtect";
+
+/// The time after the previous assertion during which the next one can be
+/// requested with `authenticatorGetNextAssertion`.
+const NEXT_ASSERTION_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The credentials of an `authenticatorGetAssertion` request that have not
+/// been asserted yet, along with the parameters to assert them with.
+pub(crate) struct PendingAssertions {
+    credential_ids: VecDeque<Vec<u8>>,
+    client_data_hash: Sha256Hash,
+    user_presence: bool,
+    user_verified: bool,
+    large_blob_key: bool,
+    /// When the previous assertion was returned.
+    timer: Instant,
+}
+
+impl<S: Storage, P: UserPresence> Authenticator<S, P> {
+    pub(crate) fn handle_make_credential(
+        &mut self,
+        request: make::Request,
+    ) -> Result<make::Response, make::Error> {
+        let state = self.storage.state();
+        let relying_party_id = request.relying_party.id.as_str();
+
+        if request.pin_uv_auth_param == PinUvAuth::Selection {
+            if !self.presence.confirm() {
+                return Err(make::Error::OperationDenied);
+            }
+            return Err(match state.pin_hash {
+                Some(_) => make::Error::PinInvalid,
+                None => make::Error::PinNotSet,
+            });
+        }
+
+        let algorithm = request
+            .public_key_credential_params
+            .iter()
+            .map(|parameters| parameters.algorithm)
+            .find(|algorithm| key::ALGORITHMS.contains(algorithm))
+            .ok_or(make::Error::UnsupportedAlgorithm)?;
+
+        let option = |key| {
+            request
+                .options
+                .and_then(|options| options.get(&key).copied())
+        };
+        let discoverable = option(make::OptionKey::Discoverable).unwrap_or(false);
+        if option(make::OptionKey::UserPresence) == Some(false) {
+            return Err(make::Error::InvalidOption);
+        }
+
+        let user_verified = match (
+            request.pin_uv_auth_param.param(),
+            request.pin_uv_auth_protocol_version,
+        ) {
+            (Some(pin_uv_auth_param), Some(version)) => {
+                if !self.pin_uv_auth_token.verify(
+                    version,
+                    request.authenticated_message(),
+                    pin_uv_auth_param.as_ref(),
+                    Permission::MakeCredential,
+                    Some(relying_party_id),
+                ) {
+                    return Err(make::Error::PinAuthInvalid);
+                }
+                true
+            }
+            (Some(_), None) => return Err(make::Error::MissingParameter),
+            (None, _) => {
+                // There is no built-in user verification method
+                if option(make::OptionKey::UserVerification) == Some(true) {
+                    return Err(make::Error::InvalidOption);
+                }
+                // Non-discoverable credentials can be created without user
+                // verification, as advertised by `makeCredUvNotRqd`
+                if state.pin_hash.is_some() && (discoverable || state.always_uv) {
+                    return Err(make::Error::PinUvAuthTokenRequired);
+                }
+                if state.always_uv {
+                    return Err(make::Error::PinNotSet);
+                }
+                false
+            }
+        };
+
+        if request.enterprise_attestation.is_some() && !state.enterprise_attestation {
+            return Err(make::Error::InvalidParameter);
+        }
+
+        // Credentials requiring user verification are not revealed without it
+        let excluded = request.exclude_list.is_some_and(|exclude_list| {
+            exclude_list.iter().any(|descriptor| {
+                self.storage
+                    .credential(&descriptor.id)
+                    .is_some_and(|credential| {
+                        credential.relying_party.id == relying_party_id
+                            && (user_verified
+                                || credential.credential_protection_policy
+                                    != cred_protect::Policy::UserVerificationRequired)
+                    })
+            })
+        });
+        if excluded {
+            // The user is asked for consent before revealing that the
+            // credential exists
+            self.presence.confirm();
+            return Err(make::Error::CredentialExcluded);
+        }
+
+        let large_blob_key =
+            extension_requested(request.extensions, extensions::Identifier::LargeBlobKey);
+        if large_blob_key && !discoverable {
+            return Err(make::Error::InvalidOption);
+        }
+        let credential_protection_policy = cred_protect_input(request.extensions)?;
+
+        if !self.presence.confirm() {
+            return Err(make::Error::OperationDenied);
+        }
+
+        let mut id = vec![0; CREDENTIAL_ID_LENGTH];
+        OsRng.fill_bytes(&mut id);
+        let credential = Credential {
+            id,
+            relying_party: request.relying_party.clone(),
+            user: request.user.clone(),
+            private_key: PrivateKey::generate(algorithm)
+                .ok_or(make::Error::UnsupportedAlgorithm)?,
+            discoverable,
+            signature_counter: 0,
+            credential_protection_policy: credential_protection_policy
+                .unwrap_or(cred_protect::Policy::UserVerificationOptional),
+            large_blob_key: large_blob_key.then(|| {
+                let mut key = [0; 32];
+                OsRng.fill_bytes(&mut key);
+                key
+            }),
+        };
+
+        if discoverable {
+            // A discoverable credential replaces any other for the same account
+            let replaced = self.storage.credentials().into_iter().find(|existing| {
+                existing.discoverable
+                    && existing.relying_party.id == relying_party_id
+                    && existing.user.id == request.user.id
+            });
+            if let Some(replaced) = replaced {
+                self.storage.delete(&replaced.id);
+            }
+        }
+        self.storage
+            .store(credential.clone())
+            .map_err(|error| match error {
+                storage::Error::Full => make::Error::KeyStoreFull,
+            })?;
+
+        let mut authenticator_data = data::Builder::new(relying_party_id)
+            .user_presence(authenticator::UserPresence::Present)
+            .user_verification(if user_verified {
+                authenticator::UserVerification::Verified
+            } else {
+                authenticator::UserVerification::NotVerified
+            })
+            .signature_counter(credential.signature_counter)
+            .attested_credential_data(attestation::CredentialData {
+                aaguid: self.aaguid,
+                id: credential.id.clone(),
+                public_key: credential.private_key.public_key(),
+            });
+        // The policy is reported as set only if it was requested
+        if let Some(policy) = credential_protection_policy {
+            let output = (CRED_PROTECT.to_owned(), vec![u8::from(policy)]);
+            let extensions = authenticator::Extensions::new([output])
+                .map_err(|_| make::Error::Other(StatusCode::Other))?;
+            authenticator_data = authenticator_data.extensions(extensions);
+        }
+
+        Ok(make::Response {
+            format: attestation::FormatIdentifier::None,
+            authenticator_data: authenticator_data.build(),
+            attestation_statement: None,
+            enterprise_attestation: None,
+            large_blob_key: credential.large_blob_key.map(|key| key.to_vec()),
+            unsigned_extension_outputs: None,
+        })
+    }
+
+    pub(crate) fn handle_get_assertion(
+        &mut self,
+        request: get::Request,
+    ) -> Result<get::Response, get::Error> {
+        let state = self.storage.state();
+
+        if request.pin_uv_auth_param == PinUvAuth::Selection {
+            if !self.presence.confirm() {
+                return Err(get::Error::OperationDenied);
+            }
+            return Err(match state.pin_hash {
+                Some(_) => get::Error::PinInvalid,
+                None => get::Error::PinNotSet,
+            });
+        }
+
+        let option = |key| {
+            request
+                .options
+                .and_then(|options| options.get(&key).copied())
+        };
+        let user_presence = option(get::OptionKey::UserPresence).unwrap_or(true);
+
+        let user_verified = match (
+            request.pin_uv_auth_param.param(),
+            request.pin_uv_auth_protocol_version,
+        ) {
+            (Some(pin_uv_auth_param), Some(version)) => {
+                if !self.pin_uv_auth_token.verify(
+                    version,
+                    request.authenticated_message(),
+                    pin_uv_auth_param.as_ref(),
+                    Permission::GetAssertion,
+                    Some(request.relying_party_id),
+                ) {
+                    return Err(get::Error::PinAuthInvalid);
+                }
+                true
+            }
+            (Some(_), None) => return Err(get::Error::MissingParameter),
+            (None, _) => {
+                // There is no built-in user verification method
+                if option(get::OptionKey::UserVerification) == Some(true) {
+                    return Err(get::Error::InvalidOption);
+                }
+                if state.always_uv {
+                    return Err(match state.pin_hash {
+                        Some(_) => get::Error::PinUvAuthTokenRequired,
+                        None => get::Error::PinNotSet,
+                    });
+                }
+                false
+            }
+        };
+
+        let credentials: Vec<Credential> = match request.allow_list {
+            Some(allow_list) => allow_list
+                .iter()
+                .filter_map(|descriptor| self.storage.credential(&descriptor.id))
+                .filter(|credential| credential.relying_party.id == request.relying_party_id)
+                .collect(),
+            // The most recently created credential is returned first
+            None => self
+                .storage
+                .credentials()
+                .into_iter()
+                .rev()
+                .filter(|credential| {
+                    credential.discoverable
+                        && credential.relying_party.id == request.relying_party_id
+                })
+                .collect(),
+        };
+        let credentials: Vec<Credential> = credentials
+            .into_iter()
+            .filter(|credential| match credential.credential_protection_policy {
+                cred_protect::Policy::UserVerificationOptional => true,
+                cred_protect::Policy::UserVerificationOptionalWithCredentialIdList => {
+                    user_verified || request.allow_list.is_some()
+                }
+                cred_protect::Policy::UserVerificationRequired => user_verified,
+            })
+            .collect();
+
+        let Some(credential) = credentials.first().cloned() else {
+            return Err(get::Error::NoCredentials);
+        };
+
+        if user_presence && !self.presence.confirm() {
+            return Err(get::Error::OperationDenied);
+        }
+
+        let large_blob_key =
+            extension_requested(request.extensions, extensions::Identifier::LargeBlobKey);
+        let mut response = self.assert(
+            credential,
+            request.client_data_hash,
+            user_presence,
+            user_verified,
+            large_blob_key,
+        )?;
+        if request.allow_list.is_none() && credentials.len() > 1 {
+            response.number_of_credentials = Some(credentials.len());
+            self.pending_assertions = Some(PendingAssertions {
+                credential_ids: credentials
+                    .into_iter()
+                    .skip(1)
+                    .map(|credential| credential.id)
+                    .collect(),
+                client_data_hash: *request.client_data_hash,
+                user_presence,
+                user_verified,
+                large_blob_key,
+                timer: Instant::now(),
+            });
+        }
+        if request.allow_list.is_some() {
+            response.user = None;
+        }
+        Ok(response)
+    }
+
+    pub(crate) fn handle_get_next_assertion(&mut self) -> Result<get::Response, get::Error> {
+        let Some(pending) = &mut self.pending_assertions else {
+            return Err(get::Error::NotAllowed);
+        };
+        if pending.timer.elapsed() > NEXT_ASSERTION_TIMEOUT {
+            self.pending_assertions = None;
+            return Err(get::Error::NotAllowed);
+        }
+        let Some(credential_id) = pending.credential_ids.pop_front() else {
+            self.pending_assertions = None;
+            return Err(get::Error::NotAllowed);
+        };
+        pending.timer = Instant::now();
+
+        let client_data_hash = pending.client_data_hash;
+        let (user_presence, user_verified, large_blob_key) = (
+            pending.user_presence,
+            pending.user_verified,
+            pending.large_blob_key,
+        );
+        let credential = self
+            .storage
+            .credential(&credential_id)
+            .ok_or(get::Error::NotAllowed)?;
+        self.assert(
+            credential,
+            &client_data_hash,
+            user_presence,
+            user_verified,
+            large_blob_key,
+        )
+    }
+
+    /// Increments the credential's signature counter and signs the
+    /// authenticator data and client data hash with it.
+    fn assert(
+        &mut self,
+        mut credential: Credential,
+        client_data_hash: &Sha256Hash,
+        user_presence: bool,
+        user_verified: bool,
+        large_blob_key: bool,
+    ) -> Result<get::Response, get::Error> {
+        credential.signature_counter = credential.signature_counter.wrapping_add(1);
+        self.storage
+            .store(credential.clone())
+            .map_err(|_| get::Error::OperationDenied)?;
+
+        let data::Signed {
+            auth_data,
+            signature,
+        } = data::Builder::new(&credential.relying_party.id)
+            .user_presence(if user_presence {
+                authenticator::UserPresence::Present
+            } else {
+                authenticator::UserPresence::NotPresent
+            })
+            .user_verification(if user_verified {
+                authenticator::UserVerification::Verified
+            } else {
+                authenticator::UserVerification::NotVerified
+            })
+            .signature_counter(credential.signature_counter)
+            .sign(client_data_hash, &credential.private_key)
+            .map_err(|_| get::Error::Other(StatusCode::Other))?;
+
+        // Identifiable user information is only returned after user
+        // verification
+        let mut user = credential.user;
+        if !user_verified {
+            user.name = None;
+            user.display_name = None;
+        }
+
+        Ok(get::Response {
+            credential: public_key::Descriptor {
+                credential_type: credential::Type::PublicKey,
+                id: credential.id,
+                transports: None,
+            },
+            auth_data,
+            signature,
+            user: Some(user),
+            number_of_credentials: None,
+            user_selected: None,
+            large_blob_key: credential
+                .large_blob_key
+                .filter(|_| large_blob_key)
+                .map(|key| key.to_vec()),
+        })
+    }
+}
+
+/// Parses the `credProtect` input: a policy between 1 and 3, which the CBOR
+/// encoding of the unsigned integer consists of.
+fn cred_protect_input(
+    extensions: Option<&BTreeMap<extensions::Identifier, Vec<u8>>>,
+) -> Result<Option<cred_protect::Policy>, make::Error> {
+    let Some(input) = extensions
+        .and_then(|extensions| extensions.get(&extensions::Identifier::CredentialProtection))
+    else {
+        return Ok(None);
+    };
+    match input.as_slice() {
+        &[policy] => cred_protect::Policy::try_from(policy)
+            .map(Some)
+            .map_err(make::Error::from),
+        _ => Err(make::Error::InvalidParameter),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        command::tests::{
+            authenticator, credential_id, make_credential, set_pin, token, user, CLIENT_DATA_HASH,
+            PIN, RELYING_PARTY_ID,
+        },
+        storage::Memory,
+    };
+    use ctap2_proto::prelude::{
+        client_pin::{self, auth_protocol::Version},
+        Ctap2_2Authenticator,
+    };
+
+    /// The `flags` of encoded authenticator data.
+    const USER_PRESENT: u8 = 0x01;
+    const USER_VERIFIED: u8 = 0x04;
+
+    fn descriptor(id: &[u8]) -> public_key::Descriptor {
+        public_key::Descriptor {
+            credential_type: credential::Type::PublicKey,
+            id: id.to_vec(),
+            transports: None,
+        }
+    }
+
+    /// Creates a discoverable credential with the extension inputs, excluding
+    /// the given credentials.
+    fn make_with<P: UserPresence>(
+        authenticator: &mut Authenticator<Memory, P>,
+        extensions: &BTreeMap<extensions::Identifier, Vec<u8>>,
+        exclude_list: &[&public_key::Descriptor],
+        token: Option<&[u8]>,
+    ) -> Result<make::Response, make::Error> {
+        let relying_party = public_key::RelyingPartyEntity {
+            id: RELYING_PARTY_ID.to_owned(),
+            name: None,
+        };
+        let parameters = [public_key::Parameters {
+            credential_type: credential::Type::PublicKey,
+            algorithm: coset::iana::Algorithm::ES256,
+        }];
+        let options = BTreeMap::from([(make::OptionKey::Discoverable, true)]);
+        let user = user(1);
+        let request = make::Request::builder()
+            .client_data_hash(&CLIENT_DATA_HASH)
+            .relying_party(&relying_party)
+            .user(&user)
+            .public_key_credential_params(&parameters)
+            .exclude_list(exclude_list)
+            .extensions(extensions)
+            .options(&options)
+            .build();
+        let request = match token {
+            Some(token) => request.authenticated(Version::Two, token),
+            None => request,
+        };
+        authenticator.make_credential(request)
+    }
+
+    fn get_assertion<P: UserPresence>(
+        authenticator: &mut Authenticator<Memory, P>,
+        allow_list: Option<&[&[u8]]>,
+        extensions: Option<&BTreeMap<extensions::Identifier, &[u8]>>,
+        token: Option<&[u8]>,
+    ) -> Result<get::Response, get::Error> {
+        let descriptors: Vec<_> = allow_list
+            .unwrap_or_default()
+            .iter()
+            .map(|id| descriptor(id))
+            .collect();
+        let descriptors: Vec<_> = descriptors.iter().collect();
+        let request = get::Request {
+            relying_party_id: RELYING_PARTY_ID,
+            client_data_hash: &CLIENT_DATA_HASH,
+            allow_list: allow_list.map(|_| &descriptors),
+            extensions,
+            options: None,
+            pin_uv_auth_param: PinUvAuth::None,
+            pin_uv_auth_protocol_version: None,
+        };
+        let request = match token {
+            Some(token) => request.authenticated(Version::Two, token),
+            None => request,
+        };
+        authenticator.get_assertion(request)
+    }
+
+    fn uv_token(authenticator: &mut Authenticator<Memory>, permission: Permission) -> Vec<u8> {
+        token(
+            authenticator,
+            Version::Two,
+            &[permission],
+            Some(RELYING_PARTY_ID),
+        )
+        .unwrap()
+    }
+
+    fn verify_signature(response: &get::Response, public_key: &public_key::Key) {
+        use p256::ecdsa::{signature::Verifier, Signature, VerifyingKey};
+
+        let key = VerifyingKey::try_from(public_key).unwrap();
+        let signature = Signature::from_der(&response.signature).unwrap();
+        let message = [response.auth_data.as_slice(), &CLIENT_DATA_HASH].concat();
+        key.verify(&message, &signature).unwrap();
+    }
+
+    fn signature_counter(auth_data: &[u8]) -> u32 {
+        u32::from_be_bytes(auth_data[33..37].try_into().unwrap())
+    }
+
+    #[test]
+    fn make_credential_stores_credential() {
+        let mut authenticator = authenticator();
+        let response = make_credential(&mut authenticator, &user(1), true, None).unwrap();
+        assert_eq!(response.format, attestation::FormatIdentifier::None);
+        assert!(response.attestation_statement.is_none());
+
+        let data = &response.authenticator_data;
+        assert_eq!(data.user_presence, authenticator::UserPresence::Present);
+        assert_eq!(
+            data.user_verification,
+            authenticator::UserVerification::NotVerified
+        );
+        assert_eq!(data.signature_counter, 0);
+        assert!(data.extensions.is_none());
+        let credential_data = data.attested_credential_data.as_ref().unwrap();
+        assert_eq!(credential_data.aaguid, [0; 16]);
+
+        let stored = authenticator
+            .storage()
+            .credential(&credential_data.id)
+            .unwrap();
+        assert!(stored.discoverable);
+        assert_eq!(stored.user, user(1));
+        assert_eq!(stored.private_key.public_key(), credential_data.public_key);
+    }
+
+    #[test]
+    fn make_credential_unsupported_algorithm() {
+        let mut authenticator = authenticator();
+        let relying_party = public_key::RelyingPartyEntity {
+            id: RELYING_PARTY_ID.to_owned(),
+            name: None,
+        };
+        let parameters = [public_key::Parameters {
+            credential_type: credential::Type::PublicKey,
+            algorithm: coset::iana::Algorithm::RS256,
+        }];
+        let user = user(1);
+        let request = make::Request::builder()
+            .client_data_hash(&CLIENT_DATA_HASH)
+            .relying_party(&relying_party)
+            .user(&user)
+            .public_key_credential_params(&parameters)
+            .build();
+        assert_eq!(
+            authenticator.make_credential(request).err(),
+            Some(make::Error::UnsupportedAlgorithm)
+        );
+    }
+
+    #[test]
+    fn make_credential_replaces_account() {
+        let mut authenticator = authenticator();
+        make_credential(&mut authenticator, &user(1), true, None).unwrap();
+        let replacement = make_credential(&mut authenticator, &user(1), true, None).unwrap();
+        make_credential(&mut authenticator, &user(2), true, None).unwrap();
+        // Non-discoverable credentials are not replaced
+        make_credential(&mut authenticator, &user(1), false, None).unwrap();
+
+        let credentials = authenticator.storage().credentials();
+        assert_eq!(credentials.len(), 3);
+        assert_eq!(credentials[0].id, credential_id(&replacement));
+    }
+
+    #[test]
+    fn make_credential_excluded() {
+        let mut authenticator = authenticator();
+        let response = make_credential(&mut authenticator, &user(1), true, None).unwrap();
+        let excluded = descriptor(&credential_id(&response));
+        assert_eq!(
+            make_with(&mut authenticator, &BTreeMap::new(), &[&excluded], None).err(),
+            Some(make::Error::CredentialExcluded)
+        );
+        assert!(make_with(
+            &mut authenticator,
+            &BTreeMap::new(),
+            &[&descriptor(&[0x01])],
+            None
+        )
+        .is_ok());
+    }
+
+    #[test]
+    fn make_credential_user_presence_denied() {
+        let mut authenticator = Authenticator::new(Memory::default(), || false);
+        assert_eq!(
+            make_credential(&mut authenticator, &user(1), true, None).err(),
+            Some(make::Error::OperationDenied)
+        );
+        assert!(authenticator.storage().credentials().is_empty());
+    }
+
+    #[test]
+    fn make_credential_user_verification() {
+        let mut authenticator = authenticator();
+        set_pin(&mut authenticator, PIN).unwrap();
+
+        // Only non-discoverable credentials can be created without a token
+        assert_eq!(
+            make_credential(&mut authenticator, &user(1), true, None).err(),
+            Some(make::Error::PinUvAuthTokenRequired)
+        );
+        let response = make_credential(&mut authenticator, &user(1), false, None).unwrap();
+        assert_eq!(
+            response.authenticator_data.user_verification,
+            authenticator::UserVerification::NotVerified
+        );
+
+        let token = uv_token(&mut authenticator, Permission::MakeCredential);
+        let response = make_credential(&mut authenticator, &user(1), true, Some(&token)).unwrap();
+        assert_eq!(
+            response.authenticator_data.user_verification,
+            authenticator::UserVerification::Verified
+        );
+
+        // A token without the permission, or for another RP, is rejected
+        let token = uv_token(&mut authenticator, Permission::GetAssertion);
+        assert_eq!(
+            make_credential(&mut authenticator, &user(1), true, Some(&token)).err(),
+            Some(make::Error::PinAuthInvalid)
+        );
+        let token = crate::command::tests::token(
+            &mut authenticator,
+            Version::Two,
+            &[Permission::MakeCredential],
+            Some("example.org"),
+        )
+        .unwrap();
+        assert_eq!(
+            make_credential(&mut authenticator, &user(1), true, Some(&token)).err(),
+            Some(make::Error::PinAuthInvalid)
+        );
+    }
+
+    #[test]
+    fn authenticator_selection() {
+        let relying_party = public_key::RelyingPartyEntity {
+            id: RELYING_PARTY_ID.to_owned(),
+            name: None,
+        };
+        let parameters = [];
+        let user = user(1);
+        let request = make::Request::builder()
+            .client_data_hash(&CLIENT_DATA_HASH)
+            .relying_party(&relying_party)
+            .user(&user)
+            .public_key_credential_params(&parameters)
+            .pin_uv_auth_param(PinUvAuth::Selection)
+            .build();
+
+        let mut authenticator = authenticator();
+        assert_eq!(
+            authenticator.make_credential(request).err(),
+            Some(make::Error::PinNotSet)
+        );
+        set_pin(&mut authenticator, PIN).unwrap();
+        assert_eq!(
+            authenticator.make_credential(request).err(),
+            Some(make::Error::PinInvalid)
+        );
+        let mut authenticator = Authenticator::new(Memory::default(), || false);
+        assert_eq!(
+            authenticator.make_credential(request).err(),
+            Some(make::Error::OperationDenied)
+        );
+    }
+
+    #[test]
+    fn cred_protect() {
+        let mut authenticator = authenticator();
+        let extensions =
+            BTreeMap::from([(extensions::Identifier::CredentialProtection, vec![0x03])]);
+        let response = make_with(&mut authenticator, &extensions, &[], None).unwrap();
+        let outputs = response.authenticator_data.extensions.as_ref().unwrap();
+        assert_eq!(outputs.get(CRED_PROTECT), Some([0x03].as_slice()));
+
+        let id = credential_id(&response);
+        assert_eq!(
+            authenticator
+                .storage()
+                .credential(&id)
+                .unwrap()
+                .credential_protection_policy,
+            cred_protect::Policy::UserVerificationRequired
+        );
+
+        // The credential is neither found nor excluded without user
+        // verification
+        assert_eq!(
+            get_assertion(&mut authenticator, None, None, None).err(),
+            Some(get::Error::NoCredentials)
+        );
+        assert_eq!(
+            get_assertion(&mut authenticator, Some(&[&id]), None, None).err(),
+            Some(get::Error::NoCredentials)
+        );
+        let response = make_with(
+            &mut authenticator,
+            &BTreeMap::new(),
+            &[&descriptor(&id)],
+            None,
+        );
+        assert!(response.is_ok());
+    }
+
+    #[test]
+    fn cred_protect_with_credential_id_list() {
+        let mut authenticator = authenticator();
+        let extensions =
+            BTreeMap::from([(extensions::Identifier::CredentialProtection, vec![0x02])]);
+        let response = make_with(&mut authenticator, &extensions, &[], None).unwrap();
+        let id = credential_id(&response);
+
+        assert_eq!(
+            get_assertion(&mut authenticator, None, None, None).err(),
+            Some(get::Error::NoCredentials)
+        );
+        assert!(get_assertion(&mut authenticator, Some(&[&id]), None, None).is_ok());
+    }
+
+    #[test]
+    fn cred_protect_user_verified() {
+        let mut authenticator = authenticator();
+        set_pin(&mut authenticator, PIN).unwrap();
+        let token = uv_token(&mut authenticator, Permission::MakeCredential);
+        let extensions =
+            BTreeMap::from([(extensions::Identifier::CredentialProtection, vec![0x03])]);
+        let response = make_with(&mut authenticator, &extensions, &[], Some(&token)).unwrap();
+        let excluded = descriptor(&credential_id(&response));
+
+        let token = uv_token(&mut authenticator, Permission::GetAssertion);
+        let response = get_assertion(&mut authenticator, None, None, Some(&token)).unwrap();
+        assert_eq!(response.credential, excluded);
+
+        let token = uv_token(&mut authenticator, Permission::MakeCredential);
+        assert_eq!(
+            make_with(
+                &mut authenticator,
+                &BTreeMap::new(),
+                &[&excluded],
+                Some(&token)
+            )
+            .err(),
+            Some(make::Error::CredentialExcluded)
+        );
+    }
+
+    #[test]
+    fn cred_protect_invalid_policy() {
+        let mut authenticator = authenticator();
+        for input in [vec![0x04], vec![0x18, 0x01], vec![0xF5]] {
+            let extensions =
+                BTreeMap::from([(extensions::Identifier::CredentialProtection, input)]);
+            assert_eq!(
+                make_with(&mut authenticator, &extensions, &[], None).err(),
+                Some(make::Error::InvalidParameter)
+            );
+        }
+        assert!(authenticator.storage().credentials().is_empty());
+    }
+
+    #[test]
+    fn get_assertion_signs() {
+        let mut authenticator = authenticator();
+        let response = make_credential(&mut authenticator, &user(1), true, None).unwrap();
+        let credential_data = response
+            .authenticator_data
+            .attested_credential_data
+            .unwrap();
+
+        for counter in 1..=2 {
+            let response = get_assertion(&mut authenticator, None, None, None).unwrap();
+            verify_signature(&response, &credential_data.public_key);
+            assert_eq!(response.credential, descriptor(&credential_data.id));
+            assert_eq!(response.auth_data[32], USER_PRESENT);
+            assert_eq!(signature_counter(&response.auth_data), counter);
+            assert_eq!(response.number_of_credentials, None);
+            // The user's name is only returned after user verification
+            let user = response.user.unwrap();
+            assert_eq!(user.id, [1]);
+            assert_eq!((user.name, user.display_name), (None, None));
+        }
+    }
+
+    #[test]
+    fn get_assertion_allow_list() {
+        let mut authenticator = authenticator();
+        assert_eq!(
+            get_assertion(&mut authenticator, None, None, None).err(),
+            Some(get::Error::NoCredentials)
+        );
+
+        // Non-discoverable credentials are only found by ID
+        let response = make_credential(&mut authenticator, &user(1), false, None).unwrap();
+        let id = credential_id(&response);
+        assert_eq!(
+            get_assertion(&mut authenticator, None, None, None).err(),
+            Some(get::Error::NoCredentials)
+        );
+        assert_eq!(
+            get_assertion(&mut authenticator, Some(&[&[0x01]]), None, None).err(),
+            Some(get::Error::NoCredentials)
+        );
+        let response =
+            get_assertion(&mut authenticator, Some(&[&[0x01], &id]), None, None).unwrap();
+        assert_eq!(response.credential.id, id);
+        assert_eq!(response.user, None);
+    }
+
+    #[test]
+    fn get_assertion_user_verified() {
+        let mut authenticator = authenticator();
+        set_pin(&mut authenticator, PIN).unwrap();
+        let token = uv_token(&mut authenticator, Permission::MakeCredential);
+        make_credential(&mut authenticator, &user(1), true, Some(&token)).unwrap();
+
+        let token = uv_token(&mut authenticator, Permission::GetAssertion);
+        let response = get_assertion(&mut authenticator, None, None, Some(&token)).unwrap();
+        assert_eq!(response.auth_data[32], USER_PRESENT | USER_VERIFIED);
+        assert_eq!(response.user, Some(user(1)));
+
+        let token = uv_token(&mut authenticator, Permission::MakeCredential);
+        assert_eq!(
+            get_assertion(&mut authenticator, None, None, Some(&token)).err(),
+            Some(get::Error::PinAuthInvalid)
+        );
+    }
+
+    #[test]
+    fn get_next_assertion() {
+        let mut authenticator = authenticator();
+        assert_eq!(
+            authenticator.get_next_assertion().err(),
+            Some(get::Error::NotAllowed)
+        );
+
+        let first = make_credential(&mut authenticator, &user(1), true, None).unwrap();
+        let second = make_credential(&mut authenticator, &user(2), true, None).unwrap();
+
+        // The most recently created credential is asserted first
+        let response = get_assertion(&mut authenticator, None, None, None).unwrap();
+        assert_eq!(response.credential.id, credential_id(&second));
+        assert_eq!(response.number_of_credentials, Some(2));
+        let response = authenticator.get_next_assertion().unwrap();
+        assert_eq!(response.credential.id, credential_id(&first));
+        assert_eq!(response.number_of_credentials, None);
+        verify_signature(
+            &response,
+            &first
+                .authenticator_data
+                .attested_credential_data
+                .unwrap()
+                .public_key,
+        );
+        assert_eq!(
+            authenticator.get_next_assertion().err(),
+            Some(get::Error::NotAllowed)
+        );
+    }
+
+    #[test]
+    fn get_next_assertion_timeout() {
+        let mut authenticator = authenticator();
+        for id in 1..=3 {
+            make_credential(&mut authenticator, &user(id), true, None).unwrap();
+        }
+        let response = get_assertion(&mut authenticator, None, None, None).unwrap();
+        assert_eq!(response.number_of_credentials, Some(3));
+
+        // The timer restarts with each assertion
+        let elapsed = NEXT_ASSERTION_TIMEOUT - Duration::from_secs(1);
+        authenticator.pending_assertions.as_mut().unwrap().timer = Instant::now() - elapsed;
+        assert!(authenticator.get_next_assertion().is_ok());
+
+        let elapsed = NEXT_ASSERTION_TIMEOUT + Duration::from_secs(1);
+        authenticator.pending_assertions.as_mut().unwrap().timer = Instant::now() - elapsed;
+        assert_eq!(
+            authenticator.get_next_assertion().err(),
+            Some(get::Error::NotAllowed)
+        );
+        assert!(authenticator.pending_assertions.is_none());
+    }
+
+    #[test]
+    fn get_next_assertion_invalidated() {
+        let mut authenticator = authenticator();
+        for id in 1..=2 {
+            make_credential(&mut authenticator, &user(id), true, None).unwrap();
+        }
+
+        // Any other command discards the remaining assertions
+        get_assertion(&mut authenticator, None, None, None).unwrap();
+        authenticator
+            .client_pin(client_pin::Request::GetPinRetries)
+            .unwrap();
+        assert_eq!(
+            authenticator.get_next_assertion().err(),
+            Some(get::Error::NotAllowed)
+        );
+
+        get_assertion(&mut authenticator, None, None, None).unwrap();
+        authenticator.selection().unwrap();
+        assert_eq!(
+            authenticator.get_next_assertion().err(),
+            Some(get::Error::NotAllowed)
+        );
+
+        // Except for authenticatorGetInfo
+        get_assertion(&mut authenticator, None, None, None).unwrap();
+        authenticator.get_info();
+        assert!(authenticator.get_next_assertion().is_ok());
+    }
+
+    #[test]
+    fn large_blob_key() {
+        let mut authenticator = authenticator();
+        let extensions = BTreeMap::from([(extensions::Identifier::LargeBlobKey, vec![0xF5])]);
+        let response = make_with(&mut authenticator, &extensions, &[], None).unwrap();
+        let large_blob_key = response.large_blob_key.unwrap();
+        assert_eq!(large_blob_key.len(), 32);
+
+        let input = BTreeMap::from([(extensions::Identifier::LargeBlobKey, [0xF5].as_slice())]);
+        let response = get_assertion(&mut authenticator, None, Some(&input), None).unwrap();
+        assert_eq!(response.large_blob_key, Some(large_blob_key));
+        let response = get_assertion(&mut authenticator, None, None, None).unwrap();
+        assert_eq!(response.large_blob_key, None);
+    }
+}
@@ -0,0 +1,336 @@
+//! `authenticatorLargeBlobs`.
+
+use crate::{storage::Storage, Authenticator, UserPresence};
+use ctap2_proto::{
+    hid::packet::MAX_MESSAGE_SIZE,
+    prelude::{
+        client_pin::Permission,
+        large_blobs::{self, array, Error, Request, Response},
+    },
+};
+
+/// The maximum size of the serialized large-blob array.
+pub(crate) const MAX_SERIALIZED_LARGE_BLOB_ARRAY_SIZE: usize = 4096;
+
+/// The maximum length of a fragment read or written at once.
+const MAX_FRAGMENT_LENGTH: usize = MAX_MESSAGE_SIZE - large_blobs::MESSAGE_OVERHEAD;
+
+/// A serialized large-blob array being written in fragments.
+pub(crate) struct PendingWrite {
+    /// The fragments received so far.
+    buffer: Vec<u8>,
+    /// The total length of the serialized array.
+    length: usize,
+}
+
+impl<S: Storage, P: UserPresence> Authenticator<S, P> {
+    pub(crate) fn handle_large_blobs(&mut self, request: Request) -> Result<Response, Error> {
+        match request {
+            Request::Get { offset, length } => {
+                if length > MAX_FRAGMENT_LENGTH {
+                    return Err(Error::InvalidLength);
+                }
+                let array = self.storage.large_blob_array();
+                if offset > array.len() {
+                    return Err(Error::InvalidParameter);
+                }
+                let end = array.len().min(offset.saturating_add(length));
+                Ok(Response {
+                    config: Some(array[offset..end].to_vec()),
+                })
+            }
+            Request::Set {
+                ref fragment,
+                offset,
+                length,
+                ..
+            } => {
+                if fragment.len() > MAX_FRAGMENT_LENGTH {
+                    return Err(Error::InvalidLength);
+                }
+                let length = match (offset, length, &self.large_blob_write) {
+                    (0, Some(length), _) => {
+                        if length > MAX_SERIALIZED_LARGE_BLOB_ARRAY_SIZE {
+                            return Err(Error::LargeBlobStorageFull);
+                        }
+                        if length < array::EMPTY.len() {
+                            return Err(Error::InvalidParameter);
+                        }
+                        length
+                    }
+                    (0, None, _) => return Err(Error::MissingParameter),
+                    (_, _, Some(pending)) if offset == pending.buffer.len() => pending.length,
+                    _ => return Err(Error::InvalidSequence),
+                };
+                self.verify_large_blobs(&request)?;
+                if offset + fragment.len() > length {
+                    return Err(Error::InvalidParameter);
+                }
+
+                // A write starting at offset zero discards any pending one
+                let mut pending = match self.large_blob_write.take() {
+                    Some(pending) if offset != 0 => pending,
+                    _ => PendingWrite {
+                        buffer: Vec::with_capacity(length),
+                        length,
+                    },
+                };
+                pending.buffer.extend_from_slice(fragment);
+                if pending.buffer.len() < pending.length {
+                    self.large_blob_write = Some(pending);
+                    return Ok(Response::default());
+                }
+
+                array::verify(&pending.buffer)?;
+                self.storage.set_large_blob_array(pending.buffer);
+                Ok(Response::default())
+            }
+        }
+    }
+
+    /// Verifies the `pinUvAuthParam` over `32 × 0xff || h'0c00' ||
+    /// uint32LittleEndian(offset) || SHA-256(set)`.
+    ///
+    /// An authenticator without a PIN is not protected by user verification,
+    /// so the parameter is not required.
+    fn verify_large_blobs(&mut self, request: &Request) -> Result<(), Error> {
+        let Request::Set {
+            pin_uv_auth_protocol,
+            pin_uv_auth_param,
+            ..
+        } = request
+        else {
+            return Ok(());
+        };
+        if self.storage.state().pin_hash.is_none() {
+            return Ok(());
+        }
+        let Some(pin_uv_auth_param) = pin_uv_auth_param else {
+            return Err(Error::PinUvAuthTokenRequired);
+        };
+        let Some(pin_uv_auth_protocol) = pin_uv_auth_protocol else {
+            return Err(Error::MissingParameter);
+        };
+        let Some(message) = request.authenticated_message()? else {
+            return Ok(());
+        };
+
+        if self.pin_uv_auth_token.verify(
+            *pin_uv_auth_protocol,
+            &message,
+            pin_uv_auth_param.as_ref(),
+            Permission::LargeBlobWrite,
+            None,
+        ) {
+            Ok(())
+        } else {
+            Err(Error::PinAuthInvalid)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        command::tests::{authenticator, set_pin, token, PIN},
+        storage::Memory,
+    };
+    use ctap2_proto::prelude::{client_pin::auth_protocol::Version, Ctap2_2Authenticator};
+    use std::borrow::Cow;
+
+    /// A serialized large-blob array of `length` bytes, holding a single byte
+    /// string.
+    fn serialized(length: usize) -> Vec<u8> {
+        // A byte string of up to 65535 bytes has a three byte header
+        let content = length - array::CHECKSUM_LENGTH - 4;
+        let mut cbor = vec![0x81, 0x59];
+        cbor.extend_from_slice(&u16::try_from(content).unwrap().to_be_bytes());
+        cbor.resize(cbor.len() + content, 0x42);
+        [cbor.as_slice(), &array::checksum(&cbor)].concat()
+    }
+
+    fn set(offset: usize, fragment: &[u8], length: Option<usize>) -> Request<'_> {
+        Request::Set {
+            fragment: Cow::Borrowed(fragment),
+            offset,
+            length,
+            pin_uv_auth_protocol: None,
+            pin_uv_auth_param: None,
+        }
+    }
+
+    fn read(authenticator: &mut Authenticator<Memory>, offset: usize, length: usize) -> Vec<u8> {
+        let request = Request::Get { offset, length };
+        authenticator.large_blobs(request).unwrap().config.unwrap()
+    }
+
+    #[test]
+    fn get() {
+        let mut authenticator = authenticator();
+        assert_eq!(read(&mut authenticator, 0, 64), array::EMPTY);
+        assert_eq!(read(&mut authenticator, 1, 2), array::EMPTY[1..3]);
+        assert!(read(&mut authenticator, 17, 64).is_empty());
+
+        let request = Request::Get {
+            offset: 18,
+            length: 1,
+        };
+        assert_eq!(
+            authenticator.large_blobs(request),
+            Err(Error::InvalidParameter)
+        );
+        let request = Request::Get {
+            offset: 0,
+            length: MAX_FRAGMENT_LENGTH + 1,
+        };
+        assert_eq!(
+            authenticator.large_blobs(request),
+            Err(Error::InvalidLength)
+        );
+    }
+
+    #[test]
+    fn set_in_fragments() {
+        let mut authenticator = authenticator();
+        let serialized = serialized(1500);
+        let (first, second) = serialized.split_at(1000);
+
+        let request = set(0, first, Some(serialized.len()));
+        assert_eq!(authenticator.large_blobs(request), Ok(Response::default()));
+        // The array is only replaced once it is complete
+        assert_eq!(authenticator.storage().large_blob_array(), array::EMPTY);
+
+        let request = set(first.len(), second, None);
+        assert_eq!(authenticator.large_blobs(request), Ok(Response::default()));
+        assert_eq!(authenticator.storage().large_blob_array(), serialized);
+        assert_eq!(read(&mut authenticator, 0, 64), serialized[..64]);
+    }
+
+    #[test]
+    fn set_out_of_sequence() {
+        let mut authenticator = authenticator();
+        let serialized = serialized(64);
+        assert_eq!(
+            authenticator.large_blobs(set(32, &serialized[32..], None)),
+            Err(Error::InvalidSequence)
+        );
+
+        authenticator
+            .large_blobs(set(0, &serialized[..32], Some(64)))
+            .unwrap();
+        assert_eq!(
+            authenticator.large_blobs(set(16, &serialized[16..], None)),
+            Err(Error::InvalidSequence)
+        );
+        // Writing past the total length is rejected
+        assert_eq!(
+            authenticator.large_blobs(set(32, &[0; 33], None)),
+            Err(Error::InvalidParameter)
+        );
+
+        // Starting over at offset zero discards the pending write
+        authenticator
+            .large_blobs(set(0, &serialized[..32], Some(64)))
+            .unwrap();
+        authenticator
+            .large_blobs(set(32, &serialized[32..], None))
+            .unwrap();
+        assert_eq!(authenticator.storage().large_blob_array(), serialized);
+    }
+
+    #[test]
+    fn set_invalid_length() {
+        let mut authenticator = authenticator();
+        assert_eq!(
+            authenticator.large_blobs(set(0, &array::EMPTY, None)),
+            Err(Error::MissingParameter)
+        );
+        assert_eq!(
+            authenticator.large_blobs(set(0, &array::EMPTY[..16], Some(16))),
+            Err(Error::InvalidParameter)
+        );
+        let request = set(0, &[], Some(MAX_SERIALIZED_LARGE_BLOB_ARRAY_SIZE + 1));
+        assert_eq!(
+            authenticator.large_blobs(request),
+            Err(Error::LargeBlobStorageFull)
+        );
+        let fragment = vec![0; MAX_FRAGMENT_LENGTH + 1];
+        assert_eq!(
+            authenticator.large_blobs(set(0, &fragment, Some(fragment.len()))),
+            Err(Error::InvalidLength)
+        );
+    }
+
+    #[test]
+    fn set_checksum_mismatch() {
+        let mut authenticator = authenticator();
+        let mut serialized = serialized(64);
+        serialized[63] ^= 0x01;
+        assert_eq!(
+            authenticator.large_blobs(set(0, &serialized, Some(64))),
+            Err(Error::IntegrityFailure)
+        );
+        assert_eq!(authenticator.storage().large_blob_array(), array::EMPTY);
+    }
+
+    #[test]
+    fn set_with_pin() {
+        let mut authenticator = authenticator();
+        set_pin(&mut authenticator, PIN).unwrap();
+        let serialized = serialized(64);
+        assert_eq!(
+            authenticator.large_blobs(set(0, &serialized, Some(64))),
+            Err(Error::PinUvAuthTokenRequired)
+        );
+
+        let token = token(
+            &mut authenticator,
+            Version::Two,
+            &[Permission::CredentialManagement],
+            None,
+        )
+        .unwrap();
+        let request = Request::set(&serialized, 0, Some(64), Version::Two, &token).unwrap();
+        assert_eq!(
+            authenticator.large_blobs(request),
+            Err(Error::PinAuthInvalid)
+        );
+
+        let token = crate::command::tests::token(
+            &mut authenticator,
+            Version::Two,
+            &[Permission::LargeBlobWrite],
+            None,
+        )
+        .unwrap();
+        // The parameter covers the offset and the fragment
+        let request = Request::set(&serialized[..32], 0, Some(64), Version::Two, &token).unwrap();
+        let Request::Set {
+            pin_uv_auth_param, ..
+        } = request
+        else {
+            unreachable!();
+        };
+        let request = Request::Set {
+            fragment: Cow::Borrowed(&serialized[32..]),
+            offset: 32,
+            length: None,
+            pin_uv_auth_protocol: Some(Version::Two),
+            pin_uv_auth_param,
+        };
+        authenticator
+            .large_blobs(
+                Request::set(&serialized[..32], 0, Some(64), Version::Two, &token).unwrap(),
+            )
+            .unwrap();
+        assert_eq!(
+            authenticator.large_blobs(request),
+            Err(Error::PinAuthInvalid)
+        );
+
+        let request = Request::set(&serialized[32..], 32, None, Version::Two, &token).unwrap();
+        assert_eq!(authenticator.large_blobs(request), Ok(Response::default()));
+        assert_eq!(authenticator.storage().large_blob_array(), serialized);
+    }
+}
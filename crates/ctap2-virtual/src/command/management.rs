@@ -0,0 +1,511 @@
+//! `authenticatorCredentialManagement`.
+
+use super::relying_party_id_hash;
+use crate::{
+    storage::{Credential, Storage},
+    Authenticator, UserPresence,
+};
+use ctap2_proto::prelude::{
+    client_pin::Permission,
+    credential,
+    credential::public_key,
+    management::{self, Error, Request, Response},
+    Sha256Hash,
+};
+use std::collections::VecDeque;
+
+/// The results of an enumeration that have not been returned yet.
+pub(crate) enum Enumeration {
+    RelyingParties(VecDeque<management::RelyingParty>),
+    Credentials(VecDeque<management::Credential>),
+}
+
+impl<S: Storage, P: UserPresence> Authenticator<S, P> {
+    pub(crate) fn handle_credential_management(
+        &mut self,
+        request: Request,
+    ) -> Result<Response, Error> {
+        match request {
+            Request::GetCredentialsMetadata { .. } => {
+                self.enumeration = None;
+                self.verify_management(&request)?;
+                self.check_scope(None)?;
+
+                let existing = self.discoverable_credentials().len();
+                Ok(Response::GetCredentialsMetadata {
+                    existing_resident_credentials_count: existing,
+                    max_possible_remaining_resident_credentials_count: self
+                        .storage
+                        .capacity()
+                        .saturating_sub(existing),
+                })
+            }
+            Request::EnumerateRPsBegin { .. } => {
+                self.enumeration = None;
+                self.verify_management(&request)?;
+                self.check_scope(None)?;
+
+                let mut relying_parties = VecDeque::<management::RelyingParty>::new();
+                for credential in self.discoverable_credentials() {
+                    let listed = relying_parties
+                        .iter()
+                        .any(|listed| listed.relying_party.id == credential.relying_party.id);
+                    if !listed {
+                        relying_parties.push_back(management::RelyingParty {
+                            relying_party_id_hash: relying_party_id_hash(
+                                &credential.relying_party.id,
+                            ),
+                            relying_party: credential.relying_party,
+                        });
+                    }
+                }
+
+                let total_relying_parties = relying_parties.len();
+                let relying_party = relying_parties.pop_front().ok_or(Error::NoCredentials)?;
+                self.enumeration = Some(Enumeration::RelyingParties(relying_parties));
+                Ok(Response::EnumerateRPsBegin {
+                    relying_party,
+                    total_relying_parties,
+                })
+            }
+            Request::EnumerateRPsGetNextRP => match &mut self.enumeration {
+                Some(Enumeration::RelyingParties(relying_parties)) => relying_parties
+                    .pop_front()
+                    .map(|relying_party| Response::EnumerateRPsGetNextRP { relying_party })
+                    .ok_or(Error::NoCredentials),
+                _ => Err(Error::NoCredentials),
+            },
+            Request::EnumerateCredentialsBegin {
+                relying_party_id_hash: ref hash,
+                ..
+            } => {
+                self.enumeration = None;
+                self.verify_management(&request)?;
+                self.check_scope(Some(&**hash))?;
+
+                let mut credentials: VecDeque<management::Credential> = self
+                    .discoverable_credentials()
+                    .iter()
+                    .filter(|credential| {
+                        relying_party_id_hash(&credential.relying_party.id) == **hash
+                    })
+                    .map(management_credential)
+                    .collect();
+
+                let total_credentials = credentials.len();
+                let credential = credentials.pop_front().ok_or(Error::NoCredentials)?;
+                self.enumeration = Some(Enumeration::Credentials(credentials));
+                Ok(Response::EnumerateCredentialsBegin {
+                    credential,
+                    total_credentials,
+                })
+            }
+            Request::EnumerateCredentialsGetNextCredential => match &mut self.enumeration {
+                Some(Enumeration::Credentials(credentials)) => credentials
+                    .pop_front()
+                    .map(
+                        |credential| Response::EnumerateCredentialsGetNextCredential { credential },
+                    )
+                    .ok_or(Error::NoCredentials),
+                _ => Err(Error::NoCredentials),
+            },
+            Request::DeleteCredential {
+                ref credential_id, ..
+            } => {
+                self.enumeration = None;
+                self.verify_management(&request)?;
+
+                let credential = self
+                    .storage
+                    .credential(&credential_id.id)
+                    .ok_or(Error::NoCredentials)?;
+                self.check_scope(Some(&relying_party_id_hash(&credential.relying_party.id)))?;
+
+                self.storage.delete(&credential.id);
+                Ok(Response::DeleteCredential)
+            }
+            Request::UpdateUserInformation {
+                ref credential_id,
+                ref user,
+                ..
+            } => {
+                self.enumeration = None;
+                self.verify_management(&request)?;
+
+                let mut credential = self
+                    .storage
+                    .credential(&credential_id.id)
+                    .ok_or(Error::NoCredentials)?;
+                self.check_scope(Some(&relying_party_id_hash(&credential.relying_party.id)))?;
+                if credential.user.id != user.id {
+                    return Err(Error::InvalidParameter);
+                }
+
+                credential.user = user.clone().into_owned();
+                self.storage
+                    .store(credential)
+                    .map_err(|_| Error::KeyStoreFull)?;
+                Ok(Response::UpdateUserInformation)
+            }
+        }
+    }
+
+    fn discoverable_credentials(&self) -> Vec<Credential> {
+        self.storage
+            .credentials()
+            .into_iter()
+            .filter(|credential| credential.discoverable)
+            .collect()
+    }
+
+    /// Verifies the `pinUvAuthParam` over the subcommand and its CBOR encoded
+    /// parameters, if any.
+    fn verify_management(&mut self, request: &Request) -> Result<(), Error> {
+        let (Some((version, pin_uv_auth_param)), Some(message)) =
+            (request.pin_uv_auth(), request.authenticated_message())
+        else {
+            return Ok(());
+        };
+
+        if self.pin_uv_auth_token.verify(
+            version,
+            &message,
+            pin_uv_auth_param.as_ref(),
+            Permission::CredentialManagement,
+            None,
+        ) {
+            Ok(())
+        } else {
+            Err(Error::PinAuthInvalid)
+        }
+    }
+
+    /// Checks that a `pinUvAuthToken` bound to a relying party is only used
+    /// for that relying party's credentials, and not for operations on all
+    /// credentials.
+    fn check_scope(&self, relying_party_id_hash: Option<&Sha256Hash>) -> Result<(), Error> {
+        match (
+            self.pin_uv_auth_token.relying_party_id(),
+            relying_party_id_hash,
+        ) {
+            (None, _) => Ok(()),
+            (Some(bound), Some(hash)) if super::relying_party_id_hash(bound) == *hash => Ok(()),
+            (Some(_), _) => Err(Error::PinAuthInvalid),
+        }
+    }
+}
+
+fn management_credential(credential: &Credential) -> management::Credential {
+    management::Credential {
+        user: credential.user.clone(),
+        credential_id: public_key::Descriptor {
+            credential_type: credential::Type::PublicKey,
+            id: credential.id.clone(),
+            transports: None,
+        },
+        public_key: credential.private_key.public_key(),
+        credential_protection_policy: credential.credential_protection_policy,
+        large_blob_key: credential
+            .large_blob_key
+            .map(|key| key.to_vec())
+            .unwrap_or_default(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        command::tests::{authenticator, set_pin, token, user, user as user_entity, PIN},
+        key::PrivateKey,
+        storage::Memory,
+    };
+    use ctap2_proto::{
+        extensions::cred_protect,
+        prelude::{client_pin::auth_protocol::Version, Ctap2_2Authenticator},
+    };
+
+    /// Stores a discoverable credential, returning its ID.
+    fn store(
+        authenticator: &mut Authenticator<Memory>,
+        relying_party_id: &str,
+        user: u8,
+    ) -> Vec<u8> {
+        let id = [relying_party_id.as_bytes(), &[user]].concat();
+        authenticator
+            .storage_mut()
+            .store(Credential {
+                id: id.clone(),
+                relying_party: public_key::RelyingPartyEntity {
+                    id: relying_party_id.to_owned(),
+                    name: None,
+                },
+                user: user_entity(user),
+                private_key: PrivateKey::generate(coset::iana::Algorithm::EdDSA).unwrap(),
+                discoverable: true,
+                signature_counter: 0,
+                credential_protection_policy: cred_protect::Policy::UserVerificationOptional,
+                large_blob_key: None,
+            })
+            .unwrap();
+        id
+    }
+
+    fn descriptor(id: &[u8]) -> public_key::Descriptor {
+        public_key::Descriptor {
+            credential_type: credential::Type::PublicKey,
+            id: id.to_vec(),
+            transports: None,
+        }
+    }
+
+    /// An authenticator with a PIN, and a token for credential management.
+    fn authenticator_with_token(
+        relying_party_id: Option<&str>,
+    ) -> (Authenticator<Memory>, Vec<u8>) {
+        let mut authenticator = authenticator();
+        set_pin(&mut authenticator, PIN).unwrap();
+        let token = token(
+            &mut authenticator,
+            Version::Two,
+            &[Permission::CredentialManagement],
+            relying_party_id,
+        )
+        .unwrap();
+        (authenticator, token)
+    }
+
+    #[test]
+    fn get_credentials_metadata() {
+        let (mut authenticator, token) = authenticator_with_token(None);
+        store(&mut authenticator, "example.com", 1);
+        store(&mut authenticator, "example.org", 1);
+
+        let request = Request::get_credentials_metadata(Version::Two, &token);
+        assert_eq!(
+            authenticator.credential_management(request),
+            Ok(Response::GetCredentialsMetadata {
+                existing_resident_credentials_count: 2,
+                max_possible_remaining_resident_credentials_count: 23,
+            })
+        );
+    }
+
+    #[test]
+    fn authentication() {
+        let (mut authenticator, token) = authenticator_with_token(None);
+        let request = Request::get_credentials_metadata(Version::Two, &[0x42; 32]);
+        assert_eq!(
+            authenticator.credential_management(request),
+            Err(Error::PinAuthInvalid)
+        );
+        assert!(authenticator
+            .credential_management(Request::get_credentials_metadata(Version::Two, &token))
+            .is_ok());
+
+        let token = crate::command::tests::token(
+            &mut authenticator,
+            Version::Two,
+            &[Permission::LargeBlobWrite],
+            None,
+        )
+        .unwrap();
+        let request = Request::get_credentials_metadata(Version::Two, &token);
+        assert_eq!(
+            authenticator.credential_management(request),
+            Err(Error::PinAuthInvalid)
+        );
+    }
+
+    #[test]
+    fn enumerate_relying_parties() {
+        let (mut authenticator, token) = authenticator_with_token(None);
+        let request = Request::enumerate_rps_begin(Version::Two, &token);
+        assert_eq!(
+            authenticator.credential_management(request.clone()),
+            Err(Error::NoCredentials)
+        );
+
+        store(&mut authenticator, "example.com", 1);
+        store(&mut authenticator, "example.org", 1);
+        store(&mut authenticator, "example.com", 2);
+
+        let Ok(Response::EnumerateRPsBegin {
+            relying_party,
+            total_relying_parties,
+        }) = authenticator.credential_management(request)
+        else {
+            panic!("no relying parties");
+        };
+        assert_eq!(total_relying_parties, 2);
+        assert_eq!(relying_party.relying_party.id, "example.com");
+        assert_eq!(
+            relying_party.relying_party_id_hash,
+            relying_party_id_hash("example.com")
+        );
+
+        let Ok(Response::EnumerateRPsGetNextRP { relying_party }) =
+            authenticator.credential_management(Request::EnumerateRPsGetNextRP)
+        else {
+            panic!("no next relying party");
+        };
+        assert_eq!(relying_party.relying_party.id, "example.org");
+        assert_eq!(
+            authenticator.credential_management(Request::EnumerateRPsGetNextRP),
+            Err(Error::NoCredentials)
+        );
+    }
+
+    #[test]
+    fn enumerate_credentials() {
+        let (mut authenticator, token) = authenticator_with_token(None);
+        let first = store(&mut authenticator, "example.com", 1);
+        store(&mut authenticator, "example.org", 1);
+        let second = store(&mut authenticator, "example.com", 2);
+
+        let hash = relying_party_id_hash("example.com");
+        let request = Request::enumerate_credentials_begin(&hash, Version::Two, &token);
+        let Ok(Response::EnumerateCredentialsBegin {
+            credential,
+            total_credentials,
+        }) = authenticator.credential_management(request)
+        else {
+            panic!("no credentials");
+        };
+        assert_eq!(total_credentials, 2);
+        assert_eq!(credential.credential_id, descriptor(&first));
+        assert_eq!(credential.user, user(1));
+        assert_eq!(
+            credential.public_key,
+            authenticator
+                .storage()
+                .credential(&first)
+                .unwrap()
+                .private_key
+                .public_key()
+        );
+
+        let next = Request::EnumerateCredentialsGetNextCredential;
+        let Ok(Response::EnumerateCredentialsGetNextCredential { credential }) =
+            authenticator.credential_management(next.clone())
+        else {
+            panic!("no next credential");
+        };
+        assert_eq!(credential.credential_id, descriptor(&second));
+        assert_eq!(
+            authenticator.credential_management(next),
+            Err(Error::NoCredentials)
+        );
+    }
+
+    #[test]
+    fn enumeration_interrupted() {
+        let (mut authenticator, token) = authenticator_with_token(None);
+        store(&mut authenticator, "example.com", 1);
+        store(&mut authenticator, "example.org", 1);
+
+        let request = Request::enumerate_rps_begin(Version::Two, &token);
+        authenticator.credential_management(request).unwrap();
+        // Any other command ends the enumeration
+        authenticator.selection().unwrap();
+        assert_eq!(
+            authenticator.credential_management(Request::EnumerateRPsGetNextRP),
+            Err(Error::NoCredentials)
+        );
+        assert_eq!(
+            authenticator.credential_management(Request::EnumerateCredentialsGetNextCredential),
+            Err(Error::NoCredentials)
+        );
+    }
+
+    #[test]
+    fn delete_credential() {
+        let (mut authenticator, token) = authenticator_with_token(None);
+        let id = store(&mut authenticator, "example.com", 1);
+
+        let credential_id = descriptor(&id);
+        let request = Request::delete_credential(&credential_id, Version::Two, &token);
+        assert_eq!(
+            authenticator.credential_management(request.clone()),
+            Ok(Response::DeleteCredential)
+        );
+        assert!(authenticator.storage().credential(&id).is_none());
+        assert_eq!(
+            authenticator.credential_management(request),
+            Err(Error::NoCredentials)
+        );
+    }
+
+    #[test]
+    fn update_user_information() {
+        let (mut authenticator, token) = authenticator_with_token(None);
+        let id = store(&mut authenticator, "example.com", 1);
+        let credential_id = descriptor(&id);
+
+        let renamed = public_key::UserEntity {
+            id: vec![1],
+            name: Some("renamed".to_owned()),
+            display_name: None,
+        };
+        let request =
+            Request::update_user_information(&credential_id, &renamed, Version::Two, &token);
+        assert_eq!(
+            authenticator.credential_management(request),
+            Ok(Response::UpdateUserInformation)
+        );
+        assert_eq!(
+            authenticator.storage().credential(&id).unwrap().user,
+            renamed
+        );
+
+        // The user ID cannot be changed
+        let other = user(2);
+        let request =
+            Request::update_user_information(&credential_id, &other, Version::Two, &token);
+        assert_eq!(
+            authenticator.credential_management(request),
+            Err(Error::InvalidParameter)
+        );
+    }
+
+    #[test]
+    fn token_bound_to_relying_party() {
+        let (mut authenticator, token) = authenticator_with_token(Some("example.com"));
+        let own = store(&mut authenticator, "example.com", 1);
+        let other = store(&mut authenticator, "example.org", 1);
+
+        let request = Request::get_credentials_metadata(Version::Two, &token);
+        assert_eq!(
+            authenticator.credential_management(request),
+            Err(Error::PinAuthInvalid)
+        );
+        let request = Request::enumerate_rps_begin(Version::Two, &token);
+        assert_eq!(
+            authenticator.credential_management(request),
+            Err(Error::PinAuthInvalid)
+        );
+
+        let hash = relying_party_id_hash("example.org");
+        let request = Request::enumerate_credentials_begin(&hash, Version::Two, &token);
+        assert_eq!(
+            authenticator.credential_management(request),
+            Err(Error::PinAuthInvalid)
+        );
+        let other = descriptor(&other);
+        let request = Request::delete_credential(&other, Version::Two, &token);
+        assert_eq!(
+            authenticator.credential_management(request),
+            Err(Error::PinAuthInvalid)
+        );
+
+        let hash = relying_party_id_hash("example.com");
+        let request = Request::enumerate_credentials_begin(&hash, Version::Two, &token);
+        assert!(authenticator.credential_management(request).is_ok());
+        let own = descriptor(&own);
+        let request = Request::delete_credential(&own, Version::Two, &token);
+        assert_eq!(
+            authenticator.credential_management(request),
+            Ok(Response::DeleteCredential)
+        );
+    }
+}
@@ -0,0 +1,289 @@
+//! The authenticator's handling of each CTAP2 command.
+
+use crate::{key, storage::Storage, Authenticator, UserPresence};
+use ctap2_proto::{
+    hid::packet::MAX_MESSAGE_SIZE,
+    prelude::{
+        attestation, client_pin::auth_protocol, credential::public_key, device, extensions,
+        Sha256Hash,
+    },
+};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, BTreeSet};
+
+mod client_pin;
+mod config;
+pub(crate) mod credential;
+pub(crate) mod large_blobs;
+pub(crate) mod management;
+
+/// The CBOR encoding of `true`, the input of extensions that are simply
+/// requested.
+const CBOR_TRUE: u8 = 0xF5;
+
+impl<S: Storage, P: UserPresence> Authenticator<S, P> {
+    pub(crate) fn info(&self) -> device::Info {
+        let state = self.storage.state();
+        let discoverable = self
+            .storage
+            .credentials()
+            .iter()
+            .filter(|credential| credential.discoverable)
+            .count();
+
+        let options = BTreeMap::from([
+            (device::OptionId::DiscoverableCredentials, true),
+            (device::OptionId::UserPresence, true),
+            (device::OptionId::ClientPin, state.pin_hash.is_some()),
+            (device::OptionId::PinUvAuthToken, true),
+            (device::OptionId::CredentialManagement, true),
+            (device::OptionId::AuthenticatorConfig, true),
+            (device::OptionId::SetMinPinLength, true),
+            (device::OptionId::LargeBlobs, true),
+            (device::OptionId::MakeCredentialUvNotRequired, true),
+            (device::OptionId::AlwaysRequireUv, state.always_uv),
+            (
+                device::OptionId::EnterpriseAttestation,
+                state.enterprise_attestation,
+            ),
+        ]);
+
+        device::Info {
+            versions: BTreeSet::from([device::Version::Fido2_0, device::Version::Fido2_1]),
+            extensions: Some(BTreeSet::from([
+                extensions::Identifier::CredentialProtection,
+                extensions::Identifier::LargeBlobKey,
+            ])),
+            aaguid: self.aaguid,
+            options: Some(options),
+            max_message_size: Some(MAX_MESSAGE_SIZE),
+            pin_uv_auth_protocols: Some(vec![
+                auth_protocol::Version::Two,
+                auth_protocol::Version::One,
+            ]),
+            max_credential_count_in_list: None,
+            max_credential_id_length: None,
+            transports: None,
+            algorithms: Some(
+                key::ALGORITHMS
+                    .iter()
+                    .map(|&algorithm| public_key::Parameters {
+                        credential_type: ctap2_proto::prelude::credential::Type::PublicKey,
+                        algorithm,
+                    })
+                    .collect(),
+            ),
+            max_serialized_large_blob_array_size: device::UsizeN::new(
+                large_blobs::MAX_SERIALIZED_LARGE_BLOB_ARRAY_SIZE,
+            ),
+            force_pin_change: Some(state.force_pin_change),
+            min_pin_length: Some(state.min_pin_length),
+            firmware_version: None,
+            max_cred_blob_length: None,
+            max_rpids_for_set_min_pin_length: None,
+            preferred_platform_uv_attempts: None,
+            uv_modality: None,
+            certifications: None,
+            remaining_discoverable_credentials: Some(
+                self.storage.capacity().saturating_sub(discoverable),
+            ),
+            vendor_prototype_config_commands: None,
+            attestation_formats: Some(BTreeSet::from([attestation::FormatIdentifier::None])),
+            uv_count_since_last_pin_entry: None,
+            long_touch_for_reset: None,
+        }
+    }
+}
+
+pub(crate) fn relying_party_id_hash(relying_party_id: &str) -> Sha256Hash {
+    Sha256::digest(relying_party_id.as_bytes()).into()
+}
+
+/// Returns whether the extension input is present and `true`.
+fn extension_requested<V: AsRef<[u8]>>(
+    extensions: Option<&BTreeMap<extensions::Identifier, V>>,
+    identifier: extensions::Identifier,
+) -> bool {
+    extensions
+        .and_then(|extensions| extensions.get(&identifier))
+        .is_some_and(|input| input.as_ref() == [CBOR_TRUE])
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    //! Platform-side helpers shared by the tests of each command.
+
+    use super::*;
+    use crate::{pin, storage::Memory, AlwaysPresent};
+    use ctap2_proto::prelude::{
+        client_pin::{
+            self,
+            auth_protocol::{KeyAgreementKey, SharedSecret, Version},
+            Permission,
+        },
+        credential, make, Ctap2_2Authenticator,
+    };
+
+    pub(crate) const PIN: &str = "1234";
+
+    pub(crate) const RELYING_PARTY_ID: &str = "example.com";
+
+    pub(crate) const CLIENT_DATA_HASH: Sha256Hash = [0x42; 32];
+
+    pub(crate) fn authenticator() -> Authenticator<Memory> {
+        Authenticator::new(Memory::default(), AlwaysPresent)
+    }
+
+    /// Establishes a shared secret with the authenticator, returning the
+    /// platform's `keyAgreement` along with it.
+    pub(crate) fn key_agreement<P: UserPresence>(
+        authenticator: &mut Authenticator<Memory, P>,
+        version: Version,
+    ) -> (cosey::PublicKey, SharedSecret) {
+        let request = client_pin::Request::GetKeyAgreement { version };
+        let Ok(client_pin::Response::GetKeyAgreement { key_agreement }) =
+            authenticator.client_pin(request)
+        else {
+            panic!("no key agreement");
+        };
+        let platform = KeyAgreementKey::generate();
+        let shared_secret = platform.shared_secret(version, &key_agreement).unwrap();
+        (platform.public_key(), shared_secret)
+    }
+
+    /// The PIN padded to 64 bytes, as encrypted in `newPinEnc`.
+    pub(crate) fn padded(pin: &str) -> Vec<u8> {
+        let mut padded = pin.as_bytes().to_vec();
+        padded.resize(64, 0);
+        padded
+    }
+
+    /// `pinHashEnc` of the PIN.
+    pub(crate) fn pin_hash_encrypted(shared_secret: &SharedSecret, pin: &str) -> Vec<u8> {
+        shared_secret
+            .encrypt(&pin::pin_hash(pin.as_bytes()))
+            .unwrap()
+    }
+
+    pub(crate) fn set_pin<P: UserPresence>(
+        authenticator: &mut Authenticator<Memory, P>,
+        pin: &str,
+    ) -> Result<client_pin::Response, client_pin::Error> {
+        let (key_agreement, shared_secret) = key_agreement(authenticator, Version::Two);
+        let new_pin_encrypted = shared_secret.encrypt(&padded(pin)).unwrap();
+        let pin_uv_auth_param = shared_secret.authenticate(&new_pin_encrypted);
+        authenticator.client_pin(client_pin::Request::SetPin {
+            version: Version::Two,
+            key_agreement,
+            new_pin_encrypted,
+            pin_uv_auth_param,
+        })
+    }
+
+    /// Obtains a `pinUvAuthToken` with the permissions using [`PIN`].
+    pub(crate) fn token<P: UserPresence>(
+        authenticator: &mut Authenticator<Memory, P>,
+        version: Version,
+        permissions: &[Permission],
+        relying_party_id: Option<&str>,
+    ) -> Result<Vec<u8>, client_pin::Error> {
+        let (key_agreement, shared_secret) = key_agreement(authenticator, version);
+        let permissions = permissions.iter().copied().collect();
+        let response = authenticator.client_pin(
+            client_pin::Request::GetPinUvAuthTokenUsingPinWithPermissions {
+                version,
+                key_agreement,
+                pin_hash_encrypted: pin_hash_encrypted(&shared_secret, PIN),
+                permissions: std::borrow::Cow::Owned(permissions),
+                relying_party_id: relying_party_id.map(|id| id.to_owned().into()),
+            },
+        )?;
+        let client_pin::Response::GetPinUvAuthTokenUsingPinWithPermissions { pin_uv_auth_token } =
+            response
+        else {
+            panic!("unexpected response");
+        };
+        Ok(shared_secret.decrypt(&pin_uv_auth_token).unwrap())
+    }
+
+    pub(crate) fn user(id: u8) -> public_key::UserEntity {
+        public_key::UserEntity {
+            id: vec![id],
+            name: Some(format!("user{id}")),
+            display_name: Some(format!("User {id}")),
+        }
+    }
+
+    /// Creates an ES256 credential for [`RELYING_PARTY_ID`], authenticated
+    /// with the token if one is given.
+    pub(crate) fn make_credential<P: UserPresence>(
+        authenticator: &mut Authenticator<Memory, P>,
+        user: &public_key::UserEntity,
+        discoverable: bool,
+        token: Option<&[u8]>,
+    ) -> Result<make::Response, make::Error> {
+        let relying_party = public_key::RelyingPartyEntity {
+            id: RELYING_PARTY_ID.to_owned(),
+            name: None,
+        };
+        let parameters = [public_key::Parameters {
+            credential_type: credential::Type::PublicKey,
+            algorithm: coset::iana::Algorithm::ES256,
+        }];
+        let options = BTreeMap::from([(make::OptionKey::Discoverable, discoverable)]);
+        let request = make::Request::builder()
+            .client_data_hash(&CLIENT_DATA_HASH)
+            .relying_party(&relying_party)
+            .user(user)
+            .public_key_credential_params(&parameters)
+            .options(&options)
+            .build();
+        let request = match token {
+            Some(token) => request.authenticated(Version::Two, token),
+            None => request,
+        };
+        authenticator.make_credential(request)
+    }
+
+    /// The ID of the credential created by a `authenticatorMakeCredential`
+    /// response.
+    pub(crate) fn credential_id(response: &make::Response) -> Vec<u8> {
+        response
+            .authenticator_data
+            .attested_credential_data
+            .as_ref()
+            .unwrap()
+            .id
+            .clone()
+    }
+
+    #[test]
+    fn info() {
+        let mut authenticator = authenticator();
+        let info = authenticator.get_info();
+        let option = |id| info.options.as_ref().unwrap()[&id];
+        assert!(!option(device::OptionId::ClientPin));
+        assert_eq!(info.min_pin_length, Some(4));
+        assert_eq!(info.remaining_discoverable_credentials, Some(25));
+
+        assert!(info
+            .extensions
+            .as_ref()
+            .unwrap()
+            .contains(&extensions::Identifier::CredentialProtection));
+
+        set_pin(&mut authenticator, PIN).unwrap();
+        let token = token(
+            &mut authenticator,
+            auth_protocol::Version::Two,
+            &[Permission::MakeCredential],
+            Some(RELYING_PARTY_ID),
+        )
+        .unwrap();
+        make_credential(&mut authenticator, &user(1), true, Some(&token)).unwrap();
+
+        let info = authenticator.get_info();
+        assert!(info.options.as_ref().unwrap()[&device::OptionId::ClientPin]);
+        assert_eq!(info.remaining_discoverable_credentials, Some(24));
+    }
+}
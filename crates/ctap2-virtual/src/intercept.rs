@@ -0,0 +1,289 @@
+//! Overriding single commands of a virtual authenticator.
+//!
+//! An [`Intercepted`] authenticator forwards every command to an
+//! [`Authenticator`], except those given a closure, which answers in its
+//! place. This lets tests simulate behavior the virtual authenticator does
+//! not have, e.g. built-in user verification or failing commands, without
+//! reimplementing the other commands.
+//!
+//! Each closure is also given the virtual authenticator, e.g. to forward the
+//! request after inspecting it.
+
+use crate::{AlwaysPresent, Authenticator, Storage, UserPresence};
+use ctap2_proto::prelude::{
+    bio_enrollment, client_pin, config, device, get, large_blobs, make, management, reset,
+    selection, Ctap2_2Authenticator,
+};
+
+type MakeCredential<'a, A> =
+    dyn FnMut(&mut A, make::Request) -> Result<make::Response, make::Error> + 'a;
+type GetAssertion<'a, A> =
+    dyn FnMut(&mut A, get::Request) -> Result<get::Response, get::Error> + 'a;
+type GetNextAssertion<'a, A> = dyn FnMut(&mut A) -> Result<get::Response, get::Error> + 'a;
+type GetInfo<'a, A> = dyn Fn(&A) -> device::Info + 'a;
+type ClientPin<'a, A> =
+    dyn FnMut(&mut A, client_pin::Request) -> Result<client_pin::Response, client_pin::Error> + 'a;
+type Reset<'a, A> = dyn FnMut(&mut A) -> Result<(), reset::Error> + 'a;
+type BioEnrollment<'a, A> = dyn FnMut(
+        &mut A,
+        bio_enrollment::Request,
+    ) -> Result<bio_enrollment::Response, bio_enrollment::Error>
+    + 'a;
+type CredentialManagement<'a, A> =
+    dyn FnMut(&mut A, management::Request) -> Result<management::Response, management::Error> + 'a;
+type Selection<'a, A> = dyn FnMut(&mut A) -> Result<(), selection::Error> + 'a;
+type LargeBlobs<'a, A> = dyn FnMut(&mut A, large_blobs::Request) -> Result<large_blobs::Response, large_blobs::Error>
+    + 'a;
+type AuthenticatorConfig<'a, A> =
+    dyn FnMut(&mut A, config::Request) -> Result<(), config::Error> + 'a;
+
+/// A virtual authenticator, some of whose commands are answered by closures.
+pub struct Intercepted<'a, S: Storage, P: UserPresence = AlwaysPresent> {
+    authenticator: Authenticator<S, P>,
+    make_credential: Option<Box<MakeCredential<'a, Authenticator<S, P>>>>,
+    get_assertion: Option<Box<GetAssertion<'a, Authenticator<S, P>>>>,
+    get_next_assertion: Option<Box<GetNextAssertion<'a, Authenticator<S, P>>>>,
+    get_info: Option<Box<GetInfo<'a, Authenticator<S, P>>>>,
+    client_pin: Option<Box<ClientPin<'a, Authenticator<S, P>>>>,
+    reset: Option<Box<Reset<'a, Authenticator<S, P>>>>,
+    bio_enrollment: Option<Box<BioEnrollment<'a, Authenticator<S, P>>>>,
+    credential_management: Option<Box<CredentialManagement<'a, Authenticator<S, P>>>>,
+    selection: Option<Box<Selection<'a, Authenticator<S, P>>>>,
+    large_blobs: Option<Box<LargeBlobs<'a, Authenticator<S, P>>>>,
+    authenticator_config: Option<Box<AuthenticatorConfig<'a, Authenticator<S, P>>>>,
+}
+
+impl<'a, S: Storage, P: UserPresence> Intercepted<'a, S, P> {
+    /// Intercepts no command of the authenticator yet.
+    pub fn new(authenticator: Authenticator<S, P>) -> Self {
+        Self {
+            authenticator,
+            make_credential: None,
+            get_assertion: None,
+            get_next_assertion: None,
+            get_info: None,
+            client_pin: None,
+            reset: None,
+            bio_enrollment: None,
+            credential_management: None,
+            selection: None,
+            large_blobs: None,
+            authenticator_config: None,
+        }
+    }
+
+    pub fn authenticator(&self) -> &Authenticator<S, P> {
+        &self.authenticator
+    }
+
+    pub fn authenticator_mut(&mut self) -> &mut Authenticator<S, P> {
+        &mut self.authenticator
+    }
+
+    /// Answers `authenticatorMakeCredential` with the closure.
+    pub fn on_make_credential<F>(mut self, make_credential: F) -> Self
+    where
+        F: FnMut(&mut Authenticator<S, P>, make::Request) -> Result<make::Response, make::Error>
+            + 'a,
+    {
+        self.make_credential = Some(Box::new(make_credential));
+        self
+    }
+
+    /// Answers `authenticatorGetAssertion` with the closure.
+    pub fn on_get_assertion<F>(mut self, get_assertion: F) -> Self
+    where
+        F: FnMut(&mut Authenticator<S, P>, get::Request) -> Result<get::Response, get::Error> + 'a,
+    {
+        self.get_assertion = Some(Box::new(get_assertion));
+        self
+    }
+
+    /// Answers `authenticatorGetNextAssertion` with the closure.
+    pub fn on_get_next_assertion<F>(mut self, get_next_assertion: F) -> Self
+    where
+        F: FnMut(&mut Authenticator<S, P>) -> Result<get::Response, get::Error> + 'a,
+    {
+        self.get_next_assertion = Some(Box::new(get_next_assertion));
+        self
+    }
+
+    /// Answers `authenticatorGetInfo` with the closure.
+    pub fn on_get_info<F>(mut self, get_info: F) -> Self
+    where
+        F: Fn(&Authenticator<S, P>) -> device::Info + 'a,
+    {
+        self.get_info = Some(Box::new(get_info));
+        self
+    }
+
+    /// Answers `authenticatorClientPIN` with the closure.
+    pub fn on_client_pin<F>(mut self, client_pin: F) -> Self
+    where
+        F: FnMut(
+                &mut Authenticator<S, P>,
+                client_pin::Request,
+            ) -> Result<client_pin::Response, client_pin::Error>
+            + 'a,
+    {
+        self.client_pin = Some(Box::new(client_pin));
+        self
+    }
+
+    /// Answers `authenticatorReset` with the closure.
+    pub fn on_reset<F>(mut self, reset: F) -> Self
+    where
+        F: FnMut(&mut Authenticator<S, P>) -> Result<(), reset::Error> + 'a,
+    {
+        self.reset = Some(Box::new(reset));
+        self
+    }
+
+    /// Answers `authenticatorBioEnrollment` with the closure.
+    pub fn on_bio_enrollment<F>(mut self, bio_enrollment: F) -> Self
+    where
+        F: FnMut(
+                &mut Authenticator<S, P>,
+                bio_enrollment::Request,
+            ) -> Result<bio_enrollment::Response, bio_enrollment::Error>
+            + 'a,
+    {
+        self.bio_enrollment = Some(Box::new(bio_enrollment));
+        self
+    }
+
+    /// Answers `authenticatorCredentialManagement` with the closure.
+    pub fn on_credential_management<F>(mut self, credential_management: F) -> Self
+    where
+        F: FnMut(
+                &mut Authenticator<S, P>,
+                management::Request,
+            ) -> Result<management::Response, management::Error>
+            + 'a,
+    {
+        self.credential_management = Some(Box::new(credential_management));
+        self
+    }
+
+    /// Answers `authenticatorSelection` with the closure.
+    pub fn on_selection<F>(mut self, selection: F) -> Self
+    where
+        F: FnMut(&mut Authenticator<S, P>) -> Result<(), selection::Error> + 'a,
+    {
+        self.selection = Some(Box::new(selection));
+        self
+    }
+
+    /// Answers `authenticatorLargeBlobs` with the closure.
+    pub fn on_large_blobs<F>(mut self, large_blobs: F) -> Self
+    where
+        F: FnMut(
+                &mut Authenticator<S, P>,
+                large_blobs::Request,
+            ) -> Result<large_blobs::Response, large_blobs::Error>
+            + 'a,
+    {
+        self.large_blobs = Some(Box::new(large_blobs));
+        self
+    }
+
+    /// Answers `authenticatorConfig` with the closure.
+    pub fn on_authenticator_config<F>(mut self, authenticator_config: F) -> Self
+    where
+        F: FnMut(&mut Authenticator<S, P>, config::Request) -> Result<(), config::Error> + 'a,
+    {
+        self.authenticator_config = Some(Box::new(authenticator_config));
+        self
+    }
+}
+
+impl<S: Storage, P: UserPresence> Ctap2_2Authenticator for Intercepted<'_, S, P> {
+    fn make_credential(&mut self, request: make::Request) -> Result<make::Response, make::Error> {
+        match &mut self.make_credential {
+            Some(make_credential) => make_credential(&mut self.authenticator, request),
+            None => self.authenticator.make_credential(request),
+        }
+    }
+
+    fn get_assertion(&mut self, request: get::Request) -> Result<get::Response, get::Error> {
+        match &mut self.get_assertion {
+            Some(get_assertion) => get_assertion(&mut self.authenticator, request),
+            None => self.authenticator.get_assertion(request),
+        }
+    }
+
+    fn get_next_assertion(&mut self) -> Result<get::Response, get::Error> {
+        match &mut self.get_next_assertion {
+            Some(get_next_assertion) => get_next_assertion(&mut self.authenticator),
+            None => self.authenticator.get_next_assertion(),
+        }
+    }
+
+    fn get_info(&self) -> device::Info {
+        match &self.get_info {
+            Some(get_info) => get_info(&self.authenticator),
+            None => self.authenticator.get_info(),
+        }
+    }
+
+    fn client_pin(
+        &mut self,
+        request: client_pin::Request,
+    ) -> Result<client_pin::Response, client_pin::Error> {
+        match &mut self.client_pin {
+            Some(client_pin) => client_pin(&mut self.authenticator, request),
+            None => self.authenticator.client_pin(request),
+        }
+    }
+
+    fn reset(&mut self) -> Result<(), reset::Error> {
+        match &mut self.reset {
+            Some(reset) => reset(&mut self.authenticator),
+            None => self.authenticator.reset(),
+        }
+    }
+
+    fn bio_enrollment(
+        &mut self,
+        request: bio_enrollment::Request,
+    ) -> Result<bio_enrollment::Response, bio_enrollment::Error> {
+        match &mut self.bio_enrollment {
+            Some(bio_enrollment) => bio_enrollment(&mut self.authenticator, request),
+            None => self.authenticator.bio_enrollment(request),
+        }
+    }
+
+    fn credential_management(
+        &mut self,
+        request: management::Request,
+    ) -> Result<management::Response, management::Error> {
+        match &mut self.credential_management {
+            Some(credential_management) => credential_management(&mut self.authenticator, request),
+            None => self.authenticator.credential_management(request),
+        }
+    }
+
+    fn selection(&mut self) -> Result<(), selection::Error> {
+        match &mut self.selection {
+            Some(selection) => selection(&mut self.authenticator),
+            None => self.authenticator.selection(),
+        }
+    }
+
+    fn large_blobs(
+        &mut self,
+        request: large_blobs::Request,
+    ) -> Result<large_blobs::Response, large_blobs::Error> {
+        match &mut self.large_blobs {
+            Some(large_blobs) => large_blobs(&mut self.authenticator, request),
+            None => self.authenticator.large_blobs(request),
+        }
+    }
+
+    fn authenticator_config(&mut self, request: config::Request) -> Result<(), config::Error> {
+        match &mut self.authenticator_config {
+            Some(authenticator_config) => authenticator_config(&mut self.authenticator, request),
+            None => self.authenticator.authenticator_config(request),
+        }
+    }
+}
@@ -0,0 +1,62 @@
+//! Credential key pairs for the supported signature algorithms.
+
+use coset::iana;
+use ctap2_proto::{authenticator::data, prelude::credential::public_key};
+use rand::rngs::OsRng;
+
+/// The algorithms supported for credential generation, in order of
+/// preference.
+pub const ALGORITHMS: [iana::Algorithm; 2] = [iana::Algorithm::ES256, iana::Algorithm::EdDSA];
+
+/// The private key of a credential.
+#[derive(Clone)]
+pub enum PrivateKey {
+    /// ECDSA using the P-256 curve and SHA-256.
+    Es256(p256::ecdsa::SigningKey),
+    /// EdDSA using Ed25519.
+    EdDsa(ed25519_dalek::SigningKey),
+}
+
+impl PrivateKey {
+    /// Generates a fresh key pair, or returns `None` if the algorithm is not
+    /// supported.
+    pub fn generate(algorithm: iana::Algorithm) -> Option<Self> {
+        match algorithm {
+            iana::Algorithm::ES256 => {
+                Some(Self::Es256(p256::ecdsa::SigningKey::random(&mut OsRng)))
+            }
+            iana::Algorithm::EdDSA => {
+                Some(Self::EdDsa(ed25519_dalek::SigningKey::generate(&mut OsRng)))
+            }
+            _ => None,
+        }
+    }
+
+    pub fn algorithm(&self) -> iana::Algorithm {
+        match self {
+            PrivateKey::Es256(_) => iana::Algorithm::ES256,
+            PrivateKey::EdDsa(_) => iana::Algorithm::EdDSA,
+        }
+    }
+
+    /// The public key, as included in the attested credential data.
+    pub fn public_key(&self) -> public_key::Key {
+        match self {
+            PrivateKey::Es256(key) => key.verifying_key().into(),
+            PrivateKey::EdDsa(key) => (&key.verifying_key()).into(),
+        }
+    }
+}
+
+impl data::Signer for PrivateKey {
+    fn algorithm(&self) -> iana::Algorithm {
+        PrivateKey::algorithm(self)
+    }
+
+    fn sign(&self, message: &[u8]) -> Vec<u8> {
+        match self {
+            PrivateKey::Es256(key) => data::Signer::sign(key, message),
+            PrivateKey::EdDsa(key) => data::Signer::sign(key, message),
+        }
+    }
+}
@@ -0,0 +1,439 @@
+//! A software implementation of a CTAP2 authenticator.
+//!
+//! The [`Authenticator`] implements [`Ctap2_2Authenticator`] entirely in
+//! software, so that platform code can be exercised without hardware. Its
+//! persistent state is kept in a pluggable [`Storage`] backend, and user
+//! presence is requested through a [`UserPresence`] implementation.
+//!
+//! It also implements the CTAPHID [`Handler`], so that it can be attached to a
+//! [`Server`](ctap2_proto::hid::authenticator::Server) and reached over a
+//! (virtual) HID device.
+//!
+//! Single commands can be overridden by wrapping it in an [`Intercepted`]
+//! authenticator.
+
+use ctap2_proto::{
+    dispatch::dispatch,
+    hid::authenticator::Handler,
+    prelude::{
+        bio_enrollment, client_pin, config, device, get, large_blobs, make, management, reset,
+        selection, Ctap2_2Authenticator, StatusCode,
+    },
+};
+use std::time::{Duration, Instant};
+
+mod command;
+pub mod intercept;
+pub mod key;
+mod pin;
+pub mod storage;
+
+pub use intercept::Intercepted;
+pub use key::PrivateKey;
+pub use storage::{Memory, Storage};
+
+/// The time after power-up during which `authenticatorReset` is allowed.
+const RESET_WINDOW: Duration = Duration::from_secs(10);
+
+/// Collects a test of user presence, e.g. a touch.
+pub trait UserPresence {
+    /// Returns whether the user confirmed their presence.
+    fn confirm(&mut self) -> bool;
+}
+
+impl<F: FnMut() -> bool> UserPresence for F {
+    fn confirm(&mut self) -> bool {
+        self()
+    }
+}
+
+/// A user who confirms every request.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AlwaysPresent;
+
+impl UserPresence for AlwaysPresent {
+    fn confirm(&mut self) -> bool {
+        true
+    }
+}
+
+/// A software authenticator, supporting ES256 and EdDSA credentials, ClientPIN
+/// with both PIN/UV auth protocols, credential management, large blobs and
+/// `authenticatorConfig`.
+pub struct Authenticator<S: Storage, P: UserPresence = AlwaysPresent> {
+    storage: S,
+    presence: P,
+    aaguid: device::Aaguid,
+    powered_up: Instant,
+    protocol_one: pin::KeyAgreementKey,
+    protocol_two: pin::KeyAgreementKey,
+    pin_uv_auth_token: pin::PinUvAuthToken,
+    /// The number of consecutive PIN mismatches since power-up.
+    pin_mismatches: u8,
+    /// The remaining results of an enumeration started by credential
+    /// management.
+    enumeration: Option<command::management::Enumeration>,
+    /// The remaining credentials of the last `authenticatorGetAssertion`
+    /// request, to be returned by `authenticatorGetNextAssertion`.
+    pending_assertions: Option<command::credential::PendingAssertions>,
+    /// The serialized large-blob array being written by
+    /// `authenticatorLargeBlobs`, until all of its fragments are received.
+    large_blob_write: Option<command::large_blobs::PendingWrite>,
+}
+
+impl<S: Storage, P: UserPresence> Authenticator<S, P> {
+    /// Powers up an authenticator with an all-zero AAGUID.
+    pub fn new(storage: S, presence: P) -> Self {
+        Self::with_aaguid(storage, presence, [0; 16])
+    }
+
+    pub fn with_aaguid(storage: S, presence: P, aaguid: device::Aaguid) -> Self {
+        Self {
+            storage,
+            presence,
+            aaguid,
+            powered_up: Instant::now(),
+            protocol_one: pin::KeyAgreementKey::generate(),
+            protocol_two: pin::KeyAgreementKey::generate(),
+            pin_uv_auth_token: pin::PinUvAuthToken::new(),
+            pin_mismatches: 0,
+            enumeration: None,
+            pending_assertions: None,
+            large_blob_write: None,
+        }
+    }
+
+    pub fn storage(&self) -> &S {
+        &self.storage
+    }
+
+    pub fn storage_mut(&mut self) -> &mut S {
+        &mut self.storage
+    }
+
+    pub fn presence(&mut self) -> &mut P {
+        &mut self.presence
+    }
+
+    /// Simulates removing and reinserting the authenticator, discarding all
+    /// state that does not persist in storage.
+    pub fn power_cycle(&mut self) {
+        self.powered_up = Instant::now();
+        self.regenerate_key_agreement();
+        self.pin_uv_auth_token.reset();
+        self.pin_mismatches = 0;
+        self.enumeration = None;
+        self.pending_assertions = None;
+        self.large_blob_write = None;
+    }
+
+    fn regenerate_key_agreement(&mut self) {
+        self.protocol_one = pin::KeyAgreementKey::generate();
+        self.protocol_two = pin::KeyAgreementKey::generate();
+    }
+
+    fn key_agreement(&self, version: client_pin::auth_protocol::Version) -> &pin::KeyAgreementKey {
+        match version {
+            client_pin::auth_protocol::Version::One => &self.protocol_one,
+            client_pin::auth_protocol::Version::Two => &self.protocol_two,
+        }
+    }
+}
+
+impl<S: Storage, P: UserPresence> Ctap2_2Authenticator for Authenticator<S, P> {
+    fn make_credential(&mut self, request: make::Request) -> Result<make::Response, make::Error> {
+        self.enumeration = None;
+        self.pending_assertions = None;
+        self.handle_make_credential(request)
+    }
+
+    fn get_assertion(&mut self, request: get::Request) -> Result<get::Response, get::Error> {
+        self.enumeration = None;
+        self.pending_assertions = None;
+        self.handle_get_assertion(request)
+    }
+
+    fn get_next_assertion(&mut self) -> Result<get::Response, get::Error> {
+        self.enumeration = None;
+        self.handle_get_next_assertion()
+    }
+
+    fn get_info(&self) -> device::Info {
+        self.info()
+    }
+
+    fn client_pin(
+        &mut self,
+        request: client_pin::Request,
+    ) -> Result<client_pin::Response, client_pin::Error> {
+        self.enumeration = None;
+        self.pending_assertions = None;
+        self.handle_client_pin(request)
+    }
+
+    fn reset(&mut self) -> Result<(), reset::Error> {
+        self.enumeration = None;
+        self.pending_assertions = None;
+        if self.powered_up.elapsed() > RESET_WINDOW {
+            return Err(reset::Error::NotAllowed);
+        }
+        if !self.presence.confirm() {
+            return Err(reset::Error::OperationDenied);
+        }
+
+        self.storage.clear();
+        self.large_blob_write = None;
+        self.regenerate_key_agreement();
+        self.pin_uv_auth_token.reset();
+        self.pin_mismatches = 0;
+        Ok(())
+    }
+
+    fn bio_enrollment(
+        &mut self,
+        _request: bio_enrollment::Request,
+    ) -> Result<bio_enrollment::Response, bio_enrollment::Error> {
+        self.pending_assertions = None;
+        // There is no fingerprint sensor to enroll
+        Err(bio_enrollment::Error::Other(StatusCode::InvalidCommand))
+    }
+
+    fn credential_management(
+        &mut self,
+        request: management::Request,
+    ) -> Result<management::Response, management::Error> {
+        self.pending_assertions = None;
+        self.handle_credential_management(request)
+    }
+
+    fn selection(&mut self) -> Result<(), selection::Error> {
+        self.enumeration = None;
+        self.pending_assertions = None;
+        if self.presence.confirm() {
+            Ok(())
+        } else {
+            Err(selection::Error::OperationDenied)
+        }
+    }
+
+    fn large_blobs(
+        &mut self,
+        request: large_blobs::Request,
+    ) -> Result<large_blobs::Response, large_blobs::Error> {
+        self.enumeration = None;
+        self.pending_assertions = None;
+        self.handle_large_blobs(request)
+    }
+
+    fn authenticator_config(&mut self, request: config::Request) -> Result<(), config::Error> {
+        self.enumeration = None;
+        self.pending_assertions = None;
+        self.handle_authenticator_config(request)
+    }
+}
+
+impl<S: Storage, P: UserPresence> Handler for Authenticator<S, P> {
+    fn cbor(&mut self, request: &[u8]) -> Vec<u8> {
+        dispatch(self, request)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::tests::{authenticator, make_credential, set_pin, user, PIN};
+    use ctap2_proto::prelude::{client_pin::auth_protocol::Version, credential, Command};
+    use serde::Serialize;
+    use std::cell::{Cell, RefCell};
+
+    fn request<T: Serialize>(command: Command, parameters: &T) -> Vec<u8> {
+        let mut request = vec![command.into()];
+        ciborium::ser::into_writer(parameters, &mut request).unwrap();
+        request
+    }
+
+    #[test]
+    fn reset() {
+        let mut authenticator = authenticator();
+        set_pin(&mut authenticator, PIN).unwrap();
+        make_credential(&mut authenticator, &user(1), false, None).unwrap();
+
+        assert_eq!(authenticator.reset(), Ok(()));
+        assert!(authenticator.storage().credentials().is_empty());
+        assert_eq!(authenticator.storage().state(), storage::State::default());
+    }
+
+    #[test]
+    fn reset_only_after_power_up() {
+        let mut authenticator = authenticator();
+        authenticator.powered_up = Instant::now() - RESET_WINDOW - Duration::from_secs(1);
+        assert_eq!(authenticator.reset(), Err(reset::Error::NotAllowed));
+
+        authenticator.power_cycle();
+        assert_eq!(authenticator.reset(), Ok(()));
+    }
+
+    #[test]
+    fn reset_denied() {
+        let mut authenticator = Authenticator::new(Memory::default(), || false);
+        set_pin(&mut authenticator, PIN).unwrap();
+        assert_eq!(authenticator.reset(), Err(reset::Error::OperationDenied));
+        assert!(authenticator.storage().state().pin_hash.is_some());
+    }
+
+    #[test]
+    fn selection() {
+        assert_eq!(authenticator().selection(), Ok(()));
+        let mut authenticator = Authenticator::new(Memory::default(), || false);
+        assert_eq!(
+            authenticator.selection(),
+            Err(selection::Error::OperationDenied)
+        );
+    }
+
+    #[test]
+    fn bio_enrollment_unsupported() {
+        let request = bio_enrollment::Request::GetFingerprintSensorInfo;
+        assert_eq!(
+            authenticator().bio_enrollment(request),
+            Err(bio_enrollment::Error::Other(StatusCode::InvalidCommand))
+        );
+    }
+
+    #[test]
+    fn cbor() {
+        let mut authenticator = authenticator();
+        // authenticatorSelection succeeds with only a status byte
+        assert_eq!(authenticator.cbor(&[0x0B]), [0x00]);
+        let response = authenticator.cbor(&[0x04]);
+        assert_eq!(response[0], 0x00);
+        assert!(response.len() > 1);
+    }
+
+    #[test]
+    fn cbor_invalid_requests() {
+        let mut authenticator = authenticator();
+        assert_eq!(authenticator.cbor(&[]), [0x03]);
+        assert_eq!(authenticator.cbor(&[0x55]), [0x01]);
+        // Commands with parameters require them
+        assert_eq!(authenticator.cbor(&[0x06]), [0x14]);
+        assert_eq!(authenticator.cbor(&[0x06, 0xFF]), [0x12]);
+    }
+
+    #[test]
+    fn cbor_error_status() {
+        let mut authenticator = Authenticator::new(Memory::default(), || false);
+        assert_eq!(authenticator.cbor(&[0x0B]), [0x27]);
+        assert_eq!(authenticator.cbor(&[0x08]), [0x30]);
+
+        let mut authenticator =
+            Intercepted::new(authenticator).on_client_pin(|_, _| Err(client_pin::Error::PinNotSet));
+        let get_key_agreement = client_pin::Request::GetKeyAgreement {
+            version: Version::Two,
+        };
+        assert_eq!(
+            dispatch(
+                &mut authenticator,
+                &request(Command::AuthenticatorClientPin, &get_key_agreement)
+            ),
+            [0x35]
+        );
+    }
+
+    #[test]
+    fn cbor_client_pin() {
+        let response = authenticator().cbor(&request(
+            Command::AuthenticatorClientPin,
+            &client_pin::Request::GetPinRetries,
+        ));
+        // {3: 8, 4: false}
+        assert_eq!(response, [0x00, 0xA2, 0x03, 0x08, 0x04, 0xF4]);
+    }
+
+    #[test]
+    fn cbor_empty_responses() {
+        let reset = Cell::new(false);
+        let configured = RefCell::new(None);
+        let mut authenticator = Intercepted::new(authenticator())
+            .on_reset(|authenticator| {
+                reset.set(true);
+                authenticator.reset()
+            })
+            .on_authenticator_config(|authenticator, request| {
+                if let config::Request::ToggleAlwaysUserVerification {
+                    pin_uv_auth_protocol,
+                    pin_uv_auth_param,
+                } = request
+                {
+                    configured.replace(Some(config::Request::ToggleAlwaysUserVerification {
+                        pin_uv_auth_protocol,
+                        pin_uv_auth_param,
+                    }));
+                }
+                authenticator.authenticator_config(request)
+            });
+        assert_eq!(dispatch(&mut authenticator, &[0x07]), [0x00]);
+        assert!(reset.get());
+
+        let toggle = config::Request::ToggleAlwaysUserVerification {
+            pin_uv_auth_protocol: None,
+            pin_uv_auth_param: None,
+        };
+        let response = dispatch(
+            &mut authenticator,
+            &request(Command::AuthenticatorConfig, &toggle),
+        );
+        assert_eq!(response, [0x00]);
+        assert_eq!(*configured.borrow(), Some(toggle));
+    }
+
+    #[test]
+    fn cbor_credential_management() {
+        let metadata = management::Request::get_credentials_metadata(Version::Two, &[0x42; 32]);
+        let credential_id = credential::public_key::Descriptor {
+            credential_type: credential::Type::PublicKey,
+            id: vec![0x01; 16],
+            transports: None,
+        };
+        let delete =
+            management::Request::delete_credential(&credential_id, Version::Two, &[0x42; 32]);
+
+        for command in [
+            Command::AuthenticatorCredentialManagement,
+            Command::PrototypeAuthenticatorCredentialManagement,
+        ] {
+            let deleted = RefCell::new(None);
+            let mut authenticator =
+                Intercepted::new(authenticator()).on_credential_management(|_, request| {
+                    match request {
+                        management::Request::GetCredentialsMetadata { .. } => {
+                            Ok(management::Response::GetCredentialsMetadata {
+                                existing_resident_credentials_count: 1,
+                                max_possible_remaining_resident_credentials_count: 24,
+                            })
+                        }
+                        management::Request::DeleteCredential { credential_id, .. } => {
+                            deleted.replace(Some(credential_id.id.clone()));
+                            Ok(management::Response::DeleteCredential)
+                        }
+                        _ => unreachable!(),
+                    }
+                });
+            let response = dispatch(&mut authenticator, &request(command, &metadata));
+            assert_eq!(response[0], 0x00);
+            let response: management::Response = ciborium::de::from_reader(&response[1..]).unwrap();
+            assert_eq!(
+                response,
+                management::Response::GetCredentialsMetadata {
+                    existing_resident_credentials_count: 1,
+                    max_possible_remaining_resident_credentials_count: 24,
+                }
+            );
+
+            // `deleteCredential` returns no data
+            let response = dispatch(&mut authenticator, &request(command, &delete));
+            assert_eq!(response, [0x00]);
+            assert_eq!(*deleted.borrow(), Some(vec![0x01; 16]));
+        }
+    }
+}
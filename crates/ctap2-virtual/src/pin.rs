@@ -0,0 +1,117 @@
+//! The `pinUvAuthToken` state shared by PIN/UV auth protocols one and two.
+
+use ctap2_proto::prelude::client_pin::{
+    auth_protocol::{self, Version},
+    Permission,
+};
+use rand::{rngs::OsRng, RngCore};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::BTreeSet,
+    time::{Duration, Instant},
+};
+
+pub(crate) use auth_protocol::{KeyAgreementKey, SharedSecret};
+
+/// The maximum time a `pinUvAuthToken` can be used for after it is issued.
+const MAX_USAGE_TIME: Duration = Duration::from_secs(600);
+
+/// The authenticator's single `pinUvAuthToken`, shared by both protocols.
+pub(crate) struct PinUvAuthToken {
+    token: [u8; 32],
+    permissions: BTreeSet<Permission>,
+    relying_party_id: Option<String>,
+    /// When the token was last issued, if it is in use.
+    issued: Option<Instant>,
+}
+
+impl PinUvAuthToken {
+    pub(crate) fn new() -> Self {
+        let mut token = Self {
+            token: [0; 32],
+            permissions: BTreeSet::new(),
+            relying_party_id: None,
+            issued: None,
+        };
+        token.reset();
+        token
+    }
+
+    /// Generates a fresh token, invalidating the previous one.
+    pub(crate) fn reset(&mut self) {
+        OsRng.fill_bytes(&mut self.token);
+        self.stop_using();
+    }
+
+    pub(crate) fn begin_using(
+        &mut self,
+        permissions: BTreeSet<Permission>,
+        relying_party_id: Option<String>,
+    ) {
+        self.permissions = permissions;
+        self.relying_party_id = relying_party_id;
+        self.issued = Some(Instant::now());
+    }
+
+    pub(crate) fn stop_using(&mut self) {
+        self.permissions.clear();
+        self.relying_party_id = None;
+        self.issued = None;
+    }
+
+    pub(crate) fn as_bytes(&self) -> &[u8; 32] {
+        &self.token
+    }
+
+    pub(crate) fn relying_party_id(&self) -> Option<&str> {
+        self.relying_party_id.as_deref()
+    }
+
+    fn in_use(&mut self) -> bool {
+        match self.issued {
+            Some(issued) if issued.elapsed() < MAX_USAGE_TIME => true,
+            Some(_) => {
+                self.stop_using();
+                false
+            }
+            None => false,
+        }
+    }
+
+    /// Verifies a `pinUvAuthParam` computed with the token, and that the token
+    /// grants the permission.
+    ///
+    /// If a relying party ID is given, the token must either be bound to the
+    /// same ID or not bound at all, in which case it becomes bound to it.
+    pub(crate) fn verify(
+        &mut self,
+        version: Version,
+        message: &[u8],
+        signature: &[u8],
+        permission: Permission,
+        relying_party_id: Option<&str>,
+    ) -> bool {
+        if !self.in_use()
+            || auth_protocol::verify(version, &self.token, message, signature).is_err()
+            || !self.permissions.contains(&permission)
+        {
+            return false;
+        }
+
+        match (self.relying_party_id.as_deref(), relying_party_id) {
+            (Some(bound), Some(relying_party_id)) => bound == relying_party_id,
+            (None, Some(relying_party_id)) => {
+                self.relying_party_id = Some(relying_party_id.to_owned());
+                true
+            }
+            (_, None) => true,
+        }
+    }
+}
+
+/// `LEFT(SHA-256(pin), 16)`
+pub(crate) fn pin_hash(pin: &[u8]) -> [u8; 16] {
+    let mut hash = [0; 16];
+    hash.copy_from_slice(&Sha256::digest(pin)[..16]);
+    hash
+}
@@ -0,0 +1,204 @@
+//! Persistent state of the authenticator.
+//!
+//! Everything that must survive a power cycle is kept behind the [`Storage`]
+//! trait, so that the authenticator can run purely in [`Memory`] or be backed
+//! by e.g. a file.
+
+use crate::key::PrivateKey;
+use ctap2_proto::{
+    extensions::cred_protect,
+    prelude::{credential::public_key, large_blobs},
+};
+use std::fmt::Display;
+
+/// The number of PIN attempts allowed before the PIN is blocked.
+pub const MAX_PIN_RETRIES: u8 = 8;
+
+/// The minimum PIN length, in Unicode code points, enforced until the
+/// authenticator is configured otherwise.
+pub const DEFAULT_MIN_PIN_LENGTH: usize = 4;
+
+/// A credential generated by the authenticator.
+#[derive(Clone)]
+pub struct Credential {
+    pub id: Vec<u8>,
+    pub relying_party: public_key::RelyingPartyEntity,
+    pub user: public_key::UserEntity,
+    pub private_key: PrivateKey,
+    /// Whether the credential can be used without the platform providing its
+    /// ID, and is listed by credential management.
+    pub discoverable: bool,
+    pub signature_counter: u32,
+    pub credential_protection_policy: cred_protect::Policy,
+    pub large_blob_key: Option<[u8; 32]>,
+}
+
+/// Authenticator state, other than credentials, that persists across power
+/// cycles.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct State {
+    /// `LEFT(SHA-256(pin), 16)`, if a PIN is set.
+    pub pin_hash: Option<[u8; 16]>,
+    pub pin_retries: u8,
+    pub min_pin_length: usize,
+    pub force_pin_change: bool,
+    pub always_uv: bool,
+    pub enterprise_attestation: bool,
+}
+
+impl Default for State {
+    fn default() -> Self {
+        Self {
+            pin_hash: None,
+            pin_retries: MAX_PIN_RETRIES,
+            min_pin_length: DEFAULT_MIN_PIN_LENGTH,
+            force_pin_change: false,
+            always_uv: false,
+            enterprise_attestation: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// There is no space left for another discoverable credential.
+    Full,
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Full => write!(f, "Storage full"),
+        }
+    }
+}
+
+/// A storage backend for the authenticator's persistent state.
+pub trait Storage {
+    /// The maximum number of discoverable credentials that can be stored.
+    fn capacity(&self) -> usize;
+
+    /// All stored credentials, in the order they were created.
+    fn credentials(&self) -> Vec<Credential>;
+
+    fn credential(&self, id: &[u8]) -> Option<Credential> {
+        self.credentials()
+            .into_iter()
+            .find(|credential| credential.id == id)
+    }
+
+    /// Stores a new credential, or replaces the credential with the same ID.
+    fn store(&mut self, credential: Credential) -> Result<(), Error>;
+
+    /// Deletes the credential, returning whether it existed.
+    fn delete(&mut self, id: &[u8]) -> bool;
+
+    fn state(&self) -> State;
+
+    fn set_state(&mut self, state: State);
+
+    /// The serialized large-blob array, including its checksum.
+    fn large_blob_array(&self) -> Vec<u8>;
+
+    fn set_large_blob_array(&mut self, array: Vec<u8>);
+
+    /// Deletes all credentials and restores the default state and large-blob
+    /// array.
+    fn clear(&mut self);
+}
+
+/// Volatile storage, lost when the authenticator is dropped.
+#[derive(Clone)]
+pub struct Memory {
+    capacity: usize,
+    credentials: Vec<Credential>,
+    state: State,
+    large_blob_array: Vec<u8>,
+}
+
+impl Memory {
+    /// Creates an empty store with room for `capacity` discoverable
+    /// credentials. Non-discoverable credentials are not limited.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            credentials: Vec::new(),
+            state: State::default(),
+            large_blob_array: large_blobs::array::EMPTY.to_vec(),
+        }
+    }
+}
+
+impl Default for Memory {
+    fn default() -> Self {
+        Self::new(25)
+    }
+}
+
+impl Storage for Memory {
+    fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    fn credentials(&self) -> Vec<Credential> {
+        self.credentials.clone()
+    }
+
+    fn credential(&self, id: &[u8]) -> Option<Credential> {
+        self.credentials
+            .iter()
+            .find(|credential| credential.id == id)
+            .cloned()
+    }
+
+    fn store(&mut self, credential: Credential) -> Result<(), Error> {
+        if let Some(existing) = self
+            .credentials
+            .iter_mut()
+            .find(|existing| existing.id == credential.id)
+        {
+            *existing = credential;
+            return Ok(());
+        }
+
+        let discoverable = self
+            .credentials
+            .iter()
+            .filter(|credential| credential.discoverable)
+            .count();
+        if credential.discoverable && discoverable >= self.capacity {
+            return Err(Error::Full);
+        }
+
+        self.credentials.push(credential);
+        Ok(())
+    }
+
+    fn delete(&mut self, id: &[u8]) -> bool {
+        let count = self.credentials.len();
+        self.credentials.retain(|credential| credential.id != id);
+        self.credentials.len() != count
+    }
+
+    fn state(&self) -> State {
+        self.state.clone()
+    }
+
+    fn set_state(&mut self, state: State) {
+        self.state = state;
+    }
+
+    fn large_blob_array(&self) -> Vec<u8> {
+        self.large_blob_array.clone()
+    }
+
+    fn set_large_blob_array(&mut self, array: Vec<u8>) {
+        self.large_blob_array = array;
+    }
+
+    fn clear(&mut self) {
+        self.credentials.clear();
+        self.state = State::default();
+        self.large_blob_array = large_blobs::array::EMPTY.to_vec();
+    }
+}
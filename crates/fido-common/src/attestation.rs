@@ -1,11 +1,15 @@
 #[cfg(feature = "serde")]
 use crate::credential::public_key::algorithm;
+use crate::credential::public_key::Key;
 #[cfg(feature = "serde")]
 use serde::{Deserialize, Serialize};
 #[cfg(feature = "serde")]
-use serde_with::{serde_as, Bytes};
+use serde_with::{serde_as, skip_serializing_none, Bytes};
 
+pub mod certificate;
 pub mod enterprise;
+#[cfg(all(feature = "serde", feature = "crypto"))]
+pub mod verification;
 
 /// > Attestation statement formats are identified by a string, called an
 /// > attestation statement format identifier, chosen by the author of the
@@ -76,31 +80,183 @@ pub enum FormatIdentifier {
     None,
 }
 
+/// > Attestation statements conveyed in attestations come in different formats
+/// > and are independent of the authenticator data.
+///
+/// Every variant is the `attStmt` of the attestation statement format of the
+/// same name. The `none` format has an empty statement, which is represented
+/// by the absence of a statement.
+///
+/// It is serialized as a map with the format identifier as the only key and
+/// the `attStmt` as its value. See [`Statement::from_cbor_value`] and
+/// [`Statement::to_cbor_value`] for the `attStmt` alone.
 #[cfg_eval]
 #[derive(Clone, Debug, PartialEq, Eq)]
 #[cfg_attr(
     feature = "serde",
     serde_as,
-    derive(Serialize, Deserialize),
-    // TODO: Workaround until serde can use integer keys as tag, since "fmt" is CBOR key 0x01.
-    serde(untagged) 
+    skip_serializing_none,
+    derive(Serialize, Deserialize)
 )]
 pub enum Statement {
+    /// > The "packed" attestation statement format is a WebAuthn-optimized
+    /// > format for attestation.
+    ///
+    /// The deprecated ECDAA attestation, identified by `ecdaaKeyId`, is not
+    /// supported.
     #[cfg_attr(feature = "serde", serde(rename = "packed"))]
     Packed {
+        /// > A COSEAlgorithmIdentifier containing the identifier of the
+        /// > algorithm used to generate the attestation signature.
         #[cfg_attr(feature = "serde", serde(rename = "alg", with = "algorithm"))]
         algorithm: coset::iana::Algorithm,
+        /// > A byte string containing the attestation signature.
+        #[cfg_attr(feature = "serde", serde_as(as = "Bytes"), serde(rename = "sig"))]
+        signature: Vec<u8>,
+        /// > The elements of this array contain attestnCert and its
+        /// > certificate chain (if any), each encoded in X.509 format. The
+        /// > attestation certificate attestnCert MUST be the first element in
+        /// > the array.
+        ///
+        /// Absent for self attestation.
+        #[cfg_attr(feature = "serde", serde(rename = "x5c", default))]
+        attestation_certificate_chain: Option<certificate::Chain>,
+    },
+    /// > The TPM attestation statement format returns an attestation statement
+    /// > in the same format as the packed attestation statement format,
+    /// > although the rawData and signature fields are computed differently.
+    #[cfg_attr(feature = "serde", serde(rename = "tpm"))]
+    Tpm {
+        /// > The version of the TPM specification to which the signature
+        /// > conforms.
+        #[cfg_attr(feature = "serde", serde(rename = "ver"))]
+        version: String,
+        /// > A COSEAlgorithmIdentifier containing the identifier of the
+        /// > algorithm used to generate the attestation signature.
+        #[cfg_attr(feature = "serde", serde(rename = "alg", with = "algorithm"))]
+        algorithm: coset::iana::Algorithm,
+        /// > aikCert followed by its certificate chain, in X.509 encoding.
+        #[cfg_attr(feature = "serde", serde(rename = "x5c"))]
+        attestation_certificate_chain: certificate::Chain,
+        /// > The attestation signature, in the form of a TPMT_SIGNATURE
+        /// > structure as specified in [TPMv2-Part2] section 11.3.4.
+        #[cfg_attr(feature = "serde", serde_as(as = "Bytes"), serde(rename = "sig"))]
+        signature: Vec<u8>,
+        /// > The TPMS_ATTEST structure over which the above signature was
+        /// > computed, as specified in [TPMv2-Part2] section 10.12.8.
+        #[cfg_attr(feature = "serde", serde_as(as = "Bytes"), serde(rename = "certInfo"))]
+        certify_info: Vec<u8>,
+        /// > The TPMT_PUBLIC structure (see [TPMv2-Part2] section 12.2.4) used
+        /// > by the TPM to represent the credential public key.
+        #[cfg_attr(feature = "serde", serde_as(as = "Bytes"), serde(rename = "pubArea"))]
+        public_area: Vec<u8>,
+    },
+    /// > When the authenticator in question is a platform authenticator on
+    /// > the Android "N" or later platform, the attestation statement is based
+    /// > on the Android key attestation.
+    #[cfg_attr(feature = "serde", serde(rename = "android-key"))]
+    AndroidKey {
+        /// > A COSEAlgorithmIdentifier containing the identifier of the
+        /// > algorithm used to generate the attestation signature.
+        #[cfg_attr(feature = "serde", serde(rename = "alg", with = "algorithm"))]
+        algorithm: coset::iana::Algorithm,
+        /// > A byte string containing the attestation signature.
+        #[cfg_attr(feature = "serde", serde_as(as = "Bytes"), serde(rename = "sig"))]
+        signature: Vec<u8>,
+        /// > The elements of this array contain credCert and its certificate
+        /// > chain (if any), each encoded in X.509 format.
+        #[cfg_attr(feature = "serde", serde(rename = "x5c"))]
+        attestation_certificate_chain: certificate::Chain,
+    },
+    /// > When the authenticator is a platform authenticator on certain
+    /// > Android platforms, the attestation statement may be based on the
+    /// > SafetyNet API.
+    #[cfg_attr(feature = "serde", serde(rename = "android-safetynet"))]
+    AndroidSafetyNet {
+        /// > The version number of Google Play Services responsible for
+        /// > providing the SafetyNet API.
+        #[cfg_attr(feature = "serde", serde(rename = "ver"))]
+        version: String,
+        /// > The UTF-8 encoded result of the getJwsResult() call of the
+        /// > SafetyNet API.
+        #[cfg_attr(feature = "serde", serde_as(as = "Bytes"), serde(rename = "response"))]
+        response: Vec<u8>,
+    },
+    /// > This attestation statement format is used with FIDO U2F
+    /// > authenticators using the formats defined in
+    /// > [FIDO-U2F-Message-Formats].
+    #[cfg_attr(feature = "serde", serde(rename = "fido-u2f"))]
+    FidoU2f {
+        /// > A single element array containing the attestation certificate in
+        /// > X.509 format.
+        #[cfg_attr(feature = "serde", serde(rename = "x5c"))]
+        attestation_certificate_chain: certificate::Chain,
+        /// > The attestation signature.
         #[cfg_attr(feature = "serde", serde_as(as = "Bytes"), serde(rename = "sig"))]
         signature: Vec<u8>,
-        #[cfg_attr(feature = "serde", serde_as(as = "Vec<Bytes>"), serde(rename = "x5c"))]
-        attestation_certificate_chain: Vec<Vec<u8>>, // TODO: Parse X.509 certs
     },
-    Unregistered {
-        identifier: String,
-        data: Vec<u8>,
+    /// > This attestation statement format is exclusively used by Apple for
+    /// > certain types of Apple devices (Apple Anonymous Attestation).
+    #[cfg_attr(feature = "serde", serde(rename = "apple"))]
+    Apple {
+        /// > credCert followed by its certificate chain, each encoded in X.509
+        /// > format.
+        #[cfg_attr(feature = "serde", serde(rename = "x5c"))]
+        attestation_certificate_chain: certificate::Chain,
     },
 }
 
+impl Statement {
+    /// The identifier of the statement's format.
+    pub fn format(&self) -> FormatIdentifier {
+        match self {
+            Statement::Packed { .. } => FormatIdentifier::Packed,
+            Statement::Tpm { .. } => FormatIdentifier::Tpm,
+            Statement::AndroidKey { .. } => FormatIdentifier::AndroidKey,
+            Statement::AndroidSafetyNet { .. } => FormatIdentifier::AndroidSafetyNet,
+            Statement::FidoU2f { .. } => FormatIdentifier::FidoU2f,
+            Statement::Apple { .. } => FormatIdentifier::Apple,
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl Statement {
+    /// Decodes the `attStmt` of an attestation in the given format. The
+    /// statement of the `none` format must be empty, and decodes as `None`.
+    pub fn from_cbor_value(
+        format: FormatIdentifier,
+        statement: ciborium::value::Value,
+    ) -> Result<Option<Self>, ciborium::value::Error> {
+        use ciborium::value::{Error, Value};
+
+        if format == FormatIdentifier::None {
+            return match statement {
+                Value::Map(map) if map.is_empty() => Ok(None),
+                _ => Err(Error::Custom(
+                    "the none attestation statement must be empty".to_owned(),
+                )),
+            };
+        }
+        // The format identifier tags the statement's variant
+        let format = Value::serialized(&format)?;
+        Value::Map(vec![(format, statement)])
+            .deserialized()
+            .map(Some)
+    }
+
+    /// Encodes the statement as the `attStmt` of an attestation, omitting the
+    /// format identifier.
+    pub fn to_cbor_value(&self) -> Result<ciborium::value::Value, ciborium::value::Error> {
+        use ciborium::value::{Error, Value};
+
+        match Value::serialized(self)? {
+            Value::Map(mut map) if map.len() == 1 => Ok(map.remove(0).1),
+            _ => Err(Error::Custom("expected a tagged statement".to_owned())),
+        }
+    }
+}
+
 /// > Attested credential data is a variable-length byte array added to the
 /// > authenticator data when generating an attestation object for a given
 /// > credential.
@@ -111,15 +267,40 @@ pub struct CredentialData {
     /// The ID of the credential.
     pub id: Vec<u8>,
     /// The public key of the credential.
-    pub public_key: coset::CoseKey,
+    pub public_key: Key,
+}
+
+impl CredentialData {
+    /// Encodes the attested credential data, as included in the authenticator
+    /// data.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, coset::CoseError> {
+        use coset::CborSerializable;
+
+        // > Byte length L of credentialId, 16-bit unsigned big-endian integer. Value
+        // > MUST be ≤ 1023.
+        let credential_id_length = u16::try_from(self.id.len())
+            .ok()
+            .filter(|&length| length <= 1023)
+            .ok_or(coset::CoseError::OutOfRangeIntegerValue)?;
+
+        let mut data = self.aaguid.to_vec();
+        data.extend_from_slice(&credential_id_length.to_be_bytes());
+        data.extend_from_slice(&self.id);
+        data.extend(self.public_key.clone().to_vec()?);
+        Ok(data)
+    }
 }
 
 #[cfg(feature = "serde")]
-impl TryFrom<&[u8]> for CredentialData {
-    // TODO: Custom error type?
-    type Error = coset::CoseError;
+impl CredentialData {
+    /// Decodes the attested credential data at the start of `data`, returning
+    /// it along with the number of bytes it occupies. The bytes that follow,
+    /// e.g. the extensions of the authenticator data, are left to the caller.
+    pub fn from_bytes(data: &[u8]) -> Result<(Self, usize), coset::CoseError> {
+        use coset::AsCborValue;
+
+        let length = data.len();
 
-    fn try_from(data: &[u8]) -> Result<Self, Self::Error> {
         // aaguid: 16 Bytes
         // SAFETY: Validate that data.len >= 16 for aaguid bytes
         if data.len() < 16 {
@@ -148,12 +329,53 @@ impl TryFrom<&[u8]> for CredentialData {
         }
 
         // credentialId: L (credential_id_length) Bytes
-        let credential_id: &[u8] = data.take(..credential_id_length as usize)
-            .ok_or(coset::CoseError::DecodeFailed(ciborium::de::Error::Io(
-                coset::EndOfFile,
-            )))?;
+        let credential_id: &[u8] =
+            data.take(..credential_id_length as usize)
+                .ok_or(coset::CoseError::DecodeFailed(ciborium::de::Error::Io(
+                    coset::EndOfFile,
+                )))?;
+
+        // credentialPublicKey: Variable length
+        // > The credential public key encoded in COSE_Key format... using the
+        // > CTAP2 canonical CBOR encoding form.
+        // The reader advances past the key, leaving the bytes that follow it
+        let value: ciborium::value::Value =
+            ciborium::de::from_reader(&mut data).map_err(|error| {
+                coset::CoseError::DecodeFailed(match error {
+                    ciborium::de::Error::Io(_) => ciborium::de::Error::Io(coset::EndOfFile),
+                    ciborium::de::Error::Syntax(offset) => ciborium::de::Error::Syntax(offset),
+                    ciborium::de::Error::Semantic(offset, message) => {
+                        ciborium::de::Error::Semantic(offset, message)
+                    }
+                    ciborium::de::Error::RecursionLimitExceeded => {
+                        ciborium::de::Error::RecursionLimitExceeded
+                    }
+                })
+            })?;
+        let public_key = Key::from_cbor_value(value)?;
 
-        Ok(Self { aaguid, id: credential_id.to_vec(), public_key: Default::default() })
+        let credential_data = Self {
+            aaguid,
+            id: credential_id.to_vec(),
+            public_key,
+        };
+        Ok((credential_data, length - data.len()))
+    }
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<&[u8]> for CredentialData {
+    // TODO: Custom error type?
+    type Error = coset::CoseError;
+
+    /// Decodes attested credential data that is not followed by any other
+    /// bytes.
+    fn try_from(data: &[u8]) -> Result<Self, Self::Error> {
+        let (credential_data, length) = Self::from_bytes(data)?;
+        if length != data.len() {
+            return Err(coset::CoseError::ExtraneousData);
+        }
+        Ok(credential_data)
     }
 }
 
@@ -161,9 +383,110 @@ impl TryFrom<&[u8]> for CredentialData {
 impl<'de> Deserialize<'de> for CredentialData {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
-        D: serde::Deserializer<'de> {
-            let data = Vec::<u8>::deserialize(deserializer)?;
-            // TODO: Improve error handling
-            CredentialData::try_from(data.as_slice()).map_err(serde::de::Error::custom)
+        D: serde::Deserializer<'de>,
+    {
+        let data = Vec::<u8>::deserialize(deserializer)?;
+        // TODO: Improve error handling
+        CredentialData::try_from(data.as_slice()).map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(all(test, feature = "serde"))]
+mod tests {
+    use super::*;
+    use coset::{iana, CborSerializable, CoseKeyBuilder};
+
+    fn credential_data() -> CredentialData {
+        let key = CoseKeyBuilder::new_ec2_pub_key(
+            iana::EllipticCurve::P_256,
+            vec![0x01; 32],
+            vec![0x02; 32],
+        )
+        .algorithm(iana::Algorithm::ES256)
+        .build();
+        CredentialData {
+            aaguid: [0x42; 16],
+            id: vec![0x43; 20],
+            public_key: Key(key),
+        }
+    }
+
+    fn assert_decoded(decoded: &CredentialData, expected: &CredentialData) {
+        assert_eq!(decoded.aaguid, expected.aaguid);
+        assert_eq!(decoded.id, expected.id);
+        assert_eq!(decoded.public_key.0, expected.public_key.0);
+    }
+
+    #[test]
+    fn round_trip() {
+        let credential_data = credential_data();
+        let encoded = credential_data.to_bytes().unwrap();
+        assert_eq!(encoded[..16], [0x42; 16]);
+        assert_eq!(encoded[16..18], [0x00, 20]);
+
+        let (decoded, length) = CredentialData::from_bytes(&encoded).unwrap();
+        assert_eq!(length, encoded.len());
+        assert_decoded(&decoded, &credential_data);
+        assert_decoded(
+            &CredentialData::try_from(encoded.as_slice()).unwrap(),
+            &credential_data,
+        );
+    }
+
+    #[test]
+    fn consumed_length() {
+        // The extensions of the authenticator data follow the key
+        let credential_data = credential_data();
+        let encoded = credential_data.to_bytes().unwrap();
+        let data = [encoded.as_slice(), &[0xA1, 0x61, 0x78, 0xF5]].concat();
+
+        let (decoded, length) = CredentialData::from_bytes(&data).unwrap();
+        assert_eq!(length, encoded.len());
+        assert_decoded(&decoded, &credential_data);
+        assert!(matches!(
+            CredentialData::try_from(data.as_slice()),
+            Err(coset::CoseError::ExtraneousData)
+        ));
+    }
+
+    #[test]
+    fn truncated() {
+        let encoded = credential_data().to_bytes().unwrap();
+        // Within the AAGUID, the credential ID length, the credential ID and
+        // the key
+        for length in [0, 15, 17, 18 + 19, encoded.len() - 1] {
+            assert!(
+                CredentialData::from_bytes(&encoded[..length]).is_err(),
+                "{length} bytes"
+            );
+        }
+    }
+
+    #[test]
+    fn credential_id_too_long() {
+        let mut encoded = credential_data().to_bytes().unwrap();
+        encoded[16..18].copy_from_slice(&1024u16.to_be_bytes());
+        encoded.splice(18..18, [0x00; 1004]);
+        assert!(matches!(
+            CredentialData::from_bytes(&encoded),
+            Err(coset::CoseError::UnexpectedItem(..))
+        ));
+    }
+
+    #[test]
+    fn invalid_key() {
+        let prefix = [[0x42; 16].as_slice(), &[0x00, 0x01, 0x43]].concat();
+        // Not a map, a key type of the wrong type, and invalid CBOR
+        let keys: [&[u8]; 3] = [&[0x01], &[0xA1, 0x01, 0x40], &[0xFF]];
+        for key in keys {
+            let data = [prefix.as_slice(), key].concat();
+            assert!(CredentialData::from_bytes(&data).is_err(), "{key:02X?}");
+        }
+
+        let key = CoseKeyBuilder::new_okp_key().build().to_vec().unwrap();
+        let data = [prefix.as_slice(), &key].concat();
+        let (decoded, length) = CredentialData::from_bytes(&data).unwrap();
+        assert_eq!(length, data.len());
+        assert_eq!(decoded.id, [0x43]);
     }
 }
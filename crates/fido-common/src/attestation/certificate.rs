@@ -0,0 +1,645 @@
+//! The X.509 certificates of attestation statements.
+
+use crate::authenticator::Transport;
+use std::time::SystemTime;
+use x509_cert::{
+    der::{
+        asn1::{BitString, ObjectIdentifier, OctetString, PrintableStringRef, Utf8StringRef},
+        Decode,
+    },
+    ext::{
+        pkix::{BasicConstraints, ExtendedKeyUsage},
+        Extension,
+    },
+    name::Name,
+    spki::SubjectPublicKeyInfoOwned,
+    time::Validity,
+    Version,
+};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// > id-fido-gen-ce-aaguid
+const AAGUID_OID: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.3.6.1.4.1.45724.1.1.4");
+/// > id-fido-u2f-ce-transports
+const TRANSPORTS_OID: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.3.6.1.4.1.45724.2.1.1");
+const BASIC_CONSTRAINTS_OID: ObjectIdentifier = ObjectIdentifier::new_unwrap("2.5.29.19");
+const EXTENDED_KEY_USAGE_OID: ObjectIdentifier = ObjectIdentifier::new_unwrap("2.5.29.37");
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// A certificate or one of its extensions is not valid DER.
+    Malformed,
+    /// A certificate chain has no certificates.
+    EmptyChain,
+    /// A certificate is not valid at the given time.
+    Expired,
+    /// A certificate is signed with an unsupported algorithm or key.
+    UnsupportedAlgorithm,
+    /// A certificate's signature does not verify with its issuer's key.
+    InvalidSignature,
+    /// A certificate issuing another is not a certificate authority.
+    NotAuthority,
+    /// The chain does not lead to one of the trust anchors.
+    Untrusted,
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Malformed => write!(f, "Certificate is malformed"),
+            Error::EmptyChain => write!(f, "Certificate chain is empty"),
+            Error::Expired => write!(f, "Certificate is not valid at the given time"),
+            Error::UnsupportedAlgorithm => {
+                write!(f, "Certificate signature algorithm is not supported")
+            }
+            Error::InvalidSignature => write!(f, "Certificate signature is invalid"),
+            Error::NotAuthority => write!(f, "Certificate issuer is not a certificate authority"),
+            Error::Untrusted => write!(f, "Certificate chain does not lead to a trust anchor"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// An X.509 certificate, kept along with its DER encoding.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Certificate {
+    der: Vec<u8>,
+    certificate: x509_cert::Certificate,
+}
+
+impl Certificate {
+    pub fn from_der(der: &[u8]) -> Result<Self, Error> {
+        let certificate = x509_cert::Certificate::from_der(der).map_err(|_| Error::Malformed)?;
+        Ok(Self {
+            der: der.to_vec(),
+            certificate,
+        })
+    }
+
+    /// The DER encoding the certificate was parsed from.
+    pub fn as_der(&self) -> &[u8] {
+        &self.der
+    }
+
+    pub fn version(&self) -> Version {
+        self.certificate.tbs_certificate.version
+    }
+
+    pub fn subject(&self) -> &Name {
+        &self.certificate.tbs_certificate.subject
+    }
+
+    pub fn issuer(&self) -> &Name {
+        &self.certificate.tbs_certificate.issuer
+    }
+
+    pub fn validity(&self) -> &Validity {
+        &self.certificate.tbs_certificate.validity
+    }
+
+    /// Whether the time is within the certificate's validity period.
+    pub fn is_valid_at(&self, time: SystemTime) -> bool {
+        let validity = self.validity();
+        validity.not_before.to_system_time() <= time && time <= validity.not_after.to_system_time()
+    }
+
+    pub fn public_key(&self) -> &SubjectPublicKeyInfoOwned {
+        &self.certificate.tbs_certificate.subject_public_key_info
+    }
+
+    fn find_extension(&self, identifier: ObjectIdentifier) -> Option<&Extension> {
+        self.certificate
+            .tbs_certificate
+            .extensions
+            .as_ref()?
+            .iter()
+            .find(|extension| extension.extn_id == identifier)
+    }
+
+    /// The DER encoded value of the extension with the given identifier.
+    pub fn extension(&self, identifier: ObjectIdentifier) -> Option<&[u8]> {
+        self.find_extension(identifier)
+            .map(|extension| extension.extn_value.as_bytes())
+    }
+
+    /// The value of the subject's attribute with the given type, if it is a
+    /// UTF-8 or printable string.
+    pub fn subject_attribute(&self, attribute_type: ObjectIdentifier) -> Option<String> {
+        self.subject()
+            .0
+            .iter()
+            .flat_map(|name| name.0.iter())
+            .find(|attribute| attribute.oid == attribute_type)
+            .and_then(|attribute| {
+                attribute
+                    .value
+                    .decode_as::<Utf8StringRef<'_>>()
+                    .map(|value| value.to_string())
+                    .or_else(|_| {
+                        attribute
+                            .value
+                            .decode_as::<PrintableStringRef<'_>>()
+                            .map(|value| value.to_string())
+                    })
+                    .ok()
+            })
+    }
+
+    /// The AAGUID of the authenticator model the certificate attests, if the
+    /// certificate has the extension.
+    ///
+    /// > If the related attestation root certificate is used for multiple
+    /// > authenticator models, the Extension OID 1.3.6.1.4.1.45724.1.1.4
+    /// > (id-fido-gen-ce-aaguid) MUST be present, containing the AAGUID as a
+    /// > 16-byte OCTET STRING. The extension MUST NOT be marked as critical.
+    pub fn aaguid(&self) -> Result<Option<[u8; 16]>, Error> {
+        let Some(extension) = self.find_extension(AAGUID_OID) else {
+            return Ok(None);
+        };
+        if extension.critical {
+            return Err(Error::Malformed);
+        }
+        let aaguid =
+            OctetString::from_der(extension.extn_value.as_bytes()).map_err(|_| Error::Malformed)?;
+        aaguid
+            .as_bytes()
+            .try_into()
+            .map(Some)
+            .map_err(|_| Error::Malformed)
+    }
+
+    /// The transports of the authenticator, if the certificate has the
+    /// `id-fido-u2f-ce-transports` extension.
+    ///
+    /// The extension does not tell Bluetooth Classic from Bluetooth Low
+    /// Energy, both are [`Transport::Ble`].
+    pub fn transports(&self) -> Result<Option<Vec<Transport>>, Error> {
+        let Some(transports) = self.extension(TRANSPORTS_OID) else {
+            return Ok(None);
+        };
+        let transports = BitString::from_der(transports).map_err(|_| Error::Malformed)?;
+        // > FIDOU2FTransports ::= BIT STRING {
+        // > bluetoothRadio(0), -- Bluetooth Classic
+        // > bluetoothLowEnergyRadio(1),
+        // > uSB(2),
+        // > nFC(3),
+        // > uSBInternal(4)
+        // > }
+        let mut supported = Vec::new();
+        for (bit, set) in transports.bits().enumerate() {
+            let transport = match bit {
+                0 | 1 => Transport::Ble,
+                2 => Transport::Usb,
+                3 => Transport::Nfc,
+                4 => Transport::Internal,
+                _ => continue,
+            };
+            if set && !supported.contains(&transport) {
+                supported.push(transport);
+            }
+        }
+        Ok(Some(supported))
+    }
+
+    /// Whether the basic constraints extension marks the certificate as that
+    /// of a certificate authority.
+    pub fn is_authority(&self) -> Result<bool, Error> {
+        let Some(basic_constraints) = self.extension(BASIC_CONSTRAINTS_OID) else {
+            return Ok(false);
+        };
+        BasicConstraints::from_der(basic_constraints)
+            .map(|basic_constraints| basic_constraints.ca)
+            .map_err(|_| Error::Malformed)
+    }
+
+    /// The purposes of the extended key usage extension, if the certificate
+    /// has it.
+    pub fn extended_key_usage(&self) -> Result<Option<Vec<ObjectIdentifier>>, Error> {
+        self.extension(EXTENDED_KEY_USAGE_OID)
+            .map(|usage| {
+                ExtendedKeyUsage::from_der(usage)
+                    .map(|usage| usage.0)
+                    .map_err(|_| Error::Malformed)
+            })
+            .transpose()
+    }
+
+    /// Verifies the certificate is issued by the given certificate, i.e. has
+    /// its subject as issuer and is signed by its key.
+    #[cfg(feature = "crypto")]
+    pub fn verify_issued_by(&self, issuer: &Certificate) -> Result<(), Error> {
+        use crypto::*;
+        use sha2::{Sha256, Sha384, Sha512};
+        use x509_cert::der::Encode;
+
+        if self.issuer() != issuer.subject() {
+            return Err(Error::Untrusted);
+        }
+        let message = self
+            .certificate
+            .tbs_certificate
+            .to_der()
+            .map_err(|_| Error::Malformed)?;
+        let signature = self
+            .certificate
+            .signature
+            .as_bytes()
+            .ok_or(Error::Malformed)?;
+        let public_key = issuer.public_key().to_der().map_err(|_| Error::Malformed)?;
+
+        let algorithm = self.certificate.signature_algorithm.oid;
+        if algorithm == ECDSA_WITH_SHA256 {
+            verify_ecdsa::<Sha256>(&public_key, &message, signature)
+        } else if algorithm == ECDSA_WITH_SHA384 {
+            verify_ecdsa::<Sha384>(&public_key, &message, signature)
+        } else if algorithm == ED25519 {
+            verify_ed25519(&public_key, &message, signature)
+        } else if algorithm == SHA256_WITH_RSA_ENCRYPTION {
+            verify_rsa::<Sha256>(&public_key, &message, signature)
+        } else if algorithm == SHA384_WITH_RSA_ENCRYPTION {
+            verify_rsa::<Sha384>(&public_key, &message, signature)
+        } else if algorithm == SHA512_WITH_RSA_ENCRYPTION {
+            verify_rsa::<Sha512>(&public_key, &message, signature)
+        } else {
+            Err(Error::UnsupportedAlgorithm)
+        }
+    }
+}
+
+impl TryFrom<&[u8]> for Certificate {
+    type Error = Error;
+
+    fn try_from(der: &[u8]) -> Result<Self, Self::Error> {
+        Self::from_der(der)
+    }
+}
+
+impl AsRef<[u8]> for Certificate {
+    fn as_ref(&self) -> &[u8] {
+        self.as_der()
+    }
+}
+
+#[cfg(feature = "serde")]
+impl Serialize for Certificate {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.serialize_bytes(&self.der)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> Deserialize<'de> for Certificate {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        use serde_with::DeserializeAs;
+
+        let der: Vec<u8> = serde_with::Bytes::deserialize_as(deserializer)?;
+        Self::from_der(&der).map_err(serde::de::Error::custom)
+    }
+}
+
+/// A certificate followed by the certificates of its issuers, e.g. the `x5c`
+/// of an attestation statement. It has at least one certificate.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Chain(Vec<Certificate>);
+
+impl Chain {
+    pub fn new(certificates: Vec<Certificate>) -> Result<Self, Error> {
+        if certificates.is_empty() {
+            return Err(Error::EmptyChain);
+        }
+        Ok(Self(certificates))
+    }
+
+    /// The first certificate of the chain, e.g. the attestation certificate.
+    pub fn leaf(&self) -> &Certificate {
+        &self.0[0]
+    }
+
+    pub fn certificates(&self) -> &[Certificate] {
+        &self.0
+    }
+
+    /// Validates that the certificates are valid at the given time and each
+    /// is issued by the next, up to one of the trust anchors.
+    ///
+    /// The chain may end with the trust anchor itself, or with a certificate
+    /// issued by it.
+    #[cfg(feature = "crypto")]
+    pub fn validate(&self, trust_anchors: &[Certificate], time: SystemTime) -> Result<(), Error> {
+        for (index, certificate) in self.0.iter().enumerate() {
+            if trust_anchors.contains(certificate) {
+                return Ok(());
+            }
+            if !certificate.is_valid_at(time) {
+                return Err(Error::Expired);
+            }
+            match self.0.get(index + 1) {
+                Some(issuer) => {
+                    if !issuer.is_authority()? {
+                        return Err(Error::NotAuthority);
+                    }
+                    certificate.verify_issued_by(issuer)?;
+                }
+                None => {
+                    let trusted = trust_anchors
+                        .iter()
+                        .any(|anchor| certificate.verify_issued_by(anchor).is_ok());
+                    return if trusted {
+                        Ok(())
+                    } else {
+                        Err(Error::Untrusted)
+                    };
+                }
+            }
+        }
+        Err(Error::Untrusted)
+    }
+}
+
+impl TryFrom<Vec<Certificate>> for Chain {
+    type Error = Error;
+
+    fn try_from(certificates: Vec<Certificate>) -> Result<Self, Self::Error> {
+        Self::new(certificates)
+    }
+}
+
+impl From<Chain> for Vec<Certificate> {
+    fn from(chain: Chain) -> Self {
+        chain.0
+    }
+}
+
+#[cfg(feature = "serde")]
+impl Serialize for Chain {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        self.0.serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> Deserialize<'de> for Chain {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let certificates = Vec::<Certificate>::deserialize(deserializer)?;
+        Self::new(certificates).map_err(serde::de::Error::custom)
+    }
+}
+
+/// Verifying the signatures of certificates.
+#[cfg(feature = "crypto")]
+mod crypto {
+    use super::Error;
+    use sha2::Digest;
+    use x509_cert::der::{asn1::ObjectIdentifier, oid::AssociatedOid};
+
+    pub(super) const ECDSA_WITH_SHA256: ObjectIdentifier =
+        ObjectIdentifier::new_unwrap("1.2.840.10045.4.3.2");
+    pub(super) const ECDSA_WITH_SHA384: ObjectIdentifier =
+        ObjectIdentifier::new_unwrap("1.2.840.10045.4.3.3");
+    pub(super) const ED25519: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.3.101.112");
+    pub(super) const SHA256_WITH_RSA_ENCRYPTION: ObjectIdentifier =
+        ObjectIdentifier::new_unwrap("1.2.840.113549.1.1.11");
+    pub(super) const SHA384_WITH_RSA_ENCRYPTION: ObjectIdentifier =
+        ObjectIdentifier::new_unwrap("1.2.840.113549.1.1.12");
+    pub(super) const SHA512_WITH_RSA_ENCRYPTION: ObjectIdentifier =
+        ObjectIdentifier::new_unwrap("1.2.840.113549.1.1.13");
+
+    /// Verifies an ECDSA signature over the digest of the message, with either
+    /// a P-256 or a P-384 key. A digest longer than the curve's order is
+    /// truncated, as ECDSA requires.
+    pub(super) fn verify_ecdsa<D>(
+        public_key: &[u8],
+        message: &[u8],
+        signature: &[u8],
+    ) -> Result<(), Error>
+    where
+        D: Digest,
+    {
+        use p256::{ecdsa::signature::hazmat::PrehashVerifier, pkcs8::DecodePublicKey};
+
+        let digest = D::digest(message);
+        let verified = if let Ok(key) = p256::ecdsa::VerifyingKey::from_public_key_der(public_key) {
+            let signature =
+                p256::ecdsa::Signature::from_der(signature).map_err(|_| Error::InvalidSignature)?;
+            key.verify_prehash(&digest, &signature)
+        } else if let Ok(key) = p384::ecdsa::VerifyingKey::from_public_key_der(public_key) {
+            let signature =
+                p384::ecdsa::Signature::from_der(signature).map_err(|_| Error::InvalidSignature)?;
+            key.verify_prehash(&digest, &signature)
+        } else {
+            return Err(Error::UnsupportedAlgorithm);
+        };
+        verified.map_err(|_| Error::InvalidSignature)
+    }
+
+    pub(super) fn verify_ed25519(
+        public_key: &[u8],
+        message: &[u8],
+        signature: &[u8],
+    ) -> Result<(), Error> {
+        use ed25519_dalek::pkcs8::DecodePublicKey;
+
+        let key = ed25519_dalek::VerifyingKey::from_public_key_der(public_key)
+            .map_err(|_| Error::UnsupportedAlgorithm)?;
+        let signature =
+            ed25519_dalek::Signature::from_slice(signature).map_err(|_| Error::InvalidSignature)?;
+        key.verify_strict(message, &signature)
+            .map_err(|_| Error::InvalidSignature)
+    }
+
+    pub(super) fn verify_rsa<D>(
+        public_key: &[u8],
+        message: &[u8],
+        signature: &[u8],
+    ) -> Result<(), Error>
+    where
+        D: Digest + AssociatedOid,
+    {
+        use rsa::{pkcs8::DecodePublicKey, signature::Verifier};
+
+        let key = rsa::RsaPublicKey::from_public_key_der(public_key)
+            .map_err(|_| Error::UnsupportedAlgorithm)?;
+        let signature =
+            rsa::pkcs1v15::Signature::try_from(signature).map_err(|_| Error::InvalidSignature)?;
+        rsa::pkcs1v15::VerifyingKey::<D>::new(key)
+            .verify(message, &signature)
+            .map_err(|_| Error::InvalidSignature)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "crypto")]
+    use std::time::Duration;
+
+    // The attestation certificate of an Apple anonymous attestation, its
+    // intermediate and Apple's WebAuthn root. The root and intermediate have
+    // P-384 keys and sign with ecdsa-with-SHA384, the intermediate signs the
+    // P-256 leaf with ecdsa-with-SHA256.
+    const APPLE_LEAF: &[u8] = include_bytes!("testdata/apple-attestation.der");
+    const APPLE_INTERMEDIATE: &[u8] = include_bytes!("testdata/apple-webauthn-ca-1.der");
+    const APPLE_ROOT: &[u8] = include_bytes!("testdata/apple-webauthn-root-ca.der");
+    // A P-256 root, a leaf it signs with ecdsa-with-SHA384 and that has the
+    // AAGUID and transports extensions, and a leaf issued through an
+    // intermediate that is not a certificate authority. All are valid from
+    // 2020 to 2050.
+    #[cfg(feature = "crypto")]
+    const ROOT: &[u8] = include_bytes!("testdata/root.der");
+    const LEAF: &[u8] = include_bytes!("testdata/leaf.der");
+    const NOT_CA: &[u8] = include_bytes!("testdata/not-ca.der");
+    #[cfg(feature = "crypto")]
+    const LEAF_OF_NOT_CA: &[u8] = include_bytes!("testdata/leaf-of-not-ca.der");
+    const CRITICAL_AAGUID: &[u8] = include_bytes!("testdata/critical-aaguid.der");
+    const SHORT_AAGUID: &[u8] = include_bytes!("testdata/short-aaguid.der");
+
+    const AAGUID: [u8; 16] = [
+        0xF8, 0xA0, 0x11, 0xF3, 0x8C, 0x0A, 0x4D, 0x15, 0x80, 0x06, 0x17, 0x11, 0x1F, 0x9E, 0xDC,
+        0x7D,
+    ];
+
+    fn certificate(der: &[u8]) -> Certificate {
+        Certificate::from_der(der).unwrap()
+    }
+
+    #[cfg(feature = "crypto")]
+    fn chain(certificates: &[&[u8]]) -> Chain {
+        Chain::new(certificates.iter().map(|der| certificate(der)).collect()).unwrap()
+    }
+
+    /// 2020-12-09, while the Apple attestation certificate is valid.
+    #[cfg(feature = "crypto")]
+    fn apple_time() -> SystemTime {
+        SystemTime::UNIX_EPOCH + Duration::from_secs(1_607_472_000)
+    }
+
+    #[test]
+    fn aaguid() {
+        assert_eq!(certificate(LEAF).aaguid(), Ok(Some(AAGUID)));
+        assert_eq!(certificate(APPLE_LEAF).aaguid(), Ok(None));
+        assert_eq!(certificate(CRITICAL_AAGUID).aaguid(), Err(Error::Malformed));
+        assert_eq!(certificate(SHORT_AAGUID).aaguid(), Err(Error::Malformed));
+    }
+
+    #[test]
+    fn transports() {
+        assert_eq!(
+            certificate(LEAF).transports(),
+            Ok(Some(vec![Transport::Usb, Transport::Nfc]))
+        );
+        assert_eq!(certificate(APPLE_LEAF).transports(), Ok(None));
+    }
+
+    #[test]
+    fn is_authority() {
+        assert_eq!(certificate(APPLE_ROOT).is_authority(), Ok(true));
+        assert_eq!(certificate(APPLE_INTERMEDIATE).is_authority(), Ok(true));
+        assert_eq!(certificate(APPLE_LEAF).is_authority(), Ok(false));
+        assert_eq!(certificate(NOT_CA).is_authority(), Ok(false));
+    }
+
+    #[test]
+    #[cfg(feature = "crypto")]
+    fn validate_p384_chain() {
+        let chain = chain(&[APPLE_LEAF, APPLE_INTERMEDIATE]);
+        assert_eq!(
+            chain.validate(&[certificate(APPLE_ROOT)], apple_time()),
+            Ok(())
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "crypto")]
+    fn validate_chain_with_anchor() {
+        let anchors = [certificate(APPLE_ROOT)];
+        let with_root = chain(&[APPLE_LEAF, APPLE_INTERMEDIATE, APPLE_ROOT]);
+        assert_eq!(with_root.validate(&anchors, apple_time()), Ok(()));
+
+        let anchors = [certificate(APPLE_INTERMEDIATE)];
+        let with_intermediate = chain(&[APPLE_LEAF, APPLE_INTERMEDIATE]);
+        assert_eq!(with_intermediate.validate(&anchors, apple_time()), Ok(()));
+    }
+
+    #[test]
+    #[cfg(feature = "crypto")]
+    fn validate_sha384_over_p256() {
+        let root = certificate(ROOT);
+        assert_eq!(certificate(LEAF).verify_issued_by(&root), Ok(()));
+        assert_eq!(chain(&[LEAF]).validate(&[root], SystemTime::now()), Ok(()));
+    }
+
+    #[test]
+    #[cfg(feature = "crypto")]
+    fn validate_expired() {
+        // 2021-01-01, after the Apple attestation certificate expired
+        let time = SystemTime::UNIX_EPOCH + Duration::from_secs(1_609_459_200);
+        let chain = chain(&[APPLE_LEAF, APPLE_INTERMEDIATE]);
+        assert_eq!(
+            chain.validate(&[certificate(APPLE_ROOT)], time),
+            Err(Error::Expired)
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "crypto")]
+    fn validate_not_authority() {
+        let chain = chain(&[LEAF_OF_NOT_CA, NOT_CA]);
+        assert_eq!(
+            chain.validate(&[certificate(ROOT)], SystemTime::now()),
+            Err(Error::NotAuthority)
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "crypto")]
+    fn validate_wrong_issuer() {
+        assert_eq!(
+            certificate(APPLE_LEAF).verify_issued_by(&certificate(ROOT)),
+            Err(Error::Untrusted)
+        );
+        // The root is a certificate authority, but not the leaf's issuer
+        let chain = chain(&[APPLE_LEAF, ROOT]);
+        assert_eq!(
+            chain.validate(&[certificate(ROOT)], apple_time()),
+            Err(Error::Untrusted)
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "crypto")]
+    fn validate_untrusted() {
+        let chain = chain(&[APPLE_LEAF, APPLE_INTERMEDIATE]);
+        assert_eq!(
+            chain.validate(&[certificate(ROOT)], apple_time()),
+            Err(Error::Untrusted)
+        );
+        assert_eq!(chain.validate(&[], apple_time()), Err(Error::Untrusted));
+    }
+
+    #[test]
+    #[cfg(feature = "crypto")]
+    fn validate_tampered_signature() {
+        // The last byte belongs to the signature's `s`
+        let mut leaf = LEAF.to_vec();
+        *leaf.last_mut().unwrap() ^= 0x01;
+        assert_eq!(
+            certificate(&leaf).verify_issued_by(&certificate(ROOT)),
+            Err(Error::InvalidSignature)
+        );
+    }
+}
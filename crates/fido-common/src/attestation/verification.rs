@@ -0,0 +1,1031 @@
+//! The verification procedures of the attestation statement formats, as
+//! performed by a relying party registering a credential.
+//!
+//! > An attestation statement format's verification procedure... takes the
+//! > following verification procedure inputs: attStmt, authenticatorData,
+//! > clientDataHash. \[It\] returns either an error indicating that the
+//! > attestation is invalid, or an implementation-specific value representing
+//! > the attestation type, and the trust path.
+//!
+//! Whether the trust path chains up to an acceptable root of trust is left to
+//! the relying party, see [`Chain::validate`].
+
+use super::{
+    certificate::{self, Certificate, Chain},
+    CredentialData, Statement,
+};
+use crate::{credential::public_key::Key, registry, Sha256Hash};
+use coset::iana;
+use sha2::{Digest, Sha256};
+use x509_cert::der::{asn1::ObjectIdentifier, Encode};
+
+/// The Android key attestation extension, containing the key description.
+const ANDROID_KEY_DESCRIPTION_OID: ObjectIdentifier =
+    ObjectIdentifier::new_unwrap("1.3.6.1.4.1.11129.2.1.17");
+/// The Apple anonymous attestation extension, containing the nonce.
+const APPLE_NONCE_OID: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.113635.100.8.2");
+/// > tcg-kp-AIKCertificate
+const TCG_KP_AIK_CERTIFICATE_OID: ObjectIdentifier = ObjectIdentifier::new_unwrap("2.23.133.8.3");
+const COUNTRY_NAME_OID: ObjectIdentifier = ObjectIdentifier::new_unwrap("2.5.4.6");
+const ORGANIZATION_NAME_OID: ObjectIdentifier = ObjectIdentifier::new_unwrap("2.5.4.10");
+const ORGANIZATIONAL_UNIT_NAME_OID: ObjectIdentifier = ObjectIdentifier::new_unwrap("2.5.4.11");
+const COMMON_NAME_OID: ObjectIdentifier = ObjectIdentifier::new_unwrap("2.5.4.3");
+
+/// The result of a successful verification.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Verified {
+    /// The attestation type. A basic attestation may also be an attestation
+    /// CA attestation, which is only told apart by the relying party's
+    /// knowledge of the authenticator.
+    pub attestation_type: registry::Attestation,
+    /// The certificate chain of the trust path, absent for self attestation
+    /// and no attestation.
+    pub trust_path: Option<Chain>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// The authenticator data does not include the attested credential data.
+    MissingCredentialData,
+    /// The authenticator data, the attestation statement or a certificate is
+    /// malformed.
+    Malformed,
+    /// The algorithm is not supported, or does not match the key.
+    UnsupportedAlgorithm,
+    /// The attestation signature does not verify.
+    InvalidSignature,
+    /// The attestation certificate does not meet the requirements of the
+    /// format.
+    InvalidCertificate,
+    /// The attestation does not attest the credential, e.g. it certifies
+    /// another public key or another authenticator's AAGUID.
+    Mismatch,
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::MissingCredentialData => {
+                write!(f, "Authenticator data does not include a credential")
+            }
+            Error::Malformed => write!(f, "Attestation is malformed"),
+            Error::UnsupportedAlgorithm => write!(f, "Attestation algorithm is not supported"),
+            Error::InvalidSignature => write!(f, "Attestation signature is invalid"),
+            Error::InvalidCertificate => {
+                write!(f, "Attestation certificate does not meet the requirements")
+            }
+            Error::Mismatch => write!(f, "Attestation does not attest the credential"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<certificate::Error> for Error {
+    fn from(e: certificate::Error) -> Self {
+        match e {
+            certificate::Error::Malformed | certificate::Error::EmptyChain => Error::Malformed,
+            certificate::Error::UnsupportedAlgorithm => Error::UnsupportedAlgorithm,
+            certificate::Error::InvalidSignature => Error::InvalidSignature,
+            certificate::Error::Expired
+            | certificate::Error::NotAuthority
+            | certificate::Error::Untrusted => Error::InvalidCertificate,
+        }
+    }
+}
+
+/// Verifies the attestation statement of the credential in the authenticator
+/// data, where a `None` statement is that of the `none` format.
+///
+/// `auth_data` must be the authenticator data as signed by the authenticator,
+/// e.g. as encoded by [`Data::to_bytes`](crate::authenticator::Data::to_bytes).
+pub fn verify(
+    statement: Option<&Statement>,
+    auth_data: &[u8],
+    client_data_hash: &Sha256Hash,
+) -> Result<Verified, Error> {
+    let credential = attested_credential_data(auth_data)?;
+    let Some(statement) = statement else {
+        return Ok(Verified {
+            attestation_type: registry::Attestation::None,
+            trust_path: None,
+        });
+    };
+    let signed = [auth_data, client_data_hash].concat();
+
+    match statement {
+        Statement::Packed {
+            algorithm,
+            signature,
+            attestation_certificate_chain,
+        } => match attestation_certificate_chain {
+            Some(chain) => {
+                let certificate = chain.leaf();
+                VerifyingKey::from_certificate(certificate, *algorithm)?
+                    .verify(&signed, signature)?;
+                check_packed_certificate(certificate, &credential)?;
+                Ok(Verified {
+                    attestation_type: registry::Attestation::BasicFull,
+                    trust_path: Some(chain.clone()),
+                })
+            }
+            None => {
+                // > Validate that alg matches the algorithm of the
+                // > credentialPublicKey in authenticatorData.
+                if credential.public_key.algorithm() != Some(*algorithm) {
+                    return Err(Error::UnsupportedAlgorithm);
+                }
+                VerifyingKey::from_credential(&credential.public_key, *algorithm)?
+                    .verify(&signed, signature)?;
+                Ok(Verified {
+                    attestation_type: registry::Attestation::BasicSurrogate,
+                    trust_path: None,
+                })
+            }
+        },
+        Statement::Tpm {
+            version,
+            algorithm,
+            attestation_certificate_chain,
+            signature,
+            certify_info,
+            public_area,
+        } => {
+            if version != "2.0" {
+                return Err(Error::Malformed);
+            }
+            let public_area_key = tpm::public_key(public_area)?;
+            let credential_key = VerifyingKey::from_credential(
+                &credential.public_key,
+                credential
+                    .public_key
+                    .algorithm()
+                    .unwrap_or(public_area_key.algorithm()),
+            )?;
+            if public_area_key != credential_key {
+                return Err(Error::Mismatch);
+            }
+            tpm::check_certify_info(certify_info, public_area, *algorithm, &signed)?;
+
+            let certificate = attestation_certificate_chain.leaf();
+            VerifyingKey::from_certificate(certificate, *algorithm)?
+                .verify(certify_info, signature)?;
+            check_tpm_certificate(certificate, &credential)?;
+            Ok(Verified {
+                attestation_type: registry::Attestation::PrivacyCA,
+                trust_path: Some(attestation_certificate_chain.clone()),
+            })
+        }
+        Statement::AndroidKey {
+            algorithm,
+            signature,
+            attestation_certificate_chain,
+        } => {
+            let certificate = attestation_certificate_chain.leaf();
+            let certificate_key = VerifyingKey::from_certificate(certificate, *algorithm)?;
+            certificate_key.verify(&signed, signature)?;
+            if certificate_key != VerifyingKey::from_credential(&credential.public_key, *algorithm)?
+            {
+                return Err(Error::Mismatch);
+            }
+            let key_description = certificate
+                .extension(ANDROID_KEY_DESCRIPTION_OID)
+                .ok_or(Error::InvalidCertificate)?;
+            android_key::check_key_description(key_description, client_data_hash)?;
+            Ok(Verified {
+                attestation_type: registry::Attestation::BasicFull,
+                trust_path: Some(attestation_certificate_chain.clone()),
+            })
+        }
+        Statement::AndroidSafetyNet { version, response } => {
+            if version.is_empty() {
+                return Err(Error::Malformed);
+            }
+            let trust_path = android_safetynet::verify(response, &signed)?;
+            Ok(Verified {
+                attestation_type: registry::Attestation::BasicFull,
+                trust_path: Some(trust_path),
+            })
+        }
+        Statement::FidoU2f {
+            attestation_certificate_chain,
+            signature,
+        } => {
+            // > Check that x5c has exactly one element
+            let [certificate] = attestation_certificate_chain.certificates() else {
+                return Err(Error::Malformed);
+            };
+            // > If certificate public key is not an Elliptic Curve (EC) public
+            // > key over the P-256 curve, terminate this algorithm and return an
+            // > appropriate error.
+            let certificate_key =
+                VerifyingKey::from_certificate(certificate, iana::Algorithm::ES256)?;
+            let public_key = p256::ecdsa::VerifyingKey::try_from(&credential.public_key)
+                .map_err(|_| Error::UnsupportedAlgorithm)?;
+            let relying_party_id_hash = &auth_data[..32];
+            let verification_data = [
+                [0x00].as_slice(),
+                relying_party_id_hash,
+                client_data_hash,
+                &credential.id,
+                public_key.to_encoded_point(false).as_bytes(),
+            ]
+            .concat();
+            certificate_key.verify(&verification_data, signature)?;
+            Ok(Verified {
+                attestation_type: registry::Attestation::BasicFull,
+                trust_path: Some(attestation_certificate_chain.clone()),
+            })
+        }
+        Statement::Apple {
+            attestation_certificate_chain,
+        } => {
+            let certificate = attestation_certificate_chain.leaf();
+            // > Perform SHA-256 hash of nonceToHash to produce nonce.
+            let nonce = Sha256::digest(&signed);
+            // > Verify that nonce equals the value of the extension with OID
+            // > 1.2.840.113635.100.8.2 in credCert.
+            let expected = [
+                [0x30, 0x24, 0xA1, 0x22, 0x04, 0x20].as_slice(),
+                nonce.as_slice(),
+            ]
+            .concat();
+            if certificate.extension(APPLE_NONCE_OID) != Some(expected.as_slice()) {
+                return Err(Error::InvalidCertificate);
+            }
+            let algorithm = credential
+                .public_key
+                .algorithm()
+                .ok_or(Error::UnsupportedAlgorithm)?;
+            if VerifyingKey::from_certificate(certificate, algorithm)?
+                != VerifyingKey::from_credential(&credential.public_key, algorithm)?
+            {
+                return Err(Error::Mismatch);
+            }
+            Ok(Verified {
+                attestation_type: registry::Attestation::AnonymizationCA,
+                trust_path: Some(attestation_certificate_chain.clone()),
+            })
+        }
+    }
+}
+
+/// Reads the attested credential data following the RP ID hash, flags and
+/// signature counter.
+fn attested_credential_data(auth_data: &[u8]) -> Result<CredentialData, Error> {
+    const ATTESTED_CREDENTIAL_DATA: u8 = 0b1 << 6;
+
+    let flags = *auth_data.get(32).ok_or(Error::Malformed)?;
+    if flags & ATTESTED_CREDENTIAL_DATA == 0 {
+        return Err(Error::MissingCredentialData);
+    }
+    let data = auth_data.get(37..).ok_or(Error::Malformed)?;
+    CredentialData::from_bytes(data)
+        .map(|(credential, _)| credential)
+        .map_err(|_| Error::Malformed)
+}
+
+/// A public key verifying attestation signatures.
+#[derive(Debug, PartialEq, Eq)]
+enum VerifyingKey {
+    Es256(p256::ecdsa::VerifyingKey),
+    EdDsa(ed25519_dalek::VerifyingKey),
+    Rs256(rsa::RsaPublicKey),
+    /// RSASSA-PKCS1-v1_5 with SHA-1, which TPM attestations, e.g. those of
+    /// Windows Hello, are signed with.
+    Rs1(rsa::RsaPublicKey),
+}
+
+impl VerifyingKey {
+    fn from_credential(key: &Key, algorithm: iana::Algorithm) -> Result<Self, Error> {
+        match algorithm {
+            iana::Algorithm::ES256 => p256::ecdsa::VerifyingKey::try_from(key).map(Self::Es256),
+            iana::Algorithm::EdDSA => ed25519_dalek::VerifyingKey::try_from(key).map(Self::EdDsa),
+            iana::Algorithm::RS256 => rsa::RsaPublicKey::try_from(key).map(Self::Rs256),
+            _ => return Err(Error::UnsupportedAlgorithm),
+        }
+        .map_err(|_| Error::UnsupportedAlgorithm)
+    }
+
+    fn from_certificate(
+        certificate: &Certificate,
+        algorithm: iana::Algorithm,
+    ) -> Result<Self, Error> {
+        use p256::pkcs8::DecodePublicKey;
+
+        let public_key = certificate
+            .public_key()
+            .to_der()
+            .map_err(|_| Error::Malformed)?;
+        match algorithm {
+            iana::Algorithm::ES256 => {
+                p256::ecdsa::VerifyingKey::from_public_key_der(&public_key).map(Self::Es256)
+            }
+            iana::Algorithm::EdDSA => {
+                ed25519_dalek::VerifyingKey::from_public_key_der(&public_key).map(Self::EdDsa)
+            }
+            iana::Algorithm::RS256 => {
+                rsa::RsaPublicKey::from_public_key_der(&public_key).map(Self::Rs256)
+            }
+            iana::Algorithm::RS1 => {
+                rsa::RsaPublicKey::from_public_key_der(&public_key).map(Self::Rs1)
+            }
+            _ => return Err(Error::UnsupportedAlgorithm),
+        }
+        .map_err(|_| Error::UnsupportedAlgorithm)
+    }
+
+    fn algorithm(&self) -> iana::Algorithm {
+        match self {
+            VerifyingKey::Es256(_) => iana::Algorithm::ES256,
+            VerifyingKey::EdDsa(_) => iana::Algorithm::EdDSA,
+            VerifyingKey::Rs256(_) => iana::Algorithm::RS256,
+            VerifyingKey::Rs1(_) => iana::Algorithm::RS1,
+        }
+    }
+
+    /// Verifies a signature encoded as in attestation statements, i.e. DER
+    /// encoded for ECDSA.
+    fn verify(&self, message: &[u8], signature: &[u8]) -> Result<(), Error> {
+        match self {
+            VerifyingKey::Es256(key) => {
+                use p256::ecdsa::signature::Verifier;
+
+                let signature = p256::ecdsa::Signature::from_der(signature)
+                    .map_err(|_| Error::InvalidSignature)?;
+                key.verify(message, &signature)
+            }
+            VerifyingKey::EdDsa(key) => {
+                let signature = ed25519_dalek::Signature::from_slice(signature)
+                    .map_err(|_| Error::InvalidSignature)?;
+                key.verify_strict(message, &signature)
+            }
+            VerifyingKey::Rs256(key) => {
+                use rsa::signature::Verifier;
+
+                let signature = rsa::pkcs1v15::Signature::try_from(signature)
+                    .map_err(|_| Error::InvalidSignature)?;
+                rsa::pkcs1v15::VerifyingKey::<Sha256>::new(key.clone()).verify(message, &signature)
+            }
+            VerifyingKey::Rs1(key) => {
+                use rsa::signature::Verifier;
+
+                let signature = rsa::pkcs1v15::Signature::try_from(signature)
+                    .map_err(|_| Error::InvalidSignature)?;
+                rsa::pkcs1v15::VerifyingKey::<sha1::Sha1>::new(key.clone())
+                    .verify(message, &signature)
+            }
+        }
+        .map_err(|_| Error::InvalidSignature)
+    }
+}
+
+/// Checks the requirements on attestation certificates shared by the formats.
+fn check_certificate(certificate: &Certificate, credential: &CredentialData) -> Result<(), Error> {
+    // > Version MUST be set to 3 (which is indicated by an ASN.1 INTEGER with
+    // > value 2).
+    if certificate.version() != x509_cert::Version::V3 {
+        return Err(Error::InvalidCertificate);
+    }
+    // > If attestnCert contains an extension with OID 1.3.6.1.4.1.45724.1.1.4
+    // > (id-fido-gen-ce-aaguid) verify that the value of this extension
+    // > matches the aaguid in authenticatorData.
+    if let Some(aaguid) = certificate.aaguid()? {
+        if aaguid != credential.aaguid {
+            return Err(Error::Mismatch);
+        }
+    }
+    // > The Basic Constraints extension MUST have the CA component set to
+    // > false.
+    if certificate.is_authority()? {
+        return Err(Error::InvalidCertificate);
+    }
+    Ok(())
+}
+
+/// > Packed Attestation Statement Certificate Requirements
+fn check_packed_certificate(
+    certificate: &Certificate,
+    credential: &CredentialData,
+) -> Result<(), Error> {
+    check_certificate(certificate, credential)?;
+    // > Subject field MUST be set to: Subject-C, Subject-O, Subject-OU,
+    // > Subject-CN
+    let organizational_unit = certificate.subject_attribute(ORGANIZATIONAL_UNIT_NAME_OID);
+    let has_subject = [COUNTRY_NAME_OID, ORGANIZATION_NAME_OID, COMMON_NAME_OID]
+        .into_iter()
+        .all(|identifier| certificate.subject_attribute(identifier).is_some());
+    if !has_subject || organizational_unit.as_deref() != Some("Authenticator Attestation") {
+        return Err(Error::InvalidCertificate);
+    }
+    Ok(())
+}
+
+/// > TPM Attestation Statement Certificate Requirements
+fn check_tpm_certificate(
+    certificate: &Certificate,
+    credential: &CredentialData,
+) -> Result<(), Error> {
+    check_certificate(certificate, credential)?;
+    // > Subject field MUST be set to empty.
+    if !certificate.subject().0.is_empty() {
+        return Err(Error::InvalidCertificate);
+    }
+    // > The Extended Key Usage extension MUST contain the OID 2.23.133.8.3
+    // > ("joint-iso-itu-t(2) internationalorganizations(23) 133 tcg-kp(8)
+    // > tcg-kp-AIKCertificate(3)").
+    let extended_key_usage = certificate.extended_key_usage()?.unwrap_or_default();
+    if !extended_key_usage.contains(&TCG_KP_AIK_CERTIFICATE_OID) {
+        return Err(Error::InvalidCertificate);
+    }
+    Ok(())
+}
+
+/// The TPM structures certifying the credential public key.
+mod tpm {
+    use super::{Error, VerifyingKey};
+    use coset::iana;
+    use sha2::{Digest, Sha256, Sha384, Sha512};
+
+    /// > TPM_GENERATED_VALUE
+    const GENERATED_VALUE: u32 = 0xFF54_4347;
+    /// > TPM_ST_ATTEST_CERTIFY
+    const ST_ATTEST_CERTIFY: u16 = 0x8017;
+    const ALG_RSA: u16 = 0x0001;
+    const ALG_SHA1: u16 = 0x0004;
+    const ALG_SHA256: u16 = 0x000B;
+    const ALG_SHA384: u16 = 0x000C;
+    const ALG_SHA512: u16 = 0x000D;
+    const ALG_NULL: u16 = 0x0010;
+    const ALG_ECC: u16 = 0x0023;
+    const ECC_NIST_P256: u16 = 0x0003;
+
+    /// Reads the big-endian TPM structures.
+    struct Reader<'a>(&'a [u8]);
+
+    impl<'a> Reader<'a> {
+        fn bytes(&mut self, length: usize) -> Result<&'a [u8], Error> {
+            if self.0.len() < length {
+                return Err(Error::Malformed);
+            }
+            let (bytes, rest) = self.0.split_at(length);
+            self.0 = rest;
+            Ok(bytes)
+        }
+
+        fn u16(&mut self) -> Result<u16, Error> {
+            self.bytes(2)
+                .map(|bytes| u16::from_be_bytes([bytes[0], bytes[1]]))
+        }
+
+        fn u32(&mut self) -> Result<u32, Error> {
+            self.bytes(4)
+                .map(|bytes| u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+        }
+
+        /// Reads a `TPM2B` structure, i.e. a buffer prefixed by its 16-bit
+        /// length.
+        fn sized(&mut self) -> Result<&'a [u8], Error> {
+            let length = self.u16()?;
+            self.bytes(length.into())
+        }
+
+        /// Reads a `TPMT_SYM_DEF_OBJECT`, `TPMT_RSA_SCHEME`, `TPMT_ECC_SCHEME`
+        /// or `TPMT_KDF_SCHEME`, whose details are absent for the null
+        /// algorithm.
+        fn scheme(&mut self, details: usize) -> Result<(), Error> {
+            if self.u16()? != ALG_NULL {
+                self.bytes(details)?;
+            }
+            Ok(())
+        }
+    }
+
+    fn digest(algorithm: u16, data: &[u8]) -> Result<Vec<u8>, Error> {
+        match algorithm {
+            ALG_SHA1 => Ok(sha1::Sha1::digest(data).to_vec()),
+            ALG_SHA256 => Ok(Sha256::digest(data).to_vec()),
+            ALG_SHA384 => Ok(Sha384::digest(data).to_vec()),
+            ALG_SHA512 => Ok(Sha512::digest(data).to_vec()),
+            _ => Err(Error::UnsupportedAlgorithm),
+        }
+    }
+
+    /// Reads the public key of the `TPMT_PUBLIC` structure.
+    pub(super) fn public_key(public_area: &[u8]) -> Result<VerifyingKey, Error> {
+        let mut reader = Reader(public_area);
+        let key_type = reader.u16()?;
+        let _name_algorithm = reader.u16()?;
+        let _object_attributes = reader.u32()?;
+        let _auth_policy = reader.sized()?;
+        let key = match key_type {
+            ALG_RSA => {
+                // The symmetric algorithm has a key size and mode, and the
+                // signing scheme a hash algorithm
+                reader.scheme(4)?;
+                reader.scheme(2)?;
+                let _key_bits = reader.u16()?;
+                // > When zero, indicates that the exponent is the default of
+                // > 2^16 + 1
+                let exponent = match reader.u32()? {
+                    0 => 65537,
+                    exponent => exponent,
+                };
+                let modulus = reader.sized()?;
+                rsa::RsaPublicKey::new(
+                    rsa::BigUint::from_bytes_be(modulus),
+                    rsa::BigUint::from(exponent),
+                )
+                .map(VerifyingKey::Rs256)
+                .map_err(|_| Error::Malformed)?
+            }
+            ALG_ECC => {
+                reader.scheme(4)?;
+                reader.scheme(2)?;
+                if reader.u16()? != ECC_NIST_P256 {
+                    return Err(Error::UnsupportedAlgorithm);
+                }
+                reader.scheme(2)?;
+                let (x, y) = (reader.sized()?, reader.sized()?);
+                if x.len() != 32 || y.len() != 32 {
+                    return Err(Error::Malformed);
+                }
+                let point = p256::EncodedPoint::from_affine_coordinates(x.into(), y.into(), false);
+                p256::ecdsa::VerifyingKey::from_encoded_point(&point)
+                    .map(VerifyingKey::Es256)
+                    .map_err(|_| Error::Malformed)?
+            }
+            _ => return Err(Error::UnsupportedAlgorithm),
+        };
+        if !reader.0.is_empty() {
+            return Err(Error::Malformed);
+        }
+        Ok(key)
+    }
+
+    /// Checks the `TPMS_ATTEST` structure certifies the public area and the
+    /// signed data.
+    pub(super) fn check_certify_info(
+        certify_info: &[u8],
+        public_area: &[u8],
+        algorithm: iana::Algorithm,
+        signed: &[u8],
+    ) -> Result<(), Error> {
+        let mut reader = Reader(certify_info);
+        // > Verify that magic is set to TPM_GENERATED_VALUE.
+        // > Verify that type is set to TPM_ST_ATTEST_CERTIFY.
+        if reader.u32()? != GENERATED_VALUE || reader.u16()? != ST_ATTEST_CERTIFY {
+            return Err(Error::Malformed);
+        }
+        let _qualified_signer = reader.sized()?;
+        // > Verify that extraData is set to the hash of attToBeSigned using
+        // > the hash algorithm employed in "alg".
+        let extra_data = reader.sized()?;
+        let hash_algorithm = match algorithm {
+            iana::Algorithm::RS1 => ALG_SHA1,
+            iana::Algorithm::ES256 | iana::Algorithm::RS256 => ALG_SHA256,
+            _ => return Err(Error::UnsupportedAlgorithm),
+        };
+        if extra_data != digest(hash_algorithm, signed)? {
+            return Err(Error::Mismatch);
+        }
+        // The clock information and firmware version are not verified
+        reader.bytes(17 + 8)?;
+        // > Verify that attested contains a TPMS_CERTIFY_INFO structure as
+        // > specified in [TPMv2-Part2] section 10.12.3, whose name field
+        // > contains a valid Name for pubArea, as computed using the algorithm
+        // > in the nameAlg field of pubArea using the procedure specified in
+        // > [TPMv2-Part1] section 16.
+        let name = reader.sized()?;
+        let _qualified_name = reader.sized()?;
+        let name_algorithm = Reader(public_area.get(2..).ok_or(Error::Malformed)?).u16()?;
+        let expected = [
+            name_algorithm.to_be_bytes().as_slice(),
+            &digest(name_algorithm, public_area)?,
+        ]
+        .concat();
+        if name != expected {
+            return Err(Error::Mismatch);
+        }
+        Ok(())
+    }
+}
+
+/// The Android key attestation extension.
+mod android_key {
+    use super::Error;
+    use crate::Sha256Hash;
+
+    /// > KM_ORIGIN_GENERATED
+    const ORIGIN_GENERATED: &[u8] = &[0x00];
+    /// > KM_PURPOSE_SIGN
+    const PURPOSE_SIGN: &[u8] = &[0x02];
+    const TAG_PURPOSE: u32 = 1;
+    const TAG_ALL_APPLICATIONS: u32 = 600;
+    const TAG_ORIGIN: u32 = 702;
+
+    /// Splits DER encoded values into their tag numbers and contents. Unlike
+    /// [`der`](x509_cert::der), it allows the tag numbers above 30 of the
+    /// authorization lists.
+    fn values(mut data: &[u8]) -> Result<Vec<(u32, &[u8])>, Error> {
+        let mut values = Vec::new();
+        while let Some((&tag, rest)) = data.split_first() {
+            data = rest;
+            let mut number = u32::from(tag & 0x1F);
+            if number == 0x1F {
+                number = 0;
+                loop {
+                    let (&byte, rest) = data.split_first().ok_or(Error::Malformed)?;
+                    data = rest;
+                    number = number
+                        .checked_mul(128)
+                        .ok_or(Error::Malformed)?
+                        .wrapping_add(u32::from(byte & 0x7F));
+                    if byte & 0x80 == 0 {
+                        break;
+                    }
+                }
+            }
+
+            let (&length, rest) = data.split_first().ok_or(Error::Malformed)?;
+            data = rest;
+            let length = if length & 0x80 == 0 {
+                usize::from(length)
+            } else {
+                let size = usize::from(length & 0x7F);
+                if size > std::mem::size_of::<usize>() || data.len() < size {
+                    return Err(Error::Malformed);
+                }
+                let (length, rest) = data.split_at(size);
+                data = rest;
+                length
+                    .iter()
+                    .fold(0, |length, &byte| (length << 8) | usize::from(byte))
+            };
+            if data.len() < length {
+                return Err(Error::Malformed);
+            }
+            let (content, rest) = data.split_at(length);
+            data = rest;
+            values.push((number, content));
+        }
+        Ok(values)
+    }
+
+    /// The contents of the single value of the given data.
+    fn single(data: &[u8]) -> Result<&[u8], Error> {
+        match values(data)?.as_slice() {
+            [(_, content)] => Ok(content),
+            _ => Err(Error::Malformed),
+        }
+    }
+
+    /// Checks the `KeyDescription` of the attestation certificate.
+    pub(super) fn check_key_description(
+        key_description: &[u8],
+        client_data_hash: &Sha256Hash,
+    ) -> Result<(), Error> {
+        let fields = values(single(key_description)?)?;
+        // attestationVersion, attestationSecurityLevel, keymasterVersion,
+        // keymasterSecurityLevel, attestationChallenge, uniqueId,
+        // softwareEnforced and teeEnforced
+        let [_, _, _, _, (_, challenge), _, (_, software_enforced), (_, tee_enforced), ..] =
+            fields.as_slice()
+        else {
+            return Err(Error::InvalidCertificate);
+        };
+        // > Verify that the attestationChallenge field in the attestation
+        // > certificate extension data is identical to clientDataHash.
+        if *challenge != client_data_hash.as_slice() {
+            return Err(Error::Mismatch);
+        }
+
+        let authorizations = [values(software_enforced)?, values(tee_enforced)?].concat();
+        let authorized = |tag| {
+            authorizations
+                .iter()
+                .filter(move |(number, _)| *number == tag)
+                .map(|(_, content)| *content)
+        };
+        // > The AuthorizationList.allApplications field is not present on
+        // > either authorization list (softwareEnforced nor teeEnforced),
+        // > since PublicKeyCredential MUST be scoped to the RP ID.
+        if authorized(TAG_ALL_APPLICATIONS).next().is_some() {
+            return Err(Error::InvalidCertificate);
+        }
+        // > The value in the AuthorizationList.origin field is equal to
+        // > KM_ORIGIN_GENERATED.
+        // > The value in the AuthorizationList.purpose field is equal to
+        // > KM_PURPOSE_SIGN.
+        let generated = authorized(TAG_ORIGIN)
+            .map(single)
+            .collect::<Result<Vec<_>, _>>()?
+            .contains(&ORIGIN_GENERATED);
+        let mut purposes = Vec::new();
+        for purpose in authorized(TAG_PURPOSE) {
+            for (_, purpose) in values(single(purpose)?)? {
+                purposes.push(purpose);
+            }
+        }
+        if !generated || !purposes.contains(&PURPOSE_SIGN) {
+            return Err(Error::InvalidCertificate);
+        }
+        Ok(())
+    }
+}
+
+/// The SafetyNet attestation response, a JSON Web Signature.
+mod android_safetynet {
+    use super::{Certificate, Chain, Error, VerifyingKey, COMMON_NAME_OID};
+    use base64::{
+        engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD},
+        Engine,
+    };
+    use coset::iana;
+    use sha2::{Digest, Sha256};
+
+    /// > Verify that attestationCert is issued to the hostname
+    /// > "attest.android.com"
+    const HOSTNAME: &str = "attest.android.com";
+
+    fn decode_json(part: &str) -> Result<serde_json::Value, Error> {
+        let part = URL_SAFE_NO_PAD.decode(part).map_err(|_| Error::Malformed)?;
+        serde_json::from_slice(&part).map_err(|_| Error::Malformed)
+    }
+
+    /// Verifies the response and returns the certificate chain of its
+    /// signature.
+    pub(super) fn verify(response: &[u8], signed: &[u8]) -> Result<Chain, Error> {
+        let response = std::str::from_utf8(response).map_err(|_| Error::Malformed)?;
+        let parts: Vec<&str> = response.split('.').collect();
+        let [header, payload, signature] = parts.as_slice() else {
+            return Err(Error::Malformed);
+        };
+
+        let header_json = decode_json(header)?;
+        let chain = header_json["x5c"]
+            .as_array()
+            .ok_or(Error::Malformed)?
+            .iter()
+            .map(|certificate| {
+                let certificate = certificate.as_str().ok_or(Error::Malformed)?;
+                let certificate = STANDARD.decode(certificate).map_err(|_| Error::Malformed)?;
+                Certificate::from_der(&certificate).map_err(Error::from)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let chain = Chain::new(chain)?;
+        let algorithm = match header_json["alg"].as_str() {
+            Some("RS256") => iana::Algorithm::RS256,
+            Some("ES256") => iana::Algorithm::ES256,
+            _ => return Err(Error::UnsupportedAlgorithm),
+        };
+        let certificate = chain.leaf();
+        if certificate.subject_attribute(COMMON_NAME_OID).as_deref() != Some(HOSTNAME) {
+            return Err(Error::InvalidCertificate);
+        }
+        let signature = URL_SAFE_NO_PAD
+            .decode(signature)
+            .map_err(|_| Error::Malformed)?;
+        // JWS signs the encoded header and payload, with ECDSA signatures in
+        // their fixed size encoding
+        let signing_input = format!("{header}.{payload}");
+        let signature = match algorithm {
+            iana::Algorithm::ES256 => p256::ecdsa::Signature::from_slice(&signature)
+                .map_err(|_| Error::InvalidSignature)?
+                .to_der()
+                .as_bytes()
+                .to_vec(),
+            _ => signature,
+        };
+        VerifyingKey::from_certificate(certificate, algorithm)?
+            .verify(signing_input.as_bytes(), &signature)?;
+
+        let payload = decode_json(payload)?;
+        // > Verify that the nonce attribute in the payload of response is
+        // > identical to the Base64 encoding of the SHA-256 hash of the
+        // > concatenation of authenticatorData and clientDataHash.
+        if payload["nonce"].as_str() != Some(STANDARD.encode(Sha256::digest(signed)).as_str()) {
+            return Err(Error::Mismatch);
+        }
+        // > Verify that the ctsProfileMatch attribute in the payload of
+        // > response is true.
+        if payload["ctsProfileMatch"].as_bool() != Some(true) {
+            return Err(Error::InvalidCertificate);
+        }
+        Ok(chain)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::attestation::FormatIdentifier;
+    use ciborium::value::Value;
+
+    // Attestation objects and the client data they were created with, as
+    // published with webauthn-rs. The packed attestations with an
+    // authority or without an organizational unit are made up, and signed by
+    // their own certificate.
+    const PACKED: &[u8] = include_bytes!("testdata/packed.cbor");
+    const PACKED_CLIENT_DATA: &[u8] = include_bytes!("testdata/packed.json");
+    const PACKED_AAGUID_MISMATCH: &[u8] = include_bytes!("testdata/packed-aaguid-mismatch.cbor");
+    const PACKED_AUTHORITY: &[u8] = include_bytes!("testdata/packed-authority.cbor");
+    const PACKED_WITHOUT_UNIT: &[u8] = include_bytes!("testdata/packed-without-unit.cbor");
+    const PACKED_SELF: &[u8] = include_bytes!("testdata/packed-self.cbor");
+    const PACKED_SELF_CLIENT_DATA: &[u8] = include_bytes!("testdata/packed-self.json");
+    const TPM_ECC: &[u8] = include_bytes!("testdata/tpm-ecc.cbor");
+    const TPM_ECC_CLIENT_DATA: &[u8] = include_bytes!("testdata/tpm-ecc.json");
+    const TPM_RSA: &[u8] = include_bytes!("testdata/tpm-rsa.cbor");
+    const TPM_RSA_CLIENT_DATA: &[u8] = include_bytes!("testdata/tpm-rsa.json");
+    const ANDROID_KEY: &[u8] = include_bytes!("testdata/android-key.cbor");
+    const ANDROID_KEY_CLIENT_DATA: &[u8] = include_bytes!("testdata/android-key.json");
+    const ANDROID_SAFETYNET: &[u8] = include_bytes!("testdata/android-safetynet.cbor");
+    const ANDROID_SAFETYNET_CLIENT_DATA: &[u8] = include_bytes!("testdata/android-safetynet.json");
+    const FIDO_U2F: &[u8] = include_bytes!("testdata/fido-u2f.cbor");
+    const FIDO_U2F_CLIENT_DATA: &[u8] = include_bytes!("testdata/fido-u2f.json");
+    const APPLE: &[u8] = include_bytes!("testdata/apple.cbor");
+    const APPLE_CLIENT_DATA: &[u8] = include_bytes!("testdata/apple.json");
+    const NONE: &[u8] = include_bytes!("testdata/none.cbor");
+    const NONE_CLIENT_DATA: &[u8] = include_bytes!("testdata/none.json");
+
+    /// Decodes an attestation object into its statement and authenticator
+    /// data.
+    fn decode(attestation_object: &[u8]) -> (Option<Statement>, Vec<u8>) {
+        let Ok(Value::Map(fields)) = ciborium::de::from_reader(attestation_object) else {
+            panic!("attestation object is not a map");
+        };
+        let field = |name: &str| {
+            fields
+                .iter()
+                .find(|(key, _)| key.as_text() == Some(name))
+                .map(|(_, value)| value.clone())
+                .unwrap()
+        };
+        let format: FormatIdentifier = field("fmt").deserialized().unwrap();
+        let statement = Statement::from_cbor_value(format, field("attStmt")).unwrap();
+        let auth_data = field("authData").into_bytes().unwrap();
+        (statement, auth_data)
+    }
+
+    fn verify_object(attestation_object: &[u8], client_data: &[u8]) -> Result<Verified, Error> {
+        let (statement, auth_data) = decode(attestation_object);
+        verify(
+            statement.as_ref(),
+            &auth_data,
+            &Sha256::digest(client_data).into(),
+        )
+    }
+
+    fn attestation_type(attestation_object: &[u8], client_data: &[u8]) -> registry::Attestation {
+        verify_object(attestation_object, client_data)
+            .unwrap()
+            .attestation_type
+    }
+
+    #[test]
+    fn packed() {
+        let verified = verify_object(PACKED, PACKED_CLIENT_DATA).unwrap();
+        assert_eq!(verified.attestation_type, registry::Attestation::BasicFull);
+        assert_eq!(verified.trust_path.unwrap().certificates().len(), 1);
+    }
+
+    #[test]
+    fn packed_self() {
+        let verified = verify_object(PACKED_SELF, PACKED_SELF_CLIENT_DATA).unwrap();
+        assert_eq!(
+            verified.attestation_type,
+            registry::Attestation::BasicSurrogate
+        );
+        assert_eq!(verified.trust_path, None);
+    }
+
+    #[test]
+    fn tpm() {
+        assert_eq!(
+            attestation_type(TPM_ECC, TPM_ECC_CLIENT_DATA),
+            registry::Attestation::PrivacyCA
+        );
+        assert_eq!(
+            attestation_type(TPM_RSA, TPM_RSA_CLIENT_DATA),
+            registry::Attestation::PrivacyCA
+        );
+    }
+
+    #[test]
+    fn android_key() {
+        assert_eq!(
+            attestation_type(ANDROID_KEY, ANDROID_KEY_CLIENT_DATA),
+            registry::Attestation::BasicFull
+        );
+    }
+
+    #[test]
+    fn android_safetynet() {
+        assert_eq!(
+            attestation_type(ANDROID_SAFETYNET, ANDROID_SAFETYNET_CLIENT_DATA),
+            registry::Attestation::BasicFull
+        );
+    }
+
+    #[test]
+    fn fido_u2f() {
+        assert_eq!(
+            attestation_type(FIDO_U2F, FIDO_U2F_CLIENT_DATA),
+            registry::Attestation::BasicFull
+        );
+    }
+
+    #[test]
+    fn apple() {
+        let verified = verify_object(APPLE, APPLE_CLIENT_DATA).unwrap();
+        assert_eq!(
+            verified.attestation_type,
+            registry::Attestation::AnonymizationCA
+        );
+        assert_eq!(verified.trust_path.unwrap().certificates().len(), 2);
+    }
+
+    #[test]
+    fn none() {
+        let verified = verify_object(NONE, NONE_CLIENT_DATA).unwrap();
+        assert_eq!(verified.attestation_type, registry::Attestation::None);
+        assert_eq!(verified.trust_path, None);
+    }
+
+    #[test]
+    fn missing_credential_data() {
+        let (_, mut auth_data) = decode(NONE);
+        auth_data[32] &= !(0b1 << 6);
+        assert_eq!(
+            verify(None, &auth_data, &Sha256::digest(NONE_CLIENT_DATA).into()),
+            Err(Error::MissingCredentialData)
+        );
+    }
+
+    #[test]
+    fn tampered_signature() {
+        let (statement, auth_data) = decode(PACKED);
+        let Some(Statement::Packed {
+            algorithm,
+            mut signature,
+            attestation_certificate_chain,
+        }) = statement
+        else {
+            panic!("expected a packed statement");
+        };
+        *signature.last_mut().unwrap() ^= 0x01;
+        let statement = Statement::Packed {
+            algorithm,
+            signature,
+            attestation_certificate_chain,
+        };
+        assert_eq!(
+            verify(
+                Some(&statement),
+                &auth_data,
+                &Sha256::digest(PACKED_CLIENT_DATA).into()
+            ),
+            Err(Error::InvalidSignature)
+        );
+    }
+
+    #[test]
+    fn other_client_data() {
+        assert_eq!(
+            verify_object(PACKED, APPLE_CLIENT_DATA),
+            Err(Error::InvalidSignature)
+        );
+        assert_eq!(
+            verify_object(APPLE, PACKED_CLIENT_DATA),
+            Err(Error::InvalidCertificate)
+        );
+        assert_eq!(
+            verify_object(ANDROID_KEY, PACKED_CLIENT_DATA),
+            Err(Error::InvalidSignature)
+        );
+    }
+
+    #[test]
+    fn aaguid_mismatch() {
+        assert_eq!(
+            verify_object(PACKED_AAGUID_MISMATCH, PACKED_CLIENT_DATA),
+            Err(Error::Mismatch)
+        );
+    }
+
+    #[test]
+    fn authority_certificate() {
+        assert_eq!(
+            verify_object(PACKED_AUTHORITY, PACKED_CLIENT_DATA),
+            Err(Error::InvalidCertificate)
+        );
+    }
+
+    #[test]
+    fn missing_organizational_unit() {
+        assert_eq!(
+            verify_object(PACKED_WITHOUT_UNIT, PACKED_CLIENT_DATA),
+            Err(Error::InvalidCertificate)
+        );
+    }
+}
@@ -1,9 +1,8 @@
 use crate::{
     attestation,
     credential::{BackupEligibility, BackupState},
-    extensions, Sha256Hash,
+    Sha256Hash,
 };
-use std::collections::BTreeMap;
 
 #[cfg(feature = "serde")]
 use serde::{Deserialize, Serialize};
@@ -43,7 +42,112 @@ pub struct Data {
     pub backup_state: BackupState,
     pub signature_counter: u32,
     pub attested_credential_data: Option<attestation::CredentialData>,
-    pub extensions: Option<BTreeMap<extensions::Identifier, Vec<u8>>>,
+    pub extensions: Option<Extensions>,
+}
+
+/// > Extension-defined authenticator data. This is a CBOR map with extension
+/// > identifiers as keys, and authenticator extension outputs as values.
+///
+/// The map is kept exactly as it was encoded, so that authenticator data is
+/// encoded again byte for byte as it was signed, including the outputs of
+/// extensions that are not known to this crate.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Extensions {
+    /// The extension identifiers and outputs, each output in its CBOR
+    /// encoding, in the order they appear in the map.
+    outputs: Vec<(String, Vec<u8>)>,
+    /// The CBOR encoding of the map.
+    encoded: Vec<u8>,
+}
+
+impl Extensions {
+    /// The output of the extension with the given identifier, in its CBOR
+    /// encoding.
+    pub fn get(&self, identifier: &str) -> Option<&[u8]> {
+        self.outputs
+            .iter()
+            .find(|(id, _)| id == identifier)
+            .map(|(_, output)| output.as_slice())
+    }
+
+    /// The extension identifiers and outputs, in the order they are encoded.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &[u8])> {
+        self.outputs
+            .iter()
+            .map(|(identifier, output)| (identifier.as_str(), output.as_slice()))
+    }
+
+    /// The CBOR encoding of the map.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.encoded
+    }
+}
+
+#[cfg(feature = "serde")]
+impl Extensions {
+    /// Encodes the outputs, each given in its CBOR encoding, as a map in CTAP2
+    /// canonical order: shorter identifiers first, and identifiers of the same
+    /// length in lexical order.
+    pub fn new<I>(outputs: I) -> Result<Self, coset::CoseError>
+    where
+        I: IntoIterator<Item = (String, Vec<u8>)>,
+    {
+        use ciborium::value::Value;
+
+        let mut outputs: Vec<_> = outputs.into_iter().collect();
+        outputs.sort_by(|(a, _), (b, _)| a.len().cmp(&b.len()).then_with(|| a.cmp(b)));
+        if outputs.windows(2).any(|pair| pair[0].0 == pair[1].0) {
+            return Err(coset::CoseError::DuplicateMapKey);
+        }
+
+        let map = outputs
+            .iter()
+            .map(|(identifier, output)| {
+                let output: Value = ciborium::de::from_reader(output.as_slice())?;
+                Ok((Value::Text(identifier.clone()), output))
+            })
+            .collect::<Result<_, coset::CoseError>>()?;
+        let mut encoded = Vec::new();
+        ciborium::ser::into_writer(&Value::Map(map), &mut encoded)
+            .map_err(|_| coset::CoseError::EncodeFailed)?;
+        Ok(Self { outputs, encoded })
+    }
+
+    /// Decodes the map at the start of `data`, returning it along with the
+    /// number of bytes it occupies.
+    pub fn from_bytes(data: &[u8]) -> Result<(Self, usize), coset::CoseError> {
+        use ciborium::value::Value;
+
+        let mut remaining = data;
+        let map: Value = ciborium::de::from_reader(&mut remaining)?;
+        let length = data.len() - remaining.len();
+        let Value::Map(entries) = map else {
+            return Err(coset::CoseError::UnexpectedItem("non-map", "map"));
+        };
+
+        let outputs = entries
+            .into_iter()
+            .map(|(identifier, output)| {
+                let Value::Text(identifier) = identifier else {
+                    return Err(coset::CoseError::UnexpectedItem(
+                        "non-text",
+                        "text extension identifier",
+                    ));
+                };
+                let mut encoded = Vec::new();
+                ciborium::ser::into_writer(&output, &mut encoded)
+                    .map_err(|_| coset::CoseError::EncodeFailed)?;
+                Ok((identifier, encoded))
+            })
+            .collect::<Result<_, _>>()?;
+        Ok((
+            Self {
+                outputs,
+                encoded: data[..length].to_vec(),
+            },
+            length,
+        ))
+    }
 }
 
 #[cfg(feature = "serde")]
@@ -142,12 +246,32 @@ impl<'de> Deserialize<'de> for Data {
         let (&counter_be_bytes, data) = data.split_array_ref::<4>();
         let signature_counter = u32::from_be_bytes(counter_be_bytes);
 
-        let attested_credential_data: Option<attestation::CredentialData> =
-            if flags.has_attested_credential_data() {
-                Some(attestation::CredentialData::try_from(data).map_err(de::Error::custom)?)
-            } else {
-                None
-            };
+        // attestedCredentialData: Variable length
+        let (attested_credential_data, data) = if flags.has_attested_credential_data() {
+            let (attested_credential_data, length) =
+                attestation::CredentialData::from_bytes(data).map_err(de::Error::custom)?;
+            (Some(attested_credential_data), &data[length..])
+        } else {
+            (None, data)
+        };
+
+        // extensions: Variable length
+        // > Extension-defined authenticator data. This is a CBOR map with
+        // > extension identifiers as keys, and authenticator extension outputs
+        // > as values.
+        let (extensions, data) = if flags.contains(DataFlags::EXTENSION_DATA_INCLUDED) {
+            let (extensions, length) = Extensions::from_bytes(data).map_err(de::Error::custom)?;
+            (Some(extensions), &data[length..])
+        } else {
+            (None, data)
+        };
+
+        if !data.is_empty() {
+            return Err(de::Error::invalid_length(
+                data.len(),
+                &"no bytes after the authenticator data",
+            ));
+        }
 
         Ok(Self {
             relying_party_id_hash,
@@ -157,11 +281,67 @@ impl<'de> Deserialize<'de> for Data {
             backup_state: flags.backup_state(),
             signature_counter,
             attested_credential_data,
-            extensions: None,
+            extensions,
         })
     }
 }
 
+#[cfg(feature = "serde")]
+impl Data {
+    /// Encodes the authenticator data, e.g. to be signed along with the
+    /// client data hash.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, coset::CoseError> {
+        let mut flags = DataFlags::empty();
+        flags.set(
+            DataFlags::USER_PRESENCE,
+            self.user_presence == UserPresence::Present,
+        );
+        flags.set(
+            DataFlags::USER_VERIFIED,
+            self.user_verification == UserVerification::Verified,
+        );
+        flags.set(
+            DataFlags::BACKUP_ELIGIBLE,
+            self.backup_eligibility == BackupEligibility::Eligible,
+        );
+        flags.set(
+            DataFlags::BACKUP_STATE,
+            self.backup_state == BackupState::BackedUp,
+        );
+        flags.set(
+            DataFlags::ATTESTED_CREDENTIAL_DATA,
+            self.attested_credential_data.is_some(),
+        );
+        flags.set(
+            DataFlags::EXTENSION_DATA_INCLUDED,
+            self.extensions.is_some(),
+        );
+
+        let mut data = self.relying_party_id_hash.to_vec();
+        data.push(flags.bits());
+        data.extend_from_slice(&self.signature_counter.to_be_bytes());
+        if let Some(attested_credential_data) = &self.attested_credential_data {
+            data.extend(attested_credential_data.to_bytes()?);
+        }
+        if let Some(extensions) = &self.extensions {
+            data.extend_from_slice(extensions.as_bytes());
+        }
+
+        Ok(data)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl Serialize for Data {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        let data = self.to_bytes().map_err(serde::ser::Error::custom)?;
+        serializer.serialize_bytes(&data)
+    }
+}
+
 /// > Authenticators may implement various transports for communicating with
 /// > clients. This enumeration defines hints as to how clients might
 /// > communicate with a particular authenticator in order to obtain an
@@ -198,3 +378,132 @@ pub enum Transport {
     // TODO: Serialize as contents of string
     Unknown(String), 
 }
+
+#[cfg(all(test, feature = "serde"))]
+mod tests {
+    use super::*;
+
+    /// The CBOR encoding of a short text string.
+    fn text(value: &str) -> Vec<u8> {
+        [&[0x60 + value.len() as u8], value.as_bytes()].concat()
+    }
+
+    /// `{"hmac-secret": true, "credProtect": 2, "myCompany_extension": h'FF'}`,
+    /// not in CTAP2 canonical order.
+    fn extensions() -> Vec<u8> {
+        [
+            vec![0xA3],
+            text("hmac-secret"),
+            vec![0xF5],
+            text("credProtect"),
+            vec![0x02],
+            text("myCompany_extension"),
+            vec![0x41, 0xFF],
+        ]
+        .concat()
+    }
+
+    fn decode(data: &[u8]) -> Result<Data, impl std::fmt::Debug> {
+        let mut encoded = Vec::new();
+        ciborium::ser::into_writer(&ciborium::value::Value::Bytes(data.to_vec()), &mut encoded)
+            .unwrap();
+        ciborium::de::from_reader(encoded.as_slice())
+    }
+
+    fn header(flags: u8) -> Vec<u8> {
+        [[0xAA; 32].as_slice(), &[flags], &7u32.to_be_bytes()].concat()
+    }
+
+    #[test]
+    fn without_extensions() {
+        let data = header(0b1);
+        let decoded = decode(&data).unwrap();
+        assert_eq!(decoded.relying_party_id_hash, [0xAA; 32]);
+        assert_eq!(decoded.user_presence, UserPresence::Present);
+        assert_eq!(decoded.user_verification, UserVerification::NotVerified);
+        assert_eq!(decoded.signature_counter, 7);
+        assert!(decoded.extensions.is_none());
+        assert_eq!(decoded.to_bytes().unwrap(), data);
+    }
+
+    #[test]
+    fn extensions_round_trip() {
+        let data = [header(0b1000_0001), extensions()].concat();
+        let decoded = decode(&data).unwrap();
+        let extensions = decoded.extensions.as_ref().unwrap();
+        assert_eq!(extensions.get("hmac-secret"), Some([0xF5].as_slice()));
+        assert_eq!(extensions.get("credProtect"), Some([0x02].as_slice()));
+        assert_eq!(
+            extensions.get("myCompany_extension"),
+            Some([0x41, 0xFF].as_slice())
+        );
+        assert_eq!(extensions.get("largeBlobKey"), None);
+        assert_eq!(
+            extensions.iter().map(|(id, _)| id).collect::<Vec<_>>(),
+            ["hmac-secret", "credProtect", "myCompany_extension"]
+        );
+        // The outputs are encoded again in their original order
+        assert_eq!(decoded.to_bytes().unwrap(), data);
+    }
+
+    #[test]
+    fn canonical_order() {
+        let extensions = Extensions::new([
+            ("largeBlobKey".to_owned(), vec![0x41, 0x01]),
+            ("myCompany_extension".to_owned(), vec![0x41, 0xFF]),
+            ("hmac-secret".to_owned(), vec![0xF5]),
+            ("credProtect".to_owned(), vec![0x02]),
+        ])
+        .unwrap();
+        assert_eq!(
+            extensions.as_bytes(),
+            [
+                vec![0xA4],
+                text("credProtect"),
+                vec![0x02],
+                text("hmac-secret"),
+                vec![0xF5],
+                text("largeBlobKey"),
+                vec![0x41, 0x01],
+                text("myCompany_extension"),
+                vec![0x41, 0xFF],
+            ]
+            .concat()
+        );
+        let (decoded, length) = Extensions::from_bytes(extensions.as_bytes()).unwrap();
+        assert_eq!(decoded, extensions);
+        assert_eq!(length, extensions.as_bytes().len());
+    }
+
+    #[test]
+    fn invalid_extensions() {
+        assert!(matches!(
+            Extensions::new([
+                ("credProtect".to_owned(), vec![0x02]),
+                ("credProtect".to_owned(), vec![0x03]),
+            ]),
+            Err(coset::CoseError::DuplicateMapKey)
+        ));
+        // An output that is not valid CBOR
+        assert!(Extensions::new([("credProtect".to_owned(), vec![0x41])]).is_err());
+        // Not a map
+        assert!(Extensions::from_bytes(&[0x80]).is_err());
+        // {1: 2}
+        assert!(Extensions::from_bytes(&[0xA1, 0x01, 0x02]).is_err());
+    }
+
+    #[test]
+    fn trailing_bytes() {
+        let data = [header(0b1000_0001), extensions(), vec![0x00]].concat();
+        assert!(decode(&data).is_err());
+        // The extension data flag is not set
+        assert!(decode(&[header(0b1), extensions()].concat()).is_err());
+    }
+
+    #[test]
+    fn truncated() {
+        assert!(decode(&header(0b1)[..36]).is_err());
+        let data = [header(0b1000_0001), extensions()].concat();
+        assert!(decode(&data[..data.len() - 1]).is_err());
+    }
+}
@@ -1,4 +1,5 @@
 use crate::{authenticator::Transport, credential};
+use coset::{cbor::value::Value, AsCborValue, CoseKey};
 use std::collections::BTreeSet;
 
 #[cfg(feature = "serde")]
@@ -60,16 +61,17 @@ pub struct Parameters {
     derive(Serialize, Deserialize)
 )]
 pub struct Descriptor {
-    /// > This member contains the type of the public key credential
-    /// > the caller is referring to.
-    #[cfg_attr(feature = "serde", serde(rename = "type"))]
-    pub credential_type: credential::Type,
+    // The fields are ordered as in the CTAP2 canonical CBOR encoding
     /// > A probabilistically-unique byte sequence identifying a
     /// > public key credential source and its authentication
     /// > assertions.
     // Bounds: [16, 1023] bytes
     #[cfg_attr(feature = "serde", serde_as(as = "Bytes"))]
     pub id: Vec<u8>,
+    /// > This member contains the type of the public key credential
+    /// > the caller is referring to.
+    #[cfg_attr(feature = "serde", serde(rename = "type"))]
+    pub credential_type: credential::Type,
     /// > This... member contains a hint as to how the client might
     /// > communicate with the managing authenticator of the public
     /// > key credential the caller is referring to.
@@ -128,3 +130,353 @@ pub struct RelyingPartyEntity {
     /// > Widgets, Inc." or "ОАО Примертех".
     pub name: Option<String>,
 }
+
+/// > The credential public key encoded in `COSE_Key` format, as defined in
+/// > Section 7 of [RFC9052], using the CTAP2 canonical CBOR encoding form.
+///
+/// With the `crypto` feature, it converts to and from the verifying keys of
+/// the ES256 and EdDSA algorithms.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Key(pub CoseKey);
+
+impl Key {
+    /// The algorithm the key is restricted to, if any.
+    pub fn algorithm(&self) -> Option<coset::iana::Algorithm> {
+        match self.0.alg {
+            Some(coset::Algorithm::Assigned(algorithm)) => Some(algorithm),
+            _ => None,
+        }
+    }
+}
+
+impl From<CoseKey> for Key {
+    fn from(value: CoseKey) -> Self {
+        Self(value)
+    }
+}
+
+impl From<Key> for CoseKey {
+    fn from(value: Key) -> Self {
+        value.0
+    }
+}
+
+impl AsRef<CoseKey> for Key {
+    fn as_ref(&self) -> &CoseKey {
+        &self.0
+    }
+}
+
+impl AsCborValue for Key {
+    fn from_cbor_value(value: Value) -> coset::Result<Self> {
+        CoseKey::from_cbor_value(value).map(Self)
+    }
+
+    fn to_cbor_value(self) -> coset::Result<Value> {
+        self.0.to_cbor_value()
+    }
+}
+
+impl coset::CborSerializable for Key {}
+
+#[cfg(feature = "serde")]
+impl Serialize for Key {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        self.clone()
+            .to_cbor_value()
+            .map_err(serde::ser::Error::custom)?
+            .serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> Deserialize<'de> for Key {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let value = Value::deserialize(deserializer)?;
+        Self::from_cbor_value(value).map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(feature = "crypto")]
+mod crypto {
+    use super::Key;
+    use coset::{
+        cbor::value::Value,
+        iana::{self, EnumI64},
+        CoseError, CoseKey, KeyType, Label,
+    };
+
+    impl Key {
+        /// The value of the key type parameter with the given label, if
+        /// present.
+        fn parameter(&self, label: i64) -> Option<&Value> {
+            self.0
+                .params
+                .iter()
+                .find_map(|(key, value)| (*key == Label::Int(label)).then_some(value))
+        }
+
+        /// Checks that the key is of the given type and curve, and the
+        /// algorithm is either absent or the given one.
+        fn expect(
+            &self,
+            key_type: iana::KeyType,
+            curve: iana::EllipticCurve,
+            algorithm: iana::Algorithm,
+        ) -> Result<(), CoseError> {
+            // The curve has the same label for EC2 and OKP keys
+            let curve_matches = self
+                .parameter(iana::Ec2KeyParameter::Crv.to_i64())
+                .and_then(Value::as_integer)
+                .is_some_and(|crv| i128::from(crv) == i128::from(curve.to_i64()));
+            let algorithm_matches = match self.0.alg {
+                None => true,
+                Some(ref alg) => *alg == coset::Algorithm::Assigned(algorithm),
+            };
+            if self.0.kty != KeyType::Assigned(key_type) || !curve_matches || !algorithm_matches {
+                return Err(CoseError::UnexpectedItem(
+                    "a key of another type, curve or algorithm",
+                    "a key of the requested type",
+                ));
+            }
+            Ok(())
+        }
+
+        /// The byte string value of the key type parameter with the given
+        /// label, which must be `length` bytes long.
+        fn coordinate(&self, label: i64, length: usize) -> Result<&[u8], CoseError> {
+            self.parameter(label)
+                .and_then(Value::as_bytes)
+                .map(Vec::as_slice)
+                .filter(|bytes| bytes.len() == length)
+                .ok_or(CoseError::UnexpectedItem(
+                    "a missing or malformed coordinate",
+                    "a byte string coordinate",
+                ))
+        }
+    }
+
+    impl TryFrom<&Key> for p256::ecdsa::VerifyingKey {
+        type Error = CoseError;
+
+        /// Reads an ES256 key, i.e. an EC2 key on the P-256 curve with both
+        /// coordinates.
+        fn try_from(key: &Key) -> Result<Self, Self::Error> {
+            key.expect(
+                iana::KeyType::EC2,
+                iana::EllipticCurve::P_256,
+                iana::Algorithm::ES256,
+            )?;
+            let x = key.coordinate(iana::Ec2KeyParameter::X.to_i64(), 32)?;
+            let y = key.coordinate(iana::Ec2KeyParameter::Y.to_i64(), 32)?;
+            let point = p256::EncodedPoint::from_affine_coordinates(x.into(), y.into(), false);
+            Self::from_encoded_point(&point).map_err(|_| {
+                CoseError::UnexpectedItem("an invalid point", "a point on the P-256 curve")
+            })
+        }
+    }
+
+    impl From<&p256::ecdsa::VerifyingKey> for Key {
+        fn from(value: &p256::ecdsa::VerifyingKey) -> Self {
+            let point = value.to_encoded_point(false);
+            // Neither coordinate is absent for an uncompressed point
+            let x = point.x().map(|x| x.to_vec()).unwrap_or_default();
+            let y = point.y().map(|y| y.to_vec()).unwrap_or_default();
+            Self(
+                coset::CoseKeyBuilder::new_ec2_pub_key(iana::EllipticCurve::P_256, x, y)
+                    .algorithm(iana::Algorithm::ES256)
+                    .build(),
+            )
+        }
+    }
+
+    impl TryFrom<&Key> for ed25519_dalek::VerifyingKey {
+        type Error = CoseError;
+
+        /// Reads an EdDSA key, i.e. an OKP key on the Ed25519 curve.
+        fn try_from(key: &Key) -> Result<Self, Self::Error> {
+            key.expect(
+                iana::KeyType::OKP,
+                iana::EllipticCurve::Ed25519,
+                iana::Algorithm::EdDSA,
+            )?;
+            let x = key.coordinate(iana::OkpKeyParameter::X.to_i64(), 32)?;
+            let x = <&[u8; 32]>::try_from(x).expect("coordinate is 32 bytes long");
+            Self::from_bytes(x).map_err(|_| {
+                CoseError::UnexpectedItem("an invalid point", "a point on the Ed25519 curve")
+            })
+        }
+    }
+
+    impl From<&ed25519_dalek::VerifyingKey> for Key {
+        fn from(value: &ed25519_dalek::VerifyingKey) -> Self {
+            Self(CoseKey {
+                kty: KeyType::Assigned(iana::KeyType::OKP),
+                alg: Some(coset::Algorithm::Assigned(iana::Algorithm::EdDSA)),
+                params: vec![
+                    (
+                        Label::Int(iana::OkpKeyParameter::Crv.to_i64()),
+                        Value::from(iana::EllipticCurve::Ed25519.to_i64()),
+                    ),
+                    (
+                        Label::Int(iana::OkpKeyParameter::X.to_i64()),
+                        Value::Bytes(value.to_bytes().to_vec()),
+                    ),
+                ],
+                ..Default::default()
+            })
+        }
+    }
+
+    impl TryFrom<&Key> for rsa::RsaPublicKey {
+        type Error = CoseError;
+
+        /// Reads an RSA key, e.g. of the RS256 algorithm.
+        fn try_from(key: &Key) -> Result<Self, Self::Error> {
+            if key.0.kty != KeyType::Assigned(iana::KeyType::RSA) {
+                return Err(CoseError::UnexpectedItem(
+                    "a key of another type",
+                    "an RSA key",
+                ));
+            }
+            let integer = |label: iana::RsaKeyParameter| {
+                key.parameter(label.to_i64())
+                    .and_then(Value::as_bytes)
+                    .map(|bytes| rsa::BigUint::from_bytes_be(bytes))
+                    .ok_or(CoseError::UnexpectedItem(
+                        "a missing or malformed parameter",
+                        "a byte string modulus and exponent",
+                    ))
+            };
+            Self::new(
+                integer(iana::RsaKeyParameter::N)?,
+                integer(iana::RsaKeyParameter::E)?,
+            )
+            .map_err(|_| CoseError::UnexpectedItem("an invalid key", "a valid RSA public key"))
+        }
+    }
+}
+
+#[cfg(all(test, feature = "crypto"))]
+mod tests {
+    use super::*;
+    use coset::{
+        iana::{self, EnumI64},
+        CoseKeyBuilder, Label,
+    };
+
+    fn p256_key() -> p256::ecdsa::VerifyingKey {
+        *p256::ecdsa::SigningKey::from_bytes(&[0x01; 32].into())
+            .unwrap()
+            .verifying_key()
+    }
+
+    fn ed25519_key() -> ed25519_dalek::VerifyingKey {
+        ed25519_dalek::SigningKey::from_bytes(&[0x01; 32]).verifying_key()
+    }
+
+    /// Replaces the value of the key type parameter with the given label.
+    fn with_parameter(key: &Key, label: i64, value: Value) -> Key {
+        let mut key = key.clone();
+        for (parameter, old) in &mut key.0.params {
+            if *parameter == Label::Int(label) {
+                *old = value.clone();
+            }
+        }
+        key
+    }
+
+    #[test]
+    fn p256_round_trip() {
+        let verifying_key = p256_key();
+        let key = Key::from(&verifying_key);
+        assert_eq!(key.0.kty, coset::KeyType::Assigned(iana::KeyType::EC2));
+        assert_eq!(key.algorithm(), Some(iana::Algorithm::ES256));
+        assert_eq!(
+            p256::ecdsa::VerifyingKey::try_from(&key).unwrap(),
+            verifying_key
+        );
+
+        // The algorithm is optional
+        let mut key = key;
+        key.0.alg = None;
+        assert_eq!(
+            p256::ecdsa::VerifyingKey::try_from(&key).unwrap(),
+            verifying_key
+        );
+    }
+
+    #[test]
+    fn ed25519_round_trip() {
+        let verifying_key = ed25519_key();
+        let key = Key::from(&verifying_key);
+        assert_eq!(key.0.kty, coset::KeyType::Assigned(iana::KeyType::OKP));
+        assert_eq!(key.algorithm(), Some(iana::Algorithm::EdDSA));
+        assert_eq!(
+            ed25519_dalek::VerifyingKey::try_from(&key).unwrap(),
+            verifying_key
+        );
+    }
+
+    #[test]
+    fn rejects_other_key_types() {
+        let p256 = Key::from(&p256_key());
+        let ed25519 = Key::from(&ed25519_key());
+        assert!(ed25519_dalek::VerifyingKey::try_from(&p256).is_err());
+        assert!(p256::ecdsa::VerifyingKey::try_from(&ed25519).is_err());
+        assert!(rsa::RsaPublicKey::try_from(&p256).is_err());
+        assert!(rsa::RsaPublicKey::try_from(&ed25519).is_err());
+    }
+
+    #[test]
+    fn rejects_other_curves() {
+        let crv = iana::Ec2KeyParameter::Crv.to_i64();
+        let p384 = Value::from(iana::EllipticCurve::P_384.to_i64());
+        let key = with_parameter(&Key::from(&p256_key()), crv, p384);
+        assert!(p256::ecdsa::VerifyingKey::try_from(&key).is_err());
+
+        let x25519 = Value::from(iana::EllipticCurve::X25519.to_i64());
+        let key = with_parameter(&Key::from(&ed25519_key()), crv, x25519);
+        assert!(ed25519_dalek::VerifyingKey::try_from(&key).is_err());
+    }
+
+    #[test]
+    fn rejects_other_algorithms() {
+        let mut key = Key::from(&p256_key());
+        key.0.alg = Some(coset::Algorithm::Assigned(iana::Algorithm::ES384));
+        assert!(p256::ecdsa::VerifyingKey::try_from(&key).is_err());
+
+        let mut key = Key::from(&ed25519_key());
+        key.0.alg = Some(coset::Algorithm::Assigned(iana::Algorithm::ES256));
+        assert!(ed25519_dalek::VerifyingKey::try_from(&key).is_err());
+    }
+
+    #[test]
+    fn rejects_malformed_coordinates() {
+        let x = iana::Ec2KeyParameter::X.to_i64();
+        let key = Key::from(&p256_key());
+        let short = with_parameter(&key, x, Value::Bytes(vec![0x01; 31]));
+        assert!(p256::ecdsa::VerifyingKey::try_from(&short).is_err());
+        // Not a point on the curve
+        let off_curve = with_parameter(&key, x, Value::Bytes(vec![0x01; 32]));
+        assert!(p256::ecdsa::VerifyingKey::try_from(&off_curve).is_err());
+
+        let compressed = CoseKeyBuilder::new_ec2_pub_key_y_sign(
+            iana::EllipticCurve::P_256,
+            vec![0x01; 32],
+            true,
+        )
+        .build();
+        assert!(p256::ecdsa::VerifyingKey::try_from(&Key(compressed)).is_err());
+
+        let key = Key::from(&ed25519_key());
+        let text = with_parameter(&key, x, Value::Text("x".to_owned()));
+        assert!(ed25519_dalek::VerifyingKey::try_from(&text).is_err());
+    }
+}
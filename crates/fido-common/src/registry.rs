@@ -447,6 +447,7 @@ pub mod algorithms {
 /// > The [`Attestation`] constants are 16 bit long integers indicating the
 /// > specific attestation that authenticator supports.
 #[repr(u16)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum Attestation {
     /// > Indicates full basic attestation, based on an attestation private key